@@ -0,0 +1,298 @@
+//! Mapping of Rust target triples to Debian architecture names.
+//!
+//! The built-in table can be extended with `arch-map` in `[package.metadata.deb]` or `--arch-map <file>`.
+use crate::assets::AssetSource;
+use crate::config::PackageConfig;
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// `(arch, abi, Debian architecture)`. Rust's arch is the first part of the triple, and the abi is the last.
+/// `*` at the start or end of a pattern matches anything. The first matching row wins.
+///
+/// <https://wiki.debian.org/Multiarch/Tuples>, `rustc --print target-list`,
+/// <https://doc.rust-lang.org/std/env/consts/constant.ARCH.html>
+const ARCH_TABLE: &[(&str, &str, &str)] = &[
+    ("aarch64", "gnu_ilp32", "arm64ilp32"),
+    ("aarch64", "*", "arm64"),
+    ("mips64", "gnuabin32", "mipsn32"),
+    ("mips64el", "gnuabin32", "mipsn32el"),
+    ("mipsisa32r6", "*", "mipsr6"),
+    ("mipsisa32r6el", "*", "mipsr6el"),
+    ("mipsisa64r6", "gnuabi64", "mips64r6"),
+    ("mipsisa64r6", "gnuabin32", "mipsn32r6"),
+    ("mipsisa64r6el", "gnuabi64", "mips64r6el"),
+    ("mipsisa64r6el", "gnuabin32", "mipsn32r6el"),
+    ("powerpc", "*spe", "powerpcspe"),
+    ("powerpc64", "*", "ppc64"),
+    ("powerpc64le", "*", "ppc64el"),
+    ("riscv64*", "*", "riscv64"),
+    ("i586", "*", "i386"),
+    ("i686", "*", "i386"),
+    ("x86", "*", "i386"),
+    ("x86_64", "gnux32", "x32"),
+    ("x86_64", "*", "amd64"),
+    ("loongarch64", "*", "loong64"),
+    ("armeb", "*", "armeb"),
+    ("arm*", "*hf", "armhf"),
+    ("thumb*", "*hf", "armhf"),
+    ("arm*", "*", "armel"),
+    ("thumb*", "*", "armel"),
+];
+
+fn matches(pattern: &str, value: &str) -> bool {
+    if pattern == "*" {
+        true
+    } else if let Some(prefix) = pattern.strip_suffix('*') {
+        value.starts_with(prefix)
+    } else if let Some(suffix) = pattern.strip_prefix('*') {
+        value.ends_with(suffix)
+    } else {
+        pattern == value
+    }
+}
+
+/// Debianizes the architecture name using the built-in table. Unknown architectures are returned unchanged.
+/// Weirdly, architecture and multiarch use different naming conventions in Debian!
+pub(crate) fn debian_architecture_from_rust_triple(target: &str) -> &str {
+    let mut parts = target.split('-');
+    let arch = parts.next().unwrap_or_default();
+    let abi = parts.last().unwrap_or("");
+    ARCH_TABLE.iter()
+        .find(|(arch_pattern, abi_pattern, _)| matches(arch_pattern, arch) && matches(abi_pattern, abi))
+        .map_or(arch, |&(_, _, debian_arch)| debian_arch)
+}
+
+/// Like [`debian_architecture_from_rust_triple`], but checks the user-supplied maps of `(triple glob, Debian architecture)` pairs first,
+/// in the order of precedence (`--arch-map` before the metadata). Within a map, an exact match wins over globs,
+/// and longer globs win over shorter ones.
+pub(crate) fn debian_architecture(target: &str, custom: &[&[(String, String)]]) -> CDResult<String> {
+    for map in custom {
+        let mut best: Option<&(String, String)> = None;
+        for entry in *map {
+            if entry.0 == target {
+                return Ok(entry.1.clone());
+            }
+            if glob::Pattern::new(&entry.0)?.matches(target) && !best.is_some_and(|b| b.0.len() >= entry.0.len()) {
+                best = Some(entry);
+            }
+        }
+        if let Some((_, arch)) = best {
+            return Ok(arch.clone());
+        }
+    }
+    Ok(debian_architecture_from_rust_triple(target).to_owned())
+}
+
+/// `(Debian architecture, multiarch tuple)`, from <https://wiki.debian.org/Multiarch/Tuples>
+const MULTIARCH_TABLE: &[(&str, &str)] = &[
+    ("amd64", "x86_64-linux-gnu"),
+    ("arm64", "aarch64-linux-gnu"),
+    ("arm64ilp32", "aarch64-linux-gnu_ilp32"),
+    ("armel", "arm-linux-gnueabi"),
+    ("armhf", "arm-linux-gnueabihf"),
+    ("armeb", "armeb-linux-gnueabi"),
+    ("i386", "i386-linux-gnu"),
+    ("x32", "x86_64-linux-gnux32"),
+    ("loong64", "loongarch64-linux-gnu"),
+    ("m68k", "m68k-linux-gnu"),
+    ("mips", "mips-linux-gnu"),
+    ("mipsel", "mipsel-linux-gnu"),
+    ("mips64", "mips64-linux-gnuabi64"),
+    ("mips64el", "mips64el-linux-gnuabi64"),
+    ("mipsn32", "mips64-linux-gnuabin32"),
+    ("mipsn32el", "mips64el-linux-gnuabin32"),
+    ("mipsr6", "mipsisa32r6-linux-gnu"),
+    ("mipsr6el", "mipsisa32r6el-linux-gnu"),
+    ("mips64r6", "mipsisa64r6-linux-gnuabi64"),
+    ("mips64r6el", "mipsisa64r6el-linux-gnuabi64"),
+    ("mipsn32r6", "mipsisa64r6-linux-gnuabin32"),
+    ("mipsn32r6el", "mipsisa64r6el-linux-gnuabin32"),
+    ("powerpc", "powerpc-linux-gnu"),
+    ("powerpcspe", "powerpc-linux-gnuspe"),
+    ("ppc64", "powerpc64-linux-gnu"),
+    ("ppc64el", "powerpc64le-linux-gnu"),
+    ("riscv64", "riscv64-linux-gnu"),
+    ("s390x", "s390x-linux-gnu"),
+    ("sparc", "sparc-linux-gnu"),
+    ("sparc64", "sparc64-linux-gnu"),
+];
+
+/// Multiarch tuple (used in `/usr/lib/<tuple>` and names of cross-compilers) of a Debian architecture
+pub(crate) fn multiarch_tuple(debian_arch: &str) -> Option<&'static str> {
+    MULTIARCH_TABLE.iter().find(|&&(arch, _)| arch == debian_arch).map(|&(_, tuple)| tuple)
+}
+
+/// `(Debian architecture, ELF e_machine, 64-bit, big-endian, name of the ELF machine)`.
+/// ARM soft/hard float and MIPS ABI variants have the same header, so they're not distinguished.
+const ELF_MACHINES: &[(&str, u16, bool, bool, &str)] = &[
+    ("amd64", 62, true, false, "x86-64"),
+    ("x32", 62, false, false, "x32"),
+    ("i386", 3, false, false, "x86"),
+    ("arm64", 183, true, false, "AArch64"),
+    ("arm64ilp32", 183, false, false, "AArch64 ILP32"),
+    ("armhf", 40, false, false, "ARM"),
+    ("armel", 40, false, false, "ARM"),
+    ("armeb", 40, false, true, "big-endian ARM"),
+    ("riscv64", 243, true, false, "RISC-V 64"),
+    ("ppc64el", 21, true, false, "little-endian PowerPC 64"),
+    ("ppc64", 21, true, true, "PowerPC 64"),
+    ("powerpc", 20, false, true, "PowerPC"),
+    ("powerpcspe", 20, false, true, "PowerPC"),
+    ("s390x", 22, true, true, "S/390"),
+    ("loong64", 258, true, false, "LoongArch 64"),
+    ("mips", 8, false, true, "MIPS"),
+    ("mipsel", 8, false, false, "little-endian MIPS"),
+    ("mips64", 8, true, true, "MIPS 64"),
+    ("mips64el", 8, true, false, "little-endian MIPS 64"),
+    ("sparc64", 43, true, true, "SPARC 64"),
+    ("m68k", 4, false, true, "m68k"),
+];
+
+/// `(e_machine, 64-bit, big-endian)`, or `None` if the file isn't an ELF file
+fn read_elf_header(path: &Path) -> io::Result<Option<(u16, bool, bool)>> {
+    let mut header = [0u8; 20];
+    let mut file = fs::File::open(path)?;
+    if file.read_exact(&mut header).is_err() || header[..4] != *b"\x7fELF" {
+        return Ok(None);
+    }
+    let big_endian = header[5] == 2;
+    let machine = [header[18], header[19]];
+    let machine = if big_endian { u16::from_be_bytes(machine) } else { u16::from_le_bytes(machine) };
+    Ok(Some((machine, header[4] == 2, big_endian)))
+}
+
+fn elf_machine_name(header: (u16, bool, bool)) -> String {
+    ELF_MACHINES.iter()
+        .find(|&&(_, machine, is_64, big_endian, _)| (machine, is_64, big_endian) == header)
+        .map_or_else(|| format!("ELF machine {}", header.0), |&(.., name)| name.to_owned())
+}
+
+/// Checks that prebuilt executables and libraries in the assets (the ones that aren't built by Cargo) have been compiled
+/// for the package's architecture. Mismatches are errors, unless `allow_foreign` is set for intentionally multi-arch payloads.
+/// Architectures that aren't in the table can't be checked.
+pub(crate) fn check_prebuilt_binaries(package_deb: &PackageConfig, allow_foreign: bool, listener: &dyn Listener) -> CDResult<()> {
+    let arch = package_deb.architecture.as_str();
+    let expected = ELF_MACHINES.iter()
+        .filter(|&&(debian_arch, ..)| debian_arch == arch)
+        .map(|&(_, machine, is_64, big_endian, _)| (machine, is_64, big_endian))
+        .collect::<Vec<_>>();
+    if expected.is_empty() && arch != "all" {
+        log::debug!("can't check ELF machine of prebuilt binaries for {arch}");
+        return Ok(());
+    }
+
+    for asset in package_deb.assets.resolved.iter().filter(|a| !a.c.is_built()) {
+        let AssetSource::Path(path) = &asset.source else { continue };
+        let header = match read_elf_header(path) {
+            Ok(Some(header)) => header,
+            Ok(None) => continue,
+            Err(e) => return Err(CargoDebError::IoFile("unable to read asset", e, path.clone())),
+        };
+        if expected.contains(&header) {
+            continue;
+        }
+        let elf_arch = elf_machine_name(header);
+        if !allow_foreign {
+            return Err(CargoDebError::ForeignBinary(path.clone(), elf_arch, arch.to_owned()));
+        }
+        listener.warning(format!("{} is a binary for {elf_arch}, not {arch}", path.display()));
+    }
+    Ok(())
+}
+
+/// Reads a TOML file with `"target-triple-or-glob" = "debian-arch"` lines
+pub(crate) fn read_arch_map(path: &Path) -> CDResult<BTreeMap<String, String>> {
+    let text = fs::read_to_string(path).map_err(|e| CargoDebError::IoFile("unable to read the arch map", e, path.into()))?;
+    toml::from_str(&text).map_err(|e| CargoDebError::ArchMapParse(Box::new(e), path.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arch_table() {
+        assert_eq!("armhf", debian_architecture_from_rust_triple("arm-unknown-linux-gnueabihf"));
+        assert_eq!("armhf", debian_architecture_from_rust_triple("thumbv7neon-unknown-linux-gnueabihf"));
+        assert_eq!("armel", debian_architecture_from_rust_triple("armv5te-unknown-linux-gnueabi"));
+        assert_eq!("powerpcspe", debian_architecture_from_rust_triple("powerpc-unknown-linux-muslspe"));
+        assert_eq!("x32", debian_architecture_from_rust_triple("x86_64-unknown-linux-gnux32"));
+        assert_eq!("amd64", debian_architecture_from_rust_triple("x86_64-unknown-linux-musl"));
+        assert_eq!("mipsn32r6el", debian_architecture_from_rust_triple("mipsisa64r6el-unknown-linux-gnuabin32"));
+        assert_eq!("wasm32", debian_architecture_from_rust_triple("wasm32-unknown-unknown"));
+    }
+
+    #[test]
+    fn custom_arch_map() {
+        let custom = [
+            ("x86_64-*".to_owned(), "custom64".to_owned()),
+            ("x86_64-unknown-linux-*".to_owned(), "linux64".to_owned()),
+            ("riscv32gc-unknown-linux-gnu".to_owned(), "riscv32".to_owned()),
+        ];
+        assert_eq!("linux64", debian_architecture("x86_64-unknown-linux-musl", &[&custom]).unwrap());
+        assert_eq!("custom64", debian_architecture("x86_64-pc-windows-gnu", &[&custom]).unwrap());
+        assert_eq!("riscv32", debian_architecture("riscv32gc-unknown-linux-gnu", &[&custom]).unwrap());
+        assert_eq!("arm64", debian_architecture("aarch64-unknown-linux-gnu", &[&custom]).unwrap());
+
+        // --arch-map wins over the metadata, even over its exact matches
+        let cli = [("x86_64-*".to_owned(), "from-cli".to_owned())];
+        assert_eq!("from-cli", debian_architecture("x86_64-unknown-linux-musl", &[&cli, &custom]).unwrap());
+        assert_eq!("riscv32", debian_architecture("riscv32gc-unknown-linux-gnu", &[&cli, &custom]).unwrap());
+
+        assert_eq!(Some("arm-linux-gnueabihf"), multiarch_tuple("armhf"));
+        assert_eq!(None, multiarch_tuple("riscv32"));
+    }
+
+    #[test]
+    fn prebuilt_binaries_arch() {
+        let dir = tempfile::tempdir().unwrap();
+        let elf = |name: &str, machine: u16, is_64: bool, big_endian: bool| {
+            let mut header = b"\x7fELF".to_vec();
+            header.extend([if is_64 { 2 } else { 1 }, if big_endian { 2 } else { 1 }, 1]);
+            header.resize(18, 0);
+            header.extend(if big_endian { machine.to_be_bytes() } else { machine.to_le_bytes() });
+            header.resize(64, 0);
+            let path = dir.path().join(name);
+            fs::write(&path, header).unwrap();
+            path
+        };
+        assert_eq!(Some((62, true, false)), read_elf_header(&elf("amd64", 62, true, false)).unwrap());
+        assert_eq!(Some((21, true, true)), read_elf_header(&elf("ppc64", 21, true, true)).unwrap());
+        fs::write(dir.path().join("script"), "#!/bin/sh\n").unwrap();
+        assert_eq!(None, read_elf_header(&dir.path().join("script")).unwrap());
+
+        assert_eq!("AArch64", elf_machine_name((183, true, false)));
+        assert_eq!("x32", elf_machine_name((62, false, false)));
+        assert_eq!("ELF machine 9999", elf_machine_name((9999, true, false)));
+    }
+
+    /// Every Linux target of the installed rustc must map to an architecture that dpkg knows
+    #[test]
+    #[ignore = "needs rustc, run with --ignored"]
+    fn all_rustc_linux_targets() {
+        const DEBIAN_ARCHS: &[&str] = &[
+            "amd64", "arm64", "arm64ilp32", "armeb", "armel", "armhf", "i386", "loong64", "m68k",
+            "mips", "mipsel", "mips64", "mips64el", "mipsn32", "mipsn32el", "mipsr6", "mipsr6el",
+            "mips64r6", "mips64r6el", "mipsn32r6", "mipsn32r6el", "powerpc", "powerpcspe", "ppc64", "ppc64el",
+            "riscv64", "s390x", "sparc", "sparc64", "x32",
+        ];
+        // No Debian port, so arch-map is needed for these
+        const UNSUPPORTED: &[&str] = &["aarch64_be", "csky", "hexagon", "riscv32gc", "wasm32"];
+
+        let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+        let output = std::process::Command::new(rustc).args(["--print", "target-list"]).output().expect("rustc --print target-list");
+        let targets = String::from_utf8(output.stdout).unwrap();
+        for target in targets.lines().filter(|t| t.contains("-linux-")) {
+            if UNSUPPORTED.iter().any(|arch| target.starts_with(&format!("{arch}-"))) {
+                continue;
+            }
+            let arch = debian_architecture_from_rust_triple(target);
+            assert!(DEBIAN_ARCHS.contains(&arch), "{target} maps to unknown Debian architecture {arch}");
+            assert!(multiarch_tuple(arch).is_some(), "{arch} has no multiarch tuple");
+        }
+    }
+}
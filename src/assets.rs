@@ -7,16 +7,22 @@ use crate::util::read_file_to_bytes;
 use std::borrow::Cow;
 use std::env::consts::DLL_SUFFIX;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::path::{Component, Path, PathBuf};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub enum AssetSource {
     /// Copy file from the path (and strip binary if needed).
     Path(PathBuf),
     /// A symlink existing in the file system
     Symlink(PathBuf),
     /// Write data to destination as-is.
-    Data(Vec<u8>),
+    Data(#[serde(serialize_with = "serialize_data_len")] Vec<u8>),
+}
+
+/// Serializes only the length, since the data may be large and binary
+fn serialize_data_len<S: serde::Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(data.len() as u64)
 }
 
 impl AssetSource {
@@ -67,6 +73,18 @@ impl AssetSource {
         }
     }
 
+    /// Size and a reader of the contents, so that large files don't have to be loaded into memory
+    pub(crate) fn open(&self) -> CDResult<(u64, Box<dyn io::Read + '_>)> {
+        Ok(match self {
+            AssetSource::Path(p) | AssetSource::Symlink(p) => {
+                let file = fs::File::open(p).map_err(|e| CargoDebError::IoFile("unable to read asset to add to archive", e, p.clone()))?;
+                let size = file.metadata().map_err(|e| CargoDebError::IoFile("unable to read asset to add to archive", e, p.clone()))?.len();
+                (size, Box::new(io::BufReader::with_capacity(1 << 16, file)))
+            },
+            AssetSource::Data(d) => (d.len() as u64, Box::new(&d[..])),
+        })
+    }
+
     pub fn data(&self) -> CDResult<Cow<'_, [u8]>> {
         Ok(match self {
             AssetSource::Path(p) => {
@@ -84,18 +102,53 @@ impl AssetSource {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub(crate) struct Assets {
     pub unresolved: Vec<UnresolvedAsset>,
     pub resolved: Vec<Asset>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(try_from = "CargoDebAssetArrayOrTable")]
 pub(crate) struct RawAsset {
     pub source_path: PathBuf,
     pub target_path: PathBuf,
     pub chmod: u32,
+    pub transforms: Vec<AssetTransform>,
+    /// `{ bin = "…" }` names an executable in the workspace. `source_path` is only its likely build output
+    pub bin: Option<String>,
+}
+
+/// Post-build processing of an asset's file, e.g. to reduce its installed size
+#[derive(Debug, Copy, Clone, Eq, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssetTransform {
+    /// Expand `{{variable}}` placeholders in a text file
+    Template,
+    /// `objcopy --compress-debug-sections`
+    CompressDebugSections,
+    /// `upx --best`
+    Upx,
+}
+
+impl AssetTransform {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "template" => Self::Template,
+            "compress-debug-sections" => Self::CompressDebugSections,
+            "upx" => Self::Upx,
+            _ => return None,
+        })
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Template => "template",
+            Self::CompressDebugSections => "compress-debug-sections",
+            Self::Upx => "upx",
+        }
+    }
 }
 
 impl Assets {
@@ -121,7 +174,7 @@ impl Assets {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, serde::Serialize)]
 pub enum IsBuilt {
     No,
     SamePackage,
@@ -129,7 +182,7 @@ pub enum IsBuilt {
     Workspace,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct UnresolvedAsset {
     pub source_path: PathBuf,
     pub c: AssetCommon,
@@ -139,13 +192,13 @@ impl UnresolvedAsset {
     pub(crate) fn new(source_path: PathBuf, target_path: PathBuf, chmod: u32, is_built: IsBuilt, is_example: bool) -> Self {
         Self {
             source_path,
-            c: AssetCommon { target_path, chmod, is_example, is_built },
+            c: AssetCommon { target_path, chmod, is_example, is_built, transforms: Vec::new() },
         }
     }
 
     /// Convert `source_path` (with glob or dir) to actual path
-    pub fn resolve(self, preserve_symlinks: bool) -> CDResult<Vec<Asset>> {
-        let Self { source_path, c: AssetCommon { target_path, chmod, is_built, is_example } } = self;
+    pub fn resolve(self, preserve_symlinks: bool, excludes: &GlobExcludes) -> CDResult<Vec<Asset>> {
+        let Self { source_path, c: AssetCommon { target_path, chmod, is_built, is_example, transforms } } = self;
         let source_prefix = is_glob_pattern(&source_path).then(|| {
             source_path.iter()
                 .take_while(|&part| !is_glob_pattern(part.as_ref()))
@@ -155,7 +208,16 @@ impl UnresolvedAsset {
             // Remove dirs from globs without throwing away errors
             .map(|entry| {
                 let source_file = entry?;
-                Ok(if source_file.is_dir() { None } else { Some(source_file) })
+                if source_file.is_dir() {
+                    return Ok(None);
+                }
+                if let Some(source_prefix) = &source_prefix {
+                    if excludes.is_excluded(&source_file, source_prefix, is_built != IsBuilt::No) {
+                        log::debug!("glob match {} is excluded", source_file.display());
+                        return Ok(None);
+                    }
+                }
+                Ok(Some(source_file))
             })
             .filter_map(|res| {
                 Some(res.transpose()?.map(|source_file| {
@@ -165,13 +227,14 @@ impl UnresolvedAsset {
                         target_path.clone()
                     };
                     log::debug!("asset {} -> {} {} {:o}", source_file.display(), target_file.display(), if is_built != IsBuilt::No {"copy"} else {"build"}, chmod);
-                    let asset = Asset::new(
+                    let mut asset = Asset::new(
                         AssetSource::from_path(source_file, preserve_symlinks),
                         target_file,
                         chmod,
                         is_built,
                         is_example,
                     );
+                    asset.c.transforms.clone_from(&transforms);
                     if source_prefix.is_some() {
                         asset.processed("glob", None)
                     } else {
@@ -188,15 +251,84 @@ impl UnresolvedAsset {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Files that asset globs don't match
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct GlobExcludes {
+    /// Directories with cargo-deb's own output, e.g. `target/debian`
+    pub dirs: Vec<PathBuf>,
+    /// Package root that the patterns are relative to
+    pub root: PathBuf,
+    /// gitignore-style patterns, from `package.exclude` or `.gitignore`
+    pub exclude: Vec<String>,
+    /// gitignore-style patterns from `package.include`. If not empty, non-built files must match one of them.
+    pub include: Vec<String>,
+}
+
+impl GlobExcludes {
+    /// `glob_prefix` is the non-glob part of the pattern that matched the `path`
+    pub(crate) fn is_excluded(&self, path: &Path, glob_prefix: &Path, is_built: bool) -> bool {
+        // VCS internals are never wanted, but allowed if the pattern explicitly points inside them
+        let matched_part = path.strip_prefix(glob_prefix).unwrap_or(path);
+        if matched_part.iter().any(|c| c == ".git" || c == ".hg" || c == ".svn") {
+            return true;
+        }
+        if self.dirs.iter().any(|dir| path.starts_with(dir)) {
+            return true;
+        }
+        if is_built {
+            return false;
+        }
+        let Ok(rel_path) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if self.exclude.iter().any(|pattern| gitignore_pattern_matches(pattern, rel_path)) {
+            return true;
+        }
+        !self.include.is_empty() && !self.include.iter().any(|pattern| gitignore_pattern_matches(pattern, rel_path))
+    }
+}
+
+/// Subset of the `.gitignore` syntax used by `package.exclude`. Negations (`!`) aren't supported.
+fn gitignore_pattern_matches(pattern: &str, rel_path: &Path) -> bool {
+    let pattern = pattern.trim();
+    if pattern.is_empty() || pattern.starts_with('#') || pattern.starts_with('!') {
+        return false;
+    }
+    let (pattern, dir_only) = match pattern.strip_suffix('/') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let anchored = pattern.contains('/');
+    let Ok(glob_pattern) = glob::Pattern::new(pattern.trim_start_matches('/')) else {
+        return false;
+    };
+    let options = glob::MatchOptions { require_literal_separator: true, ..glob::MatchOptions::new() };
+
+    // A match of a parent directory excludes everything in it
+    let num_components = rel_path.components().count();
+    let num_checked = if dir_only { num_components.saturating_sub(1) } else { num_components };
+    if anchored {
+        let mut prefix = PathBuf::new();
+        rel_path.components().take(num_checked).any(|c| {
+            prefix.push(c);
+            glob_pattern.matches_path_with(&prefix, options)
+        })
+    } else {
+        rel_path.components().take(num_checked).any(|c| glob_pattern.matches_with(&c.as_os_str().to_string_lossy(), options))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct AssetCommon {
     pub target_path: PathBuf,
     pub chmod: u32,
     pub(crate) is_example: bool,
     is_built: IsBuilt,
+    /// Applied in order after the build and stripping
+    pub transforms: Vec<AssetTransform>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Asset {
     pub source: AssetSource,
     /// For prettier path display not "/tmp/blah.tmp"
@@ -204,7 +336,7 @@ pub struct Asset {
     pub c: AssetCommon,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct ProcessedFrom {
     pub original_path: Option<PathBuf>,
     pub action: &'static str,
@@ -222,11 +354,23 @@ impl Asset {
         if target_path.is_absolute() || target_path.has_root() {
             target_path = target_path.strip_prefix("/").expect("no root dir").to_owned();
         }
+        // `./usr//bin/` is the same path as `usr/bin`, and has to compare equal
+        if target_path.components().any(|c| matches!(c, Component::CurDir)) {
+            target_path = target_path.components().filter(|c| !matches!(c, Component::CurDir)).collect();
+        }
 
         Self {
             source,
             processed_from: None,
-            c: AssetCommon { target_path, chmod, is_example, is_built },
+            c: AssetCommon { target_path, chmod, is_example, is_built, transforms: Vec::new() },
+        }
+    }
+
+    /// The file the asset has been made from, for messages
+    pub(crate) fn source_description(&self) -> String {
+        match self.processed_from.as_ref().and_then(|p| p.original_path.as_deref()).or(self.source.path()) {
+            Some(path) => path.display().to_string(),
+            None => "generated file".into(),
         }
     }
 
@@ -274,6 +418,11 @@ impl AssetCommon {
     }
 }
 
+/// Destination paths must not escape the root dir of the package (leading `/` is OK)
+pub(crate) fn is_target_path_in_package(target_path: &Path) -> bool {
+    !target_path.components().any(|c| matches!(c, Component::ParentDir | Component::Prefix(_)))
+}
+
 /// Adds `.debug` to the end of a path to a filename
 ///
 fn debug_filename(path: &Path) -> PathBuf {
@@ -282,6 +431,46 @@ fn debug_filename(path: &Path) -> PathBuf {
     debug_filename.into()
 }
 
+/// Replaces `{{name}}` with values of the variables. Unknown variables are an error.
+pub(crate) fn expand_template(template: &str, vars: &[(&str, &str)]) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some((before, after)) = rest.split_once("{{") {
+        out.push_str(before);
+        let (name, after) = after.split_once("}}").ok_or("unclosed {{ in template")?;
+        let name = name.trim();
+        let (_, value) = vars.iter().find(|(var, _)| *var == name).ok_or_else(|| {
+            let known = vars.iter().map(|(var, _)| format!("{{{{{var}}}}}")).collect::<Vec<_>>().join(", ");
+            format!("unknown template variable {{{{{name}}}}}. Supported: {known}")
+        })?;
+        out.push_str(value);
+        rest = after;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Expands assets with `template = true`. It's done before `compress_assets`, because compressed assets are new data.
+pub fn expand_templates(package_deb: &mut PackageConfig) -> CDResult<()> {
+    let template_variables = package_deb.template_variables();
+    let template_variables: Vec<_> = template_variables.iter().map(|(k, v)| (*k, v.as_str())).collect();
+
+    for asset in &mut package_deb.assets.resolved {
+        let Some(pos) = asset.c.transforms.iter().position(|t| *t == AssetTransform::Template) else { continue };
+        let data = asset.source.data()?;
+        let text = std::str::from_utf8(&data)
+            .map_err(|_| CargoDebError::AssetTemplate(asset.c.target_path.clone(), "the file is not UTF-8 text".into()))?;
+        let expanded = expand_template(text, &template_variables)
+            .map_err(|msg| CargoDebError::AssetTemplate(asset.c.target_path.clone(), msg))?;
+        drop(data);
+        let old_source = std::mem::replace(&mut asset.source, AssetSource::Data(expanded.into_bytes()));
+        let original_path = asset.processed_from.take().and_then(|p| p.original_path).or_else(|| old_source.into_path());
+        asset.processed_from = Some(ProcessedFrom { original_path, action: AssetTransform::Template.name() });
+        asset.c.transforms.remove(pos);
+    }
+    Ok(())
+}
+
 pub(crate) fn is_dynamic_library_filename(path: &Path) -> bool {
     path.file_name()
         .and_then(|f| f.to_str())
@@ -302,7 +491,12 @@ pub fn compress_assets(package_deb: &mut PackageConfig, listener: &dyn Listener)
         !path.ends_with(".gz") &&
             (path.starts_with("usr/share/man/") ||
                 (path.starts_with("usr/share/doc/") && (path.ends_with("/NEWS") || path.ends_with("/changelog"))) ||
-                (path.starts_with("usr/share/info/") && path.ends_with(".info")))
+                (path.starts_with("usr/share/info/") && is_info_file(path)))
+    }
+
+    /// `foo.info`, or split parts of it like `foo.info-1`
+    fn is_info_file(path: &str) -> bool {
+        path.ends_with(".info") || path.rsplit_once(".info-").is_some_and(|(_, n)| !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()))
     }
 
     for (idx, orig_asset) in package_deb.assets.resolved.iter().enumerate() {
@@ -369,6 +563,59 @@ mod tests {
         assert!(a.c.is_built == IsBuilt::No);
     }
 
+    #[test]
+    fn glob_excludes() {
+        assert!(gitignore_pattern_matches("*.orig", Path::new("src/foo.orig")));
+        assert!(gitignore_pattern_matches("/tests", Path::new("tests/data/1.txt")));
+        assert!(!gitignore_pattern_matches("/tests", Path::new("src/tests/1.txt")));
+        assert!(gitignore_pattern_matches("data/", Path::new("a/data/1.txt")));
+        assert!(!gitignore_pattern_matches("data/", Path::new("a/data")));
+        assert!(gitignore_pattern_matches("docs/**/*.tmp", Path::new("docs/a/b/c.tmp")));
+        assert!(!gitignore_pattern_matches("!keep", Path::new("keep")));
+
+        let excludes = GlobExcludes {
+            dirs: vec!["/pkg/target/debian".into()],
+            root: "/pkg".into(),
+            exclude: vec!["*.bak".into()],
+            include: vec!["assets/".into()],
+        };
+        assert!(excludes.is_excluded(Path::new("/pkg/assets/.git/config"), Path::new("/pkg/assets"), false));
+        assert!(excludes.is_excluded(Path::new("/pkg/target/debian/x.deb"), Path::new("/pkg/target"), true));
+        assert!(excludes.is_excluded(Path::new("/pkg/assets/a.bak"), Path::new("/pkg/assets"), false));
+        assert!(excludes.is_excluded(Path::new("/pkg/other/a.txt"), Path::new("/pkg"), false));
+        assert!(!excludes.is_excluded(Path::new("/pkg/assets/a.txt"), Path::new("/pkg/assets"), false));
+        assert!(!excludes.is_excluded(Path::new("/pkg/target/release/a.so"), Path::new("/pkg/target/release"), true));
+    }
+
+    #[test]
+    fn template() {
+        let vars = [("version", "1.0-1"), ("arch", "arm64")];
+        assert_eq!("v=1.0-1 a=arm64 ${HOME}", expand_template("v={{version}} a={{ arch }} ${HOME}", &vars).unwrap());
+        assert_eq!("no vars", expand_template("no vars", &vars).unwrap());
+        assert!(expand_template("{{nope}}", &vars).unwrap_err().contains("{{version}}, {{arch}}"));
+        assert!(expand_template("{{version", &vars).is_err());
+    }
+
+    #[test]
+    fn templates_expanded_before_compression() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (_, mut package_deb) = crate::config::test_config(&mock_listener);
+        let mut man = Asset::new(AssetSource::Data(b".TH X 1 \"{{version}}\"".to_vec()), "usr/share/man/man1/x.1".into(), 0o644, IsBuilt::No, false);
+        man.c.transforms.push(AssetTransform::Template);
+        package_deb.assets.resolved = vec![man];
+
+        expand_templates(&mut package_deb).unwrap();
+        compress_assets(&mut package_deb, &mock_listener).unwrap();
+
+        let gz = &package_deb.assets.resolved[0];
+        assert_eq!("usr/share/man/man1/x.1.gz", gz.c.target_path.to_str().unwrap());
+        let mut text = String::new();
+        io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&gz.source.data().unwrap()[..]), &mut text).unwrap();
+        assert_eq!(format!(".TH X 1 \"{}\"", package_deb.deb_version), text);
+    }
+
     /// Tests that getting the debug filename from a path returns the same path
     /// with ".debug" appended
     #[test]
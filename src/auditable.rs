@@ -0,0 +1,34 @@
+//! Binaries built with [`cargo auditable`](https://github.com/rust-secure-code/cargo-auditable) have their dependency list
+//! embedded in a `.dep-v0` section, which scanners like `cargo audit bin` and Trivy can check for vulnerable crates.
+use crate::config::PackageConfig;
+use crate::error::CDResult;
+use crate::hardening::Elf;
+use crate::listener::Listener;
+use std::path::Path;
+
+/// Name of the ELF section with zlib-compressed JSON of the dependency tree
+const AUDIT_DATA_SECTION: &[u8] = b".dep-v0";
+
+/// Finds packaged executables and libraries that carry audit data, to list them in the control file.
+/// When the build was supposed to embed it (`--auditable`), built binaries without the data are warned about.
+pub(crate) fn find_audit_data(package_deb: &mut PackageConfig, expected: bool, listener: &dyn Listener) -> CDResult<()> {
+    let debug_dir = Path::new("usr/lib/debug");
+    let mut found = Vec::new();
+    for asset in &package_deb.assets.resolved {
+        if asset.source.archive_as_symlink_only() || asset.c.target_path.starts_with(debug_dir) {
+            continue;
+        }
+        if !asset.c.is_executable() && !asset.c.is_dynamic_library() {
+            continue;
+        }
+        let data = asset.source.data()?;
+        let Some(elf) = Elf::new(&data) else { continue };
+        if elf.has_section(AUDIT_DATA_SECTION) {
+            found.push(asset.c.target_path.clone());
+        } else if expected && asset.c.is_built() {
+            listener.warning(format!("/{} has no audit data from cargo auditable", asset.c.target_path.display()));
+        }
+    }
+    package_deb.auditable_binaries = found;
+    Ok(())
+}
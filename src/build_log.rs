@@ -0,0 +1,417 @@
+//! Append-only log of every run in `target/debian/build.log` (or the work dir), for debugging builds that can't be rerun interactively.
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::shell_quote;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Number of lines of stderr included in errors of failed commands
+const STDERR_TAIL_LINES: usize = 10;
+
+enum State {
+    /// The target dir isn't known until the manifest is read
+    Buffered(Vec<String>),
+    File(fs::File),
+    Disabled,
+}
+
+/// Passes messages through to the `inner` listener, and writes all of them,
+/// including verbose info and [`Listener::log`] details, to the log file.
+pub(crate) struct BuildLog<'a> {
+    inner: &'a dyn Listener,
+    started: Instant,
+    state: Mutex<State>,
+    /// For the [`crate::PackageReport`]
+    warnings: Mutex<Vec<String>>,
+}
+
+impl<'a> BuildLog<'a> {
+    pub fn new(inner: &'a dyn Listener) -> Self {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let args = std::env::args().map(|a| shell_quote(&a)).collect::<Vec<_>>().join(" ");
+        let header = format!("\n=== cargo-deb {} started {}\n=== {args}\n", env!("CARGO_PKG_VERSION"), crate::bump::rfc2822_date(now));
+        Self {
+            inner,
+            started: Instant::now(),
+            state: Mutex::new(State::Buffered(vec![header])),
+            warnings: Mutex::default(),
+        }
+    }
+
+    /// Warnings reported so far
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.lock().unwrap().clone()
+    }
+
+    /// Starts writing to the file, after the messages logged so far
+    pub fn open(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        let State::Buffered(lines) = &*state else { return };
+        let opened = path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::OpenOptions::new().create(true).append(true).open(path))
+            .and_then(|mut file| {
+                file.write_all(lines.concat().as_bytes())?;
+                Ok(file)
+            });
+        *state = match opened {
+            Ok(file) => State::File(file),
+            Err(err) => {
+                self.inner.warning(format!("Unable to write the build log {}: {err}", path.display()));
+                State::Disabled
+            },
+        };
+    }
+
+    fn write(&self, kind: &str, message: &str) {
+        let elapsed = self.started.elapsed().as_secs_f32();
+        let mut entry = format!("[{elapsed:8.3}s] {kind}: ");
+        for (i, line) in message.lines().enumerate() {
+            if i > 0 {
+                entry.push_str("    ");
+            }
+            entry.push_str(line);
+            entry.push('\n');
+        }
+        match &mut *self.state.lock().unwrap() {
+            State::Buffered(lines) => lines.push(entry),
+            State::File(file) => { let _ = file.write_all(entry.as_bytes()); },
+            State::Disabled => {},
+        }
+    }
+}
+
+impl Listener for BuildLog<'_> {
+    fn warning(&self, s: String) {
+        self.write("warning", &s);
+        self.warnings.lock().unwrap().push(s.clone());
+        self.inner.warning(s);
+    }
+
+    fn info(&self, s: String) {
+        self.write("info", &s);
+        self.inner.info(s);
+    }
+
+    fn log(&self, s: String) {
+        self.write("log", &s);
+        self.inner.log(s);
+    }
+
+    fn generated_archive(&self, path: &Path) {
+        self.write("generated", &path.display().to_string());
+        self.inner.generated_archive(path);
+    }
+
+    fn generated_checksums(&self, summary: &str) {
+        self.write("checksums", summary);
+        self.inner.generated_checksums(summary);
+    }
+
+    fn generated_comparison(&self, changes: &str) {
+        self.write("compare", changes);
+        self.inner.generated_comparison(changes);
+    }
+}
+
+/// `build.log` in the work dir, which is `target/debian` by default
+pub(crate) fn log_path(work_dir: &Path) -> PathBuf {
+    work_dir.join("build.log")
+}
+
+/// The program and its arguments, quoted for `sh`
+pub(crate) fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program()).chain(cmd.get_args())
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>().join(" ")
+}
+
+fn log_start(cmd: &Command, listener: &dyn Listener) -> String {
+    let command_line = command_line(cmd);
+    let dir = cmd.get_current_dir().map(|d| format!(" (in {})", d.display())).unwrap_or_default();
+    listener.log(format!("Running {command_line}{dir}"));
+    let env = cmd.get_envs()
+        .filter_map(|(key, value)| Some(format!("{}={}", key.to_string_lossy(), shell_quote(&value?.to_string_lossy()))))
+        .collect::<Vec<_>>();
+    if !env.is_empty() {
+        listener.log(format!("Environment: {}", env.join(" ")));
+    }
+    command_line
+}
+
+/// Copies the output to `out` as soon as it's read (prompts may not end with a newline),
+/// and its lines, without colors, to the build log
+fn tee(mut pipe: impl Read, mut out: impl Write, program: &str, listener: &dyn Listener) {
+    let ansi_escape = regex::Regex::new("\x1b\\[[0-9;]*[A-Za-z]").unwrap();
+    let log_line = |line: &[u8]| {
+        listener.log(format!("{program}: {}", ansi_escape.replace_all(String::from_utf8_lossy(line).trim_end(), "")));
+    };
+    let mut buf = [0; 8192];
+    let mut pending = Vec::new();
+    loop {
+        let len = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        let _ = out.write_all(&buf[..len]).and_then(|()| out.flush());
+        pending.extend_from_slice(&buf[..len]);
+        while let Some(end) = pending.iter().position(|&c| c == b'\n') {
+            log_line(&pending[..end]);
+            pending.drain(..=end);
+        }
+    }
+    if !pending.is_empty() {
+        log_line(&pending);
+    }
+}
+
+/// Runs the command, and logs it with its output, exit status and how long it took.
+/// The output is passed through as it's printed.
+pub(crate) fn run_logged(cmd: &mut Command, listener: &dyn Listener) -> io::Result<ExitStatus> {
+    log_start(cmd, listener);
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    // Cargo prints colors only to terminals, and its output is piped now
+    if io::stderr().is_terminal() && std::env::var_os("CARGO_TERM_COLOR").is_none() && !cmd.get_envs().any(|(key, _)| key == "CARGO_TERM_COLOR") {
+        cmd.env("CARGO_TERM_COLOR", "always");
+    }
+    let started = Instant::now();
+    let result = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().and_then(|mut child| {
+        let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+        std::thread::scope(|s| {
+            if let Some(stdout) = stdout {
+                s.spawn(|| tee(stdout, io::stdout(), &program, listener));
+            }
+            if let Some(stderr) = stderr {
+                s.spawn(|| tee(stderr, io::stderr(), &program, listener));
+            }
+            child.wait()
+        })
+    });
+    let elapsed = started.elapsed().as_secs_f32();
+    match &result {
+        Ok(status) => listener.log(format!("{program} finished with {status} in {elapsed:.2}s")),
+        Err(err) => listener.log(format!("{program} could not be run: {err}")),
+    }
+    result
+}
+
+/// Runs `cargo build`, and writes its output to the build log. With `quiet`, it's written only to the build log,
+/// and if the build fails, the error has Cargo's and rustc's error messages.
+pub(crate) fn run_cargo(cmd: &mut Command, quiet: bool, listener: &dyn Listener) -> CDResult<()> {
+    if !quiet {
+        let status = run_logged(cmd, listener).map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
+        if !status.success() {
+            return Err(CargoDebError::CommandUnsuccessful(command_line(cmd), status.to_string()));
+        }
+        return Ok(());
+    }
+
+    let command_line = log_start(cmd, listener);
+    let started = Instant::now();
+    let output = cmd.stdin(Stdio::null()).output().map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
+    let elapsed = started.elapsed().as_secs_f32();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    listener.log(format!("cargo finished with {} in {elapsed:.2}s\n{}", output.status, stderr.trim_end()));
+    // stdout is used only by --message-format, which asked for it
+    io::stdout().write_all(&output.stdout)?;
+    if !output.status.success() {
+        return Err(CargoDebError::CommandUnsuccessful(command_line, format!("{}\n{}", output.status, error_messages(&stderr))));
+    }
+    Ok(())
+}
+
+/// Error diagnostics from Cargo's output, without warnings and progress.
+/// If it doesn't have any, the last lines.
+fn error_messages(output: &str) -> String {
+    let message_start = regex::Regex::new(r"^(?:(error|warning)(?:\[[A-Z0-9]+\])?:| {1,12}[A-Z][a-z]+ )").unwrap();
+    let mut errors = String::new();
+    let mut in_error = false;
+    for line in output.lines() {
+        if let Some(start) = message_start.captures(line) {
+            in_error = start.get(1).is_some_and(|kind| kind.as_str() == "error");
+        }
+        if in_error {
+            errors.push_str(line);
+            errors.push('\n');
+        }
+    }
+    if errors.trim().is_empty() {
+        let lines = output.lines().collect::<Vec<_>>();
+        return lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+    }
+    errors.trim_end().to_owned()
+}
+
+/// Like [`run_logged`], but a failure is an error with the full command line, the exit status,
+/// and the last lines of stderr. Stderr is still passed through as it's printed.
+///
+/// The command is killed if it takes longer than `CARGO_DEB_COMMAND_TIMEOUT` seconds.
+/// For tools like `strip` and `objcopy`, not for `cargo build`.
+pub(crate) fn run_checked(cmd: &mut Command, listener: &dyn Listener) -> CDResult<()> {
+    run_checked_(cmd, false, listener).map(drop)
+}
+
+/// Like [`run_checked`], but returns stdout instead of passing it through.
+/// For tools like `dpkg-shlibdeps`, `apt-cache` and `git` that print their results.
+pub(crate) fn run_checked_output(cmd: &mut Command, listener: &dyn Listener) -> CDResult<Vec<u8>> {
+    run_checked_(cmd, true, listener)
+}
+
+fn run_checked_(cmd: &mut Command, capture_stdout: bool, listener: &dyn Listener) -> CDResult<Vec<u8>> {
+    let command_line = log_start(cmd, listener);
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let started = Instant::now();
+    if capture_stdout {
+        cmd.stdout(Stdio::piped());
+    }
+    let mut child = cmd.stderr(Stdio::piped()).spawn().map_err(|err| {
+        listener.log(format!("{program} could not be run: {err}"));
+        CargoDebError::CommandUnsuccessful(command_line.clone(), format!("could not be run: {err}"))
+    })?;
+
+    let stdout_thread = child.stdout.take().map(|mut stdout| std::thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = stdout.read_to_end(&mut out);
+        out
+    }));
+
+    let stderr = child.stderr.take().ok_or(CargoDebError::Str("stderr"))?;
+    let tail_thread = std::thread::spawn(move || {
+        let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+        let mut all = String::new();
+        let mut stderr = io::BufReader::new(stderr);
+        let mut line = Vec::new();
+        while stderr.read_until(b'\n', &mut line).is_ok_and(|len| len > 0) {
+            let text = String::from_utf8_lossy(&line);
+            eprint!("{text}");
+            all.push_str(&text);
+            if tail.len() == STDERR_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(text.trim_end().to_owned());
+            line.clear();
+        }
+        (tail, all)
+    });
+
+    let timeout = std::env::var("CARGO_DEB_COMMAND_TIMEOUT").ok()
+        .and_then(|secs| secs.parse().ok()).map(Duration::from_secs);
+    let status = wait_with_timeout(&mut child, timeout)
+        .map_err(|err| CargoDebError::CommandUnsuccessful(command_line.clone(), format!("could not be run: {err}")))?;
+    let elapsed = started.elapsed().as_secs_f32();
+    // Subprocesses of a killed command may still keep stderr open
+    if status.is_none() {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !tail_thread.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+    let (tail, all) = if tail_thread.is_finished() || status.is_some() { tail_thread.join().unwrap_or_default() } else { Default::default() };
+    if !all.trim().is_empty() {
+        listener.log(format!("{program} stderr:\n{}", all.trim_end()));
+    }
+
+    let mut reason = match status {
+        Some(status) => {
+            listener.log(format!("{program} finished with {status} in {elapsed:.2}s"));
+            if status.success() {
+                return Ok(stdout_thread.and_then(|t| t.join().ok()).unwrap_or_default());
+            }
+            status.to_string()
+        },
+        None => {
+            listener.log(format!("{program} has been killed after {elapsed:.2}s"));
+            format!("timed out after {elapsed:.0}s (CARGO_DEB_COMMAND_TIMEOUT)")
+        },
+    };
+    if !tail.is_empty() {
+        reason.push_str("\n  stderr:");
+        for line in tail.iter().filter(|l| !l.is_empty()) {
+            reason.push_str("\n    ");
+            reason.push_str(line);
+        }
+    }
+    Err(CargoDebError::CommandUnsuccessful(command_line, reason))
+}
+
+/// `None` if the command had to be killed
+fn wait_with_timeout(child: &mut Child, timeout: Option<Duration>) -> io::Result<Option<ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return child.wait().map(Some);
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn buffers_until_opened() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = log_path(dir.path());
+    let log = BuildLog::new(&crate::listener::NoOpListener);
+    log.info("before\nsecond line".into());
+    log.open(&path);
+    log.log("after".into());
+    #[cfg(unix)]
+    run_logged(Command::new("sh").args(["-c", "echo out; printf 'err\\033[0m' >&2"]), &log).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.contains("=== cargo-deb"));
+    assert!(text.contains("] info: before\n    second line\n"));
+    assert!(text.contains("] log: after\n"));
+    #[cfg(unix)]
+    assert!(text.contains("] log: sh: out\n") && text.contains("] log: sh: err\n"), "{text}");
+}
+
+#[test]
+fn cargo_error_messages() {
+    let output = "   Compiling foo v0.1.0 (/tmp/foo)
+warning: unused variable: `x`
+ --> src/main.rs:2:9
+  |
+2 |     let x = 1;
+  |         ^ help: prefix it with an underscore: `_x`
+
+error[E0425]: cannot find value `y` in this scope
+ --> src/main.rs:3:5
+  |
+3 |     y
+  |     ^ not found in this scope
+
+For more information about this error, try `rustc --explain E0425`.
+warning: `foo` (bin \"foo\") generated 1 warning
+error: could not compile `foo` (bin \"foo\") due to 1 previous error; 1 warning emitted
+";
+    let errors = error_messages(output);
+    assert!(errors.starts_with("error[E0425]: cannot find value `y` in this scope\n --> src/main.rs:3:5\n"), "{errors}");
+    assert!(errors.ends_with("try `rustc --explain E0425`.\nerror: could not compile `foo` (bin \"foo\") due to 1 previous error; 1 warning emitted"), "{errors}");
+    assert!(!errors.contains("unused") && !errors.contains("Compiling"));
+    assert_eq!("b\nc", error_messages("    Updating index\nb\nc\n").lines().skip(1).collect::<Vec<_>>().join("\n"));
+}
+
+#[test]
+#[cfg(unix)]
+fn failed_command_context() {
+    let err = run_checked(Command::new("sh").args(["-c", "echo first >&2; echo 'bad things' >&2; exit 3"]), &crate::listener::NoOpListener).unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.starts_with("`sh -c 'echo first >&2; echo '\\''bad things'\\'' >&2; exit 3'` failed: exit status: 3"), "{msg}");
+    assert!(msg.ends_with("stderr:\n    first\n    bad things"), "{msg}");
+
+    let err = run_checked(&mut Command::new("cargo-deb-nonexistent-command"), &crate::listener::NoOpListener).unwrap_err();
+    assert!(err.to_string().contains("could not be run"));
+    run_checked(&mut Command::new("true"), &crate::listener::NoOpListener).unwrap();
+}
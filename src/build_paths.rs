@@ -0,0 +1,73 @@
+//! Finds absolute paths of the build machine (the project dir, `CARGO_HOME`, the home dir) embedded in packaged binaries,
+//! which make builds unreproducible and leak details of the build infrastructure.
+use crate::config::{Config, PackageConfig};
+use crate::error::CDResult;
+use crate::listener::Listener;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// `$CARGO_HOME`, or `~/.cargo`
+pub(crate) fn cargo_home() -> Option<PathBuf> {
+    #[allow(deprecated)]
+    env::var_os("CARGO_HOME").map(PathBuf::from).or_else(|| Some(env::home_dir()?.join(".cargo")))
+}
+
+/// `--remap-path-prefix` flags for rustc that replace the workspace dir with `.` and `CARGO_HOME` with `/cargo`
+pub(crate) fn remap_rustflags(workspace_root: &Path) -> Vec<String> {
+    let mut flags = Vec::new();
+    if let Some(home) = cargo_home() {
+        flags.push(format!("--remap-path-prefix={}=/cargo", home.display()));
+    }
+    // Later flags take precedence, in case the project is in CARGO_HOME
+    flags.push(format!("--remap-path-prefix={}=.", workspace_root.display()));
+    flags
+}
+
+/// Paths that shouldn't appear in binaries. Short paths like `/` or `/tmp` would match too much.
+fn build_paths(config: &Config) -> Vec<String> {
+    #[allow(deprecated)]
+    let home = env::home_dir();
+    [Some(config.workspace_root.clone()), cargo_home(), home].into_iter().flatten()
+        .filter(|p| p.components().count() > 2)
+        .filter_map(|p| Some(p.to_str()?.to_owned()))
+        .collect()
+}
+
+/// The path found in `data`, up to the end of the string it's in
+fn find_path<'a>(data: &'a [u8], paths: &[String]) -> Option<&'a str> {
+    paths.iter().find_map(|path| {
+        let start = data.windows(path.len()).position(|w| w == path.as_bytes())?;
+        let len = data[start..].iter().take(200).take_while(|&&c| c.is_ascii_graphic()).count();
+        std::str::from_utf8(&data[start..start + len]).ok()
+    })
+}
+
+/// Warns about binaries built by Cargo that contain absolute paths of the build machine
+pub(crate) fn warn_about_build_paths(config: &Config, package_deb: &PackageConfig, listener: &dyn Listener) -> CDResult<()> {
+    let paths = build_paths(config);
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let debug_dir = Path::new("usr/lib/debug");
+    for asset in package_deb.assets.resolved.iter().filter(|a| a.c.is_built() && !a.c.target_path.starts_with(debug_dir)) {
+        if !asset.c.is_executable() && !asset.c.is_dynamic_library() || asset.source.archive_as_symlink_only() {
+            continue;
+        }
+        if let Some(found) = find_path(&asset.source.data()?, &paths) {
+            let hint = if config.rustflags.iter().any(|f| f.starts_with("--remap-path-prefix")) { "" } else { ". Use --remap-build-paths to replace them" };
+            listener.warning(format!("/{} contains paths of the build machine, like {found}{hint}", asset.c.target_path.display()));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn finds_build_paths() {
+    let paths = ["/home/ci/work/project".to_owned(), "/home/ci/.cargo".to_owned()];
+    let binary = b"\x7fELF\0\0panicked at /home/ci/.cargo/registry/src/foo-1.0/src/lib.rs:10:5\0\x01";
+    assert_eq!(Some("/home/ci/.cargo/registry/src/foo-1.0/src/lib.rs:10:5"), find_path(binary, &paths));
+    assert_eq!(None, find_path(b"src/main.rs\0/rustc/1234/library/core/src/panic.rs", &paths));
+
+    let flags = remap_rustflags(Path::new("/home/ci/work/project"));
+    assert_eq!(Some("--remap-path-prefix=/home/ci/work/project=."), flags.last().map(String::as_str));
+}
@@ -0,0 +1,54 @@
+//! `<name>_<version>_<arch>.buildinfo` next to the `.deb`, in the format of deb-buildinfo(5).
+//! It's written with `--clean-env`, which makes the environment of the build known, so it can be recorded and reproduced.
+use crate::error::{CDResult, CargoDebError};
+use crate::GeneratedPackage;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// `NAME="value"`, with `"` and `\` escaped
+fn env_line(key: &OsStr, value: &OsStr) -> String {
+    let value = value.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"");
+    format!("{}=\"{value}\"", key.to_string_lossy())
+}
+
+fn buildinfo(package: &GeneratedPackage, file_name: &str, sha256: &str, build_date: u64, env: &[(&OsStr, &OsStr)]) -> String {
+    let GeneratedPackage { name, version, architecture, size, .. } = package;
+    let mut out = format!("Format: 1.0\nSource: {name}\nBinary: {name}\nArchitecture: {architecture}\nVersion: {version}\n");
+    let _ = writeln!(out, "Checksums-Sha256:\n {sha256} {size} {file_name}");
+    let build_arch = crate::arch::debian_architecture_from_rust_triple(crate::DEFAULT_TARGET);
+    let _ = writeln!(out, "Build-Architecture: {build_arch}\nBuild-Date: {}", crate::bump::rfc2822_date(build_date));
+    out.push_str("Environment:\n");
+    for (key, value) in env {
+        let _ = writeln!(out, " {}", env_line(key, value));
+    }
+    out
+}
+
+/// Writes the file for the package with the given `sha256` of the `.deb`, and the environment Cargo has been run with
+pub(crate) fn write(deb_path: &Path, package: &GeneratedPackage, sha256: &str, env: &[(&OsStr, &OsStr)]) -> CDResult<PathBuf> {
+    let file_name = deb_path.file_name().ok_or(CargoDebError::Str("bad path"))?.to_string_lossy();
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = deb_path.with_extension("buildinfo");
+    crate::util::atomic_file::write(&path, buildinfo(package, &file_name, sha256, now, env))
+        .map_err(|e| CargoDebError::IoFile("unable to write the buildinfo file", e, path.clone()))?;
+    Ok(path)
+}
+
+#[test]
+fn buildinfo_fields() {
+    let package = GeneratedPackage {
+        name: "example".into(),
+        version: "1.0-1".into(),
+        architecture: "arm64".into(),
+        size: 1234,
+        ..Default::default()
+    };
+    let env = [(OsStr::new("PATH"), OsStr::new("/usr/bin")), (OsStr::new("CARGO_HOME"), OsStr::new("/home/a \"b\""))];
+    let text = buildinfo(&package, "example_1.0-1_arm64.deb", "abcd", 0, &env);
+    assert!(text.starts_with("Format: 1.0\nSource: example\nBinary: example\nArchitecture: arm64\nVersion: 1.0-1\n"), "{text}");
+    assert!(text.contains("Checksums-Sha256:\n abcd 1234 example_1.0-1_arm64.deb\n"), "{text}");
+    assert!(text.contains("Build-Date: Thu, 01 Jan 1970 00:00:00 +0000\n"), "{text}");
+    assert!(text.ends_with("Environment:\n PATH=\"/usr/bin\"\n CARGO_HOME=\"/home/a \\\"b\\\"\"\n"), "{text}");
+}
@@ -0,0 +1,360 @@
+//! `cargo deb bump` updates the version in `Cargo.toml`, adds a changelog entry for it,
+//! and optionally commits and tags it in git.
+use crate::config::{Config, DebConfigOverrides};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::parse::manifest::{debian_version_string, revision_from_template};
+use crate::CargoLockingFlags;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+/// Which part of the version to increment
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BumpPart {
+    Major,
+    Minor,
+    Patch,
+    /// Debian revision (`revision` in `[package.metadata.deb]`)
+    Revision,
+}
+
+impl BumpPart {
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "major" => Self::Major,
+            "minor" => Self::Minor,
+            "patch" => Self::Patch,
+            "revision" => Self::Revision,
+            _ => return None,
+        })
+    }
+}
+
+/// Updates `Cargo.toml`, `Cargo.lock` and the changelog. Returns the new Debian version.
+///
+/// With `tag`, the changed files are committed, and the commit is tagged `v<version>`.
+/// `distribution` and `urgency` override `changelog-distribution` and `changelog-urgency` for the changelog entry.
+/// With a `variant`, its own `revision` is bumped if it has one, and `{variant}` in the revision is expanded.
+#[allow(clippy::too_many_arguments)]
+pub fn bump(manifest_path: Option<&Path>, selected_package_name: Option<&str>, variant: Option<&str>, part: BumpPart, tag: bool, distribution: Option<&str>, urgency: Option<&str>, listener: &dyn Listener) -> CDResult<String> {
+    let (config, package_deb) = Config::from_manifest(manifest_path, selected_package_name, None, None, variant,
+        DebConfigOverrides::default(), None, None, None, CargoLockingFlags::default(), listener)?;
+    let distribution = distribution.or(package_deb.changelog_distribution.as_deref());
+    let urgency = urgency.or(package_deb.changelog_urgency.as_deref()).unwrap_or("medium");
+    if !URGENCIES.contains(&urgency) {
+        return Err(CargoDebError::InvalidUrgency(urgency.into()));
+    }
+
+    let manifest_path = config.path_in_package("Cargo.toml");
+    let mut manifest = read(&manifest_path)?;
+    let parsed: toml::Value = toml::from_str(&manifest)?;
+    let package = parsed.get("package").ok_or("Cargo.toml has no [package]")?;
+    let deb = package.get("metadata").and_then(|m| m.get("deb"));
+    let variant_revision = variant.and_then(|v| deb?.get("variants")?.get(v)?.get("revision")?.as_str());
+    let revision_section = match variant {
+        Some(v) if variant_revision.is_some() => format!("package.metadata.deb.variants.{v}"),
+        _ => "package.metadata.deb".into(),
+    };
+    // it's the template, e.g. `-1~{variant}1`
+    let revision = variant_revision.or_else(|| deb?.get("revision")?.as_str());
+
+    // The version may be inherited from the workspace
+    let (version_path, version_section, old_version) = match package.get("version") {
+        Some(toml::Value::String(v)) => (manifest_path.clone(), "package", v.clone()),
+        Some(v) if v.get("workspace").and_then(|w| w.as_bool()) == Some(true) => {
+            let root_path = config.workspace_root.join("Cargo.toml");
+            let root: toml::Value = toml::from_str(&read(&root_path)?)?;
+            let v = root.get("workspace").and_then(|w| w.get("package")).and_then(|p| p.get("version")).and_then(|v| v.as_str())
+                .ok_or("the workspace doesn't set the package version")?;
+            (root_path, "workspace.package", v.to_owned())
+        },
+        _ => return Err(CargoDebError::Str("Cargo.toml has no package version")),
+    };
+
+    let (new_version, new_revision) = if part == BumpPart::Revision {
+        let new_revision = increment_revision(revision.unwrap_or("1"))
+            .ok_or_else(|| CargoDebError::InvalidVersion("the revision doesn't end with a number", revision.unwrap_or_default().into()))?;
+        (old_version.clone(), Some(new_revision))
+    } else {
+        let new_version = increment_version(&old_version, part)
+            .ok_or_else(|| CargoDebError::InvalidVersion("it's not a semver version", old_version.clone()))?;
+        // The revision counts packages of the same version
+        (new_version, revision.filter(|r| r.parse::<u32>().is_ok()).map(|_| "1".to_owned()))
+    };
+
+    let mut changed_files = Vec::new();
+    if new_version != old_version {
+        if version_path == manifest_path {
+            manifest = set_toml_string(&manifest, version_section, "version", &new_version, false)
+                .ok_or("unable to find the version in Cargo.toml")?;
+        } else {
+            let root = set_toml_string(&read(&version_path)?, version_section, "version", &new_version, false)
+                .ok_or("unable to find the version in the workspace's Cargo.toml")?;
+            write(&version_path, &root)?;
+            changed_files.push(version_path);
+        }
+    }
+    if let Some(new_revision) = &new_revision {
+        manifest = set_toml_string(&manifest, &revision_section, "revision", new_revision, true)
+            .ok_or("Cargo.toml has no [package.metadata.deb] section for the revision")?;
+    }
+    write(&manifest_path, &manifest)?;
+    changed_files.push(manifest_path);
+
+    let revision = new_revision.as_deref().or(revision).map(|r| revision_from_template(r, variant)).transpose()?;
+    let deb_version = debian_version_string(&new_version, revision.as_deref()).into_owned();
+    listener.info(format!("Version bumped to {deb_version}"));
+
+    let lock_path = config.workspace_root.join("Cargo.lock");
+    if new_version != old_version && lock_path.exists() {
+        let updated = crate::build_log::run_checked_output(Command::new("cargo").args(["update", "--workspace", "--offline", "--quiet"])
+            .current_dir(&config.workspace_root), listener);
+        match updated {
+            Ok(_) => changed_files.push(lock_path),
+            Err(err) => listener.warning(format!("Unable to update the version in Cargo.lock: {err}")),
+        }
+    }
+
+    if let Some(changelog) = package_deb.changelog.as_deref().filter(|c| !c.ends_with(".gz")) {
+        let changelog_path = config.path_in_package(changelog);
+        let old = fs::read_to_string(&changelog_path).unwrap_or_default();
+        let entry = changelog_entry(&package_deb.deb_name, &deb_version, distribution, urgency, &package_deb.maintainer, &old, SystemTime::now())?;
+        write(&changelog_path, &format!("{entry}{old}"))?;
+        changed_files.push(changelog_path);
+    }
+
+    if tag {
+        let tag_name = git_tag_name(if part == BumpPart::Revision { &deb_version } else { &new_version });
+        git(&config.workspace_root, &["commit", "-m", &format!("Release {deb_version}"), "--"], &changed_files, listener)?;
+        git(&config.workspace_root, &["tag", "-a", &tag_name, "-m", &format!("Release {deb_version}")], &[], listener)?;
+        listener.info(format!("Committed and tagged {tag_name}"));
+    }
+    Ok(deb_version)
+}
+
+fn read(path: &Path) -> CDResult<String> {
+    fs::read_to_string(path).map_err(|e| CargoDebError::IoFile("unable to read", e, path.into()))
+}
+
+fn write(path: &Path, contents: &str) -> CDResult<()> {
+    fs::write(path, contents).map_err(|e| CargoDebError::IoFile("unable to write", e, path.into()))
+}
+
+fn git(dir: &Path, args: &[&str], paths: &[PathBuf], listener: &dyn Listener) -> CDResult<()> {
+    // stdout is only for the new version
+    crate::build_log::run_checked_output(Command::new("git").current_dir(dir).args(args).args(paths), listener).map(drop)
+}
+
+/// `v<version>`, with characters that aren't allowed in git refs replaced the way DEP-14 does it,
+/// e.g. `v1.0-1_jammy1` for `1.0-1~jammy1`
+fn git_tag_name(version: &str) -> String {
+    format!("v{}", version.replace(':', "%").replace('~', "_").replace("..", ".#."))
+}
+
+fn increment_version(version: &str, part: BumpPart) -> Option<String> {
+    // pre-release and build metadata are dropped
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+    let (major, minor, patch) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(match part {
+        BumpPart::Major => format!("{}.0.0", major + 1),
+        BumpPart::Minor => format!("{major}.{}.0", minor + 1),
+        BumpPart::Patch if core != version => core.to_owned(),
+        BumpPart::Patch => format!("{major}.{minor}.{}", patch + 1),
+        BumpPart::Revision => version.to_owned(),
+    })
+}
+
+/// Increments the number at the end, so that `1ubuntu1` becomes `1ubuntu2`
+fn increment_revision(revision: &str) -> Option<String> {
+    let prefix = revision.trim_end_matches(|c: char| c.is_ascii_digit());
+    let number = revision[prefix.len()..].parse::<u64>().ok()?;
+    Some(format!("{prefix}{}", number + 1))
+}
+
+/// Replaces the string value of the `key` in the `[section]`, keeping the formatting of the rest of the file.
+/// With `insert`, a missing key is added at the beginning of the section.
+fn set_toml_string(toml: &str, section: &str, key: &str, value: &str, insert: bool) -> Option<String> {
+    let mut out = String::with_capacity(toml.len() + 32);
+    let mut header_line = None;
+    let mut in_section = false;
+    let mut done = false;
+    // lines inside multi-line arrays, inline tables and strings aren't headers or keys
+    let mut nesting = 0;
+    let mut in_multiline_string = None;
+    let lines = toml.split_inclusive('\n').collect::<Vec<_>>();
+    for (i, line) in lines.iter().enumerate() {
+        let at_top_level = nesting == 0 && in_multiline_string.is_none();
+        let trimmed = line.trim();
+        if at_top_level && trimmed.starts_with('[') {
+            in_section = table_header_name(trimmed).as_deref() == Some(section);
+            if in_section && header_line.is_none() {
+                header_line = Some(i);
+            }
+            out.push_str(line);
+            continue;
+        }
+        if at_top_level && in_section && !done {
+            if let Some((k, v)) = line.split_once('=') {
+                if k.trim() == key {
+                    let indent = &line[..line.len() - line.trim_start().len()];
+                    // keep comments after the value
+                    let rest = v.trim_start().strip_prefix('"').and_then(|v| v.split_once('"')).map_or("\n", |(_, rest)| rest);
+                    out.push_str(&format!("{indent}{key} = \"{value}\"{rest}"));
+                    done = true;
+                    continue;
+                }
+            }
+        }
+        scan_toml_line(line, &mut nesting, &mut in_multiline_string);
+        out.push_str(line);
+    }
+    if done {
+        return Some(out);
+    }
+    if !insert {
+        return None;
+    }
+    let header_end = header_line?;
+    let mut lines = lines;
+    let new_line = format!("{key} = \"{value}\"\n");
+    if !lines[header_end].ends_with('\n') {
+        lines[header_end] = lines[header_end].trim_end();
+        return Some(format!("{}\n{new_line}", lines.concat()));
+    }
+    lines.insert(header_end + 1, &new_line);
+    Some(lines.concat())
+}
+
+/// `package.metadata.deb` from `[package . metadata.deb] # comment`. Arrays of tables (`[[bin]]`) are `None`.
+fn table_header_name(line: &str) -> Option<String> {
+    if line.starts_with("[[") {
+        return None;
+    }
+    let (name, _) = line.strip_prefix('[')?.split_once(']')?;
+    Some(name.split('.').map(str::trim).collect::<Vec<_>>().join("."))
+}
+
+/// Tracks brackets and braces of values spanning multiple lines, skipping strings and comments
+fn scan_toml_line(line: &str, nesting: &mut usize, in_multiline_string: &mut Option<&'static str>) {
+    let mut rest = line;
+    loop {
+        if let Some(delimiter) = *in_multiline_string {
+            let Some(end) = rest.find(delimiter) else { return };
+            rest = &rest[end + delimiter.len()..];
+            *in_multiline_string = None;
+        }
+        let Some(pos) = rest.find(['#', '"', '\'', '[', ']', '{', '}']) else { return };
+        let after = &rest[pos + 1..];
+        match rest.as_bytes()[pos] {
+            b'#' => return,
+            quote @ (b'"' | b'\'') => {
+                let delimiter = if quote == b'"' { "\"\"\"" } else { "\'\'\'" };
+                if rest[pos..].starts_with(delimiter) {
+                    *in_multiline_string = Some(delimiter);
+                    rest = &rest[pos + delimiter.len()..];
+                    continue;
+                }
+                let mut escaped = false;
+                let end = after.char_indices().find(|&(_, c)| {
+                    let is_end = !escaped && c == quote as char;
+                    escaped = !escaped && c == '\\' && quote == b'"';
+                    is_end
+                });
+                let Some((end, _)) = end else { return };
+                rest = &after[end + 1..];
+            },
+            b'[' | b'{' => {
+                *nesting += 1;
+                rest = after;
+            },
+            _ => {
+                *nesting = nesting.saturating_sub(1);
+                rest = after;
+            },
+        }
+    }
+}
+
+const URGENCIES: &[&str] = &["low", "medium", "high", "emergency", "critical"];
+
+/// A new entry for the top of a Debian changelog. Without `distribution`, it's copied from the previous entry.
+fn changelog_entry(name: &str, deb_version: &str, distribution: Option<&str>, urgency: &str, maintainer: &str, old_changelog: &str, now: SystemTime) -> CDResult<String> {
+    let distribution = distribution.or_else(|| old_changelog.lines().next()
+        .and_then(|l| l.split_once(')'))
+        .and_then(|(_, rest)| rest.split(';').next())
+        .map(str::trim)
+        .filter(|d| !d.is_empty()))
+        .unwrap_or("unstable");
+    let secs = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    Ok(format!("{name} ({deb_version}) {distribution}; urgency={urgency}\n\n  * Release {deb_version}.\n\n -- {maintainer}  {}\n\n", rfc2822_date(secs)))
+}
+
+/// UTC date in the format used by Debian changelogs, like `Fri, 05 Jan 2018 09:00:00 +0000`
+pub(crate) fn rfc2822_date(unix_secs: u64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let days = unix_secs / 86400;
+    let secs = unix_secs % 86400;
+    // civil date from days since 1970-01-01, http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{}, {day:02} {} {year} {:02}:{:02}:{:02} +0000",
+        DAYS[(days % 7) as usize], MONTHS[(month - 1) as usize], secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!("2.0.0", increment_version("1.2.3", BumpPart::Major).unwrap());
+        assert_eq!("1.3.0", increment_version("1.2.3", BumpPart::Minor).unwrap());
+        assert_eq!("1.2.4", increment_version("1.2.3", BumpPart::Patch).unwrap());
+        assert_eq!("1.2.3", increment_version("1.2.3-beta.1", BumpPart::Patch).unwrap());
+        assert_eq!(None, increment_version("1.2", BumpPart::Patch));
+        assert_eq!("2", increment_revision("1").unwrap());
+        assert_eq!("1ubuntu2", increment_revision("1ubuntu1").unwrap());
+        assert_eq!(None, increment_revision("beta"));
+        assert_eq!("v1.0-1_jammy1", git_tag_name("1.0-1~jammy1"));
+        assert_eq!("v1%2.0_beta.1-1", git_tag_name("1:2.0~beta.1-1"));
+    }
+
+    #[test]
+    fn toml_edits() {
+        let toml = "[package]\nname = \"foo\"\nversion = \"1.0.0\" # comment\n\n[package.metadata.deb]\nsection = \"utils\"\n";
+        assert_eq!("[package]\nname = \"foo\"\nversion = \"1.1.0\" # comment\n\n[package.metadata.deb]\nsection = \"utils\"\n",
+            set_toml_string(toml, "package", "version", "1.1.0", false).unwrap());
+        assert_eq!("[package]\nname = \"foo\"\nversion = \"1.0.0\" # comment\n\n[package.metadata.deb]\nrevision = \"2\"\nsection = \"utils\"\n",
+            set_toml_string(toml, "package.metadata.deb", "revision", "2", true).unwrap());
+        assert_eq!(None, set_toml_string(toml, "workspace.package", "version", "2", false));
+
+        let toml = "[package.metadata.deb] # deb\nassets = [\n    [\"target/release/foo\", \"usr/bin/\", \"755\"],\n    { source = \"a]\", dest = \"usr/share/\" },\n]\nrevision = \"1\"\n";
+        assert_eq!(toml.replace("revision = \"1\"", "revision = \"2\""),
+            set_toml_string(toml, "package.metadata.deb", "revision", "2", true).unwrap());
+        let toml = "[package.metadata.deb]\nextended-description = \"\"\"\n[not a header]\nrevision = \"x\"\n\"\"\"\n";
+        assert_eq!(toml.replacen("]\n", "]\nrevision = \"2\"\n", 1),
+            set_toml_string(toml, "package.metadata.deb", "revision", "2", true).unwrap());
+    }
+
+    #[test]
+    fn changelog() {
+        assert_eq!("Fri, 05 Jan 2018 09:00:00 +0000", rfc2822_date(1_515_142_800));
+        let old = "example (0.1.0) experimental; urgency=low\n\n  * Initial release\n";
+        let entry = changelog_entry("example", "0.2.0-1", None, "medium", "Foo <foo@example.com>", old, SystemTime::UNIX_EPOCH).unwrap();
+        assert_eq!("example (0.2.0-1) experimental; urgency=medium\n\n  * Release 0.2.0-1.\n\n -- Foo <foo@example.com>  Thu, 01 Jan 1970 00:00:00 +0000\n\n", entry);
+        let entry = changelog_entry("example", "0.2.0-1ppa1", Some("noble"), "high", "Foo <foo@example.com>", old, SystemTime::UNIX_EPOCH).unwrap();
+        assert!(entry.starts_with("example (0.2.0-1ppa1) noble; urgency=high\n"));
+    }
+}
@@ -0,0 +1,101 @@
+//! `cargo deb bundle` puts the `.deb` together with all the packages it depends on, downloaded with APT,
+//! and an install script, so that it can be installed on machines without network access.
+use crate::build_log;
+use crate::deb::apt::parse_relation;
+use crate::deb::parity::{field, parse_paragraphs};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const INSTALL_SCRIPT: &str = r#"#!/bin/sh
+# Installs the packages of this bundle, which don't need network access
+set -e
+cd "$(dirname "$0")"
+if [ "$(id -u)" -ne 0 ]; then
+    exec sudo dpkg -i ./*.deb
+fi
+exec dpkg -i ./*.deb
+"#;
+
+/// Makes `<deb name>.bundle/` with the `.deb`, its dependency closure and `install.sh`, and a `.bundle.tar` of it next to the `.deb`.
+/// Packages are downloaded from the mirrors configured for APT on this machine. Returns the path of the tarball.
+pub(crate) fn bundle(deb_path: &Path, listener: &dyn Listener) -> CDResult<PathBuf> {
+    let deb_file_name = deb_path.file_name().ok_or(CargoDebError::Str("invalid package path"))?;
+    let base_name = base_name(deb_path);
+    let bundle_dir = deb_path.with_file_name(format!("{base_name}.bundle"));
+    let _ = fs::remove_dir_all(&bundle_dir);
+    fs::create_dir_all(&bundle_dir).map_err(|e| CargoDebError::IoFile("unable to create bundle dir", e, bundle_dir.clone()))?;
+
+    let control = crate::snapshot::read_control(deb_path)?;
+    let depends = direct_dependencies(&control);
+    if !depends.is_empty() {
+        let packages = dependency_closure(&depends, listener)?;
+        listener.info(format!("Downloading {} packages for the bundle", packages.len()));
+        build_log::run_checked(Command::new("apt-get").arg("download").args(&packages).current_dir(&bundle_dir), listener)?;
+    }
+
+    fs::copy(deb_path, bundle_dir.join(deb_file_name)).map_err(|e| CargoDebError::IoFile("unable to copy the package", e, deb_path.into()))?;
+    let script_path = bundle_dir.join("install.sh");
+    fs::write(&script_path, INSTALL_SCRIPT).map_err(|e| CargoDebError::IoFile("unable to write install script", e, script_path.clone()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let tar_path = bundle_tar_path(deb_path);
+    let tar_file = fs::File::create(&tar_path).map_err(|e| CargoDebError::IoFile("unable to create bundle", e, tar_path.clone()))?;
+    let mut tar = tar::Builder::new(tar_file);
+    tar.append_dir_all(format!("{base_name}.bundle"), &bundle_dir)?;
+    tar.into_inner()?;
+    Ok(tar_path)
+}
+
+/// `<deb name>.bundle.tar` next to the `.deb`
+pub(crate) fn bundle_tar_path(deb_path: &Path) -> PathBuf {
+    deb_path.with_file_name(format!("{}.bundle.tar", base_name(deb_path)))
+}
+
+fn base_name(deb_path: &Path) -> String {
+    let file_name = deb_path.file_name().unwrap_or_default().to_string_lossy();
+    file_name.strip_suffix(".deb").unwrap_or(&file_name).to_owned()
+}
+
+/// Names of packages in `Pre-Depends` and `Depends`. Of alternatives, only the first one is used, like APT does.
+fn direct_dependencies(control: &str) -> Vec<String> {
+    let control = parse_paragraphs(control).into_iter().next().unwrap_or_default();
+    ["Pre-Depends", "Depends"].iter()
+        .flat_map(|&name| field(&control, name).unwrap_or_default().split(','))
+        .filter_map(|dep| dep.split('|').find_map(parse_relation))
+        .map(|rel| rel.name.to_owned())
+        .collect()
+}
+
+/// Asks `apt-cache` for all packages needed to install `packages`, recursively
+fn dependency_closure(packages: &[String], listener: &dyn Listener) -> CDResult<Vec<String>> {
+    let mut cmd = Command::new("apt-cache");
+    cmd.args(["depends", "--recurse", "--no-recommends", "--no-suggests", "--no-conflicts", "--no-breaks", "--no-replaces", "--no-enhances"]).args(packages);
+    let output = build_log::run_checked_output(&mut cmd, listener)?;
+    Ok(closure_from_apt_cache_output(&String::from_utf8_lossy(&output)))
+}
+
+/// Package names are on lines without indentation. Virtual packages are in `<>`, and can't be downloaded.
+fn closure_from_apt_cache_output(output: &str) -> Vec<String> {
+    output.lines()
+        .filter(|line| !line.starts_with([' ', '<']) && !line.trim().is_empty())
+        .map(|line| line.trim().to_owned())
+        .collect::<BTreeSet<_>>()
+        .into_iter().collect()
+}
+
+#[test]
+fn dependency_names() {
+    let control = "Package: foo\nVersion: 1.0\nPre-Depends: dpkg (>= 1.17)\nDepends: libc6 (>= 2.34), awk | mawk, libssl3:any\n";
+    assert_eq!(["dpkg", "libc6", "awk", "libssl3"], &direct_dependencies(control)[..]);
+
+    let output = "libc6\n  Depends: libgcc-s1\n  Breaks: <locales>\nlibgcc-s1\n  Depends: gcc-12-base\n  Depends: libc6\n<awk>\ngcc-12-base\nlibc6\n";
+    assert_eq!(["gcc-12-base", "libc6", "libgcc-s1"], &closure_from_apt_cache_output(output)[..]);
+}
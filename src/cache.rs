@@ -0,0 +1,111 @@
+//! Reuses previously built `.deb` files when nothing that goes into the package has changed.
+//!
+//! The key is a hash of `Cargo.lock`, the target, features, build flags, the resolved
+//! `[package.metadata.deb]` config, and the source files of the workspace.
+//! Timestamps are in it only when they're set with `SOURCE_DATE_EPOCH`.
+use crate::config::{Config, PackageConfig};
+use crate::error::{CDResult, CargoDebError};
+use crate::util::atomic_file;
+use crate::CargoDebOptions;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path where the package built with this configuration is (or will be) cached
+pub(crate) fn cached_deb_path(config: &Config, package_deb: &PackageConfig, options: &CargoDebOptions) -> CDResult<PathBuf> {
+    let mut hasher = Sha256::new();
+    let mut add = |label: &str, data: &[u8]| {
+        hasher.update(label.as_bytes());
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(data);
+    };
+
+    add("cargo-deb", env!("CARGO_PKG_VERSION").as_bytes());
+    match fs::read(config.workspace_root.join("Cargo.lock")) {
+        Ok(lock) => add("lock", &lock),
+        Err(_) => add("lock", b""),
+    }
+    add("target", config.target.as_deref().unwrap_or_default().as_bytes());
+    add("features", config.features.join(",").as_bytes());
+    add("default-features", &[config.default_features.into()]);
+    add("profile", options.profile.as_deref().unwrap_or_default().as_bytes());
+    add("build-flags", options.cargo_build_flags.join("\x1f").as_bytes());
+    add("auditable", &[options.auditable.into()]);
+    add("normalize-permissions", &[options.normalize_permissions.into()]);
+    add("compress", format!("{} {} {} {} {:?}", options.compress_type.extension(), options.fast, options.rsyncable, options.compress_system, options.compress_tuning).as_bytes());
+    add("strip", format!("{:?} {:?}", options.strip_override, config.debug_symbols).as_bytes());
+    add("clean-env", &[config.clean_env.into()]);
+    add("rustflags", config.rustflags.join("\x1f").as_bytes());
+    for var in ["RUSTFLAGS", "CARGO_ENCODED_RUSTFLAGS", "SOURCE_DATE_EPOCH"] {
+        add(var, std::env::var_os(var).unwrap_or_default().to_string_lossy().as_bytes());
+    }
+    // Without SOURCE_DATE_EPOCH (which is added above), the timestamp comes from the mtime of Cargo.toml, which changes on checkout
+    let mut metadata = serde_json::to_value(package_deb)?;
+    if let Some(fields) = metadata.as_object_mut() {
+        fields.remove("default_timestamp");
+    }
+    add("metadata", &serde_json::to_vec(&metadata)?);
+
+    let mut files = Vec::new();
+    source_files(&config.workspace_root, &[&config.target_dir, &config.work_dir], &mut files)?;
+    files.sort_unstable();
+    for path in files {
+        add("path", path.strip_prefix(&config.workspace_root).unwrap_or(&path).to_string_lossy().as_bytes());
+        add("file", &fs::read(&path).map_err(|e| CargoDebError::IoFile("unable to read source file for the cache key", e, path.clone()))?);
+    }
+
+    let mut key = String::with_capacity(32);
+    for b in &hasher.finalize()[..16] {
+        let _ = write!(key, "{b:02x}");
+    }
+    Ok(cache_dir(config).join(format!("{}_{key}.deb", package_deb.deb_name)))
+}
+
+fn cache_dir(config: &Config) -> PathBuf {
+    config.work_dir.join("cache")
+}
+
+/// All files in the workspace, except hidden dirs like `.git`, target dirs and the `output_dirs`
+fn source_files(dir: &Path, output_dirs: &[&Path], out: &mut Vec<PathBuf>) -> CDResult<()> {
+    for entry in fs::read_dir(dir).map_err(|e| CargoDebError::IoFile("unable to list source files", e, dir.into()))? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if entry.file_name().to_string_lossy().starts_with('.') || output_dirs.iter().any(|d| d.starts_with(&path)) || path.join("CACHEDIR.TAG").exists() {
+                continue;
+            }
+            source_files(&path, output_dirs, out)?;
+        } else if file_type.is_file() {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Copies the cached package to the output path
+pub(crate) fn restore(cached_path: &Path, output_path: &Path) -> CDResult<()> {
+    if let Some(dir) = output_path.parent() {
+        fs::create_dir_all(dir).map_err(|e| CargoDebError::IoFile("unable to create output dir", e, dir.into()))?;
+    }
+    atomic_file::copy(cached_path, output_path).map_err(|e| CargoDebError::IoFile("unable to copy cached package", e, output_path.into()))?;
+    Ok(())
+}
+
+/// Saves a copy of the built package, and removes outdated copies of it
+pub(crate) fn store(cached_path: &Path, built_path: &Path, deb_name: &str) -> CDResult<()> {
+    let dir = cached_path.parent().ok_or("bad cache path")?;
+    fs::create_dir_all(dir).map_err(|e| CargoDebError::IoFile("unable to create cache dir", e, dir.into()))?;
+    let prefix = format!("{deb_name}_");
+    for entry in fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // the rest of the name is a hex key, so other packages can't have the same prefix
+        if name.strip_prefix(&prefix).is_some_and(|rest| !rest.contains('_')) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    atomic_file::copy(built_path, cached_path).map_err(|e| CargoDebError::IoFile("unable to save package in the cache", e, cached_path.into()))?;
+    Ok(())
+}
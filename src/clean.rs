@@ -0,0 +1,103 @@
+//! `cargo deb clean` removes what cargo-deb has written to the target dir, without touching Cargo's build artifacts.
+use crate::error::{CDResult, CargoDebError};
+use crate::parse::manifest::{target_dir_and_members, WorkspaceMember};
+use crate::CargoLockingFlags;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files that cargo-deb writes next to the `.deb`
+const OUTPUT_SUFFIXES: [&str; 7] = [".deb", ".deb.sha256", ".build-ids", ".buildinfo", ".bundle", ".bundle.tar", ".partial"];
+
+/// Removes `target/debian` and `target/<triple>/debian` (packages, staging dirs, the cache and the build log).
+///
+/// A custom `work_dir` may contain other files, so only the files cargo-deb makes for the workspace's packages are removed from it.
+/// Returns the removed paths.
+pub fn clean(manifest_path: Option<&Path>, work_dir: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> CDResult<Vec<PathBuf>> {
+    let (target_dir, members) = target_dir_and_members(manifest_path, cargo_locking_flags)?;
+    let mut removed = Vec::new();
+    for dir in output_dirs(&target_dir)? {
+        remove(&dir, &mut removed)?;
+    }
+    if let Some(work_dir) = work_dir.filter(|dir| dir.exists()) {
+        for entry in fs::read_dir(work_dir).map_err(|e| CargoDebError::IoFile("unable to list the work dir", e, work_dir.into()))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if is_work_dir_file(&name, &members) {
+                remove(&entry.path(), &mut removed)?;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// The default work dir, and the work dirs of builds with `--target`, which use `target/<triple>/`
+fn output_dirs(target_dir: &Path) -> CDResult<Vec<PathBuf>> {
+    let mut dirs = vec![target_dir.join("debian")];
+    if let Ok(entries) = fs::read_dir(target_dir) {
+        for entry in entries {
+            let entry = entry?;
+            // not profile dirs like `target/release-lto`
+            if !entry.file_name().to_str().is_some_and(|name| name.contains("-linux")) {
+                continue;
+            }
+            let path = entry.path().join("debian");
+            if path.is_dir() {
+                dirs.push(path);
+            }
+        }
+    }
+    dirs.retain(|dir| dir.exists());
+    Ok(dirs)
+}
+
+/// `name_version_arch` followed by a suffix of the files written next to packages, members written by `--emit`,
+/// or leftovers of interrupted writes (`.name_version_arch.deb.XXXXXX.partial`)
+fn is_package_output(name: &str, deb_names: &[&str]) -> bool {
+    let name = name.strip_prefix('.').unwrap_or(name);
+    let mut parts = name.splitn(3, '_');
+    let (Some(package), Some(version), Some(arch_and_suffix)) = (parts.next(), parts.next(), parts.next()) else { return false };
+    let Some(suffix_start) = arch_and_suffix.find('.') else { return false };
+    let (arch, suffix) = arch_and_suffix.split_at(suffix_start);
+    deb_names.contains(&package) && !version.is_empty() &&
+        !arch.is_empty() && arch.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-') &&
+        (OUTPUT_SUFFIXES.iter().any(|s| suffix.ends_with(s)) || suffix == ".debian-binary" || suffix.starts_with(".control.tar") || suffix.starts_with(".data.tar"))
+}
+
+/// Outputs of the workspace's packages, the cache, the build log, build records, and staging dirs of the packages
+fn is_work_dir_file(name: &str, members: &[WorkspaceMember]) -> bool {
+    let deb_names = members.iter().flat_map(|m| &m.deb_names).map(String::as_str).collect::<Vec<_>>();
+    name == "cache" || name == "build.log" || name == "build-records" || members.iter().any(|m| m.name == name) ||
+        is_package_output(name, &deb_names)
+}
+
+fn remove(path: &Path, removed: &mut Vec<PathBuf>) -> CDResult<()> {
+    let result = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+    result.map_err(|e| CargoDebError::IoFile("unable to remove", e, path.into()))?;
+    removed.push(path.to_owned());
+    Ok(())
+}
+
+#[test]
+fn finds_output_dirs() {
+    let tmp = tempfile::tempdir().unwrap();
+    for dir in ["debian/cache", "release/deps", "aarch64-unknown-linux-gnu/debian", "aarch64-unknown-linux-gnu/release", "release-lto/debian"] {
+        fs::create_dir_all(tmp.path().join(dir)).unwrap();
+    }
+    let mut dirs = output_dirs(tmp.path()).unwrap();
+    dirs.sort();
+    assert_eq!([tmp.path().join("aarch64-unknown-linux-gnu/debian"), tmp.path().join("debian")], &dirs[..]);
+
+    let members = [WorkspaceMember { name: "example".into(), deb_names: vec!["example".into(), "example-debug".into()] }];
+    assert!(is_work_dir_file("example_1.0-1_amd64.deb", &members));
+    assert!(is_work_dir_file("example-debug_1.0-1_amd64.deb.sha256", &members));
+    assert!(is_work_dir_file("example", &members));
+    assert!(is_work_dir_file("build.log", &members));
+    assert!(is_work_dir_file("example_1.0-1_amd64.data.tar.xz", &members));
+    assert!(is_work_dir_file(".example_1.0-1_amd64.deb.Ab12Cd.partial", &members));
+    assert!(!is_work_dir_file("notes.txt", &members));
+    assert!(!is_work_dir_file("other_1.0-1_amd64.deb", &members));
+    assert!(!is_work_dir_file("other.bundle", &members));
+    assert!(!is_work_dir_file("example.deb", &members));
+    assert!(!is_work_dir_file("backup.data.tar.gz", &members));
+}
@@ -0,0 +1,168 @@
+//! `--compare old.deb` describes what has changed since a previous release of the package:
+//! control fields, dependencies, maintainer scripts, and added, removed and changed files with their sizes.
+use crate::deb::parity::{field, parse_paragraphs, Paragraph, RELATION_FIELDS};
+use crate::error::CDResult;
+use crate::snapshot::{read_control, read_tar_files, TarFile};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Files that are different in every build, or are summarized by other fields
+const IGNORED_CONTROL_FILES: &[&str] = &["control", "md5sums", "sha256sums"];
+
+/// Parts of a `.deb` that are compared
+struct Package {
+    control: Paragraph,
+    control_files: Vec<TarFile>,
+    files: Vec<TarFile>,
+}
+
+impl Package {
+    fn read(deb_path: &Path) -> CDResult<Self> {
+        Ok(Self {
+            control: parse_paragraphs(&read_control(deb_path)?).into_iter().next().unwrap_or_default(),
+            control_files: read_tar_files(deb_path, "control.tar.")?,
+            files: read_tar_files(deb_path, "data.tar.")?,
+        })
+    }
+}
+
+/// Human-readable description of changes between the packages
+pub(crate) fn compare(old_path: &Path, new_path: &Path) -> CDResult<String> {
+    let mut out = format!("Changes since {}:\n", old_path.display());
+    out.push_str(&describe_changes(&Package::read(old_path)?, &Package::read(new_path)?));
+    Ok(out)
+}
+
+fn describe_changes(old: &Package, new: &Package) -> String {
+    let mut out = String::new();
+    let mut names = new.control.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+    names.extend(old.control.iter().map(|(n, _)| n.as_str()).filter(|n| field(&new.control, n).is_none()));
+
+    let mut fields = String::new();
+    let mut relations = String::new();
+    for name in names {
+        let (old_value, new_value) = (field(&old.control, name), field(&new.control, name));
+        if old_value == new_value {
+            continue;
+        }
+        if RELATION_FIELDS.contains(&name) {
+            let entries = |value: Option<&str>| value.iter().flat_map(|v| v.split(',')).map(|e| e.split_whitespace().collect::<Vec<_>>().join(" ")).filter(|e| !e.is_empty()).collect::<Vec<_>>();
+            let (old_entries, new_entries) = (entries(old_value), entries(new_value));
+            for removed in old_entries.iter().filter(|e| !new_entries.contains(e)) {
+                let _ = writeln!(relations, "  {name}: - {removed}");
+            }
+            for added in new_entries.iter().filter(|e| !old_entries.contains(e)) {
+                let _ = writeln!(relations, "  {name}: + {added}");
+            }
+            continue;
+        }
+        let _ = match (old_value, new_value) {
+            (Some(_), Some(_)) if name == "Description" => writeln!(fields, "  {name}: changed"),
+            (Some(old_size), Some(new_size)) if name == "Installed-Size" => {
+                let delta = new_size.parse::<i64>().unwrap_or(0) - old_size.parse::<i64>().unwrap_or(0);
+                writeln!(fields, "  {name}: {old_size} -> {new_size} KiB ({delta:+})")
+            },
+            (Some(o), Some(n)) => writeln!(fields, "  {name}: {o} -> {n}"),
+            (None, Some(n)) => writeln!(fields, "  {name}: + {n}"),
+            (Some(o), None) => writeln!(fields, "  {name}: - {o}"),
+            (None, None) => Ok(()),
+        };
+    }
+    if !fields.is_empty() {
+        let _ = write!(out, "Control fields:\n{fields}");
+    }
+    if !relations.is_empty() {
+        let _ = write!(out, "Dependencies:\n{relations}");
+    }
+
+    let control_files = |p: &Package| p.control_files.iter().filter(|f| !IGNORED_CONTROL_FILES.contains(&f.path.as_str())).cloned().collect::<Vec<_>>();
+    let (scripts, _) = file_changes(&control_files(old), &control_files(new), false);
+    if !scripts.is_empty() {
+        let _ = write!(out, "Control files:\n{scripts}");
+    }
+    let (files, summary) = file_changes(&old.files, &new.files, true);
+    if !files.is_empty() {
+        let _ = write!(out, "Files:\n{files}");
+    }
+    out.push_str(&summary);
+    out
+}
+
+/// Lines of added (`+`), removed (`-`) and changed (`~`) files, and a summary line
+fn file_changes(old: &[TarFile], new: &[TarFile], absolute: bool) -> (String, String) {
+    let old = old.iter().map(|f| (f.path.as_str(), f)).collect::<BTreeMap<_, _>>();
+    let new = new.iter().map(|f| (f.path.as_str(), f)).collect::<BTreeMap<_, _>>();
+    let prefix = if absolute { "/" } else { "" };
+    let mut out = String::new();
+    let (mut added, mut removed, mut changed, mut unchanged) = (0, 0, 0, 0);
+    let mut all_paths = old.keys().chain(new.keys()).copied().collect::<Vec<_>>();
+    all_paths.sort_unstable();
+    all_paths.dedup();
+    for path in all_paths {
+        let _ = match (old.get(path), new.get(path)) {
+            (None, Some(f)) => { added += 1; writeln!(out, "  + {prefix}{path} ({})", describe_file(f)) },
+            (Some(f), None) => { removed += 1; writeln!(out, "  - {prefix}{path} ({})", describe_file(f)) },
+            (Some(o), Some(n)) if o != n => {
+                changed += 1;
+                let mode = if o.mode == n.mode { String::new() } else { format!(", mode {:o} -> {:o}", o.mode, n.mode) };
+                let delta = n.size as i64 - o.size as i64;
+                let sign = if delta < 0 { "-" } else { "+" };
+                writeln!(out, "  ~ {prefix}{path} ({} -> {}, {sign}{}{mode})", describe_file(o), describe_file(n), human_size(delta.unsigned_abs()))
+            },
+            _ => { unchanged += 1; Ok(()) },
+        };
+    }
+    let total_delta = new.values().map(|f| f.size as i64).sum::<i64>() - old.values().map(|f| f.size as i64).sum::<i64>();
+    let sign = if total_delta < 0 { "-" } else { "+" };
+    let summary = format!("{added} added, {removed} removed, {changed} changed, {unchanged} unchanged files; size {sign}{}\n", human_size(total_delta.unsigned_abs()));
+    (out, summary)
+}
+
+fn describe_file(file: &TarFile) -> String {
+    match &file.link {
+        Some(link) => format!("-> {link}"),
+        None => human_size(file.size),
+    }
+}
+
+fn human_size(len: u64) -> String {
+    match len {
+        0..=9_999 => format!("{len} B"),
+        10_000..=9_999_999 => format!("{:.1} KB", len as f64 / 1000.),
+        _ => format!("{:.1} MB", len as f64 / 1_000_000.),
+    }
+}
+
+#[test]
+fn describes_changes() {
+    let file = |path: &str, size: u64, hash: u8| TarFile { path: path.into(), size, mode: 0o644, link: None, sha256: [hash; 32] };
+    let control = |text: &str| parse_paragraphs(text).remove(0);
+    let old = Package {
+        control: control("Package: foo\nVersion: 1.0.0-1\nInstalled-Size: 100\nDepends: libc6 (>= 2.34), libssl1.1\nDescription: foo\n"),
+        control_files: vec![file("control", 100, 0), file("postinst", 100, 1), file("prerm", 10, 1)],
+        files: vec![file("usr/bin/foo", 50_000, 1), file("usr/share/doc/foo/copyright", 500, 1), file("usr/share/foo/old", 20, 1)],
+    };
+    let new = Package {
+        control: control("Package: foo\nVersion: 1.1.0-1\nInstalled-Size: 120\nDepends: libc6 (>= 2.34), libssl3\nRecommends: bar\nDescription: foo\n"),
+        control_files: vec![file("control", 110, 1), file("postinst", 120, 2)],
+        files: vec![file("usr/bin/foo", 62_000, 2), file("usr/share/doc/foo/copyright", 500, 1), file("usr/share/foo/new", 2000, 1)],
+    };
+    assert_eq!(describe_changes(&old, &new), "\
+Control fields:
+  Version: 1.0.0-1 -> 1.1.0-1
+  Installed-Size: 100 -> 120 KiB (+20)
+Dependencies:
+  Depends: - libssl1.1
+  Depends: + libssl3
+  Recommends: + bar
+Control files:
+  ~ postinst (100 B -> 120 B, +20 B)
+  - prerm (10 B)
+Files:
+  ~ /usr/bin/foo (50.0 KB -> 62.0 KB, +12.0 KB)
+  + /usr/share/foo/new (2000 B)
+  - /usr/share/foo/old (20 B)
+1 added, 1 removed, 1 changed, 1 unchanged files; size +14.0 KB
+");
+}
@@ -1,18 +1,23 @@
-use crate::assets::is_dynamic_library_filename;
-use crate::assets::{Asset, AssetSource, Assets, IsBuilt, UnresolvedAsset, RawAsset};
+use crate::assets::{is_dynamic_library_filename, is_target_path_in_package};
+use crate::assets::{Asset, AssetSource, AssetTransform, Assets, GlobExcludes, IsBuilt, UnresolvedAsset, RawAsset};
 use crate::util::compress::gzipped;
-use crate::{debian_architecture_from_rust_triple, CargoLockingFlags};
+use crate::util::fname_from_path;
+use crate::CargoLockingFlags;
 use crate::dependencies::resolve;
 use crate::dh::dh_installsystemd;
+use crate::dh::dh_lib::pkgfile;
 use crate::error::{CDResult, CargoDebError};
+use crate::{config_defaults, init_scripts};
+use crate::locales;
 use crate::listener::Listener;
 use crate::parse::cargo::CargoConfig;
-use crate::parse::manifest::{cargo_metadata, manifest_debug_flag, manifest_version_string, LicenseFile};
+use crate::parse::manifest::{cargo_metadata, manifest_debug_flag, manifest_split_debuginfo, manifest_strip_setting, manifest_version_string, revision_from_template, LicenseFile};
 use crate::parse::manifest::{CargoDeb, CargoDebAssetArrayOrTable, CargoMetadataTarget, CargoPackageMetadata, ManifestFound};
-use crate::parse::manifest::{DependencyList, SystemUnitsSingleOrMultiple, SystemdUnitsConfig};
+use crate::parse::manifest::{ConfigDefaultConfig, DependencyList, DivertConfig, InitSystem, LocalesConfig, PreinstChecksConfig, SymbolsConfig, SystemUnitsSingleOrMultiple, SystemdUnitsConfig};
 use crate::util::ok_or::OkOrThen;
 use crate::util::pathbytes::AsUnixPathBytes;
 use crate::util::wordsplit::WordSplit;
+use cargo_toml::StripSetting;
 use rayon::prelude::*;
 use std::borrow::Cow;
 use std::collections::HashMap;
@@ -39,6 +44,8 @@ impl From<&SystemdUnitsConfig> for dh_installsystemd::Options {
             no_start: !config.start.unwrap_or(true),
             restart_after_upgrade: config.restart_after_upgrade.unwrap_or(true),
             no_stop_on_upgrade: !config.stop_on_upgrade.unwrap_or(true),
+            no_start_on_install: !config.start_on_install.unwrap_or(true),
+            instances: config.instances.clone().unwrap_or_default(),
         }
     }
 }
@@ -86,7 +93,7 @@ fn match_architecture(spec: ArchSpec, target_arch: &str) -> CDResult<bool> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize)]
 #[non_exhaustive]
 /// Cargo deb configuration read from the manifest and cargo metadata
 pub struct Config {
@@ -96,11 +103,25 @@ pub struct Config {
     pub deb_output_path: Option<String>,
     /// Triple. `None` means current machine architecture.
     pub target: Option<String>,
+    /// Debian's multiarch tuple of the `target`, like `aarch64-linux-gnu`, for paths of cross-compiled libraries and tools
+    pub debian_triple: Option<String>,
     /// `CARGO_TARGET_DIR`
     pub target_dir: PathBuf,
+    /// Temporary files, the build log, the cache and the `.deb` go here. `target/debian` by default.
+    pub work_dir: PathBuf,
+    /// Staging dirs and other intermediate files go here instead of the `work_dir` (`--temp-dir`)
+    pub temp_dir: Option<PathBuf>,
+    /// Directory with `Cargo.lock`. Same as `package_manifest_dir` outside of workspaces.
+    pub workspace_root: PathBuf,
     /// List of Cargo features to use during build
     pub features: Vec<String>,
     pub default_features: bool,
+    /// Cargo is run without host-specific environment variables (`--clean-env`)
+    pub clean_env: bool,
+    /// Cargo's output goes only to the build log, unless the build fails (`--quiet-cargo`)
+    pub quiet_cargo: bool,
+    /// Added to the flags Cargo would use otherwise
+    pub rustflags: Vec<String>,
     /// Should the binary be stripped from debug symbols?
     pub debug_symbols: DebugSymbols,
 
@@ -109,10 +130,12 @@ pub struct Config {
 
     /// Products available in the package
     build_targets: Vec<CargoMetadataTarget>,
+    /// Executables of other workspace members, as `(package, bin)`
+    workspace_bins: Vec<(String, String)>,
     cargo_locking_flags: CargoLockingFlags,
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize)]
 pub enum ExtendedDescription {
     None,
     File(PathBuf),
@@ -120,7 +143,10 @@ pub enum ExtendedDescription {
     ReadmeFallback(PathBuf),
 }
 
-#[derive(Debug)]
+/// Control files that are taken from the `maintainer-scripts` dir
+pub(crate) const MAINTAINER_SCRIPTS: &[&str] = &["config", "preinst", "postinst", "prerm", "postrm", "templates"];
+
+#[derive(Debug, serde::Serialize)]
 #[non_exhaustive]
 pub struct PackageConfig {
     /// The name of the project to build
@@ -135,10 +161,16 @@ pub struct PackageConfig {
     pub license_file_rel_path: Option<PathBuf>,
     /// number of lines to skip when reading `license_file`
     pub license_file_skip_lines: usize,
+    /// Lines of `license_file` up to and including the first line that contains this are skipped too
+    pub license_file_skip_to: Option<String>,
     /// The copyright of the project
     /// (Debian's `copyright` file contents).
     pub copyright: Option<String>,
     pub changelog: Option<String>,
+    /// Distribution for new changelog entries. Copied from the previous entry if not set
+    pub changelog_distribution: Option<String>,
+    /// Urgency for new changelog entries
+    pub changelog_urgency: Option<String>,
     /// The homepage URL of the project.
     pub homepage: Option<String>,
     /// Documentation URL from `Cargo.toml`. Fallback if `homepage` is missing.
@@ -185,6 +217,12 @@ pub struct PackageConfig {
     ///
     /// See [PackageTransition](https://wiki.debian.org/PackageTransition).
     pub provides: Option<String>,
+    /// `Protected: yes` makes dpkg refuse to remove the package without `--force-remove-protected`
+    pub protected: bool,
+    /// `Important: yes` makes apt ask for confirmation before removing the package
+    pub important: bool,
+    /// Debtags (`facet::value`) for the `Tag` field
+    pub tags: Vec<String>,
 
     /// The Debian architecture of the target system.
     pub architecture: String,
@@ -194,22 +232,75 @@ pub struct PackageConfig {
     /// All of the files that are to be packaged.
     pub(crate) assets: Assets,
     pub(crate) raw_assets: Option<Vec<RawAsset>>,
+    /// `merge-assets.append` when there are no `assets`, added to the implied assets
+    pub(crate) raw_assets_append: Vec<RawAsset>,
 
     /// Added to usr/share/doc as a fallback
     pub readme_rel_path: Option<PathBuf>,
+    /// Whether to add the readme to usr/share/doc. By default only when assets are implied
+    pub include_readme: Option<bool>,
     /// The location of the triggers file
     pub triggers_file_rel_path: Option<PathBuf>,
     /// The path where possible maintainer scripts live
     pub maintainer_scripts_rel_path: Option<PathBuf>,
+    /// Scripts from `maintainer_scripts_rel_path` that are used. `None` means all.
+    pub maintainer_scripts_only: Option<Vec<String>>,
+    /// Shell code from `*-script` keys, by maintainer script name
+    pub inline_scripts: Vec<(&'static str, String)>,
     /// Should symlinks be preserved in the assets
     pub preserve_symlinks: bool,
+    /// Files that asset globs skip
+    pub glob_excludes: GlobExcludes,
     /// Details of how to install any systemd units
     pub(crate) systemd_units: Option<Vec<SystemdUnitsConfig>>,
+    /// Init systems that services are installed for
+    pub(crate) init_systems: Vec<InitSystem>,
+    /// Executables from `usr/bin` are installed in `<install_prefix>/bin` instead (relative to `/`)
+    pub install_prefix: Option<PathBuf>,
+    /// Checks of the target system to add to the `preinst` script
+    pub(crate) preinst_checks: Option<PreinstChecksConfig>,
+    /// Values of `-C target-cpu` for additional builds of the executables
+    pub cpu_variants: Vec<String>,
+    /// Paths of executables that are symlinks managed by `update-alternatives` due to `cpu_variants`
+    pub(crate) cpu_variant_links: Vec<PathBuf>,
+    /// Shell commands that check the built package, and fail the build if they fail
+    pub inspectors: Vec<String>,
+    /// Files of other packages moved aside with `dpkg-divert`
+    pub(crate) diverts: Vec<DivertConfig>,
+    /// Config files installed in `usr/share/<package>/defaults`, and copied to their place by `postinst`
+    pub(crate) config_defaults: Vec<ConfigDefaultConfig>,
+    /// Files and dirs created at run time, deleted by `postrm` on purge
+    pub purge_paths: Vec<String>,
+    /// Executables are statically linked, so they don't need library dependencies
+    pub static_pie: bool,
+    /// Files that keep setuid, setgid and sticky bits with `--normalize-permissions`
+    pub keep_special_bits: Vec<String>,
+    /// Dir with gettext translations to install in `usr/share/locale`
+    pub(crate) locales: Option<LocalesConfig>,
+    /// Generate the `symbols` control file, optionally compared with a committed one
+    pub(crate) symbols: Option<SymbolsConfig>,
+    /// Contents of the `symbols` control file, once the libraries are built
+    pub symbols_file: Option<String>,
     /// unix timestamp for generated files
     pub default_timestamp: u64,
+    /// GNU build-ids of binaries that had their debug symbols separated
+    pub build_ids: Vec<BuildId>,
+    /// Binaries with dependency lists embedded by `cargo auditable` (relative to `/`)
+    pub auditable_binaries: Vec<PathBuf>,
+}
+
+/// Links a binary with its separated debug symbols file
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BuildId {
+    /// Lowercase hex of the `.note.gnu.build-id` section
+    pub build_id: String,
+    /// Installed path of the stripped binary
+    pub path: PathBuf,
+    /// Installed path of the debug symbols, `/usr/lib/debug/.build-id/xx/yyyy.debug`
+    pub debug_path: PathBuf,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, serde::Serialize)]
 pub enum DebugSymbols {
     Keep,
     Strip,
@@ -226,6 +317,28 @@ pub struct DebConfigOverrides {
     pub deb_version: Option<String>,
     pub deb_revision: Option<String>,
     pub maintainer: Option<String>,
+    /// Error instead of using a placeholder when there's no description
+    pub require_description: bool,
+    /// Use instead of `target/debian`, e.g. when the target dir is read-only
+    pub work_dir: Option<PathBuf>,
+    /// Use for intermediate files instead of the work dir, e.g. when it's on a slow or small disk
+    pub temp_dir: Option<PathBuf>,
+    /// TOML file mapping target triples to Debian architectures. Takes precedence over `arch-map`
+    pub arch_map: Option<PathBuf>,
+    /// Run Cargo with only whitelisted environment variables
+    pub clean_env: bool,
+    /// Write Cargo's output only to the build log, unless the build fails
+    pub quiet_cargo: bool,
+    /// Extra rustc flags, added after `rustflags` from the metadata
+    pub rustflags: Option<String>,
+    /// Add `--remap-path-prefix` to rustflags to hide the paths of the build machine
+    pub remap_build_paths: bool,
+    /// Build statically linked executables (`--static-pie`)
+    pub static_pie: bool,
+    /// Use instead of `maintainer-scripts` from the metadata
+    pub maintainer_scripts: Option<PathBuf>,
+    /// Use instead of `maintainer-scripts-only` from the metadata
+    pub maintainer_scripts_only: Option<Vec<String>>,
 }
 
 impl Config {
@@ -244,17 +357,37 @@ impl Config {
         compress_debug_symbols: Option<bool>,
         cargo_locking_flags: CargoLockingFlags,
         listener: &dyn Listener,
+    ) -> CDResult<(Self, PackageConfig)> {
+        let found = cargo_metadata(root_manifest_path, selected_package_name, cargo_locking_flags)?;
+        Self::from_found_manifest(found, deb_output_path, target, variant, overrides, build_profile_override, separate_debug_symbols, compress_debug_symbols, cargo_locking_flags, listener)
+    }
+
+    /// Same as `from_manifest`, but for a manifest that has already been located and parsed
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_found_manifest(
+        found: ManifestFound,
+        deb_output_path: Option<String>,
+        target: Option<&str>,
+        variant: Option<&str>,
+        mut overrides: DebConfigOverrides,
+        build_profile_override: Option<String>,
+        separate_debug_symbols: Option<bool>,
+        compress_debug_symbols: Option<bool>,
+        cargo_locking_flags: CargoLockingFlags,
+        listener: &dyn Listener,
     ) -> CDResult<(Self, PackageConfig)> {
         // **IMPORTANT**: This function must not create or expect to see any asset files on disk!
         // It's run before destination directory is cleaned up, and before the build start!
 
         let ManifestFound {
             build_targets,
+            workspace_bins,
             root_manifest,
             mut manifest_path,
             mut target_dir,
+            workspace_root,
             mut manifest,
-        } = cargo_metadata(root_manifest_path, selected_package_name, cargo_locking_flags)?;
+        } = found;
 
         let default_timestamp = if let Ok(source_date_epoch) = std::env::var("SOURCE_DATE_EPOCH") {
             source_date_epoch.parse().map_err(|e| CargoDebError::NumParse("SOURCE_DATE_EPOCH", e))?
@@ -276,8 +409,12 @@ impl Config {
         let selected_profile = build_profile_override.as_deref().unwrap_or("release");
 
         let debug_enabled = manifest_debug_flag(&manifest, selected_profile)
-            .or_else(move || manifest_debug_flag(root_manifest.as_ref()?, selected_profile))
+            .or_else(|| manifest_debug_flag(root_manifest.as_ref()?, selected_profile))
             .unwrap_or(false);
+        let cargo_strip = manifest_strip_setting(&manifest, selected_profile)
+            .or_else(|| manifest_strip_setting(root_manifest.as_ref()?, selected_profile));
+        let split_debuginfo = manifest_split_debuginfo(&manifest, selected_profile)
+            .or_else(|| manifest_split_debuginfo(root_manifest.as_ref()?, selected_profile));
 
         let cargo_package = manifest.package.as_mut().ok_or("bad package")?;
 
@@ -295,51 +432,105 @@ impl Config {
         } else {
             cargo_package.metadata.take().and_then(|m| m.deb).unwrap_or_default()
         };
+        for revision in [&mut overrides.deb_revision, &mut deb.revision].into_iter().flatten() {
+            *revision = revision_from_template(revision, variant)?;
+        }
 
         let separate_debug_symbols = separate_debug_symbols.unwrap_or_else(|| deb.separate_debug_symbols.unwrap_or(false));
         let compress_debug_symbols = compress_debug_symbols.unwrap_or_else(|| deb.compress_debug_symbols.unwrap_or(false));
 
-        let debug_symbols = if separate_debug_symbols {
-            if !debug_enabled {
-                log::warn!("separate-debug-symbols implies strip");
-            }
-            DebugSymbols::Separate { compress: compress_debug_symbols }
-        } else if debug_enabled {
-            if compress_debug_symbols {
-                log::warn!("separate-debug-symbols required to compress");
-            }
-            DebugSymbols::Keep
-        } else {
-            DebugSymbols::Strip
+        let debug_symbols = debug_symbols_setting(debug_enabled, cargo_strip, split_debuginfo.as_deref(), separate_debug_symbols, compress_debug_symbols, listener);
+
+        let respect_package_exclude = deb.respect_package_exclude.unwrap_or(false);
+
+        // With the default relocation model, crt-static makes static-pie executables
+        let static_pie = overrides.static_pie || deb.static_pie.unwrap_or(false);
+        // Without --target, rustflags apply to build scripts and proc-macros too, and these can't be static
+        let target = match target {
+            None if static_pie => {
+                target_dir.push(crate::DEFAULT_TARGET);
+                Some(crate::DEFAULT_TARGET)
+            },
+            target => target,
         };
 
-        let config = Self {
+        let work_dir = match overrides.work_dir.take() {
+            Some(dir) if dir.is_relative() => std::env::current_dir()?.join(dir),
+            Some(dir) => dir,
+            None => target_dir.join("debian"),
+        };
+        let temp_dir = match overrides.temp_dir.take() {
+            Some(dir) if dir.is_relative() => Some(std::env::current_dir()?.join(dir)),
+            dir => dir,
+        };
+
+        let remap_flags = if overrides.remap_build_paths { crate::build_paths::remap_rustflags(&workspace_root) } else { Vec::new() };
+        let static_flags = if static_pie { vec!["-C".into(), "target-feature=+crt-static".into()] } else { Vec::new() };
+        let mut config = Self {
             package_manifest_dir: manifest_dir,
             deb_output_path,
             target: target.map(|t| t.to_string()),
+            debian_triple: None,
             target_dir,
+            work_dir,
+            temp_dir,
+            workspace_root,
             features: deb.features.take().unwrap_or_default(),
             default_features: deb.default_features.unwrap_or(true),
+            clean_env: overrides.clean_env,
+            quiet_cargo: overrides.quiet_cargo,
+            rustflags: deb.rustflags.take().map(|flags| flags.into_vec()).unwrap_or_default().into_iter()
+                .chain(overrides.rustflags.iter().flat_map(|flags| flags.split_whitespace().map(String::from)))
+                .chain(remap_flags)
+                .chain(static_flags)
+                .collect(),
             debug_symbols,
             build_profile_override,
             build_targets,
+            workspace_bins,
             cargo_locking_flags,
         };
 
-        let package_deb = PackageConfig::new(deb, cargo_package, listener, default_timestamp, overrides, target)?;
+        if cargo_package.description.is_none() {
+            if let Some(readme_rel_path) = cargo_package.readme().as_path() {
+                if let Some(summary) = fs::read_to_string(config.path_in_package(readme_rel_path)).ok().and_then(|text| readme_summary(&text)) {
+                    listener.info(format!("description field is missing in Cargo.toml. Using the first paragraph of {}", readme_rel_path.display()));
+                    cargo_package.description = Some(cargo_toml::Inheritable::Set(summary));
+                }
+            }
+        }
+
+        let mut package_deb = PackageConfig::new(deb, cargo_package, listener, default_timestamp, overrides, target)?;
+        // the architecture may come from the arch-map
+        config.debian_triple = target.map(|t| crate::debian_triple_from_rust_triple(t, &package_deb.architecture));
+        package_deb.glob_excludes = config.glob_excludes(cargo_package, respect_package_exclude)?;
+        package_deb.static_pie = static_pie;
 
         Ok((config, package_deb))
     }
 
     pub fn prepare_assets_before_build(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+        let include_readme = package_deb.include_readme.unwrap_or(package_deb.raw_assets.is_none());
         package_deb.assets = if let Some(raw_assets) = package_deb.raw_assets.take() {
-            self.explicit_assets(raw_assets)?
+            self.explicit_assets(raw_assets, package_deb)?
         } else {
-            self.implicit_assets(&package_deb.deb_name, package_deb.readme_rel_path.as_deref())?
+            let mut assets = self.implicit_assets()?;
+            let appended = std::mem::take(&mut package_deb.raw_assets_append);
+            if !appended.is_empty() {
+                assets.unresolved = self.explicit_assets(appended, package_deb)?.unresolved;
+            }
+            assets
         };
+        if include_readme {
+            self.add_readme_asset(package_deb)?;
+        }
         self.add_copyright_asset(package_deb)?;
         self.add_changelog_asset(package_deb)?;
-        self.add_systemd_assets(package_deb)?;
+        if package_deb.init_systems.contains(&InitSystem::Systemd) {
+            self.add_systemd_assets(package_deb)?;
+        }
+        self.add_init_script_assets(package_deb)?;
+        self.add_locale_assets(package_deb)?;
 
         self.reset_deb_temp_directory(&package_deb)?;
         Ok(())
@@ -404,6 +595,16 @@ impl Config {
         }
     }
 
+    fn add_readme_asset(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+        let Some(readme_rel_path) = &package_deb.readme_rel_path else { return Ok(()) };
+        let path = self.path_in_package(readme_rel_path);
+        let target_path = Path::new("usr/share/doc")
+            .join(&package_deb.deb_name)
+            .join(path.file_name().ok_or("bad README path")?);
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Path(path), target_path, 0o644, IsBuilt::No, false));
+        Ok(())
+    }
+
     fn add_copyright_asset(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
         let (source_path, copyright_file) = self.generate_copyright_asset(package_deb)?;
         log::debug!("added copyright via {}", source_path.display());
@@ -423,14 +624,16 @@ impl Config {
         let source_path;
         if let Some(path) = &package_deb.license_file_rel_path {
             source_path = self.path_in_package(path);
-            let license_string = fs::read_to_string(&source_path)
+            let license_bytes = fs::read(&source_path)
                 .map_err(|e| CargoDebError::IoFile("unable to read license file", e, path.clone()))?;
+            let license_string = decode_text(&license_bytes);
             if !has_copyright_metadata(&license_string) {
                 package_deb.append_copyright_metadata(&mut copyright)?;
             }
 
-            // Skip the first `A` number of lines and then iterate each line after that.
-            for line in license_string.lines().skip(package_deb.license_file_skip_lines) {
+            let lines = license_lines(&license_string, package_deb.license_file_skip_lines, package_deb.license_file_skip_to.as_deref())
+                .ok_or_else(|| CargoDebError::LicenseMarkerNotFound(package_deb.license_file_skip_to.clone().unwrap_or_default(), path.clone()))?;
+            for line in lines {
                 // If the line is a space, add a dot, else write the line.
                 if line == " " {
                     copyright.write_all(b" .\n")?;
@@ -512,6 +715,68 @@ impl Config {
         Ok(())
     }
 
+    /// Installs `<package>.init` or `<package>.openrc` from `maintainer-scripts` in `/etc/init.d`,
+    /// or generates init scripts from the systemd services.
+    fn add_init_script_assets(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+        let sysvinit = package_deb.init_systems.contains(&InitSystem::Sysvinit);
+        let openrc = package_deb.init_systems.contains(&InitSystem::Openrc);
+        if sysvinit && openrc {
+            return Err(CargoDebError::Str("init-system can't have both sysvinit and openrc, because their scripts are both installed in /etc/init.d"));
+        }
+        if !sysvinit && !openrc {
+            return Ok(());
+        }
+        let package = &package_deb.name;
+        let scripts_dir = package_deb.maintainer_scripts_rel_path.as_ref().map(|dir| self.path_in_package(dir));
+        let provided = scripts_dir.as_deref().and_then(|dir| pkgfile(dir, package, package, if openrc { "openrc" } else { "init" }, None));
+        if let Some(path) = provided {
+            package_deb.assets.resolved.push(Asset::new(AssetSource::Path(path), format!("etc/init.d/{package}").into(), 0o755, IsBuilt::No, false));
+            return Ok(());
+        }
+
+        let mut generated = 0;
+        for config in package_deb.systemd_units.iter().flatten() {
+            let Some(unit_dir) = config.unit_scripts.as_ref().or(package_deb.maintainer_scripts_rel_path.as_ref()) else { continue };
+            let units = dh_installsystemd::find_units(&self.path_in_package(unit_dir), package, config.unit_name.as_deref());
+            for (source, target) in units {
+                let Some(name) = fname_from_path(&target.path).strip_suffix(".service").filter(|name| !name.ends_with('@')).map(String::from) else { continue };
+                let unit = fs::read_to_string(&source).map_err(|e| CargoDebError::IoFile("unable to read systemd unit", e, source.clone()))?;
+                let Some(service) = init_scripts::ServiceInfo::from_unit(&unit) else { continue };
+                let script = if openrc { init_scripts::openrc_script(&name, &service) } else { init_scripts::sysvinit_script(&name, &service) };
+                package_deb.assets.resolved.push(Asset::new(AssetSource::Data(script.into()), format!("etc/init.d/{name}").into(), 0o755, IsBuilt::No, false));
+                generated += 1;
+            }
+        }
+        if generated == 0 {
+            return Err(CargoDebError::Str("init-system needs an init script (`<package>.init` or `<package>.openrc`) in maintainer-scripts, or a systemd service in systemd-units to generate one from"));
+        }
+        Ok(())
+    }
+
+    /// Installs translations from the `locales` dir as `usr/share/locale/<lang>/LC_MESSAGES/<domain>.mo`, compiling `.po` files
+    fn add_locale_assets(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+        let (dir, domain) = match &package_deb.locales {
+            None => return Ok(()),
+            Some(LocalesConfig::Dir(dir)) => (dir, None),
+            Some(LocalesConfig::Table { dir, domain }) => (dir, domain.as_deref()),
+        };
+        let dir = self.path_in_package(dir);
+        let translations = locales::find_translations(&dir, domain.unwrap_or(&package_deb.name))?;
+        if translations.is_empty() {
+            return Err(CargoDebError::Str("the locales dir has no translations (`<lang>.po` or `<lang>.mo` files)"));
+        }
+        for t in translations {
+            let asset = if t.source.extension().is_some_and(|ext| ext == "po") {
+                let mo = locales::compile_po_file(&t.source)?;
+                Asset::new(AssetSource::Data(mo), t.target, 0o644, IsBuilt::No, false).processed("compiled", t.source)
+            } else {
+                Asset::new(AssetSource::Path(t.source), t.target, 0o644, IsBuilt::No, false)
+            };
+            package_deb.assets.resolved.push(asset);
+        }
+        Ok(())
+    }
+
     pub(crate) fn path_in_build<P: AsRef<Path>>(&self, rel_path: P) -> PathBuf {
         self.path_in_build_(rel_path.as_ref())
     }
@@ -534,7 +799,7 @@ impl Config {
 
     /// Store intermediate files here
     pub(crate) fn deb_temp_dir(&self, package_deb: &PackageConfig) -> PathBuf {
-        self.target_dir.join("debian").join(&package_deb.name)
+        self.temp_dir.as_ref().unwrap_or(&self.work_dir).join(&package_deb.name)
     }
 
     /// Save final .deb here
@@ -554,14 +819,42 @@ impl Config {
     }
 
     pub(crate) fn default_deb_output_dir(&self) -> PathBuf {
-        self.target_dir.join("debian")
+        self.work_dir.clone()
+    }
+
+    /// `target/debian` and the work dir are always excluded. Optionally files that `cargo package` would skip too.
+    fn glob_excludes(&self, cargo_package: &cargo_toml::Package<CargoPackageMetadata>, respect_package_exclude: bool) -> CDResult<GlobExcludes> {
+        let mut dirs = vec![self.target_dir.join("debian")];
+        if self.work_dir != dirs[0] {
+            dirs.push(self.work_dir.clone());
+        }
+        let mut excludes = GlobExcludes {
+            dirs,
+            root: self.package_manifest_dir.clone(),
+            exclude: Vec::new(),
+            include: Vec::new(),
+        };
+        if respect_package_exclude {
+            excludes.exclude = cargo_package.exclude().to_vec();
+            excludes.include = cargo_package.include().to_vec();
+            // Cargo uses .gitignore only when there's no `include`
+            if excludes.include.is_empty() {
+                let gitignore_path = self.package_manifest_dir.join(".gitignore");
+                match fs::read_to_string(&gitignore_path) {
+                    Ok(gitignore) => excludes.exclude.extend(gitignore.lines().map(From::from)),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                    Err(e) => return Err(CargoDebError::IoFile("unable to read .gitignore", e, gitignore_path)),
+                }
+            }
+        }
+        Ok(excludes)
     }
 
     pub(crate) fn cargo_config(&self) -> CDResult<Option<CargoConfig>> {
-        CargoConfig::new(&self.package_manifest_dir)
+        Ok(CargoConfig::new(&self.package_manifest_dir)?.map(|conf| conf.with_debian_triple(self.debian_triple.clone())))
     }
 
-    /// Creates empty (removes files if needed) target/debian/foo directory so that we can start fresh.
+    /// Creates empty (removes files if needed) target/debian/foo (in the work dir) so that we can start fresh.
     fn reset_deb_temp_directory(&self, package_deb: &PackageConfig) -> io::Result<()> {
         let deb_temp_dir = self.deb_temp_dir(package_deb);
         let _ = fs::remove_dir(&deb_temp_dir);
@@ -570,6 +863,7 @@ impl Config {
         deb_dir.push(format!("{}_*_{}.deb", package_deb.deb_name, package_deb.architecture));
         if let Ok(old_files) = glob::glob(deb_dir.to_str().ok_or(io::ErrorKind::InvalidInput)?) {
             for old_file in old_files.flatten() {
+                let _ = fs::remove_file(old_file.with_extension("deb.sha256"));
                 let _ = fs::remove_file(old_file);
             }
         }
@@ -579,7 +873,7 @@ impl Config {
 
 impl PackageConfig {
     pub(crate) fn new(mut deb: CargoDeb, cargo_package: &mut cargo_toml::Package<CargoPackageMetadata>, listener: &dyn Listener, default_timestamp: u64, overrides: DebConfigOverrides, target: Option<&str>) -> Result<PackageConfig, CargoDebError> {
-        let (license_file_rel_path, license_file_skip_lines) = parse_license_file(cargo_package, deb.license_file.as_ref())?;
+        let (license_file_rel_path, license_file_skip_lines, license_file_skip_to) = parse_license_file(cargo_package, deb.license_file.as_ref());
         let mut license = cargo_package.license.take().map(|v| v.unwrap());
 
         if license.is_none() && license_file_rel_path.is_none() {
@@ -592,19 +886,61 @@ impl PackageConfig {
         }
 
         let has_maintainer_override = overrides.maintainer.is_some();
+        let maintainer_scripts_only = overrides.maintainer_scripts_only.or_else(|| deb.maintainer_scripts_only.take());
+        if let Some(name) = maintainer_scripts_only.iter().flatten().find(|name| !MAINTAINER_SCRIPTS.contains(&name.as_str())) {
+            return Err(CargoDebError::UnknownMaintainerScript(name.clone()));
+        }
         let deb_version = overrides.deb_version.unwrap_or_else(|| manifest_version_string(cargo_package, overrides.deb_revision.or(deb.revision.take()).as_deref()).into_owned());
         if let Err(why) = check_debian_version(&deb_version) {
             return Err(CargoDebError::InvalidVersion(why, deb_version));
         }
-        Ok(Self {
+        if let Some(problem) = deb.section.as_deref().and_then(section_problem) {
+            listener.warning(problem);
+        }
+        if deb.section.is_none() {
+            if let Some((section, category)) = section_from_categories(cargo_package.categories()) {
+                listener.warning(format!("section is not set, so it's \"{section}\", based on the '{category}' category. Set section in [package.metadata.deb] to change it"));
+                deb.section = Some(section.into());
+            }
+        }
+        if let Some(problem) = deb.priority.as_deref().and_then(priority_problem) {
+            listener.warning(problem);
+        }
+        for tag in deb.tag.iter().flatten().filter(|t| !t.contains("::") || t.contains([',', ' '])) {
+            listener.warning(format!("tag '{tag}' is not in the debtags `facet::value` format"));
+        }
+        for asset in deb.assets.iter().flatten().chain(deb.merge_assets.iter().flat_map(|m| m.append.iter().flatten())) {
+            if let Some(problem) = unusual_mode_problem(asset.chmod) {
+                listener.warning(format!("Asset {} has mode {:o}, which {problem}", asset.source_path.display(), asset.chmod));
+            }
+        }
+        let cli_arch_map: Vec<_> = match &overrides.arch_map {
+            Some(path) => crate::arch::read_arch_map(path)?.into_iter().collect(),
+            None => Vec::new(),
+        };
+        let metadata_arch_map: Vec<_> = deb.arch_map.take().unwrap_or_default().into_iter().collect();
+        let architecture = crate::arch::debian_architecture(target.unwrap_or(crate::DEFAULT_TARGET), &[&cli_arch_map, &metadata_arch_map])?;
+
+        let raw_assets = deb.assets.take();
+        let mut raw_assets_append = Vec::new();
+        if let (None, Some(merge_assets)) = (&raw_assets, deb.merge_assets.take()) {
+            if merge_assets.by.is_some() {
+                listener.warning("merge-assets.by only works with explicitly listed assets. Use merge-assets.append to add to the default assets".into());
+            }
+            raw_assets_append = merge_assets.append.unwrap_or_default();
+        }
+        let description_fallback = deb.description_fallback.take().filter(|_| cargo_package.description.is_none() && !overrides.require_description);
+        let mut package_deb = Self {
             deb_version,
             default_timestamp,
-            raw_assets: deb.assets.take(),
+            raw_assets,
+            raw_assets_append,
             name: cargo_package.name.clone(),
             deb_name: deb.name.take().unwrap_or_else(|| debian_package_name(&cargo_package.name)),
             license,
             license_file_rel_path,
             license_file_skip_lines,
+            license_file_skip_to,
             maintainer: overrides.maintainer.or_else(|| deb.maintainer.take()).ok_or_then(|| {
                 Ok(cargo_package.authors().first()
                     .ok_or("The package must have a maintainer specified (--maintainer works too) or have the authors property")?.to_owned())
@@ -622,10 +958,16 @@ impl PackageConfig {
             homepage: cargo_package.homepage().map(From::from),
             documentation: cargo_package.documentation().map(From::from),
             repository: cargo_package.repository.take().map(|v| v.unwrap()),
-            description: cargo_package.description.take().map_or_else(|| {
-                listener.warning("description field is missing in Cargo.toml".to_owned());
-                format!("[generated from Rust crate {}]", cargo_package.name)
-            }, |v| v.unwrap()),
+            description: match cargo_package.description.take() {
+                Some(description) => description.unwrap(),
+                None if overrides.require_description => return Err(CargoDebError::MissingDescription(cargo_package.name.clone())),
+                // expanded below, when the values of the variables are known
+                None if description_fallback.is_some() => String::new(),
+                None => {
+                    listener.warning("description field is missing in Cargo.toml".to_owned());
+                    format!("[generated from Rust crate {}]", cargo_package.name)
+                },
+            },
             extended_description: if let Some(path) = deb.extended_description_file.take() {
                 if deb.extended_description.is_some() {
                     listener.warning("extended-description and extended-description-file are both set".into());
@@ -642,6 +984,7 @@ impl PackageConfig {
                 ExtendedDescription::None
             },
             readme_rel_path: cargo_package.readme().as_path().map(|p| p.to_path_buf()),
+            include_readme: deb.include_readme.take(),
             wildcard_depends: deb.depends.take().map_or_else(|| "$auto".to_owned(), DependencyList::into_depends_string),
             resolved_depends: None,
             pre_depends: deb.pre_depends.take().map(DependencyList::into_depends_string),
@@ -652,54 +995,184 @@ impl PackageConfig {
             breaks: deb.breaks.take(),
             replaces: deb.replaces.take(),
             provides: deb.provides.take(),
+            protected: deb.protected.unwrap_or(false),
+            important: deb.important.unwrap_or(false),
+            tags: deb.tag.take().unwrap_or_default(),
             section: deb.section.take(),
             priority: deb.priority.take().unwrap_or_else(|| "optional".to_owned()),
-            architecture: debian_architecture_from_rust_triple(target.unwrap_or(crate::DEFAULT_TARGET)).to_owned(),
+            architecture,
             conf_files: deb.conf_files.take().unwrap_or_default(),
             assets: Assets::new(),
             triggers_file_rel_path: deb.triggers_file.take().map(PathBuf::from),
             changelog: deb.changelog.take(),
-            maintainer_scripts_rel_path: deb.maintainer_scripts.take().map(PathBuf::from),
+            changelog_distribution: deb.changelog_distribution.take(),
+            changelog_urgency: deb.changelog_urgency.take(),
+            maintainer_scripts_rel_path: overrides.maintainer_scripts.or_else(|| deb.maintainer_scripts.take().map(PathBuf::from)),
+            maintainer_scripts_only,
+            inline_scripts: [
+                ("preinst", deb.preinst_script.take()),
+                ("postinst", deb.postinst_script.take()),
+                ("prerm", deb.prerm_script.take()),
+                ("postrm", deb.postrm_script.take()),
+            ].into_iter().filter_map(|(name, script)| Some((name, script?))).collect(),
             preserve_symlinks: deb.preserve_symlinks.unwrap_or(false),
+            glob_excludes: GlobExcludes::default(),
             systemd_units: match deb.systemd_units.take() {
                 None => None,
                 Some(SystemUnitsSingleOrMultiple::Single(s)) => Some(vec![s]),
                 Some(SystemUnitsSingleOrMultiple::Multi(v)) => Some(v),
             },
-        })
+            init_systems: deb.init_system.take().unwrap_or_else(|| vec![InitSystem::Systemd]),
+            install_prefix: deb.install_prefix.take().map(|p| p.trim_start_matches('/').into()),
+            preinst_checks: deb.preinst_checks.take(),
+            cpu_variants: deb.cpu_variants.take().unwrap_or_default(),
+            cpu_variant_links: Vec::new(),
+            inspectors: deb.inspectors.take().unwrap_or_default(),
+            diverts: deb.diverts.take().unwrap_or_default(),
+            config_defaults: deb.config_defaults.take().unwrap_or_default(),
+            purge_paths: deb.purge_paths.take().unwrap_or_default(),
+            static_pie: false,
+            keep_special_bits: deb.keep_special_bits.take().unwrap_or_default(),
+            locales: deb.locales.take(),
+            symbols: deb.symbols.take(),
+            symbols_file: None,
+            build_ids: Vec::new(),
+            auditable_binaries: Vec::new(),
+        };
+        if let Some(template) = description_fallback {
+            let template_variables = package_deb.template_variables();
+            let template_variables: Vec<_> = template_variables.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            package_deb.description = crate::assets::expand_template(&template, &template_variables).map_err(CargoDebError::DescriptionTemplate)?;
+        }
+        Ok(package_deb)
+    }
+
+    /// Whether the script with this name from the `maintainer-scripts` dir goes into the package
+    pub(crate) fn uses_maintainer_script(&self, name: &str) -> bool {
+        match &self.maintainer_scripts_only {
+            Some(only) => only.iter().any(|n| n == name),
+            None => true,
+        }
     }
 
     pub fn resolve_assets(&mut self) -> CDResult<()> {
         for u in self.assets.unresolved.drain(..) {
-            let matched = u.resolve(self.preserve_symlinks)?;
+            let matched = u.resolve(self.preserve_symlinks, &self.glob_excludes)?;
             self.assets.resolved.extend(matched);
         }
-        self.add_conf_files();
+        self.apply_install_prefix();
+        self.apply_config_defaults()?;
+        self.add_conf_files()?;
+        Ok(())
+    }
+
+    /// Like `resolve_assets`, but for listing assets before the build: files that Cargo hasn't built yet
+    /// are kept at the paths (or glob patterns) where they will be built, instead of failing.
+    pub(crate) fn resolve_assets_without_build(&mut self) -> CDResult<()> {
+        let (not_built_yet, unresolved) = self.assets.unresolved.drain(..)
+            .partition::<Vec<_>, _>(|u| u.c.is_built() && glob::glob(&u.source_path.to_string_lossy()).map_or(true, |mut matches| matches.next().is_none()));
+        self.assets.unresolved = unresolved;
+        self.assets.resolved.extend(not_built_yet.into_iter().map(|mut u| {
+            // the same as `Asset::new` does for targets that are dirs
+            if u.c.target_path.to_string_lossy().ends_with('/') {
+                if let Some(file_name) = u.source_path.file_name() {
+                    u.c.target_path = u.c.target_path.join(file_name);
+                }
+            }
+            Asset { source: AssetSource::Path(u.source_path), processed_from: None, c: u.c }
+        }));
+        self.resolve_assets()
+    }
+
+    /// Moves assets listed in `config-defaults` to `usr/share/<package>/defaults/`, so that they're not conffiles
+    fn apply_config_defaults(&mut self) -> CDResult<()> {
+        for config in &self.config_defaults {
+            let rel_path = Path::new(config.path.trim_start_matches('/'));
+            let asset = self.assets.resolved.iter_mut()
+                .find(|a| a.c.target_path == rel_path)
+                .ok_or_else(|| CargoDebError::ConfigDefaultNotPackaged(config.path.clone()))?;
+            asset.c.target_path = config_defaults::defaults_path(&self.deb_name, rel_path);
+            log::debug!("config-defaults moved {} to {}", rel_path.display(), asset.c.target_path.display());
+        }
         Ok(())
     }
 
+    /// Moves executables from `usr/bin` and `usr/sbin` to the `install-prefix` dir,
+    /// and puts wrapper scripts that run them in their place.
+    fn apply_install_prefix(&mut self) {
+        let Some(prefix) = &self.install_prefix else { return };
+
+        let mut wrappers = Vec::new();
+        for asset in &mut self.assets.resolved {
+            if !asset.c.is_executable() || asset.source.archive_as_symlink_only() {
+                continue;
+            }
+            let Some((bin_dir, rel_path)) = ["bin", "sbin"].into_iter()
+                .find_map(|bin_dir| Some((bin_dir, asset.c.target_path.strip_prefix(Path::new("usr").join(bin_dir)).ok()?)))
+                else { continue };
+
+            let prefixed_path = prefix.join(bin_dir).join(rel_path);
+            let wrapper_path = std::mem::replace(&mut asset.c.target_path, prefixed_path.clone());
+            log::debug!("install-prefix moved {} to {}", wrapper_path.display(), prefixed_path.display());
+
+            let script = format!("#!/bin/sh\nexec /{} \"$@\"\n", prefixed_path.display());
+            wrappers.push(Asset::new(
+                AssetSource::Data(script.into_bytes()),
+                wrapper_path,
+                0o755,
+                IsBuilt::No,
+                false,
+            ).processed("install-prefix", Some(Path::new("/").join(prefixed_path))));
+        }
+        self.assets.resolved.append(&mut wrappers);
+    }
+
+    /// Normalizes `conf-files` to absolute target paths of packaged files.
+    /// Entries can be target paths (with or without the leading `/`), or source paths of assets.
+    ///
     /// Debian defaults all /etc files to be conf files
     /// <https://www.debian.org/doc/manuals/maint-guide/dother.en.html#conffiles>
-    fn add_conf_files(&mut self) {
-        let existing_conf_files = self.conf_files.iter()
-            .map(|c| c.trim_start_matches('/')).collect::<HashSet<_>>();
+    fn add_conf_files(&mut self) -> CDResult<()> {
+        let mut conf_files = Vec::with_capacity(self.conf_files.len());
+        let mut seen = HashSet::new();
+        for entry in std::mem::take(&mut self.conf_files) {
+            let rel_path = Path::new(entry.trim_start_matches('/'));
+            let target_path = self.assets.resolved.iter()
+                .find(|a| a.c.target_path == rel_path)
+                .or_else(|| {
+                    // An absolute path can't be a source path relative to the package
+                    if entry.starts_with('/') {
+                        return None;
+                    }
+                    self.assets.resolved.iter().find(|a| a.source.path().is_some_and(|source| source.ends_with(rel_path)))
+                })
+                .map(|a| &a.c.target_path)
+                .ok_or_else(|| CargoDebError::ConfFileNotPackaged(entry.clone()))?;
+            if target_path != rel_path {
+                log::debug!("conf-files entry {entry} is the asset /{}", target_path.display());
+            }
+            let conf_file = format!("/{}", target_path.to_str().ok_or("utf8 path")?);
+            if seen.insert(conf_file.clone()) {
+                conf_files.push(conf_file);
+            }
+        }
 
-        let mut new_conf = Vec::new();
         for a in &self.assets.resolved {
             if a.c.target_path.starts_with("etc") {
                 let Some(path_str) = a.c.target_path.to_str() else { continue };
-                if existing_conf_files.contains(path_str) {
-                    continue;
+                let conf_file = format!("/{path_str}");
+                if seen.insert(conf_file.clone()) {
+                    log::debug!("automatically adding {conf_file} to conffiles");
+                    conf_files.push(conf_file);
                 }
-                log::debug!("automatically adding /{path_str} to conffiles");
-                new_conf.push(format!("/{path_str}"));
             }
         }
-        self.conf_files.append(&mut new_conf);
+        self.conf_files = conf_files;
+        Ok(())
     }
 
     /// run dpkg/ldd to check deps of libs
-    pub fn resolve_binary_dependencies(&mut self, target: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
+    pub fn resolve_binary_dependencies(&mut self, debian_triple: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
         let mut deps = HashSet::new();
         for word in self.wildcard_depends.split(',') {
             let word = word.trim();
@@ -708,8 +1181,11 @@ impl PackageConfig {
                 let resolved = bin.par_iter()
                     .filter(|bin| !bin.archive_as_symlink_only())
                     .filter_map(|p| p.path())
-                    .filter_map(|bname| match resolve(bname, target) {
-                        Ok(bindeps) => Some(bindeps),
+                    .filter_map(|bname| match resolve(bname, debian_triple, listener) {
+                        Ok(bindeps) => {
+                            listener.log(format!("dpkg-shlibdeps found dependencies of {}: {}", bname.display(), bindeps.join(", ")));
+                            Some(bindeps)
+                        },
                         Err(err) => {
                             listener.warning(format!("{} (no auto deps for {})", err, bname.display()));
                             None
@@ -735,11 +1211,12 @@ impl PackageConfig {
     }
 
     /// Executables AND dynamic libraries. May include symlinks.
+    /// Built executables are left out of `static-pie` packages, since they don't link to any libraries.
     fn all_binaries(&self) -> Vec<&AssetSource> {
         self.assets.resolved.iter()
             .filter(|asset| {
                 // Assumes files in build dir which have executable flag set are binaries
-                asset.c.is_dynamic_library() || asset.c.is_executable()
+                asset.c.is_dynamic_library() || (asset.c.is_executable() && !(self.static_pie && asset.c.is_built()))
             })
             .map(|asset| &asset.source)
             .collect()
@@ -755,6 +1232,38 @@ impl PackageConfig {
             .collect()
     }
 
+    /// Two assets with the same target path would be written to the tarball twice, and only one of them would be installed.
+    /// Exact duplicates, like a file matched by a glob and also listed explicitly, are removed.
+    pub(crate) fn check_conflicting_assets(&mut self) -> CDResult<()> {
+        let mut by_target: HashMap<&Path, &Asset> = HashMap::with_capacity(self.assets.resolved.len());
+        let mut duplicates = HashSet::new();
+        for (i, asset) in self.assets.resolved.iter().enumerate() {
+            let Some(&other) = by_target.get(asset.c.target_path.as_path()) else {
+                by_target.insert(&asset.c.target_path, asset);
+                continue;
+            };
+            let is_same_file = matches!((&asset.source, &other.source), (AssetSource::Path(a), AssetSource::Path(b)) if a == b) &&
+                asset.processed_from.is_none() && other.processed_from.is_none() && asset.c.chmod == other.c.chmod;
+            if !is_same_file {
+                return Err(CargoDebError::AssetConflict(asset.c.target_path.clone(), other.source_description(), asset.source_description()));
+            }
+            log::debug!("{} is listed twice as an asset", asset.c.target_path.display());
+            duplicates.insert(i);
+        }
+        // A file can't be where another asset needs a directory
+        for asset in &self.assets.resolved {
+            if let Some(file) = asset.c.target_path.ancestors().skip(1).find_map(|dir| by_target.get(dir)) {
+                return Err(CargoDebError::AssetDirConflict(file.c.target_path.clone(), file.source_description(), asset.source_description()));
+            }
+        }
+
+        if !duplicates.is_empty() {
+            let mut i = 0;
+            self.assets.resolved.retain(|_| { i += 1; !duplicates.contains(&(i - 1)) });
+        }
+        Ok(())
+    }
+
     /// similar files next to each other improve tarball compression
     pub fn sort_assets_by_type(&mut self) {
         self.assets.resolved.sort_by(|a,b| {
@@ -797,6 +1306,36 @@ impl PackageConfig {
         Ok(Some(desc.into()))
     }
 
+    /// Replaces `{version}` (the upstream part of the Debian version, without the epoch) and `{revision}`
+    /// in asset target paths and symlinks, so that versioned directories follow `--deb-version`
+    pub(crate) fn expand_version_placeholders(&self, path: &Path) -> PathBuf {
+        let Some(path_str) = path.to_str().filter(|p| p.contains('{')) else {
+            return path.to_owned();
+        };
+        let version = self.deb_version.split_once(':').map_or(self.deb_version.as_str(), |(_, v)| v);
+        let (upstream, revision) = version.rsplit_once('-').unwrap_or((version, ""));
+        path_str.replace("{version}", upstream).replace("{revision}", revision).into()
+    }
+
+    /// Values for `{{variables}}` in assets with `template = true`
+    pub(crate) fn template_variables(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("package", self.deb_name.clone()),
+            ("version", self.deb_version.clone()),
+            ("arch", self.architecture.clone()),
+            ("prefix", self.install_prefix.as_ref().map_or_else(|| "/usr".into(), |p| Path::new("/").join(p).display().to_string())),
+            ("maintainer", self.maintainer.clone()),
+        ]
+    }
+
+    /// Estimate of disk space used by the installed package, in KiB
+    pub(crate) fn installed_size(&self) -> u64 {
+        self.assets.resolved
+            .iter()
+            .map(|m| (m.source.file_size().unwrap_or(0) + 2047) / 1024) // assume 1KB of fs overhead per file
+            .sum::<u64>()
+    }
+
     /// Generates the control file that obtains all the important information about the package.
     pub fn generate_control(&self, config: &Config) -> CDResult<Vec<u8>> {
         // Create and return the handle to the control file with write access.
@@ -821,62 +1360,40 @@ impl PackageConfig {
             writeln!(&mut control, "Section: {section}")?;
         }
         writeln!(&mut control, "Priority: {}", self.priority)?;
+        if self.protected {
+            writeln!(&mut control, "Protected: yes")?;
+        }
+        if self.important {
+            writeln!(&mut control, "Important: yes")?;
+        }
         writeln!(&mut control, "Maintainer: {}", self.maintainer)?;
 
-        let installed_size = self.assets.resolved
-            .iter()
-            .map(|m| (m.source.file_size().unwrap_or(0) + 2047) / 1024) // assume 1KB of fs overhead per file
-            .sum::<u64>();
-
-        writeln!(&mut control, "Installed-Size: {installed_size}")?;
+        writeln!(&mut control, "Installed-Size: {}", self.installed_size())?;
 
         if let Some(deps) = &self.resolved_depends {
-            writeln!(&mut control, "Depends: {deps}")?;
-        }
-
-        if let Some(ref pre_depends) = self.pre_depends {
-            let pre_depends_normalized = pre_depends.trim();
-
-            if !pre_depends_normalized.is_empty() {
-                writeln!(&mut control, "Pre-Depends: {pre_depends_normalized}")?;
-            }
+            write_relation_field(&mut control, "Depends", deps)?;
         }
-
-        if let Some(ref recommends) = self.recommends {
-            let recommends_normalized = recommends.trim();
-
-            if !recommends_normalized.is_empty() {
-                writeln!(&mut control, "Recommends: {recommends_normalized}")?;
+        let relations = [
+            ("Pre-Depends", &self.pre_depends),
+            ("Recommends", &self.recommends),
+            ("Suggests", &self.suggests),
+            ("Enhances", &self.enhances),
+            ("Conflicts", &self.conflicts),
+            ("Breaks", &self.breaks),
+            ("Replaces", &self.replaces),
+            ("Provides", &self.provides),
+        ];
+        for (name, value) in relations {
+            if let Some(value) = value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+                write_relation_field(&mut control, name, value)?;
             }
         }
-
-        if let Some(ref suggests) = self.suggests {
-            let suggests_normalized = suggests.trim();
-
-            if !suggests_normalized.is_empty() {
-                writeln!(&mut control, "Suggests: {suggests_normalized}")?;
-            }
+        if !self.tags.is_empty() {
+            write_relation_field(&mut control, "Tag", &self.tags.join(", "))?;
         }
-
-        if let Some(ref enhances) = self.enhances {
-            let enhances_normalized = enhances.trim();
-
-            if !enhances_normalized.is_empty() {
-                writeln!(&mut control, "Enhances: {enhances_normalized}")?;
-            }
-        }
-
-        if let Some(ref conflicts) = self.conflicts {
-            writeln!(&mut control, "Conflicts: {conflicts}")?;
-        }
-        if let Some(ref breaks) = self.breaks {
-            writeln!(&mut control, "Breaks: {breaks}")?;
-        }
-        if let Some(ref replaces) = self.replaces {
-            writeln!(&mut control, "Replaces: {replaces}")?;
-        }
-        if let Some(ref provides) = self.provides {
-            writeln!(&mut control, "Provides: {provides}")?;
+        if !self.auditable_binaries.is_empty() {
+            let paths = self.auditable_binaries.iter().map(|p| format!("/{}", p.display())).collect::<Vec<_>>();
+            write_relation_field(&mut control, "X-Cargo-Auditable", &paths.join(", "))?;
         }
 
         write!(&mut control, "Description:")?;
@@ -948,18 +1465,46 @@ impl TryFrom<CargoDebAssetArrayOrTable> for RawAsset {
     type Error = String;
     fn try_from(toml: CargoDebAssetArrayOrTable) -> Result<Self, Self::Error> {
         fn parse_chmod(mode: &str) -> Result<u32, String> {
-            u32::from_str_radix(mode, 8).map_err(|e| format!("Unable to parse mode argument (third array element) as an octal number in an asset: {e}"))
+            let chmod = match u32::from_str_radix(mode, 8) {
+                Ok(chmod) => chmod,
+                Err(e) => parse_symbolic_mode(mode).ok_or_else(|| format!("Unable to parse mode argument (third array element) as an octal number, like \"644\", or symbolic mode, like \"rw-r--r--\" or \"u=rw,go=r\", in an asset: {e}"))?,
+            };
+            if chmod > 0o7777 {
+                return Err(format!("Mode '{mode}' of an asset is too large. It should be at most 4 octal digits, like \"644\" or \"755\""));
+            }
+            Ok(chmod)
+        }
+        fn parse_transforms(names: &[String]) -> Result<Vec<AssetTransform>, String> {
+            names.iter().map(|name| {
+                AssetTransform::from_name(name).ok_or_else(|| format!("Unknown asset transform '{name}'. Supported: template, compress-debug-sections, upx"))
+            }).collect()
         }
         let a = match toml {
-            CargoDebAssetArrayOrTable::Table(a) => Self {
-                source_path: a.source.into(), target_path: a.dest.into(), chmod: parse_chmod(&a.mode)?
+            CargoDebAssetArrayOrTable::Table(a) => {
+                let mut transforms = parse_transforms(&a.transform)?;
+                if a.template && !transforms.contains(&AssetTransform::Template) {
+                    transforms.insert(0, AssetTransform::Template);
+                }
+                let source_path = match (a.source, &a.bin) {
+                    (Some(source), None) => source.into(),
+                    (None, Some(bin)) => Path::new("target/release").join(format!("{bin}{EXE_SUFFIX}")),
+                    _ => return Err(format!("An asset with dest `{}` needs either `source` or `bin`", a.dest)),
+                };
+                let mode = a.mode.as_deref().or(a.bin.as_ref().map(|_| "755")).ok_or_else(|| format!("Missing mode in an asset with source `{}`", source_path.display()))?;
+                Self {
+                    source_path, target_path: a.dest.into(), chmod: parse_chmod(mode)?,
+                    transforms,
+                    bin: a.bin,
+                }
             },
             CargoDebAssetArrayOrTable::Array(a) => {
                 let mut a = a.into_iter();
                 Self {
                     source_path: PathBuf::from(a.next().ok_or("Missing source path (first array element) in an asset in Cargo.toml")?),
                     target_path: PathBuf::from(a.next().ok_or("missing dest path (second array entry) for asset in Cargo.toml. Use something like \"usr/local/bin/\".")?),
-                    chmod: parse_chmod(&a.next().ok_or("Missing mode (third array element) in an asset")?)?
+                    chmod: parse_chmod(&a.next().ok_or("Missing mode (third array element) in an asset")?)?,
+                    transforms: Vec::new(),
+                    bin: None,
                 }
             },
             CargoDebAssetArrayOrTable::Invalid(bad) => {
@@ -971,23 +1516,307 @@ impl TryFrom<CargoDebAssetArrayOrTable> for RawAsset {
 Please only use `target/release/` directory for built products, not `{}`.
 To add debug information or additional assertions use `[profile.release]` in `Cargo.toml` instead.", a.source_path.display()));
         }
+        if !is_target_path_in_package(&a.target_path) {
+            return Err(format!("Asset destination path `{}` is outside of the package's root. `..` is not allowed in asset paths", a.target_path.display()));
+        }
         Ok(a)
     }
 }
 
-fn parse_license_file(package: &cargo_toml::Package<CargoPackageMetadata>, license_file: Option<&LicenseFile>) -> CDResult<(Option<PathBuf>, usize)> {
-    Ok(match license_file {
+/// Parses `ls`-style `rwxr-xr-x` or `chmod`-style `u=rwx,go=rx` modes.
+/// The latter is applied to `000`, so `u+x` is the same as `u=x`.
+fn parse_symbolic_mode(mode: &str) -> Option<u32> {
+    let ls_style = mode.strip_prefix('-').filter(|m| m.len() == 9).unwrap_or(mode);
+    if ls_style.len() == 9 && !ls_style.contains(['=', '+', ',']) {
+        let b = ls_style.as_bytes();
+        let mut chmod = 0;
+        for (i, triple) in b.chunks(3).enumerate() {
+            let shift = 3 * (2 - i as u32);
+            if triple[0] == b'r' { chmod |= 4 << shift } else if triple[0] != b'-' { return None }
+            if triple[1] == b'w' { chmod |= 2 << shift } else if triple[1] != b'-' { return None }
+            let special = [0o4000, 0o2000, 0o1000][i];
+            let special_char = if i == 2 { (b't', b'T') } else { (b's', b'S') };
+            match triple[2] {
+                b'x' => chmod |= 1 << shift,
+                b'-' => {},
+                c if c == special_char.0 => chmod |= special | 1 << shift,
+                c if c == special_char.1 => chmod |= special,
+                _ => return None,
+            }
+        }
+        return Some(chmod);
+    }
+
+    let mut chmod = 0;
+    for clause in mode.split(',') {
+        let op_pos = clause.find(['=', '+', '-'])?;
+        let (who, rest) = clause.split_at(op_pos);
+        let mut who_mask = 0;
+        for c in who.chars() {
+            who_mask |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return None,
+            };
+        }
+        if who_mask == 0 {
+            who_mask = 0o7777;
+        }
+        let mut perms = 0;
+        for c in rest[1..].chars() {
+            perms |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                's' => 0o6000,
+                't' => 0o1000,
+                _ => return None,
+            };
+        }
+        let bits = perms & who_mask;
+        match rest.as_bytes()[0] {
+            b'=' => chmod = (chmod & !who_mask) | bits,
+            b'+' => chmod |= bits,
+            _ => chmod &= !bits,
+        }
+    }
+    Some(chmod)
+}
+
+/// Sections of the Debian archive, <https://packages.debian.org/unstable/>
+const SECTIONS: &[&str] = &[
+    "admin", "cli-mono", "comm", "database", "debian-installer", "debug", "devel", "doc", "editors", "education",
+    "electronics", "embedded", "fonts", "games", "gnome", "gnu-r", "gnustep", "graphics", "hamradio", "haskell",
+    "httpd", "interpreters", "introspection", "java", "javascript", "kde", "kernel", "libdevel", "libs", "lisp",
+    "localization", "mail", "math", "metapackages", "misc", "net", "news", "ocaml", "oldlibs", "otherosfs", "perl",
+    "php", "python", "ruby", "rust", "science", "shells", "sound", "tasks", "tex", "text", "utils", "vcs", "video",
+    "web", "x11", "xfce", "zope",
+];
+const AREAS: &[&str] = &["main", "contrib", "non-free", "non-free-firmware"];
+const PRIORITIES: &[&str] = &["required", "important", "standard", "optional"];
+
+/// Debian sections for crates.io categories, used when `section` isn't set
+const CATEGORY_SECTIONS: &[(&str, &str)] = &[
+    ("command-line-utilities", "utils"),
+    ("compression", "utils"),
+    ("database", "database"),
+    ("database-implementations", "database"),
+    ("development-tools", "devel"),
+    ("email", "mail"),
+    ("embedded", "embedded"),
+    ("emulators", "otherosfs"),
+    ("games", "games"),
+    ("graphics", "graphics"),
+    ("gui", "x11"),
+    ("internationalization", "localization"),
+    ("localization", "localization"),
+    ("mathematics", "math"),
+    ("multimedia::audio", "sound"),
+    ("multimedia::images", "graphics"),
+    ("multimedia::video", "video"),
+    ("network-programming", "net"),
+    ("science", "science"),
+    ("text-editors", "editors"),
+    ("text-processing", "text"),
+    ("virtualization", "admin"),
+    ("web-programming", "web"),
+    ("web-programming::http-server", "httpd"),
+];
+
+/// Section of the first category that has one, and that category. Subcategories like `multimedia::audio`
+/// can have their own sections, and otherwise use the section of their parent.
+fn section_from_categories(categories: &[String]) -> Option<(&'static str, &str)> {
+    let section_of = |category: &str| CATEGORY_SECTIONS.iter().find(|&&(c, _)| c == category).map(|&(_, section)| section);
+    categories.iter().find_map(|category| {
+        let section = section_of(category).or_else(|| section_of(category.split("::").next()?))?;
+        Some((section, category.as_str()))
+    })
+}
+
+/// Warning about a section that the archive would reject, like `utilities` or `nonfree/utils`
+fn section_problem(section: &str) -> Option<String> {
+    let (area, name) = section.rsplit_once('/').map_or((None, section), |(a, n)| (Some(a), n));
+    let mut problems = Vec::new();
+    if let Some(area) = area.filter(|a| !AREAS.contains(a)) {
+        problems.push(match crate::util::closest_match(area, AREAS) {
+            Some(suggestion) => format!("area '{area}' is unknown (did you mean '{suggestion}'?)"),
+            None => format!("area '{area}' is unknown (expected one of {})", AREAS.join(", ")),
+        });
+    }
+    if !SECTIONS.contains(&name) {
+        problems.push(match crate::util::closest_match(name, SECTIONS) {
+            Some(suggestion) => format!("'{name}' is not a Debian section (did you mean '{suggestion}'?)"),
+            None => format!("'{name}' is not a Debian section. See https://packages.debian.org/unstable/ for the list"),
+        });
+    }
+    (!problems.is_empty()).then(|| format!("section = \"{section}\": {}", problems.join(", ")))
+}
+
+fn priority_problem(priority: &str) -> Option<String> {
+    if PRIORITIES.contains(&priority) {
+        return None;
+    }
+    Some(if priority == "extra" {
+        "priority = \"extra\" is deprecated. Use \"optional\" instead".into()
+    } else if let Some(suggestion) = crate::util::closest_match(priority, PRIORITIES) {
+        format!("priority = \"{priority}\" is not valid (did you mean '{suggestion}'?)")
+    } else {
+        format!("priority = \"{priority}\" is not valid. Use one of: {}", PRIORITIES.join(", "))
+    })
+}
+
+/// The first sentence of the first paragraph of prose in a README, if it's short enough for the `Description` synopsis.
+/// Skips Markdown/reST headings, badges, HTML and code blocks.
+fn readme_summary(readme: &str) -> Option<String> {
+    let is_underline = |line: &str| line.len() >= 3 && line.chars().all(|c| matches!(c, '=' | '-' | '~' | '*' | '#'));
+    let mut in_code_block = false;
+    let mut paragraph = Vec::new();
+    let mut lines = readme.lines().map(str::trim).peekable();
+    while let Some(line) = lines.next() {
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block || is_underline(line) || lines.peek().is_some_and(|next| is_underline(next)) {
+            continue;
+        }
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                break;
+            }
+            continue;
+        }
+        let is_markup = line.starts_with(['#', '<', '!', '|', '>', '-', '*']) && !line.starts_with("**");
+        if !is_markup && !line.starts_with("[!") {
+            paragraph.push(line);
+        } else if !paragraph.is_empty() {
+            break;
+        }
+    }
+    let text = paragraph.join(" ");
+
+    // [text](link) → text
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('[') {
+        let Some((label, after)) = rest[start + 1..].split_once("](") else { break };
+        let Some(end) = after.find(')') else { break };
+        plain.push_str(&rest[..start]);
+        plain.push_str(label);
+        rest = &after[end + 1..];
+    }
+    plain.push_str(rest);
+    let plain = plain.replace(['`', '*'], "");
+
+    let sentence = plain.split_once(". ").map_or(plain.as_str(), |(first, _)| first).trim().trim_end_matches('.');
+    (!sentence.is_empty() && sentence.len() <= 80).then(|| sentence.to_owned())
+}
+
+/// Combines `debug`, `strip` and `split-debuginfo` of the Cargo profile with `separate-debug-symbols`.
+/// Binaries that Cargo has already stripped aren't stripped again, and have no debug info to separate.
+fn debug_symbols_setting(debug_enabled: bool, cargo_strip: Option<StripSetting>, split_debuginfo: Option<&str>, separate_debug_symbols: bool, compress_debug_symbols: bool, listener: &dyn Listener) -> DebugSymbols {
+    match cargo_strip {
+        Some(StripSetting::Symbols) => {
+            if separate_debug_symbols {
+                listener.warning("separate-debug-symbols has no effect, because the profile has `strip = true`, and Cargo removes debug info from the binaries. Set `strip = false` in the profile".into());
+            }
+            log::debug!("not stripping, because Cargo has stripped the binaries");
+            return DebugSymbols::Keep;
+        },
+        Some(StripSetting::Debuginfo) => {
+            if separate_debug_symbols {
+                listener.warning("separate-debug-symbols has no effect, because the profile has `strip = \"debuginfo\"`, and Cargo removes debug info from the binaries. Set `strip = false` in the profile".into());
+            }
+            // Symbol tables are still there, and are stripped like dh_strip does
+            return DebugSymbols::Strip;
+        },
+        Some(StripSetting::None) | None => {},
+    }
+
+    if separate_debug_symbols {
+        if !debug_enabled {
+            log::warn!("separate-debug-symbols implies strip");
+        }
+        if let Some(split) = split_debuginfo.filter(|&s| s != "off") {
+            listener.warning(format!("The profile has `split-debuginfo = \"{split}\"`, so most of the debug info is in .dwo or .dwp files that aren't packaged, not in the separate debug symbols. Set `split-debuginfo = \"off\"` in the profile"));
+        }
+        DebugSymbols::Separate { compress: compress_debug_symbols }
+    } else if debug_enabled {
+        if compress_debug_symbols {
+            log::warn!("separate-debug-symbols required to compress");
+        }
+        DebugSymbols::Keep
+    } else {
+        DebugSymbols::Strip
+    }
+}
+
+/// Modes that are valid, but most likely a mistake
+fn unusual_mode_problem(chmod: u32) -> Option<&'static str> {
+    Some(if chmod & 0o002 != 0 {
+        "makes the file writable by all users"
+    } else if chmod & 0o400 == 0 {
+        "doesn't give the owner read access (is a digit missing?)"
+    } else if ((chmod & 0o111) << 2) & !chmod != 0 {
+        "makes the file executable, but not readable, for some users"
+    } else if chmod & 0o6000 != 0 {
+        "sets setuid or setgid bits"
+    } else {
+        return None;
+    })
+}
+
+/// The path, the number of lines to skip, and the marker of the end of the skipped header.
+/// The second element of the array is either the number or the marker.
+fn parse_license_file(package: &cargo_toml::Package<CargoPackageMetadata>, license_file: Option<&LicenseFile>) -> (Option<PathBuf>, usize, Option<String>) {
+    match license_file {
         Some(LicenseFile::Vec(args)) => {
             let mut args = args.iter();
             let file = args.next();
-            let lines = if let Some(lines) = args.next() {
-                lines.parse().map_err(|e| CargoDebError::NumParse("invalid number of lines", e))?
-            } else {0};
-            (file.map(|s|s.into()), lines)
+            let (lines, marker) = match args.next() {
+                Some(skip) => match skip.trim().parse() {
+                    Ok(lines) => (lines, None),
+                    Err(_) => (0, Some(skip.clone())),
+                },
+                None => (0, None),
+            };
+            (file.map(|s|s.into()), lines, marker)
         },
-        Some(LicenseFile::String(s)) => (Some(s.into()), 0),
-        None => (package.license_file().as_ref().map(|s| s.into()), 0),
-    })
+        Some(LicenseFile::String(s)) => (Some(s.into()), 0, None),
+        None => (package.license_file().as_ref().map(|s| s.into()), 0, None),
+    }
+}
+
+/// License files aren't always UTF-8. This strips the BOM, decodes UTF-16 with a BOM,
+/// and reads files that aren't valid UTF-8 as Latin-1, which can decode any bytes.
+fn decode_text(bytes: &[u8]) -> String {
+    let utf16 = |rest: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        char::decode_utf16(rest.chunks_exact(2).map(|c| from_bytes([c[0], c[1]])))
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    };
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        return utf16(rest, u16::from_le_bytes);
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        return utf16(rest, u16::from_be_bytes);
+    }
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_owned(),
+        Err(_) => bytes.iter().map(|&b| char::from(b)).collect(),
+    }
+}
+
+/// Lines after the skipped header, without `\r` of CRLF line endings. `None` if the marker isn't there.
+fn license_lines<'a>(text: &'a str, skip_lines: usize, skip_to: Option<&str>) -> Option<impl Iterator<Item = &'a str>> {
+    let mut lines = text.lines().skip(skip_lines);
+    if let Some(marker) = skip_to {
+        lines.position(|line| line.contains(marker))?;
+    }
+    Some(lines)
 }
 
 fn has_copyright_metadata(file: &str) -> bool {
@@ -996,7 +1825,7 @@ fn has_copyright_metadata(file: &str) -> bool {
 }
 
 /// Debian doesn't like `_` in names
-fn debian_package_name(crate_name: &str) -> String {
+pub(crate) fn debian_package_name(crate_name: &str) -> String {
     // crate names are ASCII only
     crate_name.bytes().map(|c| {
         if c != b'_' {c.to_ascii_lowercase() as char} else {'-'}
@@ -1004,10 +1833,13 @@ fn debian_package_name(crate_name: &str) -> String {
 }
 
 impl Config {
-    fn explicit_assets(&self, assets: Vec<RawAsset>) -> CDResult<Assets> {
+    fn explicit_assets(&self, assets: Vec<RawAsset>, package_deb: &PackageConfig) -> CDResult<Assets> {
         let custom_profile_target_dir = self.build_profile_override.as_deref().map(|profile| format!("target/{profile}"));
         // Treat all explicit assets as unresolved until after the build step
-        let unresolved_assets = assets.into_iter().map(|RawAsset { source_path, target_path, chmod }| {
+        let unresolved_assets = assets.into_iter().map(|RawAsset { mut source_path, target_path, chmod, transforms, bin }| {
+            if let Some(bin) = bin {
+                source_path = Path::new("target/release").join(format!("{}{EXE_SUFFIX}", self.workspace_bin_name(&bin)?));
+            }
             // target/release is treated as a magic prefix that resolves to any profile
             let target_artifact_rel_path = source_path.strip_prefix("target/release").ok()
                 .or_else(|| source_path.strip_prefix(custom_profile_target_dir.as_ref()?).ok());
@@ -1018,13 +1850,16 @@ impl Config {
             } else {
                 (IsBuilt::No, self.path_in_package(&source_path), false)
             };
-            Ok(UnresolvedAsset::new(source_path, target_path, chmod, is_built, is_example))
+            let target_path = package_deb.expand_version_placeholders(&target_path);
+            let mut asset = UnresolvedAsset::new(source_path, target_path, chmod, is_built, is_example);
+            asset.c.transforms = transforms;
+            Ok(asset)
         }).collect::<CDResult<Vec<_>>>()?;
         Ok(Assets::with_unresolved_assets(unresolved_assets))
     }
 
-    fn implicit_assets(&self, deb_package_name: &str, readme_rel_path: Option<&Path>) -> CDResult<Assets> {
-        let mut implied_assets: Vec<_> = self.build_targets.iter()
+    fn implicit_assets(&self) -> CDResult<Assets> {
+        let implied_assets: Vec<_> = self.build_targets.iter()
             .filter_map(|t| {
                 if t.crate_types.iter().any(|ty| ty == "bin") && t.kind.iter().any(|k| k == "bin") {
                     Some(Asset::new(
@@ -1052,16 +1887,27 @@ impl Config {
         if implied_assets.is_empty() {
             return Err("No binaries or cdylibs found. The package is empty. Please specify some assets to package in Cargo.toml".into());
         }
-        if let Some(readme_rel_path) = readme_rel_path {
-            let path = self.path_in_package(readme_rel_path);
-            let target_path = Path::new("usr/share/doc")
-                .join(deb_package_name)
-                .join(path.file_name().ok_or("bad README path")?);
-            implied_assets.push(Asset::new(AssetSource::Path(path), target_path, 0o644, IsBuilt::No, false));
-        }
         Ok(Assets::with_resolved_assets(implied_assets))
     }
 
+    /// Name of the executable for a `{ bin = "…" }` asset, which can also name a workspace member that has only one executable
+    fn workspace_bin_name<'a>(&'a self, name: &'a str) -> CDResult<&'a str> {
+        let own_bins = self.build_targets.iter().filter(|t| t.kind.iter().any(|k| k == "bin")).map(|t| t.name.as_str());
+        if own_bins.clone().chain(self.workspace_bins.iter().map(|(_, bin)| bin.as_str())).any(|bin| bin == name) {
+            return Ok(name);
+        }
+        let mut member_bins = self.workspace_bins.iter().filter(|(package, _)| package == name);
+        match (member_bins.next(), member_bins.next()) {
+            (Some((_, bin)), None) => Ok(bin),
+            _ => {
+                let mut available = own_bins.chain(self.workspace_bins.iter().map(|(_, bin)| bin.as_str())).collect::<Vec<_>>();
+                available.sort_unstable();
+                available.dedup();
+                Err(CargoDebError::BinNotInWorkspace(name.into(), available.join(", ")))
+            },
+        }
+    }
+
     fn find_is_built_file_in_package(&self, rel_path: &Path, expected_kind: &str) -> IsBuilt {
         let source_name = rel_path.file_name().expect("asset filename").to_str().expect("utf-8 names");
         let source_name = source_name.strip_suffix(EXE_SUFFIX).unwrap_or(source_name);
@@ -1102,6 +1948,16 @@ fn format_conffiles<S: AsRef<str>>(files: &[S]) -> String {
     })
 }
 
+/// Writes a comma-separated field on one line if it fits in 80 columns,
+/// otherwise folds it with one item per continuation line.
+fn write_relation_field(out: &mut Vec<u8>, name: &str, value: &str) -> io::Result<()> {
+    if name.len() + 2 + value.len() <= 80 {
+        return writeln!(out, "{name}: {value}");
+    }
+    let items = value.split(',').map(str::trim).filter(|item| !item.is_empty()).collect::<Vec<_>>();
+    writeln!(out, "{name}: {}", items.join(",\n "))
+}
+
 fn check_debian_version(mut ver: &str) -> Result<(), &'static str> {
     if ver.trim_start().is_empty() {
         return Err("empty string");
@@ -1124,6 +1980,12 @@ fn check_debian_version(mut ver: &str) -> Result<(), &'static str> {
     Ok(())
 }
 
+/// `Config` of this crate's own `Cargo.toml`, for tests
+#[cfg(test)]
+pub(crate) fn test_config(listener: &dyn Listener) -> (Config, PackageConfig) {
+    Config::from_manifest(Some(Path::new("Cargo.toml")), None, None, None, None, DebConfigOverrides::default(), None, None, None, CargoLockingFlags::default(), listener).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1132,7 +1994,7 @@ mod tests {
 
     #[test]
     fn match_arm_arch() {
-        assert_eq!("armhf", debian_architecture_from_rust_triple("arm-unknown-linux-gnueabihf"));
+        assert_eq!("armhf", crate::arch::debian_architecture_from_rust_triple("arm-unknown-linux-gnueabihf"));
     }
 
     #[test]
@@ -1155,6 +2017,33 @@ mod tests {
         Box::leak(abs_path_string.into_boxed_str())
     }
 
+    #[test]
+    fn debug_symbols_follow_cargo_profile() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_warning().times(2).return_const(());
+
+        assert_eq!(DebugSymbols::Keep, debug_symbols_setting(true, None, None, false, false, &mock_listener));
+        assert_eq!(DebugSymbols::Strip, debug_symbols_setting(false, Some(StripSetting::None), None, false, false, &mock_listener));
+        assert_eq!(DebugSymbols::Keep, debug_symbols_setting(false, Some(StripSetting::Symbols), None, false, false, &mock_listener));
+        assert_eq!(DebugSymbols::Strip, debug_symbols_setting(true, Some(StripSetting::Debuginfo), None, false, false, &mock_listener));
+        assert_eq!(DebugSymbols::Separate { compress: true }, debug_symbols_setting(true, None, Some("off"), true, true, &mock_listener));
+        // warnings
+        assert_eq!(DebugSymbols::Keep, debug_symbols_setting(true, Some(StripSetting::Symbols), None, true, false, &mock_listener));
+        assert_eq!(DebugSymbols::Separate { compress: false }, debug_symbols_setting(true, None, Some("packed"), true, false, &mock_listener));
+    }
+
+    #[test]
+    fn license_file_encodings() {
+        assert_eq!("Copyright © 2024\r\nMIT", decode_text(b"\xEF\xBB\xBFCopyright \xC2\xA9 2024\r\nMIT"));
+        assert_eq!("Copyright © 2024", decode_text(b"Copyright \xA9 2024"));
+        assert_eq!("MIT ©", decode_text(b"\xFF\xFEM\0I\0T\0 \0\xA9\0"));
+
+        let text = "Internal header\r\nrevision 7\r\n---\r\nMIT License\r\n\r\nPermission\r\n";
+        assert_eq!(vec!["MIT License", "", "Permission"], license_lines(text, 0, Some("---")).unwrap().collect::<Vec<_>>());
+        assert_eq!(vec!["Permission"], license_lines(text, 5, None).unwrap().collect::<Vec<_>>());
+        assert!(license_lines(text, 0, Some("===")).is_none());
+    }
+
     #[test]
     fn add_systemd_assets_with_no_config_does_nothing() {
         let mut mock_listener = crate::listener::MockListener::new();
@@ -1197,6 +2086,272 @@ mod tests {
         assert_eq!(1, num_unit_assets);
     }
 
+    #[test]
+    fn install_prefix_wrappers() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (_config, mut package_deb) = test_config(&mock_listener);
+        package_deb.install_prefix = Some("opt/vendor/cargo-deb".into());
+        package_deb.assets = Assets::with_resolved_assets(vec![
+            Asset::new(AssetSource::Path("target/release/cargo-deb".into()), "usr/bin/cargo-deb".into(), 0o755, IsBuilt::SamePackage, false),
+            Asset::new(AssetSource::Path("README.md".into()), "usr/share/doc/cargo-deb/README".into(), 0o644, IsBuilt::No, false),
+        ]);
+        package_deb.apply_install_prefix();
+
+        let targets: Vec<_> = package_deb.assets.resolved.iter().map(|a| a.c.target_path.to_str().unwrap()).collect();
+        assert_eq!(targets, ["opt/vendor/cargo-deb/bin/cargo-deb", "usr/share/doc/cargo-deb/README", "usr/bin/cargo-deb"]);
+        let AssetSource::Data(wrapper) = &package_deb.assets.resolved[2].source else { panic!() };
+        assert_eq!(std::str::from_utf8(wrapper).unwrap(), "#!/bin/sh\nexec /opt/vendor/cargo-deb/bin/cargo-deb \"$@\"\n");
+    }
+
+    #[test]
+    fn config_defaults_are_not_conffiles() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (_config, mut package_deb) = test_config(&mock_listener);
+        package_deb.config_defaults = vec![ConfigDefaultConfig { path: "/etc/app/app.toml".into(), update: crate::parse::manifest::ConfigUpdate::Always }];
+        package_deb.assets = Assets::with_resolved_assets(vec![
+            Asset::new(AssetSource::Path("app.toml".into()), "etc/app/app.toml".into(), 0o644, IsBuilt::No, false),
+            Asset::new(AssetSource::Path("other.toml".into()), "etc/app/other.toml".into(), 0o644, IsBuilt::No, false),
+        ]);
+        package_deb.apply_config_defaults().unwrap();
+        package_deb.add_conf_files().unwrap();
+
+        assert_eq!(package_deb.assets.resolved[0].c.target_path, Path::new("usr/share/cargo-deb/defaults/etc/app/app.toml"));
+        assert_eq!(package_deb.conf_files, ["/etc/app/other.toml"]);
+        let (postinst, postrm) = config_defaults::generate_scripts(&package_deb).unwrap();
+        assert!(postinst.contains("\tmkdir -p /etc/app\n\tcp -p /usr/share/cargo-deb/defaults/etc/app/app.toml /etc/app/app.toml\n"));
+        assert!(postrm.contains("rm -f /etc/app/app.toml /etc/app/app.toml.dpkg-old"));
+
+        package_deb.config_defaults[0].path = "/etc/app/missing.toml".into();
+        assert!(matches!(package_deb.apply_config_defaults(), Err(CargoDebError::ConfigDefaultNotPackaged(_))));
+    }
+
+    #[test]
+    fn bin_assets() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (config, mut package_deb) = test_config(&mock_listener);
+        let asset: RawAsset = toml::from_str(r#"bin = "cargo-deb"
+dest = "usr/libexec/""#).unwrap();
+        assert_eq!(0o755, asset.chmod);
+        package_deb.raw_assets = Some(vec![asset]);
+        config.prepare_assets_before_build(&mut package_deb).unwrap();
+        assert!(package_deb.assets.unresolved[0].source_path.ends_with(format!("cargo-deb{EXE_SUFFIX}")));
+        assert!(package_deb.assets.unresolved[0].c.is_built());
+
+        assert!(matches!(config.workspace_bin_name("helper"), Err(CargoDebError::BinNotInWorkspace(..))));
+        assert!(toml::from_str::<RawAsset>(r#"dest = "usr/bin/""#).is_err());
+    }
+
+    #[test]
+    fn conflicting_assets() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        let (_, mut package_deb) = test_config(&mock_listener);
+        let asset = |source: &str, target: &str| Asset::new(AssetSource::Path(source.into()), target.into(), 0o644, IsBuilt::No, false);
+
+        package_deb.assets.resolved = vec![asset("README.md", "usr/share/doc/a/"), asset("LICENSE", "usr/share/doc/a/LICENSE"), asset("README.md", "./usr/share/doc/a/README.md")];
+        package_deb.check_conflicting_assets().unwrap();
+        assert_eq!(2, package_deb.assets.resolved.len());
+
+        package_deb.assets.resolved.push(asset("docs/README.md", "usr/share/doc/a/README.md"));
+        let err = package_deb.check_conflicting_assets().unwrap_err().to_string();
+        assert!(err.contains("README.md and docs/README.md"), "{err}");
+
+        package_deb.assets.resolved = vec![asset("a", "usr/lib/a"), asset("b", "usr/lib/a/b")];
+        assert!(matches!(package_deb.check_conflicting_assets(), Err(CargoDebError::AssetDirConflict(..))));
+    }
+
+    #[test]
+    fn version_placeholders_in_assets() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let overrides = DebConfigOverrides { deb_version: Some("2:1.2.3-4".into()), ..Default::default() };
+        let (config, mut package_deb) = Config::from_manifest(Some(Path::new("Cargo.toml")), None, None, None, None, overrides, None, None, None, CargoLockingFlags::default(), &mock_listener).unwrap();
+        package_deb.raw_assets = Some(vec![RawAsset {
+            source_path: "README.md".into(),
+            target_path: "usr/lib/app-{version}/README-{revision}".into(),
+            chmod: 0o644,
+            transforms: Vec::new(),
+            bin: None,
+        }]);
+        config.prepare_assets_before_build(&mut package_deb).unwrap();
+        assert_eq!(package_deb.assets.unresolved[0].c.target_path, Path::new("usr/lib/app-1.2.3/README-4"));
+
+        package_deb.deb_version = "1.0".into();
+        assert_eq!(package_deb.expand_version_placeholders(Path::new("app-{version}{revision}")), Path::new("app-1.0"));
+    }
+
+    #[test]
+    fn assets_listed_before_build() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (_config, mut package_deb) = test_config(&mock_listener);
+        package_deb.assets = Assets::new();
+        package_deb.assets.unresolved = vec![
+            UnresolvedAsset::new("target/not-built-yet/app".into(), "usr/bin/app".into(), 0o755, IsBuilt::SamePackage, false),
+            UnresolvedAsset::new("README.md".into(), "usr/share/doc/app/README".into(), 0o644, IsBuilt::No, false),
+        ];
+        package_deb.resolve_assets_without_build().unwrap();
+        let targets = package_deb.assets.resolved.iter().map(|a| a.c.target_path.to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(["usr/bin/app", "usr/share/doc/app/README"], targets.as_slice());
+
+        // files that aren't built by Cargo still have to exist
+        package_deb.assets.unresolved = vec![UnresolvedAsset::new("missing.txt".into(), "usr/share/app/missing.txt".into(), 0o644, IsBuilt::No, false)];
+        assert!(package_deb.resolve_assets_without_build().is_err());
+    }
+
+    #[test]
+    fn conf_files_by_source_or_target_path() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (_config, mut package_deb) = test_config(&mock_listener);
+        package_deb.assets = Assets::with_resolved_assets(vec![
+            Asset::new(AssetSource::Path("/src/config/app.toml".into()), "opt/app/app.toml".into(), 0o644, IsBuilt::No, false),
+            Asset::new(AssetSource::Path("/src/config/other.toml".into()), "var/lib/app/other.toml".into(), 0o644, IsBuilt::No, false),
+            Asset::new(AssetSource::Path("/src/config/default".into()), "etc/default/app".into(), 0o644, IsBuilt::No, false),
+        ]);
+        package_deb.conf_files = vec!["config/app.toml".into(), "/opt/app/app.toml".into(), "var/lib/app/other.toml".into(), "/etc/default/app".into()];
+        package_deb.add_conf_files().unwrap();
+        assert_eq!(package_deb.conf_files, ["/opt/app/app.toml", "/var/lib/app/other.toml", "/etc/default/app"]);
+
+        package_deb.conf_files = vec!["/config/app.toml".into()];
+        assert!(matches!(package_deb.add_conf_files(), Err(CargoDebError::ConfFileNotPackaged(_))));
+    }
+
+    #[test]
+    fn parse_asset_transforms() {
+        let asset: RawAsset = toml::from_str(r#"source = "target/release/foo"
+dest = "usr/bin/"
+mode = "755"
+transform = ["compress-debug-sections", "upx"]"#).unwrap();
+        assert_eq!(asset.transforms, [AssetTransform::CompressDebugSections, AssetTransform::Upx]);
+
+        let asset: RawAsset = toml::from_str(r#"source = "README.md"
+dest = "usr/share/doc/foo/"
+mode = "644""#).unwrap();
+        assert!(asset.transforms.is_empty());
+
+        let asset: RawAsset = toml::from_str(r#"source = "foo.sh"
+dest = "usr/bin/foo"
+mode = "755"
+template = true
+transform = ["upx"]"#).unwrap();
+        assert_eq!(asset.transforms, [AssetTransform::Template, AssetTransform::Upx]);
+
+        assert!(toml::from_str::<RawAsset>(r#"source = "target/release/foo"
+dest = "usr/bin/"
+mode = "755"
+transform = ["zip"]"#).is_err());
+    }
+
+    #[test]
+    fn symbolic_modes() {
+        assert_eq!(Some(0o755), parse_symbolic_mode("rwxr-xr-x"));
+        assert_eq!(Some(0o644), parse_symbolic_mode("-rw-r--r--"));
+        assert_eq!(Some(0o4755), parse_symbolic_mode("rwsr-xr-x"));
+        assert_eq!(Some(0o1777), parse_symbolic_mode("rwxrwxrwt"));
+        assert_eq!(Some(0o755), parse_symbolic_mode("u=rwx,go=rx"));
+        assert_eq!(Some(0o644), parse_symbolic_mode("a=r,u+w"));
+        assert_eq!(Some(0o640), parse_symbolic_mode("ug=rw,g-w,g+r"));
+        assert_eq!(None, parse_symbolic_mode("rwxr-xr-q"));
+        assert_eq!(None, parse_symbolic_mode("u=rwz"));
+        assert_eq!(None, parse_symbolic_mode("rwx"));
+        let asset = toml::from_str::<RawAsset>(r#"source = "README.md"
+dest = "usr/share/doc/foo/"
+mode = "u=rw,go=r""#).unwrap();
+        assert_eq!(0o644, asset.chmod);
+    }
+
+    #[test]
+    fn section_and_priority_problems() {
+        assert_eq!(None, section_problem("utils"));
+        assert_eq!(None, section_problem("non-free/net"));
+        assert_eq!(Some("section = \"util\": 'util' is not a Debian section (did you mean 'utils'?)".into()), section_problem("util"));
+        assert_eq!(Some("section = \"nonfree/net\": area 'nonfree' is unknown (did you mean 'non-free'?)".into()), section_problem("nonfree/net"));
+        assert!(CATEGORY_SECTIONS.iter().all(|(_, section)| section_problem(section).is_none()));
+
+        let categories = |c: &[&str]| c.iter().map(|&c| c.to_owned()).collect::<Vec<_>>();
+        assert_eq!(Some(("utils", "command-line-utilities")), section_from_categories(&categories(&["no-std", "command-line-utilities", "network-programming"])));
+        assert_eq!(Some(("sound", "multimedia::audio")), section_from_categories(&categories(&["multimedia::audio"])));
+        assert_eq!(Some(("web", "web-programming::websocket")), section_from_categories(&categories(&["web-programming::websocket"])));
+        assert_eq!(None, section_from_categories(&categories(&["no-std", "multimedia"])));
+        assert_eq!(None, priority_problem("optional"));
+        assert!(priority_problem("extra").unwrap().contains("deprecated"));
+        assert!(priority_problem("optinal").unwrap().contains("did you mean 'optional'"));
+    }
+
+    #[test]
+    fn readme_summaries() {
+        let readme = "# Foo\n\n[![CI](https://ci/badge.svg)](https://ci)\n<img src=\"logo.png\">\n\n```sh\ncargo install foo\n```\n\nA **fast** [foo](https://foo) for\n`bar` files. It also does baz.\n\nMore.\n";
+        assert_eq!(Some("A fast foo for bar files".into()), readme_summary(readme));
+        assert_eq!(Some("Does foo".into()), readme_summary("Foo\n===\nDoes foo.\n"));
+        assert_eq!(None, readme_summary("# Foo\n\n- list\n"));
+        assert_eq!(None, readme_summary(&"long ".repeat(20)));
+    }
+
+    #[test]
+    fn description_fallback_template() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        let manifest = b"[package]\nname = \"foo\"\nversion = \"1.2.3\"\nlicense = \"MIT\"\nauthors = [\"Foo <foo@example.com>\"]\n";
+        let package = cargo_toml::Manifest::<CargoPackageMetadata>::from_slice_with_metadata(manifest).unwrap().package.unwrap();
+
+        let deb = CargoDeb { description_fallback: Some("{{package}} {{version}} by {{maintainer}}".into()), ..Default::default() };
+        let package_deb = PackageConfig::new(deb, &mut package.clone(), &mock_listener, 0, DebConfigOverrides::default(), None).unwrap();
+        assert_eq!("foo 1.2.3-1 by Foo <foo@example.com>", package_deb.description);
+
+        let deb = CargoDeb { description_fallback: Some("{{name}}".into()), ..Default::default() };
+        let err = PackageConfig::new(deb, &mut package.clone(), &mock_listener, 0, DebConfigOverrides::default(), None).unwrap_err();
+        assert!(matches!(err, CargoDebError::DescriptionTemplate(_)), "{err}");
+    }
+
+    #[test]
+    fn unusual_modes() {
+        assert_eq!(None, unusual_mode_problem(0o644));
+        assert_eq!(None, unusual_mode_problem(0o755));
+        assert_eq!(None, unusual_mode_problem(0o750));
+        assert_eq!(None, unusual_mode_problem(0o400));
+        assert!(unusual_mode_problem(0o777).is_some());
+        assert!(unusual_mode_problem(0o666).is_some());
+        assert!(unusual_mode_problem(0o75).is_some());
+        assert!(unusual_mode_problem(0o711).is_some());
+        assert!(unusual_mode_problem(0o4755).is_some());
+        assert!(toml::from_str::<RawAsset>(r#"source = "README.md"
+dest = "usr/share/doc/foo/"
+mode = "10644""#).is_err());
+    }
+
+    #[test]
+    fn reject_target_path_traversal() {
+        let err = toml::from_str::<RawAsset>(r#"source = "README.md"
+dest = "usr/share/../../../etc/passwd"
+mode = "644""#).unwrap_err();
+        assert!(err.to_string().contains("outside of the package"), "{err}");
+        assert!(toml::from_str::<RawAsset>(r#"source = "README.md"
+dest = "/usr/share/doc/./foo/"
+mode = "644""#).is_ok());
+    }
+
+    #[test]
+    fn relation_fields_wrap_at_80_columns() {
+        let mut out = Vec::new();
+        write_relation_field(&mut out, "Depends", "libc6 (>= 2.34), libssl3").unwrap();
+        let long = (1..=8).map(|n| format!("libfoo{n} (>= 1.0)")).collect::<Vec<_>>().join(", ");
+        write_relation_field(&mut out, "Provides", &long).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("Depends: libc6 (>= 2.34), libssl3\nProvides: libfoo1 (>= 1.0),\n libfoo2 (>= 1.0),\n"));
+        assert!(out.ends_with(",\n libfoo8 (>= 1.0)\n"));
+        assert!(out.lines().all(|line| line.len() <= 80));
+    }
+
     #[test]
     fn format_conffiles_empty() {
         let actual = format_conffiles::<String>(&[]);
@@ -0,0 +1,43 @@
+//! Config files that are managed by the package instead of dpkg's conffile handling, which asks the user what to do
+//! when both the package and the local copy have changed. The package installs them as defaults in
+//! `usr/share/<package>/defaults/`, and `postinst` copies them to their real location.
+use crate::config::PackageConfig;
+use crate::parse::manifest::ConfigUpdate;
+use crate::util::shell_quote;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Where the default version of the file at `path` (relative to `/`) is installed
+pub(crate) fn defaults_path(deb_name: &str, path: &Path) -> PathBuf {
+    Path::new("usr/share").join(deb_name).join("defaults").join(path)
+}
+
+/// Shell code for `postinst` that copies the defaults, and for `postrm` that deletes the copies on purge
+pub(crate) fn generate_scripts(package_deb: &PackageConfig) -> Option<(String, String)> {
+    if package_deb.config_defaults.is_empty() {
+        return None;
+    }
+
+    let mut postinst = String::from("if [ \"$1\" = \"configure\" ] ; then\n");
+    let mut postrm = String::from("if [ \"$1\" = \"purge\" ] ; then\n");
+    for config in &package_deb.config_defaults {
+        let rel_path = Path::new(config.path.trim_start_matches('/'));
+        let path = format!("/{}", rel_path.display());
+        let default = format!("/{}", defaults_path(&package_deb.deb_name, rel_path).display());
+        let dir = format!("/{}", rel_path.parent().unwrap_or(Path::new("")).display());
+        let (path, default, dir) = (shell_quote(&path), shell_quote(&default), shell_quote(&dir));
+        match config.update {
+            ConfigUpdate::IfAbsent => {
+                let _ = write!(postinst, "\tif [ ! -e {path} ] ; then\n\t\tmkdir -p {dir}\n\t\tcp -p {default} {path}\n\tfi\n");
+            },
+            ConfigUpdate::Always => {
+                // Local changes are kept as a backup, like dpkg does with replaced conffiles
+                let _ = write!(postinst, "\tif [ -e {path} ] && ! cmp -s {default} {path} ; then\n\t\tcp -p {path} {path}.dpkg-old\n\tfi\n\tmkdir -p {dir}\n\tcp -p {default} {path}\n");
+            },
+        }
+        let _ = writeln!(postrm, "\trm -f {path} {path}.dpkg-old");
+    }
+    postinst.push_str("fi\n");
+    postrm.push_str("fi\n");
+    Some((postinst, postrm))
+}
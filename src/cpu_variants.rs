@@ -0,0 +1,181 @@
+//! Packages extra builds of executables optimized for newer CPUs (`-C target-cpu`),
+//! and registers them with `update-alternatives` on machines that support them.
+use crate::assets::AssetSource;
+use crate::config::{Config, PackageConfig};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::preinst_checks::{cpuinfo_flag, rustc_target_features};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the directory for the regular build of the executables
+const BASELINE: &str = "baseline";
+
+/// Builds the package again with `-C target-cpu={cpu}` in a separate target dir
+pub(crate) fn cargo_build(config: &Config, cpu: &str, target: Option<&str>, build_command: &str, build_flags: &[String], verbose: bool, listener: &dyn Listener) -> CDResult<()> {
+    listener.info(format!("Building for target-cpu={cpu}"));
+    let mut cmd = crate::cargo_build_command(config, target, build_command, build_flags, verbose);
+    cmd.arg("--target-dir").arg(cargo_target_dir(config, cpu));
+
+    let rustflags = variant_rustflags(config, cpu, target, &cmd);
+    cmd.env_remove("RUSTFLAGS");
+    cmd.env("CARGO_ENCODED_RUSTFLAGS", rustflags);
+
+    log::debug!("cargo build {:?}", cmd.get_args());
+
+    crate::build_log::run_cargo(&mut cmd, config.quiet_cargo, listener)
+}
+
+/// Flags of the regular build (`rustflags` from the metadata, or the ones Cargo would have used), plus `-C target-cpu`.
+/// Setting them in a variable makes Cargo ignore its config, so the config's flags have to be included.
+fn variant_rustflags(config: &Config, cpu: &str, target: Option<&str>, cmd: &Command) -> String {
+    // Set by cargo_build_command when there are `rustflags` in the metadata
+    let package_rustflags = cmd.get_envs().find(|&(var, _)| var == "CARGO_ENCODED_RUSTFLAGS").and_then(|(_, flags)| flags);
+    let mut flags = match package_rustflags {
+        Some(encoded) => encoded.to_string_lossy().into_owned(),
+        None => crate::configured_rustflags(config, target).join("\x1f"),
+    };
+    if !flags.is_empty() {
+        flags.push('\x1f');
+    }
+    flags.push_str("-C\x1ftarget-cpu=");
+    flags.push_str(cpu);
+    flags
+}
+
+/// `--target-dir` for the build, kept separate to avoid invalidating the regular build
+fn cargo_target_dir(config: &Config, cpu: &str) -> PathBuf {
+    config.target_dir.join("cpu-variants").join(cpu)
+}
+
+/// Where the `cpu` build of the executable is in the package
+fn variant_target_path(deb_name: &str, cpu: &str, target_path: &Path) -> PathBuf {
+    let mut path = Path::new("usr/lib").join(deb_name).join("cpu-variants").join(cpu);
+    path.push(target_path.file_name().unwrap_or_default());
+    path
+}
+
+/// Moves built executables to `usr/lib/<package>/cpu-variants/baseline/`, and adds their
+/// builds for each `cpu-variants` entry next to them. Their original path is left for the
+/// alternatives symlink created in `postinst`.
+pub(crate) fn add_variant_assets(config: &Config, package_deb: &mut PackageConfig, require_built: bool, listener: &dyn Listener) -> CDResult<()> {
+    if package_deb.cpu_variants.is_empty() {
+        return Ok(());
+    }
+
+    let mut variants = Vec::new();
+    for asset in &mut package_deb.assets.resolved {
+        if !asset.c.is_built() || !asset.c.is_executable() || asset.c.is_dynamic_library() {
+            continue;
+        }
+        let AssetSource::Path(built_path) = &asset.source else { continue };
+        let Ok(rel_path) = built_path.strip_prefix(&config.target_dir) else {
+            listener.warning(format!("{} is not in the target dir, so it has no cpu-variants builds", built_path.display()));
+            continue;
+        };
+
+        for cpu in &package_deb.cpu_variants {
+            let mut source_path = cargo_target_dir(config, cpu);
+            if let Some(target) = &config.target {
+                source_path.push(target);
+            }
+            source_path.push(rel_path);
+            if require_built && !source_path.exists() {
+                return Err(CargoDebError::AssetFileNotFound(source_path));
+            }
+
+            let mut variant = asset.clone();
+            variant.c.target_path = variant_target_path(&package_deb.deb_name, cpu, &asset.c.target_path);
+            variant.source = AssetSource::Path(source_path);
+            variants.push(variant);
+        }
+
+        let baseline_path = variant_target_path(&package_deb.deb_name, BASELINE, &asset.c.target_path);
+        package_deb.cpu_variant_links.push(std::mem::replace(&mut asset.c.target_path, baseline_path));
+    }
+    package_deb.assets.resolved.append(&mut variants);
+    Ok(())
+}
+
+/// Shell code for `postinst` and `prerm` that select the best executables using `update-alternatives`
+pub(crate) fn generate_scripts(package_deb: &PackageConfig, target: Option<&str>, listener: &dyn Listener) -> CDResult<Option<(String, String)>> {
+    if package_deb.cpu_variant_links.is_empty() {
+        return Ok(None);
+    }
+
+    let arch = target.unwrap_or(crate::DEFAULT_TARGET).split('-').next().unwrap_or_default();
+    let baseline_features = rustc_target_features(target, &[])?;
+    let mut cpu_flags = Vec::with_capacity(package_deb.cpu_variants.len());
+    for cpu in &package_deb.cpu_variants {
+        let features = rustc_target_features(target, &["-C".into(), format!("target-cpu={cpu}")])?;
+        let flags = features.iter()
+            .filter(|f| !baseline_features.contains(*f))
+            .filter_map(|f| cpuinfo_flag(arch, f))
+            .collect::<Vec<_>>();
+        if flags.is_empty() {
+            listener.warning(format!("target-cpu={cpu} has no features that can be checked on {arch}. It will be used on every CPU"));
+        }
+        cpu_flags.push(flags.join(" "));
+    }
+
+    let mut postinst = String::from(concat!(
+        "if [ \"$1\" = \"configure\" ] ; then\n",
+        "\tcpu_flags=$(grep -m1 -E '^(flags|Features)' /proc/cpuinfo 2>/dev/null | cut -d: -f2)\n",
+        "\tcpu_supports() {\n",
+        "\t\t[ -n \"$cpu_flags\" ] || return 1\n",
+        "\t\tfor flag in \"$@\" ; do\n",
+        "\t\t\tcase \" $cpu_flags \" in\n",
+        "\t\t\t\t*\" $flag \"*) ;;\n",
+        "\t\t\t\t*) return 1 ;;\n",
+        "\t\t\tesac\n",
+        "\t\tdone\n",
+        "\t}\n",
+    ));
+    let mut prerm = String::from("if [ \"$1\" = \"remove\" ] || [ \"$1\" = \"deconfigure\" ] ; then\n");
+
+    for link in &package_deb.cpu_variant_links {
+        let name = link.file_name().unwrap_or_default().to_string_lossy();
+        let baseline_path = variant_target_path(&package_deb.deb_name, BASELINE, link);
+        let _ = writeln!(postinst, "\tupdate-alternatives --install /{} {name} /{} 10", link.display(), baseline_path.display());
+        for (i, (cpu, flags)) in package_deb.cpu_variants.iter().zip(&cpu_flags).enumerate() {
+            let variant_path = variant_target_path(&package_deb.deb_name, cpu, link);
+            let _ = write!(postinst, concat!(
+                "\tif cpu_supports {flags} ; then\n",
+                "\t\tupdate-alternatives --install /{link} {name} /{path} {priority}\n",
+                "\telse\n",
+                "\t\tupdate-alternatives --remove {name} /{path} >/dev/null 2>&1 || true\n",
+                "\tfi\n",
+            ), flags = flags, link = link.display(), name = name, path = variant_path.display(), priority = 10 * (i + 2));
+        }
+        let _ = writeln!(prerm, "\tupdate-alternatives --remove-all {name} >/dev/null 2>&1 || true");
+    }
+    postinst.push_str("fi\n");
+    prerm.push_str("fi\n");
+    Ok(Some((postinst, prerm)))
+}
+
+#[test]
+fn variant_paths() {
+    assert_eq!(Path::new("usr/lib/foo/cpu-variants/x86-64-v3/foo-cli"), variant_target_path("foo", "x86-64-v3", Path::new("usr/bin/foo-cli")));
+}
+
+#[test]
+fn variant_rustflags_include_cargo_config() {
+    let mut mock_listener = crate::listener::MockListener::new();
+    mock_listener.expect_info().return_const(());
+    let (mut config, _) = Config::from_manifest(Some(Path::new("Cargo.toml")), None, None, None, None, crate::config::DebConfigOverrides::default(), None, None, None, crate::CargoLockingFlags::default(), &mock_listener).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join(".cargo")).unwrap();
+    std::fs::write(dir.path().join(".cargo/config.toml"), "[build]\nrustflags = [\"-C\", \"force-frame-pointers=yes\"]\n").unwrap();
+    config.package_manifest_dir = dir.path().into();
+    config.clean_env = true;
+
+    let cmd = crate::cargo_build_command(&config, None, "build", &[], false);
+    assert_eq!("-C\x1fforce-frame-pointers=yes\x1f-C\x1ftarget-cpu=x86-64-v3", variant_rustflags(&config, "x86-64-v3", None, &cmd));
+
+    config.rustflags = vec!["--cfg=foo".into()];
+    let cmd = crate::cargo_build_command(&config, None, "build", &[], false);
+    assert_eq!("-C\x1fforce-frame-pointers=yes\x1f--cfg=foo\x1f-C\x1ftarget-cpu=x86-64-v3", variant_rustflags(&config, "x86-64-v3", None, &cmd));
+}
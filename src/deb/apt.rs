@@ -0,0 +1,249 @@
+//! Checks the dependencies of the generated `control` file against the `Packages` index of an APT repository,
+//! so that packages that can't be installed on the target distro are caught before they're deployed.
+use crate::deb::parity::{field, parse_paragraphs, Paragraph};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use flate2::read::GzDecoder;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Fields that must be satisfiable for the package to be installed
+const CHECKED_FIELDS: &[&str] = &["Pre-Depends", "Depends"];
+
+/// Versions of packages available in the snapshot. Virtual packages have `None` for unversioned `Provides`.
+#[derive(Default)]
+struct Index {
+    packages: HashMap<String, Vec<Option<String>>>,
+}
+
+impl Index {
+    fn add(&mut self, name: &str, version: Option<&str>) {
+        self.packages.entry(name.to_owned()).or_default().push(version.map(str::to_owned));
+    }
+
+    /// Adds packages of the `Packages` file that can be installed on `arch`
+    fn add_packages_file(&mut self, text: &str, arch: &str) {
+        for paragraph in parse_paragraphs(text) {
+            let (Some(name), Some(version)) = (field(&paragraph, "Package"), field(&paragraph, "Version")) else { continue };
+            let package_arch = field(&paragraph, "Architecture").unwrap_or("all");
+            if package_arch != arch && package_arch != "all" && arch != "all" {
+                continue;
+            }
+            self.add(name, Some(version));
+            for provided in field(&paragraph, "Provides").unwrap_or_default().split(',').filter_map(parse_relation) {
+                self.add(provided.name, provided.constraint.filter(|(op, _)| *op == "=").map(|(_, v)| v));
+            }
+        }
+    }
+
+    fn satisfies(&self, relation: &Relation<'_>) -> bool {
+        let Some(versions) = self.packages.get(relation.name) else { return false };
+        match relation.constraint {
+            None => true,
+            Some((op, required)) => versions.iter().flatten().any(|version| {
+                let ord = compare_versions(version, required);
+                match op {
+                    "<<" => ord == Ordering::Less,
+                    "<=" | "<" => ord != Ordering::Greater,
+                    "=" => ord == Ordering::Equal,
+                    ">=" | ">" => ord != Ordering::Less,
+                    ">>" => ord == Ordering::Greater,
+                    _ => false,
+                }
+            }),
+        }
+    }
+
+    /// Versions of the package, for the error message
+    fn available_versions(&self, name: &str) -> String {
+        let mut versions = self.packages.get(name).into_iter().flatten().flatten().map(String::as_str).collect::<Vec<_>>();
+        versions.sort_by(|a, b| compare_versions(a, b));
+        versions.dedup();
+        versions.join(", ")
+    }
+}
+
+/// One alternative of a dependency, e.g. `libc6 (>= 2.34)`
+pub(crate) struct Relation<'a> {
+    pub name: &'a str,
+    constraint: Option<(&'a str, &'a str)>,
+}
+
+pub(crate) fn parse_relation(alternative: &str) -> Option<Relation<'_>> {
+    let (name, constraint) = match alternative.split_once('(') {
+        Some((name, rest)) => {
+            let rest = rest.split(')').next().unwrap_or_default().trim();
+            let op_len = rest.find(|c: char| !matches!(c, '<' | '=' | '>')).unwrap_or(rest.len());
+            (name, Some((&rest[..op_len], rest[op_len..].trim())))
+        },
+        None => (alternative, None),
+    };
+    // Architecture restrictions and build profiles don't matter for installation, and neither do `:any` qualifiers
+    let name = name.split(['[', '<', ':']).next().unwrap_or_default().trim();
+    if name.is_empty() || name.starts_with("${") {
+        return None;
+    }
+    Some(Relation { name, constraint })
+}
+
+/// Compares Debian version strings like `dpkg --compare-versions`
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(version: &str) -> (u64, &str, &str) {
+        let (epoch, rest) = match version.split_once(':') {
+            Some((epoch, rest)) if epoch.bytes().all(|c| c.is_ascii_digit()) => (epoch.parse().unwrap_or(0), rest),
+            _ => (0, version),
+        };
+        let (upstream, revision) = rest.rsplit_once('-').unwrap_or((rest, ""));
+        (epoch, upstream, revision)
+    }
+    let (a_epoch, a_upstream, a_revision) = split(a);
+    let (b_epoch, b_upstream, b_revision) = split(b);
+    a_epoch.cmp(&b_epoch)
+        .then_with(|| compare_version_part(a_upstream.as_bytes(), b_upstream.as_bytes()))
+        .then_with(|| compare_version_part(a_revision.as_bytes(), b_revision.as_bytes()))
+}
+
+/// `verrevcmp` from dpkg. Letters sort before non-letters, and `~` before anything, even the end of the string.
+fn compare_version_part(mut a: &[u8], mut b: &[u8]) -> Ordering {
+    fn order(c: Option<&u8>) -> i32 {
+        match c {
+            None => 0,
+            Some(c) if c.is_ascii_digit() => 0,
+            Some(c) if c.is_ascii_alphabetic() => i32::from(*c),
+            Some(b'~') => -1,
+            Some(c) => i32::from(*c) + 256,
+        }
+    }
+    let is_digit = |s: &[u8]| s.first().is_some_and(u8::is_ascii_digit);
+
+    while !a.is_empty() || !b.is_empty() {
+        while (!a.is_empty() && !is_digit(a)) || (!b.is_empty() && !is_digit(b)) {
+            let (ac, bc) = (order(a.first()), order(b.first()));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            a = a.get(1..).unwrap_or_default();
+            b = b.get(1..).unwrap_or_default();
+        }
+        let a_len = a.iter().take_while(|c| c.is_ascii_digit()).count();
+        let b_len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (a_num, b_num) = (&a[..a_len], &b[..b_len]);
+        let (a_num, b_num) = (trim_zeros(a_num), trim_zeros(b_num));
+        let ord = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num));
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        a = &a[a_len..];
+        b = &b[b_len..];
+    }
+    Ordering::Equal
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&c| c == b'0').count();
+    &digits[zeros..]
+}
+
+/// `Packages` files, optionally compressed. A directory, like `/var/lib/apt/lists`, is searched for `*_Packages` files.
+fn packages_files(path: &Path) -> CDResult<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut files = fs::read_dir(path).map_err(|e| CargoDebError::IoFile("unable to read APT lists", e, path.into()))?
+        .filter_map(|e| Some(e.ok()?.path()))
+        .filter(|p| p.file_name().and_then(|f| f.to_str()).is_some_and(|f| ["Packages", "Packages.gz", "Packages.xz", "Packages.lz4"].iter().any(|ext| f.ends_with(ext))))
+        .collect::<Vec<_>>();
+    files.sort();
+    if files.is_empty() {
+        return Err(CargoDebError::IoFile("no Packages files found", std::io::ErrorKind::NotFound.into(), path.into()));
+    }
+    Ok(files)
+}
+
+fn read_packages_file(path: &Path, listener: &dyn Listener) -> CDResult<String> {
+    let data = fs::read(path).map_err(|e| CargoDebError::IoFile("unable to read the Packages index", e, path.into()))?;
+    let mut text = String::new();
+    let res = match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => GzDecoder::new(&data[..]).read_to_string(&mut text),
+        #[cfg(feature = "lzma")]
+        Some("xz") => xz2::read::XzDecoder::new(&data[..]).read_to_string(&mut text),
+        #[cfg(not(feature = "lzma"))]
+        Some("xz") => return Err(CargoDebError::Str("reading Packages.xz requires the lzma feature")),
+        // APT in Docker images keeps the lists compressed with lz4, which only has a command-line tool
+        Some("lz4") => {
+            let output = crate::build_log::run_checked_output(Command::new("lz4").arg("-dc").arg(path), listener)?;
+            return Ok(String::from_utf8_lossy(&output).into_owned());
+        },
+        _ => return Ok(String::from_utf8_lossy(&data).into_owned()),
+    };
+    res.map_err(|e| CargoDebError::IoFile("unable to decompress the Packages index", e, path.into()))?;
+    Ok(text)
+}
+
+/// Returns the dependencies of the generated `control` that can't be satisfied by packages in the `Packages` indexes
+/// (or directories of APT lists) at `paths`.
+pub fn check_dependencies(generated_control: &str, paths: &[PathBuf], listener: &dyn Listener) -> CDResult<Vec<String>> {
+    let control = parse_paragraphs(generated_control).into_iter().next().unwrap_or_default();
+    let arch = field(&control, "Architecture").unwrap_or("all");
+    let mut index = Index::default();
+    for path in paths {
+        for file in packages_files(path)? {
+            index.add_packages_file(&read_packages_file(&file, listener)?, arch);
+        }
+    }
+    Ok(unsatisfied(&control, &index))
+}
+
+fn unsatisfied(control: &Paragraph, index: &Index) -> Vec<String> {
+    let mut problems = Vec::new();
+    for &field_name in CHECKED_FIELDS {
+        for dependency in field(control, field_name).unwrap_or_default().split(',') {
+            let alternatives = dependency.split('|').filter_map(parse_relation).collect::<Vec<_>>();
+            if alternatives.is_empty() || alternatives.iter().any(|rel| index.satisfies(rel)) {
+                continue;
+            }
+            let available = alternatives.iter().map(|rel| match index.available_versions(rel.name) {
+                v if v.is_empty() && index.packages.contains_key(rel.name) => format!("{} is only provided without a version", rel.name),
+                v if v.is_empty() => format!("{} doesn't exist", rel.name),
+                v => format!("{} has {v}", rel.name),
+            }).collect::<Vec<_>>();
+            problems.push(format!("{field_name}: {} can't be satisfied ({})", dependency.trim(), available.join("; ")));
+        }
+    }
+    problems
+}
+
+#[test]
+fn debian_version_order() {
+    let ordered = ["0.9", "1.0~rc1", "1.0", "1.0-1", "1.0-1ubuntu1", "1.0-2", "1.0a", "1.0+b1", "1.00.1", "1.2", "1.10", "1:0.1"];
+    for pair in ordered.windows(2) {
+        assert_eq!(Ordering::Less, compare_versions(pair[0], pair[1]), "{} < {}", pair[0], pair[1]);
+        assert_eq!(Ordering::Greater, compare_versions(pair[1], pair[0]), "{} > {}", pair[1], pair[0]);
+    }
+    assert_eq!(Ordering::Equal, compare_versions("1.01", "1.1"));
+    assert_eq!(Ordering::Equal, compare_versions("0:2.3-1", "2.3-1"));
+}
+
+#[test]
+fn dependencies_in_snapshot() {
+    let packages = "Package: libc6\nVersion: 2.36-9+deb12u4\nArchitecture: amd64\n\n\
+        Package: libc6\nVersion: 2.38-1\nArchitecture: arm64\n\n\
+        Package: mawk\nVersion: 1.3.4-1\nArchitecture: amd64\nProvides: awk\n\n\
+        Package: ca-certificates\nVersion: 20230311\nArchitecture: all\n\n\
+        Package: libssl3t64\nVersion: 3.2.1-3\nArchitecture: amd64\nProvides: libssl3 (= 3.2.1-3)\n";
+    let mut index = Index::default();
+    index.add_packages_file(packages, "amd64");
+
+    let control = parse_paragraphs("Package: foo\nArchitecture: amd64\nPre-Depends: ${misc:Pre-Depends}\n\
+        Depends: libc6 (>= 2.34), awk, ca-certificates, libssl3 (>= 3.0), libc6 (>= 2.38), awk (>= 1), gawk | nawk [amd64], libc6 (<< 3) <!nocheck>\n")
+        .into_iter().next().unwrap();
+    assert_eq!(unsatisfied(&control, &index), [
+        "Depends: libc6 (>= 2.38) can't be satisfied (libc6 has 2.36-9+deb12u4)",
+        "Depends: awk (>= 1) can't be satisfied (awk is only provided without a version)",
+        "Depends: gawk | nawk [amd64] can't be satisfied (gawk doesn't exist; nawk doesn't exist)",
+    ]);
+}
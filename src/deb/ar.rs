@@ -1,21 +1,32 @@
+use crate::error::CargoDebError;
+use crate::util::atomic_file;
 use crate::util::compress::Compressed;
 use crate::CDResult;
 use ar::{Builder, Header};
 use std::fs;
-use std::fs::File;
 use std::path::PathBuf;
+use tempfile::NamedTempFile;
 
-/// The outermost `ar` archive that contains tarballs inside
+/// Sizes in `ar` headers are 10 decimal digits, and there's no extension for larger members that dpkg would understand
+pub const MAX_MEMBER_SIZE: u64 = 9_999_999_999;
+
+/// Some tools, like 32-bit builds of `ar`, can't read members larger than this
+pub(crate) const LARGE_MEMBER_SIZE: u64 = 1 << 32;
+
+/// The outermost `ar` archive that contains tarballs inside.
+///
+/// It's written to a temporary file, which is renamed to the output path only by `finish()`.
 pub struct DebArchive {
     out_abspath: PathBuf,
-    ar_builder: Builder<File>,
+    ar_builder: Builder<NamedTempFile>,
     mtime_timestamp: u64,
 }
 
 impl DebArchive {
     pub fn new(out_abspath: PathBuf, mtime_timestamp: u64) -> CDResult<Self> {
         let _ = fs::create_dir_all(out_abspath.parent().ok_or("invalid dir")?);
-        let ar_builder = Builder::new(File::create(&out_abspath)?);
+        let file = atomic_file::create(&out_abspath).map_err(|e| CargoDebError::IoFile("unable to create package", e, out_abspath.clone()))?;
+        let ar_builder = Builder::new(file);
 
         let mut ar = DebArchive {
             out_abspath,
@@ -35,6 +46,7 @@ impl DebArchive {
     }
 
     fn add_file(&mut self, dest_path: String, data: &[u8]) -> CDResult<()> {
+        check_member(&dest_path, data.len() as u64)?;
         let mut header = Header::new(dest_path.into(), data.len() as u64);
         header.set_mode(0o100644); // dpkg uses 100644
         header.set_mtime(self.mtime_timestamp);
@@ -45,6 +57,27 @@ impl DebArchive {
     }
 
     pub fn finish(self) -> CDResult<PathBuf> {
+        let file = self.ar_builder.into_inner()?;
+        atomic_file::persist(file, &self.out_abspath).map_err(|e| CargoDebError::IoFile("unable to save package", e, self.out_abspath.clone()))?;
         Ok(self.out_abspath)
     }
 }
+
+/// The `ar` crate would write a BSD long name or an overflowing size field instead, which dpkg can't read
+fn check_member(name: &str, size: u64) -> CDResult<()> {
+    if name.len() > 16 || name.contains(' ') {
+        return Err(CargoDebError::Str("names of .deb archive members can't be longer than 16 bytes"));
+    }
+    if size > MAX_MEMBER_SIZE {
+        return Err(CargoDebError::DebMemberTooLarge(name.into(), size));
+    }
+    Ok(())
+}
+
+#[test]
+fn member_limits() {
+    assert!(check_member("control.tar.xz", 1000).is_ok());
+    assert!(check_member("data.tar.xz", MAX_MEMBER_SIZE).is_ok());
+    assert!(matches!(check_member("data.tar.xz", MAX_MEMBER_SIZE + 1), Err(CargoDebError::DebMemberTooLarge(..))));
+    assert!(check_member("data.tar.very-long-ext", 1000).is_err());
+}
@@ -1,8 +1,9 @@
-use crate::config::{Config, PackageConfig};
+use crate::config::{Config, PackageConfig, MAINTAINER_SCRIPTS};
 use crate::deb::tar::Tarball;
 use crate::dh::{dh_installsystemd, dh_lib};
 use crate::error::{CDResult, CargoDebError};
 use crate::listener::Listener;
+use crate::{config_defaults, cpu_variants, diversions, info_pages, init_scripts, preinst_checks, purge_paths};
 use crate::util::{is_path_file, read_file_to_bytes};
 use dh_lib::ScriptFragments;
 use std::fs;
@@ -24,7 +25,12 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
 
     /// Generates an uncompressed tar archive with `control`, `sha256sums`, and others
     pub fn generate_archive(&mut self, config: &Config, package_deb: &PackageConfig) -> CDResult<()> {
-        self.add_control(&package_deb.generate_control(config)?)?;
+        self.generate_archive_with_control(config, package_deb, &package_deb.generate_control(config)?)
+    }
+
+    /// Same as `generate_archive`, but uses the given contents of the `control` file
+    pub fn generate_archive_with_control(&mut self, config: &Config, package_deb: &PackageConfig, control: &[u8]) -> CDResult<()> {
+        self.add_control(control)?;
 
         if let Some(files) = package_deb.conf_files() {
             self.add_conf_files(&files)?;
@@ -34,6 +40,9 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
         if let Some(rel_path) = &package_deb.triggers_file_rel_path {
             self.add_triggers_file(config, rel_path)?;
         }
+        if let Some(symbols) = &package_deb.symbols_file {
+            self.add_file_with_log("./symbols".as_ref(), symbols.as_bytes(), 0o644, None)?;
+        }
         Ok(())
     }
 
@@ -53,17 +62,74 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
     /// If a shell fragment cannot be inserted because the target script is missing
     /// then the entire script will be generated and appended to the archive.
     ///
+    /// The same applies to the `preinst` checks configured with `preinst_checks`,
+    /// `*-script` inline scripts, `dpkg-divert` calls for `diverts`, copies of `config-defaults`, deletion of `purge-paths`, `install-info` calls for info pages, `update-rc.d` calls for init scripts, and `update-alternatives` calls for `cpu_variants`.
+    ///
     /// # Requirements
     ///
     /// When `systemd_units` is configured, user supplied `maintainer_scripts` must
     /// contain a `#DEBHELPER#` token at the point where shell script fragments
     /// should be inserted.
     fn generate_scripts(&mut self, config: &Config, package_deb: &PackageConfig) -> CDResult<()> {
-        if let Some(ref maintainer_scripts_dir) = package_deb.maintainer_scripts_rel_path {
-            let maintainer_scripts_dir = config.path_in_package(maintainer_scripts_dir);
-            let mut scripts = ScriptFragments::with_capacity(0);
+        let maintainer_scripts_dir = package_deb.maintainer_scripts_rel_path.as_ref().map(|dir| config.path_in_package(dir));
+
+        // Fragments generated by cargo-deb itself, by maintainer script name
+        let mut generated_fragments = Vec::new();
+        if let Some(checks) = &package_deb.preinst_checks {
+            if let Some(fragment) = preinst_checks::generate(checks, package_deb, config.target.as_deref(), self.listener)? {
+                generated_fragments.push(("preinst", fragment));
+            }
+        }
+        if let Some((preinst, postrm)) = diversions::generate_scripts(package_deb) {
+            generated_fragments.push(("preinst", preinst));
+            generated_fragments.push(("postrm", postrm));
+        }
+        if let Some((postinst, postrm)) = config_defaults::generate_scripts(package_deb) {
+            generated_fragments.push(("postinst", postinst));
+            generated_fragments.push(("postrm", postrm));
+        }
+        if let Some((postinst, prerm)) = info_pages::generate_scripts(package_deb) {
+            generated_fragments.push(("postinst", postinst));
+            generated_fragments.push(("prerm", prerm));
+        }
+        if let Some((postinst, prerm, postrm)) = init_scripts::generate_scripts(package_deb) {
+            generated_fragments.push(("postinst", postinst));
+            generated_fragments.push(("prerm", prerm));
+            generated_fragments.push(("postrm", postrm));
+        }
+        if let Some(postrm) = purge_paths::generate_script(package_deb)? {
+            generated_fragments.push(("postrm", postrm));
+        }
+        if let Some((postinst, prerm)) = cpu_variants::generate_scripts(package_deb, config.target.as_deref(), self.listener)? {
+            generated_fragments.push(("postinst", postinst));
+            generated_fragments.push(("prerm", prerm));
+        }
+        // Inline scripts run after the generated code when installing, and before it when removing
+        for (script, code) in &package_deb.inline_scripts {
+            // The generated script already has a shebang
+            let mut fragment = code.strip_prefix("#!").map_or(code.as_str(), |c| c.split_once('\n').map_or("", |(_, rest)| rest)).to_owned();
+            if !fragment.ends_with('\n') {
+                fragment.push('\n');
+            }
+            if matches!(*script, "prerm" | "postrm") {
+                generated_fragments.insert(0, (*script, fragment));
+            } else {
+                generated_fragments.push((*script, fragment));
+            }
+        }
+        let add_generated_fragments = |scripts: &mut ScriptFragments| {
+            for (script, fragment) in &generated_fragments {
+                scripts.entry(format!("{}.{script}.debhelper", package_deb.name)).or_default().extend_from_slice(fragment.as_bytes());
+            }
+        };
 
+        let mut scripts = ScriptFragments::with_capacity(0);
+        if let Some(maintainer_scripts_dir) = &maintainer_scripts_dir {
+            let mut units_applied = false;
             if let Some(systemd_units_config_vec) = &package_deb.systemd_units {
+                if systemd_units_config_vec.iter().any(|c| c.verify == Some(true)) {
+                    dh_installsystemd::verify_units(&package_deb.assets.resolved, self.listener)?;
+                }
                 for systemd_units_config in systemd_units_config_vec {
                     // Select and populate autoscript templates relevant to the unit
                     // file(s) in this package and the configuration settings chosen.
@@ -73,6 +139,7 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
                         &dh_installsystemd::Options::from(systemd_units_config),
                         self.listener,
                     )?;
+                    add_generated_fragments(&mut scripts);
 
                     // Get Option<&str> from Option<String>
                     let unit_name = systemd_units_config.unit_name.as_deref();
@@ -80,39 +147,57 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
                     // Replace the #DEBHELPER# token in the users maintainer scripts
                     // and/or generate maintainer scripts from scratch as needed.
                     dh_lib::apply(
-                        &maintainer_scripts_dir,
+                        maintainer_scripts_dir,
+                        |name| package_deb.uses_maintainer_script(name),
                         &mut scripts,
                         &package_deb.name,
                         unit_name,
                         self.listener,
                     )?;
+                    units_applied = true;
                 }
             }
 
-            // Add maintainer scripts to the archive, either those supplied by the
-            // user or if available prefer modified versions generated above.
-            for name in ["config", "preinst", "postinst", "prerm", "postrm", "templates"] {
-                let script_path;
-                let (contents, source_path) = match scripts.remove(name) {
-                    Some(script) => (script, Some("systemd_units")),
-                    None => {
-                        script_path = maintainer_scripts_dir.join(name);
-                        if !is_path_file(&script_path) {
-                            continue;
-                        }
-                        (read_file_to_bytes(&script_path)?, script_path.to_str())
-                    },
-                };
-
-                // The config, postinst, postrm, preinst, and prerm
-                // control files should use mode 0755; all other control files should use 0644.
-                // See Debian Policy Manual section 10.9
-                // and lintian tag control-file-has-bad-permissions
-                let permissions = if name == "templates" { 0o644 } else { 0o755 };
-                self.add_file_with_log(name.as_ref(), &contents, permissions, source_path)?;
+            if !units_applied && !generated_fragments.is_empty() {
+                add_generated_fragments(&mut scripts);
+                dh_lib::apply(maintainer_scripts_dir, |name| package_deb.uses_maintainer_script(name), &mut scripts, &package_deb.name, None, self.listener)?;
+            }
+        } else {
+            for (script, fragment) in &generated_fragments {
+                self.listener.info(format!("Generating maintainer script {script}"));
+                let text = scripts.entry((*script).to_owned()).or_insert_with(|| b"#!/bin/sh\nset -e\n".to_vec());
+                text.extend_from_slice(fragment.as_bytes());
             }
         }
 
+        // Add maintainer scripts to the archive, either those supplied by the
+        // user or if available prefer modified versions generated above.
+        for &name in MAINTAINER_SCRIPTS {
+            let script_path;
+            let (contents, source_path) = match scripts.remove(name) {
+                Some(script) => (script, Some(if package_deb.systemd_units.is_some() { "systemd_units" } else { "generated" })),
+                None => {
+                    let Some(maintainer_scripts_dir) = &maintainer_scripts_dir else { continue };
+                    script_path = maintainer_scripts_dir.join(name);
+                    if !is_path_file(&script_path) {
+                        continue;
+                    }
+                    if !package_deb.uses_maintainer_script(name) {
+                        self.listener.info(format!("Ignoring {} (not in maintainer-scripts-only)", script_path.display()));
+                        continue;
+                    }
+                    (read_file_to_bytes(&script_path)?, script_path.to_str())
+                },
+            };
+
+            // The config, postinst, postrm, preinst, and prerm
+            // control files should use mode 0755; all other control files should use 0644.
+            // See Debian Policy Manual section 10.9
+            // and lintian tag control-file-has-bad-permissions
+            let permissions = if name == "templates" { 0o644 } else { 0o755 };
+            self.add_file_with_log(name.as_ref(), &contents, permissions, source_path)?;
+        }
+
         Ok(())
     }
 
@@ -266,6 +351,41 @@ mod tests {
         assert!(archived_file_names.is_empty());
     }
 
+    #[test]
+    fn generate_scripts_adds_inline_scripts() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.inline_scripts = vec![("postinst", "#!/bin/sh\necho installed".into()), ("postrm", "echo removed\n".into())];
+
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let mut out_ar = tar::Archive::new(&archive_bytes[..]);
+        let contents = extract_contents(&mut out_ar);
+
+        assert_eq!("#!/bin/sh\nset -e\necho installed\n", contents["postinst"]);
+        assert_eq!("#!/bin/sh\nset -e\necho removed\n", contents["postrm"]);
+        assert_eq!(2, contents.len());
+    }
+
+    #[test]
+    fn generate_scripts_uses_only_selected_maintainer_scripts() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        set_test_fs_path_content("test-resources/testroot/debian/postinst", "#!/bin/sh\necho user\n#DEBHELPER#\n".into());
+        set_test_fs_path_content("test-resources/testroot/debian/prerm", "#!/bin/sh\necho user\n#DEBHELPER#\n".into());
+        package_deb.maintainer_scripts_rel_path = Some(PathBuf::from("debian"));
+        package_deb.maintainer_scripts_only = Some(vec!["postinst".into()]);
+        package_deb.inline_scripts = vec![("postinst", "echo installed\n".into()), ("prerm", "echo removing\n".into())];
+
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let contents = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+
+        assert_eq!("#!/bin/sh\necho user\necho installed\n\n", contents["postinst"]);
+        assert_eq!("#!/bin/sh\nset -e\necho removing\n", contents["prerm"]);
+        assert_eq!(2, contents.len());
+    }
+
     #[test]
     fn generate_scripts_archives_user_supplied_maintainer_scripts_in_root_package() {
         let maintainer_script_paths = vec![
@@ -0,0 +1,167 @@
+//! Compares the generated `control` file with the official packaging's `debian/control`,
+//! for crates that are also packaged in Debian.
+use crate::error::{CDResult, CargoDebError};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Fields of the source package paragraph that apply to the binary packages too
+const INHERITED_FIELDS: &[&str] = &["Section", "Priority", "Maintainer", "Homepage"];
+const SIMPLE_FIELDS: &[&str] = &["Section", "Priority", "Maintainer", "Homepage", "Essential", "Protected", "Important", "Multi-Arch"];
+pub(crate) const RELATION_FIELDS: &[&str] = &["Depends", "Pre-Depends", "Recommends", "Suggests", "Enhances", "Conflicts", "Breaks", "Replaces", "Provides"];
+
+/// Fields of a deb822 paragraph, in order
+pub(crate) type Paragraph = Vec<(String, String)>;
+
+pub(crate) fn field<'a>(paragraph: &'a Paragraph, name: &str) -> Option<&'a str> {
+    paragraph.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+}
+
+/// Reads paragraphs of a `debian/control`-like file. Continuation lines are joined with `\n`.
+pub(crate) fn parse_paragraphs(text: &str) -> Vec<Paragraph> {
+    let mut paragraphs = Vec::new();
+    let mut current = Paragraph::new();
+    for line in text.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+        } else if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = current.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            current.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+    paragraphs
+}
+
+/// Package names in a relationship field, without versions, substvars and arch qualifiers.
+/// Alternatives are kept together as `a | b`.
+fn relation_names(value: &str) -> BTreeSet<String> {
+    value.split(',')
+        .filter_map(|rel| {
+            let alternatives = rel.split('|')
+                .map(|alt| alt.trim())
+                .filter(|alt| !alt.is_empty() && !alt.starts_with("${"))
+                .map(|alt| alt.split(|c: char| c.is_whitespace() || c == '(' || c == '[' || c == '<' || c == ':').next().unwrap_or_default())
+                .collect::<Vec<_>>();
+            (!alternatives.is_empty()).then(|| alternatives.join(" | "))
+        })
+        .collect()
+}
+
+/// Section without the archive area, so that `contrib/utils` is the same as `utils`
+fn section_name(section: &str) -> &str {
+    section.rsplit('/').next().unwrap_or(section)
+}
+
+/// Returns descriptions of differences between the generated `control` and the binary package
+/// of the same name in `debian_control_path`.
+pub fn check_against(generated_control: &str, debian_control_path: &Path) -> CDResult<Vec<String>> {
+    let text = fs::read_to_string(debian_control_path)
+        .map_err(|e| CargoDebError::IoFile("unable to read debian/control", e, debian_control_path.into()))?;
+    Ok(compare(generated_control, &text))
+}
+
+fn compare(generated_control: &str, debian_control: &str) -> Vec<String> {
+    let generated = parse_paragraphs(generated_control).into_iter().next().unwrap_or_default();
+    let paragraphs = parse_paragraphs(debian_control);
+    let source = paragraphs.iter().find(|p| field(p, "Source").is_some());
+    let binaries = paragraphs.iter().filter(|p| field(p, "Package").is_some()).collect::<Vec<_>>();
+
+    let mut divergences = Vec::new();
+    let name = field(&generated, "Package").unwrap_or_default();
+    let official = match binaries.iter().find(|p| field(p, "Package") == Some(name)) {
+        Some(p) => *p,
+        None => match binaries[..] {
+            [only] => {
+                divergences.push(format!("Package is '{}' in debian/control, but '{name}' here", field(only, "Package").unwrap_or_default()));
+                only
+            },
+            _ => {
+                divergences.push(format!("debian/control has no binary package named '{name}'"));
+                return divergences;
+            },
+        },
+    };
+    let official_field = |name: &str| {
+        field(official, name).or_else(|| {
+            INHERITED_FIELDS.iter().any(|f| f.eq_ignore_ascii_case(name))
+                .then(|| source.and_then(|s| field(s, name)))
+                .flatten()
+        })
+    };
+
+    for &name in SIMPLE_FIELDS {
+        let (ours, theirs) = (field(&generated, name), official_field(name));
+        let same = match (ours, theirs) {
+            (Some(a), Some(b)) if name == "Section" => section_name(a) == section_name(b),
+            (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+            (None, None) => true,
+            // dpkg's default
+            (Some("optional"), None) | (None, Some("optional")) => name == "Priority",
+            _ => false,
+        };
+        if !same {
+            divergences.push(format!("{name} is {} in debian/control, but {} here",
+                theirs.map_or("missing".into(), |v| format!("'{v}'")),
+                ours.map_or("missing".into(), |v| format!("'{v}'"))));
+        }
+    }
+
+    let arch = field(&generated, "Architecture").unwrap_or_default();
+    if let Some(official_arch) = official_field("Architecture") {
+        let supported = official_arch.split_whitespace().any(|a| a == arch || a == "any" || a.ends_with("-any"));
+        if !supported {
+            divergences.push(format!("Architecture is '{official_arch}' in debian/control, which doesn't include '{arch}'"));
+        }
+    }
+
+    for &name in RELATION_FIELDS {
+        let ours = field(&generated, name).map(relation_names).unwrap_or_default();
+        let theirs_value = official_field(name).unwrap_or_default();
+        let theirs = relation_names(theirs_value);
+        let missing = theirs.difference(&ours).map(|s| s.as_str()).collect::<Vec<_>>();
+        if !missing.is_empty() {
+            divergences.push(format!("{name} in debian/control has {}, which are missing here", missing.join(", ")));
+        }
+        // Dependencies on libraries are added by substvars, so they're not listed explicitly there
+        if !theirs_value.contains("${shlibs:") {
+            let extra = ours.difference(&theirs).map(|s| s.as_str()).collect::<Vec<_>>();
+            if !extra.is_empty() {
+                divergences.push(format!("{name} has {} here, which are not in debian/control", extra.join(", ")));
+            }
+        }
+    }
+
+    let synopsis = |d: Option<&str>| d.and_then(|d| d.lines().next()).map(str::trim).unwrap_or_default().to_owned();
+    let (ours, theirs) = (synopsis(field(&generated, "Description")), synopsis(official_field("Description")));
+    if ours != theirs {
+        divergences.push(format!("Description is '{theirs}' in debian/control, but '{ours}' here"));
+    }
+    divergences
+}
+
+#[test]
+fn compare_with_debian_control() {
+    let official = "Source: rust-foo\nSection: rust\nPriority: optional\nMaintainer: Debian Rust Maintainers <pkg-rust-maintainers@alioth-lists.debian.net>\n\n\
+        # comment\nPackage: foo\nArchitecture: any\nSection: contrib/utils\nDepends:\n ${misc:Depends},\n ${shlibs:Depends},\n ca-certificates\nRecommends: git | mercurial\nDescription: Does foo\n Long\n";
+    let generated = "Package: foo\nVersion: 1.0-1\nArchitecture: amd64\nSection: utils\nPriority: optional\nMaintainer: Foo <foo@example.com>\nDepends: libc6 (>= 2.34)\nRecommends: git\nDescription: Does foo\n Long\n";
+    assert_eq!(compare(generated, official), [
+        "Maintainer is 'Debian Rust Maintainers <pkg-rust-maintainers@alioth-lists.debian.net>' in debian/control, but 'Foo <foo@example.com>' here",
+        "Depends in debian/control has ca-certificates, which are missing here",
+        "Recommends in debian/control has git | mercurial, which are missing here",
+        "Recommends has git here, which are not in debian/control",
+    ]);
+    assert_eq!(["debian/control has no binary package named 'foo'"], &compare(generated, "Source: foo\n\nPackage: a\n\nPackage: b\n")[..]);
+    assert_eq!(BTreeSet::from(["libc6".to_owned(), "a | b".to_owned()]), relation_names("${misc:Depends}, libc6 (>= 2.3) [amd64], a:any | b <!nocheck>"));
+}
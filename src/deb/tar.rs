@@ -1,12 +1,11 @@
-use crate::assets::AssetSource;
+use crate::assets::{is_target_path_in_package, AssetSource};
 use crate::error::{CDResult, CargoDebError};
 use crate::listener::Listener;
 use crate::PackageConfig;
 use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::{Component, Path, PathBuf};
-use std::sync::mpsc;
 use std::{fs, io};
 use tar::{EntryType, Header as TarHeader};
 
@@ -26,60 +25,57 @@ impl<W: Write> Tarball<W> {
         }
     }
 
-    /// Copies all the files to be packaged into the tar archive.
-    /// Returns MD5 hashes of files copied
+    /// Copies all the files to be packaged into the tar archive, and returns their SHA-256 hashes for `sha256sums`.
     pub fn archive_files(mut self, package_deb: &PackageConfig, rsyncable: bool, listener: &dyn Listener) -> CDResult<(W, HashMap<PathBuf, [u8; 32]>)> {
-        let hashes = std::thread::scope(|s| -> CDResult<_> {
-            let (send, recv) = mpsc::sync_channel(2);
-            let num_items = package_deb.assets.resolved.len();
-            let hash_thread = s.spawn(move || {
-                let mut hashes = HashMap::with_capacity(num_items);
-                hashes.extend(recv.into_iter().map(|(path, data)| {
-                    (path, Sha256::digest(data).into())
-                }));
-                hashes
-            });
-            let mut archive_data_added = 0;
-            let mut prev_is_built = false;
-
-            debug_assert!(package_deb.assets.unresolved.is_empty());
-            for asset in &package_deb.assets.resolved {
-                let mut log_line = format!("{} {}-> {}",
-                    asset.processed_from.as_ref().and_then(|p| p.original_path.as_deref())
-                        .or(asset.source.path())
-                        .unwrap_or_else(|| Path::new("-")).display(),
-                    asset.processed_from.as_ref().map(|p| p.action).unwrap_or_default(),
-                    asset.c.target_path.display()
-                );
-                if let Some(len) = asset.source.file_size() {
-                    let (size, unit) = human_size(len);
-                    use std::fmt::Write;
-                    let _ = write!(&mut log_line, " ({size}{unit})");
-                }
+        let mut hashes = HashMap::with_capacity(package_deb.assets.resolved.len());
+        let mut archive_data_added = 0;
+        let mut prev_is_built = false;
+
+        debug_assert!(package_deb.assets.unresolved.is_empty());
+        for asset in &package_deb.assets.resolved {
+            let mut log_line = format!("{} {}-> {}",
+                asset.processed_from.as_ref().and_then(|p| p.original_path.as_deref())
+                    .or(asset.source.path())
+                    .unwrap_or_else(|| Path::new("-")).display(),
+                asset.processed_from.as_ref().map(|p| p.action).unwrap_or_default(),
+                asset.c.target_path.display()
+            );
+
+            if !is_target_path_in_package(&asset.c.target_path) {
+                return Err(CargoDebError::InvalidAssetTarget(asset.c.target_path.clone()));
+            }
+
+            if let AssetSource::Symlink(source_path) = &asset.source {
+                listener.info(log_line);
+                let link_name = fs::read_link(source_path)
+                    .map_err(|e| CargoDebError::IoFile("symlink asset", e, source_path.clone()))?;
+                self.symlink(&asset.c.target_path, &package_deb.expand_version_placeholders(&link_name))?;
+            } else {
+                let (size, reader) = asset.source.open()?;
+                let (human, unit) = human_size(size);
+                use std::fmt::Write;
+                let _ = write!(&mut log_line, " ({human}{unit})");
                 listener.info(log_line);
 
-                if let AssetSource::Symlink(source_path) = &asset.source {
-                    let link_name = fs::read_link(source_path)
-                        .map_err(|e| CargoDebError::IoFile("symlink asset", e, source_path.clone()))?;
-                    self.symlink(&asset.c.target_path, &link_name)?;
-                } else {
-                    let out_data = asset.source.data()?;
-                    if rsyncable {
-                        if archive_data_added > 1_000_000 || prev_is_built != asset.c.is_built() {
-                            self.flush()?;
-                            archive_data_added = 0;
-                        }
-                        // puts synchronization point between non-code and code assets
-                        prev_is_built = asset.c.is_built();
-                        archive_data_added += out_data.len();
+                if rsyncable {
+                    if archive_data_added > 1_000_000 || prev_is_built != asset.c.is_built() {
+                        self.flush()?;
+                        archive_data_added = 0;
                     }
-                    self.file(&asset.c.target_path, &out_data, asset.c.chmod)?;
-                    send.send((asset.c.target_path.clone(), out_data)).unwrap();
+                    // puts synchronization point between non-code and code assets
+                    prev_is_built = asset.c.is_built();
+                    archive_data_added += size;
                 }
+                let mut reader = HashingReader { inner: reader.take(size), hasher: Sha256::new(), len: 0 };
+                self.file_from_reader(&asset.c.target_path, size, &mut reader, asset.c.chmod)?;
+                // The tar header has the size from before the file was read
+                if reader.len != size {
+                    let path = asset.source.path().unwrap_or(&asset.c.target_path).to_path_buf();
+                    return Err(CargoDebError::IoFile("the file has changed while being archived", io::ErrorKind::UnexpectedEof.into(), path));
+                }
+                hashes.insert(asset.c.target_path.clone(), reader.hasher.finalize().into());
             }
-            drop(send);
-            Ok(hash_thread.join().unwrap())
-        })?;
+        }
 
         let tar = self.tar.into_inner()?;
         Ok((tar, hashes))
@@ -124,14 +120,19 @@ impl<W: Write> Tarball<W> {
     }
 
     fn file_(&mut self, path: &Path, out_data: &[u8], chmod: u32) -> CDResult<()> {
+        self.file_from_reader(path, out_data.len() as u64, out_data, chmod)
+    }
+
+    /// The `data` must have exactly `size` bytes
+    fn file_from_reader(&mut self, path: &Path, size: u64, data: impl Read, chmod: u32) -> CDResult<()> {
         self.add_parent_directories(path)?;
 
         let mut header = TarHeader::new_gnu();
         header.set_mtime(self.time);
         header.set_mode(chmod);
-        header.set_size(out_data.len() as u64);
+        header.set_size(size);
         header.set_cksum();
-        self.tar.append_data(&mut header, path, out_data)?;
+        self.tar.append_data(&mut header, path, data)?;
         Ok(())
     }
 
@@ -157,6 +158,22 @@ impl<W: Write> Tarball<W> {
     }
 }
 
+/// Hashes the data as it's copied into the archive
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
 fn human_size(len: u64) -> (u64, &'static str) {
     if len < 1000 {
         return (len, "B");
@@ -166,3 +183,35 @@ fn human_size(len: u64) -> (u64, &'static str) {
     }
     ((len + 999_999) / 1_000_000, "MB")
 }
+
+#[test]
+fn hashes_files_while_archiving() {
+    use crate::assets::{Asset, Assets, IsBuilt};
+
+    let mut mock_listener = crate::listener::MockListener::new();
+    mock_listener.expect_info().return_const(());
+    let (_, mut package_deb) = crate::config::test_config(&mock_listener);
+    let dir = tempfile::tempdir().unwrap();
+    let big_file = dir.path().join("big");
+    let big_data = (0..300_000u32).flat_map(u32::to_le_bytes).collect::<Vec<u8>>();
+    fs::write(&big_file, &big_data).unwrap();
+    package_deb.assets = Assets::with_resolved_assets(vec![
+        Asset::new(AssetSource::Path(big_file), "usr/lib/test/big".into(), 0o644, IsBuilt::No, false),
+        Asset::new(AssetSource::Data(b"small".to_vec()), "usr/share/test/small".into(), 0o644, IsBuilt::No, false),
+    ]);
+
+    let (tar, hashes) = Tarball::new(Vec::new(), 0).archive_files(&package_deb, false, &mock_listener).unwrap();
+    assert_eq!(<[u8; 32]>::from(Sha256::digest(&big_data)), hashes[Path::new("usr/lib/test/big")]);
+    assert_eq!(<[u8; 32]>::from(Sha256::digest(b"small")), hashes[Path::new("usr/share/test/small")]);
+
+    let mut archive = tar::Archive::new(&tar[..]);
+    let mut files = archive.entries().unwrap().map(|e| e.unwrap())
+        .filter(|e| e.header().entry_type().is_file())
+        .map(|mut e| {
+            let mut data = Vec::new();
+            e.read_to_end(&mut data).unwrap();
+            data
+        });
+    assert_eq!(Some(big_data), files.next());
+    assert_eq!(Some(b"small".to_vec()), files.next());
+}
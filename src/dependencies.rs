@@ -1,10 +1,10 @@
-use crate::debian_triple_from_rust_triple;
 use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
 use std::path::Path;
 use std::process::Command;
 
 /// Resolves the dependencies based on the output of dpkg-shlibdeps on the binary.
-pub(crate) fn resolve(path: &Path, target: Option<&str>) -> CDResult<Vec<String>> {
+pub(crate) fn resolve(path: &Path, debian_triple: Option<&str>, listener: &dyn Listener) -> CDResult<Vec<String>> {
     let temp_folder = tempfile::tempdir()?;
     let debian_folder = temp_folder.path().join("debian");
     let control_file_path = debian_folder.join("control");
@@ -17,28 +17,18 @@ pub(crate) fn resolve(path: &Path, target: Option<&str>) -> CDResult<Vec<String>
     let mut args = vec!["-O"];
     let libpath_arg;
     // determine library search path from target
-    if let Some(target) = target {
-        libpath_arg = format!("-l/usr/{}/lib", debian_triple_from_rust_triple(target));
+    if let Some(debian_triple) = debian_triple {
+        libpath_arg = format!("-l/usr/{debian_triple}/lib");
         args.push(&libpath_arg);
     }
-    const DPKG_SHLIBDEPS_COMMAND: &str = "dpkg-shlibdeps";
-    let output = Command::new(DPKG_SHLIBDEPS_COMMAND)
+    let output = crate::build_log::run_checked_output(Command::new("dpkg-shlibdeps")
         .args(args)
         .arg(path)
-        .current_dir(temp_folder.path())
-        .output()
-        .map_err(|e| CargoDebError::CommandFailed(e, DPKG_SHLIBDEPS_COMMAND))?;
-    if !output.status.success() {
-        return Err(CargoDebError::CommandError(
-            DPKG_SHLIBDEPS_COMMAND,
-            path.display().to_string(),
-            output.stderr,
-        ));
-    }
+        .current_dir(temp_folder.path()), listener)?;
 
-    log::debug!("dpkg-shlibdeps for {}: {}", path.display(), String::from_utf8_lossy(&output.stdout));
+    log::debug!("dpkg-shlibdeps for {}: {}", path.display(), String::from_utf8_lossy(&output));
 
-    let deps = output.stdout.as_slice().split(|&c| c == b'\n')
+    let deps = output.split(|&c| c == b'\n')
         .find_map(|line| line.strip_prefix(b"shlibs:Depends="))
         .ok_or(CargoDebError::Str("Failed to find dependency specification."))?
         .split(|&c| c == b',')
@@ -56,7 +46,7 @@ pub(crate) fn resolve(path: &Path, target: Option<&str>) -> CDResult<Vec<String>
 #[cfg(target_os = "linux")]
 fn resolve_test() {
     let exe = std::env::current_exe().unwrap();
-    let deps = resolve(&exe, None).unwrap();
+    let deps = resolve(&exe, None, &crate::listener::NoOpListener).unwrap();
     assert!(deps.iter().any(|d| d.starts_with("libc")));
     assert!(!deps.iter().any(|d| d.starts_with("libgcc")), "{deps:?}");
 }
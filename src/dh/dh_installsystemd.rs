@@ -16,13 +16,14 @@ use itertools::Itertools; // for .next_tuple()
 use std::collections::{BTreeSet, HashMap};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::str;
 
 use crate::assets::Asset;
 use crate::dh::dh_lib::{autoscript, pkgfile, ScriptFragments};
 use crate::listener::Listener;
 use crate::util::{fname_from_path, MyJoin};
-use crate::CDResult;
+use crate::error::{CDResult, CargoDebError};
 
 /// From man 1 dh_installsystemd on Ubuntu 20.04 LTS. See:
 ///   <http://manpages.ubuntu.com/manpages/focal/en/man1/dh_installsystemd.1.html>
@@ -123,6 +124,10 @@ pub struct Options {
     pub no_start: bool,
     pub restart_after_upgrade: bool,
     pub no_stop_on_upgrade: bool,
+    /// Not a `dh_installsystemd` option. Start (or restart) the unit only on upgrades, not on the first installation
+    pub no_start_on_install: bool,
+    /// Instances of template units (`foo@.service`) to enable and start. Defaults to their `DefaultInstance=`
+    pub instances: Vec<String>,
 }
 
 /// Find installable systemd unit files for the specified debian package (and
@@ -217,6 +222,9 @@ fn unquote(s: &str) -> &str {
 ///
 /// Pass the `ScriptFragments` result to `apply()`.
 ///
+/// Template units are handled via their instances, and services
+/// activated by a socket of this package are not started on installation.
+///
 /// See:
 ///   <https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n288>
 pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &dyn Listener) -> CDResult<ScriptFragments> {
@@ -241,16 +249,36 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
     // installed, upgraded or removed.
     // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n312
 
-    // skip template service files. Enabling, disabling, starting or stopping
-    // those services without specifying the instance is not useful.
-    let mut installed_non_template_units: BTreeSet<String> = BTreeSet::new();
-    installed_non_template_units.extend(
-        assets
-            .iter()
-            .filter(|a| a.c.target_path.parent() == Some(LIB_SYSTEMD_SYSTEM_DIR.as_ref()))
-            .map(|a| fname_from_path(a.c.target_path.as_path()))
-            .filter(|fname| !fname.contains('@')),
-    );
+    // Template units can't be enabled, started or stopped without specifying
+    // the instance, so only their configured or default instances are used.
+    let unit_data = |unit: &str| {
+        let template = template_of(unit);
+        let template = template.as_deref().unwrap_or(unit);
+        assets.iter()
+            .find(|&item| item.c.target_path.parent() == Some(LIB_SYSTEMD_SYSTEM_DIR.as_ref()) && (item.c.target_path.ends_with(unit) || item.c.target_path.ends_with(template)))
+            .ok_or_else(|| CargoDebError::SystemdUnitNotFound(unit.into()))
+            .and_then(|item| item.source.data())
+    };
+    let mut installed_units: BTreeSet<String> = BTreeSet::new();
+    for fname in assets.iter()
+        .filter(|a| a.c.target_path.parent() == Some(LIB_SYSTEMD_SYSTEM_DIR.as_ref()))
+        .map(|a| fname_from_path(a.c.target_path.as_path())) {
+        let Some((prefix, suffix)) = fname.split_once("@.") else {
+            if !fname.contains('@') {
+                installed_units.insert(fname);
+            }
+            continue;
+        };
+        let instances = if options.instances.is_empty() {
+            let data = unit_data(&fname)?;
+            data.lines().map_while(Result::ok)
+                .filter_map(|line| Some(unquote(line.split_once('=').filter(|(key, _)| key.trim() == "DefaultInstance")?.1.trim()).to_owned()))
+                .collect()
+        } else {
+            options.instances.clone()
+        };
+        installed_units.extend(instances.iter().map(|instance| format!("{prefix}@{instance}.{suffix}")));
+    }
 
     // BTreeSets values iterate in sorted order irrespective of the order they
     // were inserted.
@@ -258,11 +286,12 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
     let mut aliases = BTreeSet::new();
     let mut enable_units = BTreeSet::new();
     let mut start_units = BTreeSet::new();
+    let mut socket_activated_units = BTreeSet::new();
     let mut seen = BTreeSet::new();
 
     // note: we do not support handling of services with a sysv-equivalent
     // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n373
-    let mut units = installed_non_template_units;
+    let mut units = installed_units;
 
     // for all installed non-template units and any units they refer to via
     // the 'Also=' key in their unit file, determine what if anything we need to
@@ -278,11 +307,13 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
             // the unit has to be started
             start_units.insert(unit.clone());
 
-            // get the unit file contents
-            let needle = Path::new(LIB_SYSTEMD_SYSTEM_DIR).join(unit);
-            let data = assets.iter().find(move |&item| item.c.target_path == needle).unwrap().source.data()?;
+            // get the unit file contents (of the template for instances)
+            let data = unit_data(unit)?;
             let reader = data.into_owned();
 
+            // a socket activates the service of the same name, unless it sets 'Service='
+            let mut activated_service = unit.strip_suffix(".socket").map(|name| format!("{name}.service"));
+
             // for every line in the file look for specific keys that we are
             // interested in:
             // From: https://www.freedesktop.org/software/systemd/man/systemd.syntax.html
@@ -321,12 +352,16 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
                         "Alias" => {
                             aliases.insert(other_unit);
                         },
+                        "Service" if activated_service.is_some() => {
+                            activated_service = Some(other_unit);
+                        },
                         _ => (),
                     };
                 } else if line.starts_with("[Install]") {
                     enable_units.insert(unit.clone());
                 }
             }
+            socket_activated_units.extend(activated_service);
         }
         units = also_units;
     }
@@ -348,12 +383,21 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
     // be taken is influenced by the options passed to us.
     // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n398
     if !start_units.is_empty() {
-        let mut replace = map! { "UNITFILES" => start_units.join(" ") };
-
-        if options.restart_after_upgrade {
+        // Socket-activated services are started on demand by their sockets
+        let (on_demand_units, started_units): (BTreeSet<_>, BTreeSet<_>) = start_units.iter().cloned()
+            .partition(|unit| socket_activated_units.contains(unit));
+        let mut replace = map! { "UNITFILES" => started_units.join(" ") };
+
+        if started_units.is_empty() {
+            // only socket-activated services
+        } else if options.restart_after_upgrade {
             let snippet = if options.no_start {
                 replace.insert("RESTART_ACTION", "try-restart".into());
                 "postinst-systemd-restartnostart"
+            } else if options.no_start_on_install {
+                // the restartnostart snippet only acts on upgrades
+                replace.insert("RESTART_ACTION", "restart".into());
+                "postinst-systemd-restartnostart"
             } else {
                 replace.insert("RESTART_ACTION", "restart".into());
                 "postinst-systemd-restart"
@@ -361,9 +405,22 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
             autoscript(&mut scripts, package, "postinst", snippet, &replace, true, listener)?;
         } else if !options.no_start {
             // (stop|start) service (before|after) upgrade
-            autoscript(&mut scripts, package, "postinst", "postinst-systemd-start", &replace, true, listener)?;
+            if options.no_start_on_install {
+                replace.insert("RESTART_ACTION", "start".into());
+                autoscript(&mut scripts, package, "postinst", "postinst-systemd-restartnostart", &replace, true, listener)?;
+            } else {
+                autoscript(&mut scripts, package, "postinst", "postinst-systemd-start", &replace, true, listener)?;
+            }
+        }
+
+        // if they were running during an upgrade, they're restarted to use the new version
+        if !on_demand_units.is_empty() && options.restart_after_upgrade {
+            autoscript(&mut scripts, package, "postinst", "postinst-systemd-restartnostart",
+                &map!{ "UNITFILES" => on_demand_units.join(" "), "RESTART_ACTION" => "try-restart".into() }, true, listener)?;
         }
 
+        replace.insert("UNITFILES", start_units.join(" "));
+
         if options.no_stop_on_upgrade || options.restart_after_upgrade {
             // stop service only on remove
             autoscript(&mut scripts, package, "prerm", "prerm-systemd-restart", &replace, true, listener)?;
@@ -380,6 +437,46 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
     Ok(scripts)
 }
 
+/// `foo@.service` for `foo@bar.service`
+fn template_of(unit: &str) -> Option<String> {
+    let (name, suffix) = unit.rsplit_once('.')?;
+    let (prefix, instance) = name.split_once('@')?;
+    (!instance.is_empty()).then(|| format!("{prefix}@.{suffix}"))
+}
+
+/// Checks syntax of the unit files with `systemd-analyze verify`, and reports problems as warnings.
+/// It's opt-in (`verify = true`), because the results depend on the version of systemd on the build machine. It's skipped if it's not installed.
+/// Missing executables are not reported, because the package isn't installed on the build machine.
+pub fn verify_units(assets: &[Asset], listener: &dyn Listener) -> CDResult<()> {
+    let units: Vec<_> = assets.iter().filter(|a| a.c.target_path.parent() == Some(LIB_SYSTEMD_SYSTEM_DIR.as_ref())).collect();
+    if units.is_empty() {
+        return Ok(());
+    }
+    let tmp = tempfile::tempdir()?;
+    let units_dir = tmp.path().join(LIB_SYSTEMD_SYSTEM_DIR);
+    std::fs::create_dir_all(&units_dir)?;
+    let mut paths = Vec::with_capacity(units.len());
+    for unit in units {
+        let path = units_dir.join(fname_from_path(&unit.c.target_path));
+        std::fs::write(&path, unit.source.data()?)?;
+        paths.push(path);
+    }
+    let output = match Command::new("systemd-analyze").args(["verify", "--man=no"]).args(&paths).output() {
+        Ok(output) => output,
+        Err(e) => {
+            log::debug!("systemd-analyze: {e}");
+            return Ok(());
+        },
+    };
+    let tmp_prefix = tmp.path().display().to_string();
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        if !line.trim().is_empty() && !line.contains(" is not executable: ") {
+            listener.warning(format!("systemd-analyze: {}", line.replace(&tmp_prefix, "")));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -653,6 +750,74 @@ mod tests {
         assert_eq!(expected_autoscript_text, created_autoscript_text);
     }
 
+    #[test]
+    fn generate_starts_only_on_upgrade() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        set_test_fs_path_content("debian/upgraded.service", "[Service]\nType=simple\n".into());
+        let assets = vec![Asset::new(
+            AssetSource::Path(PathBuf::from("debian/upgraded.service")),
+            "lib/systemd/system/upgraded.service".into(),
+            0o644,
+            crate::assets::IsBuilt::No,
+            false,
+        )];
+
+        for (restart_after_upgrade, action) in [(true, "restart"), (false, "start")] {
+            let options = Options { no_start_on_install: true, restart_after_upgrade, ..Options::default() };
+            let fragments = generate("mypkg", &assets, &options, &mock_listener).unwrap();
+            let postinst = String::from_utf8_lossy(&fragments["mypkg.postinst.service"]).into_owned();
+            assert!(postinst.contains("if [ -n \"$2\" ]; then"), "{postinst}");
+            assert!(postinst.contains(&format!("deb-systemd-invoke {action} upgraded.service")), "{postinst}");
+            assert!(!postinst.contains("_dh_action=start"), "{postinst}");
+        }
+    }
+
+    #[test]
+    fn generate_handles_instances_and_sockets() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        set_test_fs_path_content("debian/worker@.service", "[Service]\nExecStart=/usr/bin/worker %i\n[Install]\nWantedBy=multi-user.target\nDefaultInstance=main\n".into());
+        set_test_fs_path_content("debian/api.socket", "[Socket]\nListenStream=8080\nService=api-server.service\n[Install]\nWantedBy=sockets.target\n".into());
+        set_test_fs_path_content("debian/api-server.service", "[Service]\nExecStart=/usr/bin/api\n".into());
+        let assets: Vec<_> = ["worker@.service", "api.socket", "api-server.service"].iter().map(|name| Asset::new(
+            AssetSource::Path(PathBuf::from(format!("debian/{name}"))),
+            format!("lib/systemd/system/{name}").into(),
+            0o644,
+            crate::assets::IsBuilt::No,
+            false,
+        )).collect();
+
+        let fragments = generate("mypkg", &assets, &Options { restart_after_upgrade: true, ..Options::default() }, &mock_listener).unwrap();
+        let postinst = String::from_utf8_lossy(&fragments["mypkg.postinst.service"]).into_owned();
+        assert!(postinst.contains("deb-systemd-helper enable worker@main.service"), "{postinst}");
+        assert!(postinst.contains("deb-systemd-invoke $_dh_action api.socket worker@main.service"), "{postinst}");
+        assert!(postinst.contains("deb-systemd-invoke try-restart api-server.service"), "{postinst}");
+        let prerm = String::from_utf8_lossy(&fragments["mypkg.prerm.service"]).into_owned();
+        assert!(prerm.contains("api-server.service api.socket worker@main.service"), "{prerm}");
+
+        let options = Options { instances: vec!["a".into(), "b".into()], ..Options::default() };
+        let fragments = generate("mypkg", &assets, &options, &mock_listener).unwrap();
+        let postinst = String::from_utf8_lossy(&fragments["mypkg.postinst.service"]).into_owned();
+        assert!(postinst.contains("deb-systemd-invoke start api.socket worker@a.service worker@b.service"), "{postinst}");
+    }
+
+    #[test]
+    fn generate_reports_missing_also_units() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        set_test_fs_path_content("debian/main.service", "[Service]\nExecStart=/usr/bin/main\n[Install]\nAlso=missing.service\n".into());
+        let assets = vec![Asset::new(
+            AssetSource::Path(PathBuf::from("debian/main.service")),
+            "lib/systemd/system/main.service".into(),
+            0o644,
+            crate::assets::IsBuilt::No,
+            false,
+        )];
+        let err = generate("mypkg", &assets, &Options::default(), &mock_listener).unwrap_err();
+        assert!(matches!(&err, CargoDebError::SystemdUnitNotFound(unit) if unit == "missing.service"), "{err}");
+    }
+
     #[test]
     fn generate_filters_out_template_units() {
         // "A template unit must have a single "@" at the end of the name
@@ -660,6 +825,7 @@ mod tests {
         //   https://www.freedesktop.org/software/systemd/man/systemd.unit.html
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(0).return_const(());
+        set_test_fs_path_content("debian/my_unit@.service", "[Service]\nExecStart=/usr/bin/my_unit %i\n".into());
 
         let assets = vec![Asset::new(
             AssetSource::Path(PathBuf::from("debian/my_unit@.service")),
@@ -775,6 +941,8 @@ mod tests {
             no_start: ns,
             restart_after_upgrade: rau,
             no_stop_on_upgrade: nsou,
+            no_start_on_install: false,
+            instances: Vec::new(),
         };
 
         // setup mocks
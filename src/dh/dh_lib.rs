@@ -246,10 +246,10 @@ fn autoscript_sed(snippet_filename: &str, replacements: &HashMap<&str, String>)
 /// # References
 ///
 /// <https://git.launchpad.net/ubuntu/+source/debhelper/tree/lib/Debian/Debhelper/Dh_Lib.pm?h=applied/12.10ubuntu1#n2161>
-fn debhelper_script_subst(user_scripts_dir: &Path, scripts: &mut ScriptFragments, package: &str, script: &str, unit_name: Option<&str>,
+fn debhelper_script_subst(user_scripts_dir: Option<&Path>, scripts: &mut ScriptFragments, package: &str, script: &str, unit_name: Option<&str>,
     listener: &dyn Listener) -> CDResult<()>
 {
-    let user_file = pkgfile(user_scripts_dir, package, package, script, unit_name);
+    let user_file = user_scripts_dir.and_then(|dir| pkgfile(dir, package, package, script, unit_name));
     let mut generated_scripts: Vec<String> = vec![
         format!("{package}.{script}.debhelper"),
         format!("{package}.{script}.service"),
@@ -298,12 +298,14 @@ fn debhelper_script_subst(user_scripts_dir: &Path, scripts: &mut ScriptFragments
 /// collected in the `ScriptFragments` map  with the maintainer scripts
 /// on disk supplied by the user.
 ///
+/// Scripts for which `uses_script` is false are generated as if the user hadn't supplied them.
+///
 /// See: <https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installdeb?h=applied/12.10ubuntu1#n300>
-pub(crate) fn apply(user_scripts_dir: &Path, scripts: &mut ScriptFragments, package: &str, unit_name: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
+pub(crate) fn apply(user_scripts_dir: &Path, uses_script: impl Fn(&str) -> bool, scripts: &mut ScriptFragments, package: &str, unit_name: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
     for script in &["postinst", "preinst", "prerm", "postrm"] {
         // note: we don't support custom defines thus we don't have the final
         // 'package_subst' argument to debhelper_script_subst().
-        debhelper_script_subst(user_scripts_dir, scripts, package, script, unit_name, listener)?;
+        debhelper_script_subst(Some(user_scripts_dir).filter(|_| uses_script(script)), scripts, package, script, unit_name, listener)?;
     }
 
     Ok(())
@@ -594,7 +596,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
         assert_eq!(0, scripts.len());
     }
 
@@ -609,7 +611,7 @@ mod tests {
 
         let mut scripts = ScriptFragments::new();
 
-        match debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener) {
+        match debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener) {
             Ok(_) => (),
             Err(CargoDebError::DebHelperReplaceFailed(_)) => panic!("Test failed as expected"),
             Err(err) => panic!("Unexpected error {err:?}"),
@@ -628,7 +630,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
         assert_eq!(1, scripts.len());
         assert!(scripts.contains_key("myscript"));
     }
@@ -647,7 +649,7 @@ mod tests {
         scripts.insert("mypkg.myscript.debhelper".to_owned(), "injected".as_bytes().to_vec());
 
         assert_eq!(1, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
         assert_eq!(2, scripts.len());
         assert!(scripts.contains_key("mypkg.myscript.debhelper"));
         assert!(scripts.contains_key("myscript"));
@@ -669,7 +671,7 @@ mod tests {
         scripts.insert("mypkg.myscript.debhelper".to_owned(), "injected".as_bytes().to_vec());
 
         assert_eq!(1, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
         assert_eq!(2, scripts.len());
         assert!(scripts.contains_key("mypkg.myscript.debhelper"));
         assert!(scripts.contains_key("myscript"));
@@ -701,7 +703,7 @@ mod tests {
         scripts.insert(format!("mypkg.{maintainer_script}.service"), "second".as_bytes().to_vec());
 
         assert_eq!(2, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", maintainer_script, None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", maintainer_script, None, &mock_listener).unwrap();
         assert_eq!(3, scripts.len());
         assert!(scripts.contains_key(&format!("mypkg.{maintainer_script}.debhelper")));
         assert!(scripts.contains_key(&format!("mypkg.{maintainer_script}.service")));
@@ -735,7 +737,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        let result = debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener);
+        let result = debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener);
 
         assert!(matches!(result, Err(CargoDebError::Io(_))));
         if let CargoDebError::Io(err) = result.unwrap_err() {
@@ -749,7 +751,7 @@ mod tests {
     fn apply_with_no_matching_files() {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(0).return_const(());
-        apply(Path::new(""), &mut ScriptFragments::new(), "mypkg", None, &mock_listener).unwrap();
+        apply(Path::new(""), |_| true, &mut ScriptFragments::new(), "mypkg", None, &mock_listener).unwrap();
     }
 
     #[rstest]
@@ -765,6 +767,6 @@ mod tests {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(scripts.len()).return_const(());
 
-        apply(Path::new(""), &mut ScriptFragments::new(), "mypkg", None, &mock_listener).unwrap();
+        apply(Path::new(""), |_| true, &mut ScriptFragments::new(), "mypkg", None, &mock_listener).unwrap();
     }
 }
@@ -0,0 +1,26 @@
+//! `dpkg-divert` calls for files of other packages that this package replaces.
+use crate::config::PackageConfig;
+use crate::util::shell_quote;
+use std::fmt::Write;
+
+/// Shell code for `preinst` that adds the diversions, and for `postrm` that removes them
+pub(crate) fn generate_scripts(package_deb: &PackageConfig) -> Option<(String, String)> {
+    if package_deb.diverts.is_empty() {
+        return None;
+    }
+
+    let package = &package_deb.deb_name;
+    let mut preinst = String::from("if [ \"$1\" = \"install\" ] || [ \"$1\" = \"upgrade\" ] ; then\n");
+    // On upgrades the new version takes over the diversions
+    let mut postrm = String::from("if [ \"$1\" = \"remove\" ] || [ \"$1\" = \"abort-install\" ] || [ \"$1\" = \"disappear\" ] ; then\n");
+    for divert in &package_deb.diverts {
+        let path = format!("/{}", divert.path.trim_start_matches('/'));
+        let to = divert.to.as_deref().map_or_else(|| format!("{path}.distrib"), |to| format!("/{}", to.trim_start_matches('/')));
+        let (path, to) = (shell_quote(&path), shell_quote(&to));
+        let _ = writeln!(preinst, "\tdpkg-divert --package {package} --add --rename --divert {to} {path}");
+        let _ = writeln!(postrm, "\tdpkg-divert --package {package} --remove --rename --divert {to} {path}");
+    }
+    preinst.push_str("fi\n");
+    postrm.push_str("fi\n");
+    Some((preinst, postrm))
+}
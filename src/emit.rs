@@ -0,0 +1,66 @@
+//! `--emit control.tar.gz,data.tar.xz` and `--explode` write members of the `.deb` archive as separate files next to it,
+//! for tools that consume the control and data tarballs directly.
+use crate::error::{CDResult, CargoDebError};
+use crate::util::atomic_file;
+use crate::util::compress::Format;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// A requested name like `data` matches any compression of `data.tar.*`
+fn matches_member(requested: &str, member: &str) -> bool {
+    member == requested || member.strip_prefix(requested).is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// Writes the requested members to `<deb name>.<member>` files, and returns their paths
+pub(crate) fn emit_members(deb_path: &Path, requested: &[String]) -> CDResult<Vec<PathBuf>> {
+    let file = fs::File::open(deb_path).map_err(|e| CargoDebError::IoFile("unable to open package", e, deb_path.into()))?;
+    let mut archive = ar::Archive::new(file);
+    let mut found = vec![false; requested.len()];
+    let mut members = Vec::new();
+    let mut written = Vec::new();
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).into_owned();
+        let mut is_requested = false;
+        for (req, found) in requested.iter().zip(&mut found) {
+            if matches_member(req, &name) {
+                *found = true;
+                is_requested = true;
+            }
+        }
+        if is_requested {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            let path = deb_path.with_extension(&name);
+            atomic_file::write(&path, data).map_err(|e| CargoDebError::IoFile("unable to write package member", e, path.clone()))?;
+            written.push(path);
+        }
+        members.push(name);
+    }
+    if let Some((missing, _)) = requested.iter().zip(found).find(|(_, found)| !found) {
+        return Err(CargoDebError::DebMemberNotFound(missing.clone(), members.join(", ")));
+    }
+    Ok(written)
+}
+
+/// Paths that `emit_members` will write for a package compressed with the `format`, without reading the package
+pub(crate) fn member_paths(deb_path: &Path, requested: &[String], format: Format) -> Vec<PathBuf> {
+    requested.iter().map(|name| {
+        let name = match name.as_str() {
+            "control" | "data" => format!("{name}.tar.{}", format.extension()),
+            _ => name.clone(),
+        };
+        deb_path.with_extension(name)
+    }).collect()
+}
+
+#[test]
+fn member_names() {
+    assert!(matches_member("data", "data.tar.xz"));
+    assert!(matches_member("control.tar.gz", "control.tar.gz"));
+    assert!(!matches_member("data.tar.gz", "data.tar.xz"));
+    assert!(!matches_member("debian", "debian-binary"));
+    let paths = member_paths(Path::new("t/foo_1.0_all.deb"), &["debian-binary".into(), "data".into()], Format::Xz);
+    assert_eq!([Path::new("t/foo_1.0_all.debian-binary"), Path::new("t/foo_1.0_all.data.tar.xz")], paths.as_slice());
+}
@@ -15,6 +15,31 @@ quick_error! {
             display("Unable to parse {}", path.display())
             source(err)
         }
+        ForeignBinary(path: PathBuf, elf_arch: String, deb_arch: String) {
+            display("{} is a binary for {}, which doesn't match the package's architecture {}. Use --allow-foreign-arch if that's intentional", path.display(), elf_arch, deb_arch)
+        }
+        UnsatisfiableDependencies(problems: String) {
+            display("The package couldn't be installed from the given APT index:\n  {}", problems)
+        }
+        HardeningCheckFailed(missing: String) {
+            display("Binaries in the package lack the required hardening:\n{}", missing)
+        }
+        AssetConflict(target_path: PathBuf, source1: String, source2: String) {
+            display("Two assets are installed as /{}: {} and {}", target_path.display(), source1, source2)
+        }
+        AssetDirConflict(target_path: PathBuf, file_source: String, source: String) {
+            display("/{} is a file from {}, but {} is installed in a directory with the same path", target_path.display(), file_source, source)
+        }
+        BinNotInWorkspace(name: String, available: String) {
+            display("There's no executable or workspace member with one executable named '{}' for a `bin` asset. Available executables: {}", name, available)
+        }
+        NotStatic(linkage: String) {
+            display("static-pie is set, but executables are linked dynamically:\n{}", linkage)
+        }
+        ArchMapParse(err: Box<toml::de::Error>, path: PathBuf) {
+            display("Unable to parse the arch map {}", path.display())
+            source(err)
+        }
         IoFile(msg: &'static str, err: io::Error, file: PathBuf) {
             display("{}: {}", msg, file.display())
             source(err)
@@ -37,15 +62,27 @@ quick_error! {
         InvalidVersion(msg: &'static str, ver: String) {
             display("Version '{}' is invalid: {}", ver, msg)
         }
+        InvalidUrgency(urgency: String) {
+            display("Changelog urgency '{}' is invalid. It must be one of: low, medium, high, emergency, critical", urgency)
+        }
+        LockfileNotCommitted(path: PathBuf, reason: String) {
+            display("--locked requires {} to be committed, but {}", path.display(), reason)
+        }
+        InspectorFailed(cmd: String, status: std::process::ExitStatus) {
+            display("Package inspector `{}` rejected the package ({})", cmd, status)
+        }
+        SystemdUnitNotFound(unit: String) {
+            display("There's no unit file for the systemd unit {} in the package. Units named in `Also=` have to be in the package too", unit)
+        }
         InstallFailed {
             display("installation failed, because dpkg -i returned error")
         }
-        BuildFailed {
-            display("build failed")
-        }
         DebHelperReplaceFailed(name: PathBuf) {
             display("unable to replace #DEBHELPER# token in maintainer script '{}'", name.display())
         }
+        CommandUnsuccessful(cmd: String, reason: String) {
+            display("`{}` failed: {}", cmd, reason)
+        }
         StripFailed(name: PathBuf, reason: String) {
             display("unable to strip binary '{}': {}", name.display(), reason)
         }
@@ -74,9 +111,22 @@ quick_error! {
         PackageNotFoundInWorkspace(name: String, available: String) {
             display("The workspace doesn't have a package named {}. Available packages are: {}", name, available)
         }
+        VirtualWorkspace(workspace_root: PathBuf, available: String) {
+            display("The workspace at {} is virtual (has no package at its root). Please select a package with -p, or package all of them with --workspace. Available packages are: {}", workspace_root.display(), available)
+        }
+        CargoMetadataParse(err: serde_json::Error) {
+            display("unable to parse `cargo metadata` output. This version of Cargo may be newer than cargo-deb supports: {}", err)
+            source(err)
+        }
         NoRootFoundInWorkspace(available: String) {
             display("This is a workspace with multiple packages, and there is no single package at the root. Please specify package name with -p. Available packages are: {}", available)
         }
+        MissingDescription(name: String) {
+            display("{} has no description in Cargo.toml or the first paragraph of its readme, and --require-description is set", name)
+        }
+        DescriptionTemplate(msg: String) {
+            display("Unable to expand description-fallback: {}", msg)
+        }
         VariantNotFound(variant: String) {
             display("[package.metadata.deb.variants.{}] not found in Cargo.toml", variant)
         }
@@ -85,9 +135,48 @@ quick_error! {
             display("unable to parse glob pattern")
             source(err)
         }
+        SymbolsRemoved(symbols: String) {
+            display("Symbols have been removed from shared libraries, which breaks programs that use them:\n{}\nIf that's intended, change the SONAME, and the symbols file", symbols)
+        }
         AssetFileNotFound(path: PathBuf) {
             display("Asset file path does not match any files: {}", path.display())
         }
+        ConfFileNotPackaged(path: String) {
+            display("conf-files entry '{}' is not the source or target path of any asset. Configuration files must be packaged", path)
+        }
+        ConfigDefaultNotPackaged(path: String) {
+            display("config-defaults entry '{}' is not the target path of any asset", path)
+        }
+        DebMemberTooLarge(name: String, size: u64) {
+            display("The {} member of the package would be {} bytes, but members of .deb archives can't be larger than {} bytes. Move large assets to another package, e.g. a variant with only the data files", name, size, crate::deb::ar::MAX_MEMBER_SIZE)
+        }
+        UnknownMaintainerScript(name: String) {
+            display("'{}' in maintainer-scripts-only isn't a maintainer script. Use any of: {}", name, crate::config::MAINTAINER_SCRIPTS.join(", "))
+        }
+        LicenseMarkerNotFound(marker: String, path: PathBuf) {
+            display("The license file {} has no line with '{}', which marks the end of the skipped header", path.display(), marker)
+        }
+        CantRepackage(reason: String) {
+            display("Can't repackage without building, because {}. Build again without --repackage", reason)
+        }
+        MissingTools(list: String) {
+            display("Programs needed for the build are missing:\n{}", list)
+        }
+        NotDebPackage(path: PathBuf, reason: &'static str) {
+            display("{} is not a Debian package, because {}", path.display(), reason)
+        }
+        DebMemberNotFound(name: String, members: String) {
+            display("The package has no {} member. Its members are: {}", name, members)
+        }
+        InvalidPurgePath(path: String) {
+            display("purge-paths entry '{}' must be an absolute path of the package's own file or dir (like /var/lib/<package>), not a dir shared with other packages, and wildcards are allowed only in the file name", path)
+        }
+        InvalidAssetTarget(path: PathBuf) {
+            display("Asset destination path {} is outside of the package's root", path.display())
+        }
+        AssetTemplate(path: PathBuf, msg: String) {
+            display("Unable to expand template asset {}: {}", path.display(), msg)
+        }
         AssetGlobError(err: glob::GlobError) {
             from()
             display("unable to iterate asset glob result")
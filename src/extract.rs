@@ -0,0 +1,134 @@
+//! `cargo deb extract pkg.deb dir/` unpacks any `.deb` the way `dpkg-deb -R` does, but without needing dpkg,
+//! so packages can be inspected on macOS and Windows, and in tests. Data files go into the dir,
+//! and the control files into its `DEBIAN` subdir.
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::snapshot::decompress;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Unpacks the package into `dest`, which is created if needed.
+///
+/// Modes, mtimes of files, and symlinks are preserved. Owners are preserved only when running as root,
+/// since other users can't give files away.
+pub fn extract(deb_path: &Path, dest: &Path, listener: &dyn Listener) -> CDResult<()> {
+    let file = fs::File::open(deb_path).map_err(|e| CargoDebError::IoFile("unable to open package", e, deb_path.into()))?;
+    fs::create_dir_all(dest).map_err(|e| CargoDebError::IoFile("unable to create the destination dir", e, dest.into()))?;
+    let as_root = is_root(dest);
+    let mut archive = ar::Archive::new(file);
+    let mut has_version = false;
+    let mut has_data = false;
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(_) if !has_version => return Err(CargoDebError::NotDebPackage(deb_path.into(), "it's not an ar archive")),
+            Err(e) => return Err(e.into()),
+        };
+        let name = String::from_utf8_lossy(entry.header().identifier()).into_owned();
+        if !has_version {
+            let mut version = String::new();
+            if name != "debian-binary" || entry.read_to_string(&mut version).is_err() || !version.starts_with("2.") {
+                return Err(CargoDebError::NotDebPackage(deb_path.into(), "it doesn't start with debian-binary 2.0"));
+            }
+            has_version = true;
+            continue;
+        }
+        let (dir, ext) = if let Some(ext) = tar_compression(&name, "control.tar") {
+            (dest.join("DEBIAN"), ext)
+        } else if let Some(ext) = tar_compression(&name, "data.tar") {
+            has_data = true;
+            (dest.to_path_buf(), ext)
+        } else {
+            listener.info(format!("Skipped the {name} member"));
+            continue;
+        };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        fs::create_dir_all(&dir).map_err(|e| CargoDebError::IoFile("unable to create the destination dir", e, dir.clone()))?;
+        let mut tarball = tar::Archive::new(decompress(ext, &data)?);
+        tarball.set_preserve_permissions(true);
+        tarball.set_preserve_ownerships(as_root && has_numeric_owners(decompress(ext, &data)?));
+        tarball.set_overwrite(true);
+        tarball.unpack(&dir).map_err(|e| CargoDebError::IoFile("unable to extract package", e, dir.clone()))?;
+    }
+    if !has_data {
+        return Err(CargoDebError::NotDebPackage(deb_path.into(), "it has no data.tar member"));
+    }
+    listener.info(format!("Extracted {} into {}", deb_path.display(), dest.display()));
+    Ok(())
+}
+
+/// `data.tar.xz` is `xz`, and an uncompressed `data.tar` is an empty string
+fn tar_compression<'a>(member_name: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = member_name.strip_prefix(prefix)?;
+    if rest.is_empty() { Some(rest) } else { rest.strip_prefix('.') }
+}
+
+/// Packages made by cargo-deb leave the owner fields empty, which means root, and the `tar` crate can't restore that
+fn has_numeric_owners(tarball: impl Read) -> bool {
+    let mut tarball = tar::Archive::new(tarball);
+    let Ok(mut entries) = tarball.entries() else { return false };
+    entries.all(|e| e.is_ok_and(|e| e.header().uid().is_ok() && e.header().gid().is_ok()))
+}
+
+/// New files are owned by the effective user
+#[cfg(unix)]
+fn is_root(dir: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    tempfile::tempfile_in(dir).and_then(|f| f.metadata()).is_ok_and(|m| m.uid() == 0)
+}
+
+#[cfg(not(unix))]
+fn is_root(_: &Path) -> bool {
+    false
+}
+
+#[test]
+#[cfg(unix)]
+fn extracts_modes_and_symlinks() {
+    use std::os::unix::fs::PermissionsExt;
+
+    fn tarball(files: &[(&str, u32, Option<&str>)]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for &(path, mode, link) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(mode);
+            header.set_mtime(1_700_000_000);
+            header.set_uid(0);
+            header.set_gid(0);
+            if let Some(link) = link {
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                tar.append_link(&mut header, path, link).unwrap();
+            } else {
+                header.set_size(4);
+                tar.append_data(&mut header, path, &b"test"[..]).unwrap();
+            }
+        }
+        tar.into_inner().unwrap()
+    }
+    let mut control = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    std::io::Write::write_all(&mut control, &tarball(&[("./postinst", 0o755, None)])).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let deb_path = dir.path().join("test.deb");
+    let mut deb = ar::Builder::new(fs::File::create(&deb_path).unwrap());
+    for (name, data) in [
+        ("debian-binary", b"2.0\n".to_vec()),
+        ("control.tar.gz", control.finish().unwrap()),
+        // uncompressed
+        ("data.tar", tarball(&[("./usr/bin/test", 0o750, None), ("./usr/bin/link", 0o777, Some("test"))])),
+    ] {
+        deb.append(&ar::Header::new(name.into(), data.len() as u64), &data[..]).unwrap();
+    }
+    drop(deb);
+
+    let out = dir.path().join("out");
+    extract(&deb_path, &out, &crate::listener::NoOpListener).unwrap();
+    assert_eq!(0o750, fs::metadata(out.join("usr/bin/test")).unwrap().permissions().mode() & 0o7777);
+    assert_eq!(Path::new("test"), fs::read_link(out.join("usr/bin/link")).unwrap());
+    assert_eq!(0o755, fs::metadata(out.join("DEBIAN/postinst")).unwrap().permissions().mode() & 0o7777);
+
+    assert!(extract(&out.join("usr/bin/test"), &out, &crate::listener::NoOpListener).is_err());
+}
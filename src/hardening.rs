@@ -0,0 +1,437 @@
+//! `--check-hardening` inspects packaged ELF executables and libraries for the protections that
+//! Debian's `hardening-check` reports: PIE, RELRO, immediate binding, and stack canaries.
+//! `--static-pie` uses the same reader to check that executables don't need the dynamic loader or any libraries.
+use crate::assets::AssetSource;
+use crate::config::PackageConfig;
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use std::fmt::Write;
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HardeningFeature {
+    /// Position-independent executable, for ASLR
+    Pie,
+    /// Read-only relocations (`PT_GNU_RELRO`)
+    Relro,
+    /// Symbols resolved at load time, which together with RELRO makes the GOT read-only
+    BindNow,
+    /// Calls `__stack_chk_fail`. Rust code doesn't use stack canaries, only linked C code does
+    StackProtector,
+}
+
+impl HardeningFeature {
+    pub const ALL: [Self; 4] = [Self::Pie, Self::Relro, Self::BindNow, Self::StackProtector];
+
+    /// Name used in `--check-hardening=…`
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pie => "pie",
+            Self::Relro => "relro",
+            Self::BindNow => "bind-now",
+            Self::StackProtector => "stack-protector",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+}
+
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PT_GNU_RELRO: u32 = 0x6474_e552;
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_BIND_NOW: u64 = 24;
+const DT_FLAGS: u64 = 30;
+const DT_FLAGS_1: u64 = 0x6fff_fffb;
+const DF_BIND_NOW: u64 = 0x8;
+const DF_1_NOW: u64 = 0x1;
+const DF_1_PIE: u64 = 0x0800_0000;
+
+/// Results of the checks. `None` when a check doesn't apply (PIE of a library, BIND_NOW of a static executable),
+/// or can't be done (stack protector of an executable without any symbols).
+#[derive(Debug, PartialEq, Eq)]
+struct Hardening {
+    pie: Option<bool>,
+    relro: Option<bool>,
+    bind_now: Option<bool>,
+    stack_protector: Option<bool>,
+}
+
+impl Hardening {
+    fn get(&self, feature: HardeningFeature) -> Option<bool> {
+        match feature {
+            HardeningFeature::Pie => self.pie,
+            HardeningFeature::Relro => self.relro,
+            HardeningFeature::BindNow => self.bind_now,
+            HardeningFeature::StackProtector => self.stack_protector,
+        }
+    }
+}
+
+/// Minimal reader of ELF headers of either class and byte order
+pub(crate) struct Elf<'a> {
+    pub data: &'a [u8],
+    pub is_64: bool,
+    big_endian: bool,
+}
+
+impl<'a> Elf<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 52 || data[..4] != *b"\x7fELF" {
+            return None;
+        }
+        Some(Self { data, is_64: data[4] == 2, big_endian: data[5] == 2 })
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        self.data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+    }
+
+    pub(crate) fn u16(&self, offset: usize) -> Option<u16> {
+        let b = self.bytes(offset)?;
+        Some(if self.big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Option<u32> {
+        let b = self.bytes(offset)?;
+        Some(if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    }
+
+    fn u64(&self, offset: usize) -> Option<u64> {
+        let b = self.bytes(offset)?;
+        Some(if self.big_endian { u64::from_be_bytes(b) } else { u64::from_le_bytes(b) })
+    }
+
+    /// Address-sized field
+    pub(crate) fn word(&self, offset: usize) -> Option<u64> {
+        if self.is_64 { self.u64(offset) } else { self.u32(offset).map(u64::from) }
+    }
+
+    fn word_size(&self) -> usize {
+        if self.is_64 { 8 } else { 4 }
+    }
+
+    fn e_type(&self) -> Option<u16> {
+        self.u16(16)
+    }
+
+    /// `(p_type, p_offset, p_filesz)`
+    pub(crate) fn program_headers(&self) -> impl Iterator<Item = (u32, usize, usize)> + '_ {
+        let (phoff, phentsize, phnum) = if self.is_64 {
+            (self.u64(32), self.u16(54), self.u16(56))
+        } else {
+            (self.u32(28).map(u64::from), self.u16(42), self.u16(44))
+        };
+        let (phoff, phentsize, phnum) = (phoff.unwrap_or(0) as usize, usize::from(phentsize.unwrap_or(0)), phnum.unwrap_or(0));
+        (0..usize::from(phnum)).filter_map(move |i| {
+            let ph = phoff.checked_add(i.checked_mul(phentsize)?)?;
+            let p_type = self.u32(ph)?;
+            let (offset, filesz) = if self.is_64 { (self.u64(ph + 8)?, self.u64(ph + 32)?) } else { (u64::from(self.u32(ph + 4)?), u64::from(self.u32(ph + 16)?)) };
+            Some((p_type, usize::try_from(offset).ok()?, usize::try_from(filesz).ok()?))
+        })
+    }
+
+    /// `(sh_type, sh_offset, sh_size, sh_link, sh_name)`
+    pub(crate) fn section_headers(&self) -> Vec<(u32, usize, usize, u32, u32)> {
+        let (shoff, shentsize, shnum) = if self.is_64 {
+            (self.u64(40), self.u16(58), self.u16(60))
+        } else {
+            (self.u32(32).map(u64::from), self.u16(46), self.u16(48))
+        };
+        let (shoff, shentsize, shnum) = (shoff.unwrap_or(0) as usize, usize::from(shentsize.unwrap_or(0)), shnum.unwrap_or(0));
+        (0..usize::from(shnum)).filter_map(|i| {
+            let sh = shoff.checked_add(i.checked_mul(shentsize)?)?;
+            let sh_name = self.u32(sh)?;
+            let sh_type = self.u32(sh + 4)?;
+            let (offset, size, link) = if self.is_64 {
+                (self.u64(sh + 24)?, self.u64(sh + 32)?, self.u32(sh + 40)?)
+            } else {
+                (u64::from(self.u32(sh + 16)?), u64::from(self.u32(sh + 20)?), self.u32(sh + 24)?)
+            };
+            Some((sh_type, usize::try_from(offset).ok()?, usize::try_from(size).ok()?, link, sh_name))
+        }).collect()
+    }
+
+    /// Whether there's a section with this name, according to the section header string table
+    pub(crate) fn has_section(&self, name: &[u8]) -> bool {
+        let sections = self.section_headers();
+        let Some(&(_, offset, size, _, _)) = self.u16(if self.is_64 { 62 } else { 50 }).and_then(|i| sections.get(usize::from(i))) else { return false };
+        let Some(strings) = self.data.get(offset..offset.saturating_add(size)) else { return false };
+        sections.iter().any(|&(_, _, _, _, sh_name)| {
+            strings.get(sh_name as usize..).and_then(|s| s.split(|&b| b == 0).next()) == Some(name)
+        })
+    }
+
+    /// `(d_tag, d_val)` entries of the `PT_DYNAMIC` segment
+    pub(crate) fn dynamic_entries(&self, offset: usize, size: usize) -> Vec<(u64, u64)> {
+        let entry_size = 2 * self.word_size();
+        let mut entries = Vec::new();
+        let mut pos = offset;
+        while pos + entry_size <= offset.saturating_add(size) {
+            let (Some(tag), Some(val)) = (self.word(pos), self.word(pos + self.word_size())) else { break };
+            if tag == DT_NULL {
+                break;
+            }
+            entries.push((tag, val));
+            pos += entry_size;
+        }
+        entries
+    }
+
+    /// Whether the string tables of the symbol tables have any of the `__stack_chk_*` functions.
+    /// `None` if there are no symbol tables, e.g. in a stripped static executable.
+    fn has_stack_chk_symbols(&self) -> Option<bool> {
+        let sections = self.section_headers();
+        let mut found_symbols = false;
+        for &(_, _, _, link, _) in sections.iter().filter(|s| s.0 == SHT_DYNSYM || s.0 == SHT_SYMTAB) {
+            let Some(&(_, offset, size, _, _)) = sections.get(link as usize) else { continue };
+            let Some(strings) = self.data.get(offset..offset.saturating_add(size)) else { continue };
+            found_symbols = true;
+            if strings.split(|&b| b == 0).any(|name| name.starts_with(b"__stack_chk_fail") || name == b"__stack_chk_guard") {
+                return Some(true);
+            }
+        }
+        found_symbols.then_some(false)
+    }
+}
+
+/// `None` if it's not an ELF executable or shared library
+fn check_elf(data: &[u8]) -> Option<Hardening> {
+    let elf = Elf::new(data)?;
+    let e_type = elf.e_type()?;
+    if e_type != ET_EXEC && e_type != ET_DYN {
+        return None;
+    }
+
+    let mut has_interp = false;
+    let mut relro = false;
+    let mut dynamic = None;
+    for (p_type, offset, size) in elf.program_headers() {
+        match p_type {
+            PT_INTERP => has_interp = true,
+            PT_GNU_RELRO => relro = true,
+            PT_DYNAMIC => dynamic = Some(elf.dynamic_entries(offset, size)),
+            _ => {},
+        }
+    }
+    let flag = |tag, mask| dynamic.iter().flatten().any(|&(t, v)| t == tag && v & mask != 0);
+    // static-pie executables relocate themselves at startup, so there's no lazy binding to disable
+    let static_pie = !has_interp && flag(DT_FLAGS_1, DF_1_PIE);
+    let bind_now = dynamic.as_ref().filter(|_| !static_pie).map(|entries| {
+        entries.iter().any(|&(t, _)| t == DT_BIND_NOW) || flag(DT_FLAGS, DF_BIND_NOW) || flag(DT_FLAGS_1, DF_1_NOW)
+    });
+    // ET_DYN without an interpreter is a shared library, unless it's a static-pie
+    let pie = if e_type == ET_EXEC {
+        Some(false)
+    } else if has_interp || flag(DT_FLAGS_1, DF_1_PIE) {
+        Some(true)
+    } else {
+        None
+    };
+    Some(Hardening {
+        pie,
+        relro: Some(relro),
+        bind_now,
+        stack_protector: elf.has_stack_chk_symbols(),
+    })
+}
+
+/// Dynamic loader and libraries that the executable needs, which are none if it's statically linked.
+/// `None` if it's not an ELF file.
+fn dynamic_linkage(data: &[u8]) -> Option<Vec<String>> {
+    let elf = Elf::new(data)?;
+    let sections = elf.section_headers();
+    // the dynamic section uses the same string table as the dynamic symbols
+    let dynstr = sections.iter().find(|s| s.0 == SHT_DYNSYM)
+        .and_then(|&(_, _, _, link, _)| sections.get(link as usize))
+        .and_then(|&(_, offset, size, _, _)| data.get(offset..offset.saturating_add(size)));
+    let c_string = |bytes: &[u8]| String::from_utf8_lossy(bytes.split(|&b| b == 0).next().unwrap_or_default()).into_owned();
+
+    let mut linkage = Vec::new();
+    for (p_type, offset, size) in elf.program_headers() {
+        if p_type == PT_INTERP {
+            let interp = data.get(offset..offset.saturating_add(size)).map(c_string).filter(|i| !i.is_empty());
+            linkage.push(format!("dynamic loader {}", interp.as_deref().unwrap_or("(unknown)")));
+        } else if p_type == PT_DYNAMIC {
+            for (_, val) in elf.dynamic_entries(offset, size).into_iter().filter(|&(tag, _)| tag == DT_NEEDED) {
+                let name = dynstr.and_then(|s| s.get(usize::try_from(val).ok()?..)).map(c_string).filter(|n| !n.is_empty());
+                linkage.push(name.unwrap_or_else(|| "(unknown library)".into()));
+            }
+        }
+    }
+    Some(linkage)
+}
+
+/// For `--static-pie`. Executables that need the dynamic loader or libraries are errors, and static executables that aren't PIE are warnings.
+pub(crate) fn check_static(package_deb: &PackageConfig, listener: &dyn Listener) -> CDResult<()> {
+    let mut failures = String::new();
+    for asset in &package_deb.assets.resolved {
+        if matches!(asset.source, AssetSource::Symlink(_)) || !asset.c.is_built() || !asset.c.is_executable() || asset.c.is_dynamic_library() {
+            continue;
+        }
+        let data = asset.source.data()?;
+        let Some(linkage) = dynamic_linkage(&data) else { continue };
+        let target_path = format!("/{}", asset.c.target_path.display());
+        if !linkage.is_empty() {
+            let _ = writeln!(failures, "  {target_path}: {}", linkage.join(", "));
+        } else if Elf::new(&data).and_then(|elf| elf.e_type()) == Some(ET_EXEC) {
+            listener.warning(format!("{target_path} is statically linked, but not position-independent"));
+        }
+    }
+    if !failures.is_empty() {
+        return Err(CargoDebError::NotStatic(failures.trim_end().to_owned()));
+    }
+    Ok(())
+}
+
+fn describe(hardening: &Hardening) -> String {
+    HardeningFeature::ALL.iter().filter_map(|&feature| {
+        let state = match hardening.get(feature)? {
+            true => "yes",
+            false => "no",
+        };
+        Some(format!("{} {state}", feature.name()))
+    }).collect::<Vec<_>>().join(", ")
+}
+
+/// Reports hardening of every ELF executable and library in the package.
+/// Features in `required` that are missing are errors, other missing features are warnings.
+pub(crate) fn check_hardening(package_deb: &PackageConfig, required: &[HardeningFeature], listener: &dyn Listener) -> CDResult<()> {
+    let mut failures = String::new();
+    let debug_dir = Path::new("usr/lib/debug");
+    for asset in &package_deb.assets.resolved {
+        if matches!(asset.source, AssetSource::Symlink(_)) || asset.c.target_path.starts_with(debug_dir) {
+            continue;
+        }
+        if !asset.c.is_executable() && !asset.c.is_dynamic_library() {
+            continue;
+        }
+        let Some(hardening) = check_elf(&asset.source.data()?) else { continue };
+        let target_path = format!("/{}", asset.c.target_path.display());
+        listener.info(format!("Hardening of {target_path}: {}", describe(&hardening)));
+
+        let missing = |is_required: bool| HardeningFeature::ALL.iter()
+            .filter(|&&f| required.contains(&f) == is_required && hardening.get(f) == Some(false))
+            .map(|f| f.name())
+            .collect::<Vec<_>>();
+        let missing_required = missing(true);
+        if !missing_required.is_empty() {
+            let _ = writeln!(failures, "  {target_path}: {}", missing_required.join(", "));
+        }
+        let missing_optional = missing(false);
+        if !missing_optional.is_empty() {
+            listener.warning(format!("{target_path} is not hardened with {}", missing_optional.join(", ")));
+        }
+    }
+    if !failures.is_empty() {
+        return Err(CargoDebError::HardeningCheckFailed(failures.trim_end().to_owned()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64-bit little-endian ELF with the given program headers, dynamic entries, and dynamic symbol names
+    fn elf(e_type: u16, phdrs: &[u32], dynamic: &[(u64, u64)], symbols: &[&str]) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+        data[..7].copy_from_slice(b"\x7fELF\x02\x01\x01");
+        data[16..18].copy_from_slice(&e_type.to_le_bytes());
+
+        let mut dyn_section = Vec::new();
+        for &(tag, val) in dynamic.iter().chain([&(DT_NULL, 0)]) {
+            dyn_section.extend(tag.to_le_bytes());
+            dyn_section.extend(val.to_le_bytes());
+        }
+        let dyn_offset = 64 + phdrs.len() * 56;
+        data[32..40].copy_from_slice(&64u64.to_le_bytes());
+        data[54..56].copy_from_slice(&56u16.to_le_bytes());
+        data[56..58].copy_from_slice(&(phdrs.len() as u16).to_le_bytes());
+        for &p_type in phdrs {
+            let mut ph = [0u8; 56];
+            ph[..4].copy_from_slice(&p_type.to_le_bytes());
+            if p_type == PT_DYNAMIC {
+                ph[8..16].copy_from_slice(&(dyn_offset as u64).to_le_bytes());
+                ph[32..40].copy_from_slice(&(dyn_section.len() as u64).to_le_bytes());
+            }
+            data.extend(ph);
+        }
+        data.extend(&dyn_section);
+
+        if !symbols.is_empty() {
+            let strtab_offset = data.len();
+            let strtab = symbols.iter().flat_map(|s| [b"\0", s.as_bytes()]).flatten().chain(b"\0").copied().collect::<Vec<u8>>();
+            data.extend(&strtab);
+            let shoff = data.len();
+            data[40..48].copy_from_slice(&(shoff as u64).to_le_bytes());
+            data[58..60].copy_from_slice(&64u16.to_le_bytes());
+            data[60..62].copy_from_slice(&3u16.to_le_bytes());
+            data.extend([0u8; 64]);
+            let mut dynsym = [0u8; 64];
+            dynsym[4..8].copy_from_slice(&SHT_DYNSYM.to_le_bytes());
+            dynsym[40..44].copy_from_slice(&2u32.to_le_bytes());
+            data.extend(dynsym);
+            let mut dynstr = [0u8; 64];
+            dynstr[4..8].copy_from_slice(&3u32.to_le_bytes());
+            dynstr[24..32].copy_from_slice(&(strtab_offset as u64).to_le_bytes());
+            dynstr[32..40].copy_from_slice(&(strtab.len() as u64).to_le_bytes());
+            data.extend(dynstr);
+        }
+        data
+    }
+
+    #[test]
+    fn hardening_of_elf_files() {
+        let hardened = elf(ET_DYN, &[PT_INTERP, PT_GNU_RELRO, PT_DYNAMIC], &[(DT_FLAGS, DF_BIND_NOW), (DT_FLAGS_1, DF_1_NOW | DF_1_PIE)], &["malloc", "__stack_chk_fail"]);
+        assert_eq!(Some(Hardening { pie: Some(true), relro: Some(true), bind_now: Some(true), stack_protector: Some(true) }), check_elf(&hardened));
+
+        let legacy = elf(ET_EXEC, &[PT_INTERP, PT_DYNAMIC], &[(DT_FLAGS, 0)], &["malloc"]);
+        assert_eq!(Some(Hardening { pie: Some(false), relro: Some(false), bind_now: Some(false), stack_protector: Some(false) }), check_elf(&legacy));
+
+        let library = elf(ET_DYN, &[PT_GNU_RELRO, PT_DYNAMIC], &[(DT_BIND_NOW, 0)], &[]);
+        assert_eq!(Some(Hardening { pie: None, relro: Some(true), bind_now: Some(true), stack_protector: None }), check_elf(&library));
+        assert_eq!("relro yes, bind-now yes", describe(&check_elf(&library).unwrap()));
+
+        let static_pie = elf(ET_DYN, &[PT_GNU_RELRO, PT_DYNAMIC], &[(DT_FLAGS_1, DF_1_PIE)], &[]);
+        assert_eq!(Some(true), check_elf(&static_pie).unwrap().pie);
+        assert_eq!(None, check_elf(&static_pie).unwrap().bind_now);
+
+        assert_eq!(None, check_elf(b"#!/bin/sh\necho not an ELF file, but long enough to have a header\n"));
+        assert_eq!(None, check_elf(&hardened[..40]));
+
+        assert_eq!(Some(HardeningFeature::BindNow), HardeningFeature::from_name("bind-now"));
+        assert_eq!(None, HardeningFeature::from_name("fortify"));
+    }
+
+    #[test]
+    fn static_linkage() {
+        let static_pie = elf(ET_DYN, &[PT_GNU_RELRO, PT_DYNAMIC], &[(DT_FLAGS_1, DF_1_PIE)], &[]);
+        assert_eq!(Some(vec![]), dynamic_linkage(&static_pie));
+
+        let dynamic = elf(ET_DYN, &[PT_INTERP, PT_DYNAMIC], &[(DT_NEEDED, 1), (DT_NEEDED, 11)], &["libc.so.6", "libm.so.6"]);
+        assert_eq!(Some(vec!["dynamic loader (unknown)".into(), "libc.so.6".into(), "libm.so.6".into()]), dynamic_linkage(&dynamic));
+        assert_eq!(None, dynamic_linkage(b"#!/bin/sh\necho not an ELF file, but long enough to have a header\n"));
+    }
+
+    #[test]
+    fn finds_sections_by_name() {
+        let mut data = elf(ET_DYN, &[], &[], &["puts", ".dep-v0"]);
+        // the dynamic string table doubles as the section name table
+        data[62..64].copy_from_slice(&2u16.to_le_bytes());
+        let shoff = u64::from_le_bytes(data[40..48].try_into().unwrap()) as usize;
+        data[shoff + 64..shoff + 68].copy_from_slice(&6u32.to_le_bytes());
+        let elf = Elf::new(&data).unwrap();
+        assert!(elf.has_section(b".dep-v0"));
+        assert!(!elf.has_section(b".dep"));
+    }
+}
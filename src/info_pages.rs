@@ -0,0 +1,42 @@
+//! Registers GNU info pages in `/usr/share/info/dir` with `install-info`.
+use crate::config::PackageConfig;
+use std::fmt::Write;
+use std::path::Path;
+
+const INFO_DIR: &str = "usr/share/info/";
+
+/// Paths of the main files of info documents. Split parts (`*.info-1.gz`) are found by `install-info` itself.
+fn info_documents<'a>(target_paths: impl Iterator<Item = &'a Path>) -> Vec<String> {
+    let mut documents = target_paths
+        .filter_map(|path| path.to_str())
+        .filter(|path| path.starts_with(INFO_DIR) && (path.ends_with(".info.gz") || path.ends_with(".info")))
+        .map(|path| format!("/{path}"))
+        .collect::<Vec<_>>();
+    documents.sort();
+    documents
+}
+
+/// Shell code for `postinst` that adds the info pages to the directory, and for `prerm` that removes them
+pub(crate) fn generate_scripts(package_deb: &PackageConfig) -> Option<(String, String)> {
+    let documents = info_documents(package_deb.assets.resolved.iter().map(|asset| asset.c.target_path.as_path()));
+    if documents.is_empty() {
+        return None;
+    }
+
+    // install-info is optional, and the dir file is rebuilt when it's installed later
+    let mut postinst = String::from("if [ \"$1\" = \"configure\" ] && command -v install-info >/dev/null 2>&1 ; then\n");
+    let mut prerm = String::from("if [ \"$1\" = \"remove\" ] || [ \"$1\" = \"deconfigure\" ] ; then\n\tif command -v install-info >/dev/null 2>&1 ; then\n");
+    for path in &documents {
+        let _ = writeln!(postinst, "\tinstall-info --quiet --info-dir=/usr/share/info {path} || true");
+        let _ = writeln!(prerm, "\t\tinstall-info --quiet --remove --info-dir=/usr/share/info {path} || true");
+    }
+    postinst.push_str("fi\n");
+    prerm.push_str("\tfi\nfi\n");
+    Some((postinst, prerm))
+}
+
+#[test]
+fn finds_documents() {
+    let paths = ["usr/share/info/foo.info.gz", "usr/share/info/foo.info-1.gz", "usr/share/doc/foo/foo.info", "usr/share/info/bar.info.gz"];
+    assert_eq!(["/usr/share/info/bar.info.gz", "/usr/share/info/foo.info.gz"], &info_documents(paths.iter().map(Path::new))[..]);
+}
@@ -0,0 +1,220 @@
+//! Init scripts for sysvinit and OpenRC, for systems that don't use systemd (like Devuan).
+//!
+//! The scripts are installed from the `maintainer-scripts` dir, or generated from the package's systemd services.
+//! They're registered with `update-rc.d`, which supports both sysvinit and OpenRC.
+use crate::config::PackageConfig;
+use crate::parse::manifest::InitSystem;
+use crate::util::shell_quote;
+use std::fmt::Write;
+use std::path::Path;
+
+const INIT_D_DIR: &str = "etc/init.d/";
+
+/// What's needed from a systemd `.service` file to run the same program from an init script
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ServiceInfo {
+    pub description: Option<String>,
+    /// Absolute path of the program
+    pub program: String,
+    /// Arguments with systemd's quotes and escapes removed
+    pub args: Vec<String>,
+    pub user: Option<String>,
+}
+
+impl ServiceInfo {
+    /// Reads the first `ExecStart=` of a unit file. `None` if it doesn't have one usable outside of systemd.
+    pub(crate) fn from_unit(unit: &str) -> Option<Self> {
+        let mut info = Self::default();
+        for line in unit.lines().map(str::trim).filter(|line| !line.starts_with(['#', ';'])) {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            let value = value.trim();
+            match key.trim() {
+                "Description" if info.description.is_none() => info.description = Some(value.to_owned()),
+                "User" if info.user.is_none() => info.user = Some(value.to_owned()),
+                "ExecStart" if info.program.is_empty() => {
+                    // Prefixes like `-` or `@` change how systemd runs the command
+                    let command = value.trim_start_matches(['-', '@', ':', '+', '!']);
+                    let mut words = split_command_line(command).into_iter();
+                    info.program = words.next().unwrap_or_default();
+                    info.args = words.collect();
+                },
+                _ => {},
+            }
+        }
+        // Specifiers like %i can't be expanded without systemd
+        (info.program.starts_with('/') && !info.program.contains('%') && !info.args.iter().any(|arg| arg.contains('%'))).then_some(info)
+    }
+
+    /// Arguments quoted for `sh`
+    fn shell_args(&self) -> String {
+        self.args.iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Splits `ExecStart` into words like systemd does: on whitespace, except in `"…"` or `'…'`, and with `\` escapes
+fn split_command_line(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            _ if quote == Some(c) => quote = None,
+            '"' | '\'' if quote.is_none() => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            },
+            _ if quote.is_none() && c.is_whitespace() => words.extend(word.take()),
+            _ => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// LSB init script that runs the service's program in the background with `start-stop-daemon`
+pub(crate) fn sysvinit_script(name: &str, service: &ServiceInfo) -> String {
+    let description = service.description.as_deref().unwrap_or(name);
+    let chuid = service.user.as_deref().map(|user| format!(" --chuid {}", shell_quote(user))).unwrap_or_default();
+    let starting = shell_quote(&format!("Starting {description}"));
+    let stopping = shell_quote(&format!("Stopping {description}"));
+    format!(r#"#!/bin/sh
+### BEGIN INIT INFO
+# Provides:          {name}
+# Required-Start:    $remote_fs $syslog $network
+# Required-Stop:     $remote_fs $syslog $network
+# Default-Start:     2 3 4 5
+# Default-Stop:      0 1 6
+# Short-Description: {description}
+### END INIT INFO
+# Generated by cargo-deb from {name}.service
+
+NAME={quoted_name}
+DAEMON={program}
+PIDFILE=/run/$NAME.pid
+
+[ -x "$DAEMON" ] || exit 0
+
+. /lib/lsb/init-functions
+
+case "$1" in
+  start)
+	log_daemon_msg {starting} "$NAME"
+	start-stop-daemon --start --quiet --background --make-pidfile --pidfile "$PIDFILE"{chuid} --exec "$DAEMON" -- {args}
+	log_end_msg $?
+	;;
+  stop)
+	log_daemon_msg {stopping} "$NAME"
+	start-stop-daemon --stop --quiet --retry=TERM/30/KILL/5 --pidfile "$PIDFILE" --remove-pidfile
+	log_end_msg $?
+	;;
+  restart|force-reload)
+	"$0" stop
+	"$0" start
+	;;
+  status)
+	status_of_proc -p "$PIDFILE" "$DAEMON" "$NAME" && exit 0 || exit $?
+	;;
+  *)
+	echo "Usage: $0 {{start|stop|restart|force-reload|status}}" >&2
+	exit 3
+	;;
+esac
+"#, quoted_name = shell_quote(name), program = shell_quote(&service.program), args = service.shell_args())
+}
+
+/// `openrc-run` script that supervises the service's program
+pub(crate) fn openrc_script(name: &str, service: &ServiceInfo) -> String {
+    let mut script = format!("#!/sbin/openrc-run\n# Generated by cargo-deb from {name}.service\n\n");
+    let _ = writeln!(script, "description={}", shell_quote(service.description.as_deref().unwrap_or(name)));
+    let _ = writeln!(script, "command={}", shell_quote(&service.program));
+    if !service.args.is_empty() {
+        // openrc-run passes `command_args` through `eval`, so the quoted args are quoted again
+        let _ = writeln!(script, "command_args={}", shell_quote(&service.shell_args()));
+    }
+    if let Some(user) = &service.user {
+        let _ = writeln!(script, "command_user={}", shell_quote(user));
+    }
+    script.push_str("command_background=true\npidfile=\"/run/${RC_SVCNAME}.pid\"\n\ndepend() {\n\tneed net\n\tuse logger\n}\n");
+    script
+}
+
+/// Names of the scripts in `/etc/init.d` that are managed by the maintainer scripts
+fn init_script_names<'a>(target_paths: impl Iterator<Item = &'a Path>) -> Vec<String> {
+    let mut names = target_paths
+        .filter_map(|path| path.to_str()?.strip_prefix(INIT_D_DIR))
+        .filter(|name| !name.is_empty() && !name.contains('/'))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+/// Shell code for `postinst` that registers and starts the init scripts, for `prerm` that stops them,
+/// and for `postrm` that unregisters them on purge.
+pub(crate) fn generate_scripts(package_deb: &PackageConfig) -> Option<(String, String, String)> {
+    if !package_deb.init_systems.iter().any(|&s| s != InitSystem::Systemd) {
+        return None;
+    }
+    let names = init_script_names(package_deb.assets.resolved.iter().map(|asset| asset.c.target_path.as_path()));
+    if names.is_empty() {
+        return None;
+    }
+    // Under systemd, services that have a native unit are managed by the systemd maintainer scripts.
+    // Older invoke-rc.d doesn't have this option, so it's only used when there are units.
+    let skip_native = if package_deb.init_systems.contains(&InitSystem::Systemd) { " --skip-systemd-native" } else { "" };
+
+    let mut postinst = String::new();
+    let mut prerm = String::new();
+    let mut postrm = String::new();
+    for name in &names {
+        let _ = write!(postinst, r#"if [ "$1" = "configure" ] || [ "$1" = "abort-upgrade" ] || [ "$1" = "abort-deconfigure" ] || [ "$1" = "abort-remove" ] ; then
+	if [ -x "/etc/init.d/{name}" ]; then
+		update-rc.d {name} defaults >/dev/null
+		if [ -n "$2" ]; then
+			_dh_action=restart
+		else
+			_dh_action=start
+		fi
+		invoke-rc.d{skip_native} {name} $_dh_action || exit 1
+	fi
+fi
+"#);
+        let _ = write!(prerm, r#"if [ "$1" = "remove" ] && [ -x "/etc/init.d/{name}" ] ; then
+	invoke-rc.d{skip_native} {name} stop || exit 1
+fi
+"#);
+        let _ = write!(postrm, r#"if [ "$1" = "purge" ] ; then
+	update-rc.d {name} remove >/dev/null
+fi
+"#);
+    }
+    Some((postinst, prerm, postrm))
+}
+
+#[test]
+fn init_script_from_unit() {
+    let unit = "[Unit]\nDescription=Example server\n\n[Service]\n# ExecStart=/bin/false\nExecStart=-/usr/bin/example --port 80\nUser=www-data\n";
+    let service = ServiceInfo::from_unit(unit).unwrap();
+    assert_eq!(ServiceInfo { description: Some("Example server".into()), program: "/usr/bin/example".into(), args: vec!["--port".into(), "80".into()], user: Some("www-data".into()) }, service);
+    assert!(sysvinit_script("example", &service).contains("--chuid www-data --exec \"$DAEMON\" -- --port 80\n"));
+    assert!(openrc_script("example", &service).contains("command_args='--port 80'\ncommand_user=www-data\n"));
+
+    let unit = "[Service]\nDescription=Foo \"bar\" $HOME\nExecStart=/usr/bin/foo --motd \"it's $HOME\" a\\ b\nUser=$(id)\n";
+    let service = ServiceInfo::from_unit(unit).unwrap();
+    assert_eq!(["--motd", "it's $HOME", "a b"], &service.args[..]);
+    let sysvinit = sysvinit_script("foo", &service);
+    assert!(sysvinit.contains("log_daemon_msg 'Starting Foo \"bar\" $HOME' \"$NAME\"\n"));
+    assert!(sysvinit.contains(r#"--chuid '$(id)' --exec "$DAEMON" -- --motd 'it'\''s $HOME' 'a b'"#));
+    let openrc = openrc_script("foo", &service);
+    assert!(openrc.contains("description='Foo \"bar\" $HOME'\ncommand=/usr/bin/foo\n"));
+    assert!(openrc.contains("command_user='$(id)'\n"));
+    let command_args = openrc.lines().find_map(|l| l.strip_prefix("command_args=")).unwrap();
+    let output = std::process::Command::new("sh").arg("-c").arg(format!("command_args={command_args}; eval \"set -- $command_args\"; printf '%s|' \"$@\"")).output().unwrap();
+    assert_eq!("--motd|it's $HOME|a b|", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(None, ServiceInfo::from_unit("[Service]\nExecStart=/usr/bin/worker %i\n"));
+
+    let paths = ["etc/init.d/example", "etc/init.d/", "etc/default/example", "etc/init.d/a/b"];
+    assert_eq!(["example"], &init_script_names(paths.iter().map(Path::new))[..]);
+}
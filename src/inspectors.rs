@@ -0,0 +1,72 @@
+//! Runs user-configured commands (virus scanners, size or content policies) on the built package.
+use crate::assets::AssetSource;
+use crate::config::{Config, PackageConfig};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs each of the `inspectors` commands with the package's files unpacked in a staging dir as `$1`,
+/// and the `.deb` as `$2`. If any of them fails, the `.deb` is deleted.
+/// The staging dir is removed afterwards if `delete_temp` is set.
+pub(crate) fn run(config: &Config, package_deb: &PackageConfig, deb_path: &Path, delete_temp: bool, listener: &dyn Listener) -> CDResult<()> {
+    if package_deb.inspectors.is_empty() {
+        return Ok(());
+    }
+
+    let staging_dir = config.deb_temp_dir(package_deb).join("staging");
+    let _ = fs::remove_dir_all(&staging_dir);
+    stage_assets(package_deb, &staging_dir)?;
+    let result = run_inspectors(config, package_deb, &staging_dir, deb_path, listener);
+    if delete_temp {
+        let _ = fs::remove_dir_all(&staging_dir);
+    }
+    result
+}
+
+fn run_inspectors(config: &Config, package_deb: &PackageConfig, staging_dir: &Path, deb_path: &Path, listener: &dyn Listener) -> CDResult<()> {
+    for command in &package_deb.inspectors {
+        listener.info(format!("Inspecting the package with `{command}`"));
+        let status = crate::build_log::run_logged(Command::new("sh")
+            .arg("-c").arg(command)
+            .arg("cargo-deb-inspector").arg(staging_dir).arg(deb_path)
+            .env("CARGO_DEB_STAGING_DIR", staging_dir)
+            .env("CARGO_DEB_PATH", deb_path)
+            .current_dir(&config.package_manifest_dir), listener)
+            .map_err(|e| CargoDebError::CommandFailed(e, "sh"))?;
+        if !status.success() {
+            let _ = fs::remove_file(deb_path);
+            return Err(CargoDebError::InspectorFailed(command.clone(), status));
+        }
+    }
+    Ok(())
+}
+
+/// Writes files of the package as they would be installed
+fn stage_assets(package_deb: &PackageConfig, staging_dir: &Path) -> CDResult<()> {
+    for asset in &package_deb.assets.resolved {
+        let path = staging_dir.join(&asset.c.target_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| CargoDebError::IoFile("unable to create staging dir", e, parent.into()))?;
+        }
+        if let AssetSource::Symlink(source) = &asset.source {
+            let link = fs::read_link(source).map_err(|e| CargoDebError::IoFile("symlink asset", e, source.clone()))?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(link, &path).map_err(|e| CargoDebError::IoFile("unable to stage symlink", e, path.clone()))?;
+            #[cfg(not(unix))]
+            let _ = link;
+            continue;
+        }
+        match &asset.source {
+            AssetSource::Path(source) => fs::copy(source, &path).map(drop),
+            source => fs::write(&path, source.data()?),
+        }.map_err(|e| CargoDebError::IoFile("unable to stage asset", e, path.clone()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(asset.c.chmod))?;
+        }
+    }
+    Ok(())
+}
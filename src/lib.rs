@@ -1,4 +1,4 @@
-#![recursion_limit = "128"]
+#![recursion_limit = "256"]
 #![allow(clippy::case_sensitive_file_extension_comparisons)]
 #![allow(clippy::if_not_else)]
 #![allow(clippy::missing_errors_doc)]
@@ -24,11 +24,18 @@ cargo deb # run this in your Cargo project directory
 ## Making tools for making deb packages
 
 The library interface is experimental. See `main.rs` for usage.
+
+`CargoDeb::process` runs the phases [`CargoDeb::resolve_manifest`], [`CargoDeb::build_binaries`],
+[`CargoDeb::collect_assets`], [`CargoDeb::generate_control`] and [`CargoDeb::assemble`] in order.
+They can be called separately to inspect or modify the intermediate state (which is serializable with serde),
+or to add custom steps between them.
 */
 
 pub mod deb {
+    pub mod apt;
     pub mod ar;
     pub mod control;
+    pub mod parity;
     pub mod tar;
 }
 #[macro_use]
@@ -41,29 +48,62 @@ pub mod listener;
 pub(crate) mod parse {
     pub(crate) mod cargo;
     pub(crate) mod manifest;
+    pub(crate) mod schema;
 }
-pub use crate::config::{Config, DebugSymbols, PackageConfig};
+pub use crate::config::{BuildId, Config, DebugSymbols, PackageConfig};
 pub use crate::deb::ar::DebArchive;
 pub use crate::error::*;
+pub use crate::parse::schema::metadata_schema;
 pub use crate::util::compress;
-use crate::util::compress::{CompressConfig, Format};
+pub use crate::hardening::HardeningFeature;
+use crate::util::compress::{CompressConfig, CompressTuning, Format};
 
 pub mod assets;
+mod arch;
+mod auditable;
+pub mod bump;
+mod build_log;
+mod buildinfo;
+mod build_paths;
+mod bundle;
+mod cache;
+pub mod clean;
+mod compare;
 pub mod config;
+mod config_defaults;
 mod dependencies;
+mod cpu_variants;
+mod diversions;
+mod emit;
 mod error;
-
-use crate::assets::{Asset, AssetSource, IsBuilt, ProcessedFrom, compress_assets};
+pub mod extract;
+mod hardening;
+mod info_pages;
+mod init_scripts;
+mod inspectors;
+mod locales;
+mod moved_files;
+mod permissions;
+mod preflight;
+mod preinst_checks;
+mod purge_paths;
+mod repackage;
+mod simulate;
+pub mod snapshot;
+mod symbols;
+
+use crate::assets::{Asset, AssetSource, AssetTransform, IsBuilt, ProcessedFrom, compress_assets, expand_templates};
 use crate::deb::control::ControlArchiveBuilder;
 use crate::deb::tar::Tarball;
+use crate::build_log::BuildLog;
 use crate::listener::Listener;
 use config::DebConfigOverrides;
 use rayon::prelude::*;
 use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::io;
 use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus};
+use std::process::Command;
 
 const TAR_REJECTS_CUR_DIR: bool = true;
 
@@ -79,7 +119,233 @@ impl CargoDeb {
         Self { options }
     }
 
-    pub fn process(mut self, listener: &dyn Listener) -> CDResult<()> {
+    /// Runs all the phases: reads the manifest, builds, collects assets, and writes the `.deb`
+    ///
+    /// Everything is also logged to `target/debian/build.log`.
+    /// Returns the packages written (or reused from the cache) in this run.
+    pub fn process(self, listener: &dyn Listener) -> CDResult<PackageReport> {
+        if self.options.workspace && self.options.selected_package_name.is_none() {
+            return self.process_workspace(listener);
+        }
+
+        let log = BuildLog::new(listener);
+        let result = if let Some(jobs) = self.options.jobs {
+            rayon::ThreadPoolBuilder::new().num_threads(jobs).build()
+                .map_err(|_| CargoDebError::Str("unable to start worker threads"))
+                .and_then(|pool| pool.install(|| self.process_phases(&log)))
+        } else {
+            self.process_phases(&log)
+        };
+        match &result {
+            Ok(_) => log.log("Finished".into()),
+            Err(err) => log.log(format!("Failed: {err}")),
+        }
+        let mut package = result?;
+        package.warnings = log.warnings();
+        Ok(PackageReport { packages: vec![package] })
+    }
+
+    fn process_workspace(self, listener: &dyn Listener) -> CDResult<PackageReport> {
+        let names = parse::manifest::packageable_workspace_members(self.options.manifest_path.as_deref().map(Path::new), self.options.cargo_locking_flags)?;
+        if names.is_empty() {
+            return Err(CargoDebError::Str("none of the workspace members have executables or [package.metadata.deb]"));
+        }
+        let mut report = PackageReport::default();
+        for name in names {
+            listener.info(format!("Packaging workspace member {name}"));
+            let mut options = self.options.clone();
+            options.selected_package_name = Some(name);
+            report.packages.extend(CargoDeb { options }.process(listener)?.packages);
+        }
+        Ok(report)
+    }
+
+    fn process_phases(mut self, log: &BuildLog<'_>) -> CDResult<GeneratedPackage> {
+        let listener: &dyn Listener = log;
+        let manifest = self.resolve_manifest(listener)?;
+        log.open(&build_log::log_path(&manifest.config.work_dir));
+        if let Ok(json) = serde_json::to_string_pretty(&manifest) {
+            log.log(format!("Resolved configuration:\n{json}"));
+        }
+
+        let cached_path = if self.options.cache || self.options.refresh {
+            Some(cache::cached_deb_path(&manifest.config, &manifest.package_deb, &self.options)?)
+        } else {
+            None
+        };
+        let mut report = GeneratedPackage {
+            name: manifest.package_deb.deb_name.clone(),
+            version: manifest.package_deb.deb_version.clone(),
+            architecture: manifest.package_deb.architecture.clone(),
+            ..GeneratedPackage::default()
+        };
+        let cache_hit = cached_path.as_deref().filter(|p| !self.options.refresh && p.exists());
+        preflight::check_tools(&manifest.config, &manifest.package_deb, &self.options, cache_hit.is_some(), listener)?;
+        if let Some(cached_path) = cache_hit {
+            let output_path = manifest.config.deb_output_path(&manifest.package_deb);
+            cache::restore(cached_path, &output_path)?;
+            listener.info(format!("Nothing has changed since the last build. Reused {}", cached_path.display()));
+            listener.generated_archive(&output_path);
+            report.cached = true;
+            self.finish_package(&output_path, &mut report, false, listener)?;
+            return Ok(report);
+        }
+        let deb_name = manifest.package_deb.deb_name.clone();
+
+        let built = self.build_binaries(manifest, listener)?;
+        let mut collected = self.collect_assets(built, listener)?;
+        let previous = self.options.previous.iter().map(PathBuf::from).collect::<Vec<_>>();
+        moved_files::check(&collected.config, &mut collected.package_deb, &previous, self.options.add_breaks_replaces, listener)?;
+        let control = self.generate_control(collected)?;
+        if let Some(debian_control_path) = &self.options.check_against {
+            let divergences = deb::parity::check_against(&control.control, Path::new(debian_control_path))?;
+            if divergences.is_empty() {
+                listener.info(format!("The control file matches {debian_control_path}"));
+            }
+            for divergence in divergences {
+                listener.warning(divergence);
+            }
+        }
+        if !self.options.against.is_empty() {
+            let paths = self.options.against.iter().map(PathBuf::from).collect::<Vec<_>>();
+            let unsatisfied = deb::apt::check_dependencies(&control.control, &paths, listener)?;
+            if !unsatisfied.is_empty() {
+                return Err(CargoDebError::UnsatisfiableDependencies(unsatisfied.join("\n  ")));
+            }
+            listener.info(format!("All dependencies are available in {}", self.options.against.join(", ")));
+        }
+        let has_build_ids = !control.package_deb.build_ids.is_empty();
+        let build_env = control.config.clean_env.then(|| build_environment(&control.config, self.options.target.as_deref()));
+        let generated = self.assemble(control, listener)?;
+        if has_build_ids {
+            report.files.push(generated.with_extension("build-ids"));
+        }
+
+        if let Some(cached_path) = &cached_path {
+            cache::store(cached_path, &generated, &deb_name)?;
+        }
+        self.finish_package(&generated, &mut report, build_env.is_some(), listener)?;
+        if let Some(env) = build_env {
+            let env = env.iter().map(|(k, v)| (k.as_os_str(), v.as_os_str())).collect::<Vec<_>>();
+            report.files.push(buildinfo::write(&generated, &report, &report.sha256, &env)?);
+        }
+        Ok(report)
+    }
+
+    /// Checksums, bundle and installation of the written `.deb`. It's hashed only if the checksums are needed.
+    fn finish_package(&self, deb_path: &Path, report: &mut GeneratedPackage, for_buildinfo: bool, listener: &dyn Listener) -> CDResult<()> {
+        if self.options.print_hash || self.options.hash_in_report || for_buildinfo {
+            let (size, sha256, sha512) = hash_file(deb_path)?;
+            if self.options.print_hash {
+                listener.generated_checksums(&write_checksums_of(deb_path, size, &sha256, &sha512)?);
+                report.files.push(checksums_path(deb_path));
+            }
+            report.size = size;
+            report.sha256 = sha256;
+        } else {
+            report.size = fs::metadata(deb_path).map_err(|e| CargoDebError::IoFile("unable to read the package", e, deb_path.into()))?.len();
+        }
+        if !self.options.emit.is_empty() {
+            report.files.extend(emit::emit_members(deb_path, &self.options.emit)?);
+        }
+        if let Some(old_path) = &self.options.compare {
+            listener.generated_comparison(&compare::compare(Path::new(old_path), deb_path)?);
+        }
+        if self.options.bundle {
+            let bundle_path = bundle::bundle(deb_path, listener)?;
+            listener.info(format!("Bundle with dependencies written to {}", bundle_path.display()));
+            report.files.push(bundle_path);
+        }
+        report.path = deb_path.to_path_buf();
+
+        if self.options.install {
+            install_deb(deb_path, listener)?;
+        }
+        Ok(())
+    }
+
+    /// Paths of the packages and the files next to them that `process` would write with the same options,
+    /// without building anything. The `.build-ids` index isn't included, because it depends on the built binaries.
+    pub fn list_outputs(mut self, listener: &dyn Listener) -> CDResult<Vec<PathBuf>> {
+        if self.options.workspace && self.options.selected_package_name.is_none() {
+            let names = parse::manifest::packageable_workspace_members(self.options.manifest_path.as_deref().map(Path::new), self.options.cargo_locking_flags)?;
+            if names.is_empty() {
+                return Err(CargoDebError::Str("none of the workspace members have executables or [package.metadata.deb]"));
+            }
+            let mut paths = Vec::new();
+            for name in names {
+                let mut options = self.options.clone();
+                options.selected_package_name = Some(name);
+                paths.extend(CargoDeb { options }.list_outputs(listener)?);
+            }
+            return Ok(paths);
+        }
+
+        let manifest = self.resolve_manifest(listener)?;
+        let deb_path = manifest.config.deb_output_path(&manifest.package_deb);
+        let mut paths = Vec::new();
+        if self.options.print_hash {
+            paths.push(checksums_path(&deb_path));
+        }
+        paths.extend(emit::member_paths(&deb_path, &self.options.emit, self.options.compress_type));
+        if self.options.bundle {
+            paths.push(bundle::bundle_tar_path(&deb_path));
+        }
+        if manifest.config.clean_env {
+            paths.push(deb_path.with_extension("buildinfo"));
+        }
+        paths.insert(0, deb_path);
+        Ok(paths)
+    }
+
+    /// The files that the package would contain, after expanding globs and adding implied assets (like the copyright file
+    /// and systemd units), without building anything. Binaries that haven't been built yet are listed at the paths
+    /// where Cargo will write them. Separate debug symbols of such binaries can't be listed, and are in `not_listed`.
+    pub fn list_assets(&mut self, listener: &dyn Listener) -> CDResult<ListedAssets> {
+        let ResolvedManifest { config, mut package_deb } = self.resolve_manifest(listener)?;
+        package_deb.resolve_assets_without_build()?;
+        // The same changes of paths and modes as in `collect_assets`, as far as they can be made without the built binaries
+        cpu_variants::add_variant_assets(&config, &mut package_deb, false, listener)?;
+        expand_templates(&mut package_deb)?;
+        compress_assets(&mut package_deb, listener)?;
+        if self.options.normalize_permissions {
+            permissions::normalize(&mut package_deb, listener);
+        }
+        let mut not_listed = Vec::new();
+        let strip = self.options.strip_override.unwrap_or(config.debug_symbols != DebugSymbols::Keep);
+        if strip && matches!(config.debug_symbols, DebugSymbols::Separate { .. }) {
+            let mut debug_assets = Vec::new();
+            for asset in package_deb.built_binaries_mut() {
+                match asset.source.path().filter(|path| path.exists()) {
+                    Some(path) => debug_assets.push(Asset::new(AssetSource::Data(Vec::new()), get_target_debug_path(asset, path).0, 0o644, IsBuilt::No, false)),
+                    None => not_listed.push(format!("debug symbols of /{} in /usr/lib/debug/.build-id/ (the path depends on the build-id of the binary, which hasn't been built yet)", asset.c.target_path.display())),
+                }
+            }
+            package_deb.assets.resolved.append(&mut debug_assets);
+        }
+        package_deb.sort_assets_by_type();
+        let assets = package_deb.assets.resolved.iter().map(|asset| {
+            let target = Path::new("/").join(&asset.c.target_path);
+            ListedAsset {
+                source: asset.source.path().map(Path::to_path_buf),
+                exists: asset.source.path().map_or(true, Path::exists),
+                mode: format!("{:o}", asset.c.chmod),
+                built: asset.c.is_built(),
+                conf_file: package_deb.conf_files.iter().any(|c| Path::new(c) == target),
+                target,
+            }
+        }).collect();
+        Ok(ListedAssets { assets, not_listed })
+    }
+
+    /// Resolves the package described by a fixture (a `Cargo.toml` with a `[simulate]` table), without running Cargo,
+    /// and returns the assets and the `control` file that would be packaged, as JSON.
+    pub fn simulate(&self, fixture_path: &Path, listener: &dyn Listener) -> CDResult<String> {
+        simulate::simulate(fixture_path, &self.options, listener)
+    }
+
+    /// The first phase. Reads `Cargo.toml` and cargo metadata, and prepares the list of assets to build.
+    pub fn resolve_manifest(&mut self, listener: &dyn Listener) -> CDResult<ResolvedManifest> {
         if self.options.install || self.options.target.is_none() {
             warn_if_not_linux(); // compiling natively for non-linux = nope
         }
@@ -95,7 +361,7 @@ impl CargoDeb {
         // cargo build accordingly. you could argue that the other way around is
         // more desirable. However for now we want all commands coming in via the
         // same `interface`
-        let selected_profile = self.options.profile;
+        let selected_profile = self.options.profile.clone();
         if selected_profile.as_deref() == Some("dev") {
             listener.warning("dev profile is not supported and will be a hard error in the future. \
                 cargo-deb is for making releases, and it doesn't make sense to use it with dev profiles.".into());
@@ -103,29 +369,66 @@ impl CargoDeb {
         }
 
         let root_manifest_path = self.options.manifest_path.as_deref().map(Path::new);
-        let (mut config, mut package_deb) = Config::from_manifest(
+        let (config, mut package_deb) = Config::from_manifest(
             root_manifest_path,
             self.options.selected_package_name.as_deref(),
-            self.options.output_path,
+            self.options.output_path.clone(),
             self.options.target.as_deref(),
             self.options.variant.as_deref(),
-            self.options.overrides,
+            self.options.overrides.clone(),
             selected_profile,
             self.options.separate_debug_symbols,
             self.options.compress_debug_symbols,
             self.options.cargo_locking_flags,
             listener,
         )?;
+        // static-pie builds use an explicit target
+        if self.options.target.is_none() {
+            self.options.target.clone_from(&config.target);
+        }
+        if self.options.cargo_locking_flags.locked || self.options.cargo_locking_flags.frozen {
+            check_lockfile_committed(&config.workspace_root, listener)?;
+        }
         config.prepare_assets_before_build(&mut package_deb)?;
+        Ok(ResolvedManifest { config, package_deb })
+    }
 
-        if !self.options.no_build {
+    /// Runs `cargo build`, unless the `no_build` or `repackage` option is set
+    pub fn build_binaries(&mut self, manifest: ResolvedManifest, listener: &dyn Listener) -> CDResult<BuiltBinaries> {
+        let ResolvedManifest { config, package_deb } = manifest;
+        if !self.options.no_build && !self.options.repackage {
             config.set_cargo_build_flags_for_package(&package_deb, &mut self.options.cargo_build_flags);
-            cargo_build(&config, self.options.target.as_deref(), &self.options.cargo_build_cmd, &self.options.cargo_build_flags, self.options.verbose)?;
+            if let Some(jobs) = self.options.jobs {
+                self.options.cargo_build_flags.push(format!("--jobs={jobs}"));
+            }
+            // cargo-auditable wraps the Cargo subcommand, e.g. `cargo auditable build`
+            let build_cmd = if self.options.auditable { format!("auditable {}", self.options.cargo_build_cmd) } else { self.options.cargo_build_cmd.clone() };
+            cargo_build(&config, self.options.target.as_deref(), &build_cmd, &self.options.cargo_build_flags, self.options.verbose, listener)?;
+            for cpu in &package_deb.cpu_variants {
+                cpu_variants::cargo_build(&config, cpu, self.options.target.as_deref(), &build_cmd, &self.options.cargo_build_flags, self.options.verbose, listener)?;
+            }
         }
+        Ok(BuiltBinaries { config, package_deb })
+    }
+
+    /// Finds files of the assets, and processes them (compression, stripping, transforms)
+    pub fn collect_assets(&self, built: BuiltBinaries, listener: &dyn Listener) -> CDResult<CollectedAssets> {
+        let BuiltBinaries { mut config, mut package_deb } = built;
 
         package_deb.resolve_assets()?;
-        package_deb.resolve_binary_dependencies(config.target.as_deref(), listener)?;
+        arch::check_prebuilt_binaries(&package_deb, self.options.allow_foreign_arch, listener)?;
+        if package_deb.static_pie {
+            hardening::check_static(&package_deb, listener)?;
+        }
+        cpu_variants::add_variant_assets(&config, &mut package_deb, true, listener)?;
+        if self.options.repackage {
+            repackage::verify(&config, &package_deb, listener)?;
+        } else if !self.options.no_build {
+            repackage::record(&config, &package_deb)?;
+        }
+        package_deb.resolve_binary_dependencies(config.debian_triple.as_deref(), listener)?;
 
+        expand_templates(&mut package_deb)?;
         compress_assets(&mut package_deb, listener)?;
 
         if self.options.strip_override.unwrap_or(config.debug_symbols != DebugSymbols::Keep) {
@@ -134,26 +437,171 @@ impl CargoDeb {
             log::debug!("not stripping debug={:?} strip-flag={:?}", config.debug_symbols, self.options.strip_override);
         }
 
+        transform_assets(&config, &mut package_deb, self.options.target.as_deref(), listener)?;
+
+        build_paths::warn_about_build_paths(&config, &package_deb, listener)?;
+        auditable::find_audit_data(&mut package_deb, self.options.auditable, listener)?;
+        symbols::generate(&config, &mut package_deb, listener)?;
+        if self.options.normalize_permissions {
+            permissions::normalize(&mut package_deb, listener);
+        }
+        if let Some(required) = &self.options.check_hardening {
+            hardening::check_hardening(&package_deb, required, listener)?;
+        }
+
+        package_deb.check_conflicting_assets()?;
         package_deb.sort_assets_by_type();
+        Ok(CollectedAssets { config, package_deb })
+    }
+
+    /// Makes the contents of the `control` file
+    pub fn generate_control(&self, collected: CollectedAssets) -> CDResult<GeneratedControl> {
+        let CollectedAssets { config, package_deb } = collected;
+        let control = String::from_utf8(package_deb.generate_control(&config)?)?;
+        Ok(GeneratedControl { config, package_deb, control })
+    }
 
-        let generated = write_deb(&config, &package_deb, &CompressConfig {
+    /// The last phase. Writes the `.deb` file, and returns its path.
+    pub fn assemble(&self, generated_control: GeneratedControl, listener: &dyn Listener) -> CDResult<PathBuf> {
+        let GeneratedControl { config, package_deb, control } = generated_control;
+        let generated = write_deb_with_control(&config, &package_deb, control.as_bytes(), &CompressConfig {
             fast: self.options.fast,
             compress_type: self.options.compress_type,
             compress_system: self.options.compress_system,
             rsyncable: self.options.rsyncable,
+            tuning: self.options.compress_tuning,
         }, listener)?;
 
-        listener.generated_archive(&generated);
+        inspectors::run(&config, &package_deb, &generated, self.options.delete_temp, listener)?;
 
-        if self.options.install {
-            install_deb(&generated)?;
+        if let Some(index_path) = write_build_id_index(&generated, &package_deb)? {
+            listener.info(format!("Build-id index written to {}", index_path.display()));
         }
-        Ok(())
+
+        // Stripped binaries and other processed assets have been archived, so they'd only take space
+        let deb_temp_dir = config.deb_temp_dir(&package_deb);
+        if self.options.delete_temp && !generated.starts_with(&deb_temp_dir) {
+            let _ = fs::remove_dir_all(&deb_temp_dir);
+        }
+
+        listener.generated_archive(&generated);
+        Ok(generated)
     }
 }
 
+/// Output of [`CargoDeb::resolve_manifest`]
+#[derive(Debug, serde::Serialize)]
+pub struct ResolvedManifest {
+    pub config: Config,
+    pub package_deb: PackageConfig,
+}
+
+impl ResolvedManifest {
+    /// `KEY=VALUE` lines for `--print-env`, for use by `debian/rules` and shell scripts.
+    /// Values are quoted for `sh` when necessary. Nothing has been built yet, so `$auto` in the dependencies isn't expanded.
+    pub fn environment(&self) -> String {
+        let Self { config, package_deb } = self;
+        let resolved = package_deb.assets.resolved.iter().filter_map(|a| Some((&a.c, a.source.path()?)));
+        let unresolved = package_deb.assets.unresolved.iter().map(|a| (&a.c, a.source_path.as_path()));
+        let binaries = resolved.chain(unresolved)
+            .filter(|(c, _)| c.is_built() && (c.is_executable() || c.is_dynamic_library()))
+            .map(|(_, path)| path.display().to_string())
+            .collect::<Vec<_>>();
+        let vars = [
+            ("CARGO_DEB_PACKAGE", package_deb.deb_name.clone()),
+            ("CARGO_DEB_VERSION", package_deb.deb_version.clone()),
+            ("CARGO_DEB_ARCH", package_deb.architecture.clone()),
+            ("CARGO_DEB_MAINTAINER", package_deb.maintainer.clone()),
+            ("CARGO_DEB_DEPENDS", package_deb.wildcard_depends.clone()),
+            ("CARGO_DEB_BINARIES", binaries.join(" ")),
+            ("CARGO_DEB_TARGET_DIR", config.target_dir.display().to_string()),
+            ("CARGO_DEB_OUTPUT", config.deb_output_path(package_deb).display().to_string()),
+        ];
+        vars.iter().map(|(name, value)| format!("{name}={}\n", util::shell_quote(value))).collect()
+    }
+}
+
+/// Output of [`CargoDeb::process`]: the packages made in this run, so that other tools don't need to look for them
+#[derive(Debug, Default, serde::Serialize)]
+pub struct PackageReport {
+    /// More than one when packaging a workspace
+    pub packages: Vec<GeneratedPackage>,
+}
+
+/// A `.deb` written by [`CargoDeb::process`]
+#[derive(Debug, Default, serde::Serialize)]
+pub struct GeneratedPackage {
+    /// Debian package name
+    pub name: String,
+    /// Debian version, including the revision
+    pub version: String,
+    /// Debian architecture
+    pub architecture: String,
+    pub path: PathBuf,
+    /// Size of the `.deb` in bytes
+    pub size: u64,
+    /// Lowercase hex SHA256 of the `.deb`, if it was needed for `print_hash`, `hash_in_report` or the `.buildinfo`. Otherwise empty
+    pub sha256: String,
+    /// Other files written next to the `.deb`, like the `.sha256` file, build-id index or bundle
+    pub files: Vec<PathBuf>,
+    /// Reused from the cache instead of being built
+    pub cached: bool,
+    pub warnings: Vec<String>,
+}
+
+/// Output of [`CargoDeb::list_assets`]
+#[derive(Debug, serde::Serialize)]
+pub struct ListedAssets {
+    pub assets: Vec<ListedAsset>,
+    /// Files that will be in the package, but whose paths aren't known before the build.
+    /// The list of assets is complete only if this is empty.
+    pub not_listed: Vec<String>,
+}
+
+/// An asset listed by [`CargoDeb::list_assets`]
+#[derive(Debug, serde::Serialize)]
+pub struct ListedAsset {
+    /// `None` for files generated by cargo-deb, like the copyright file
+    pub source: Option<PathBuf>,
+    /// Absolute path in the installed package
+    pub target: PathBuf,
+    /// Octal, like `755`
+    pub mode: String,
+    /// Built by Cargo
+    pub built: bool,
+    /// `false` for files that Cargo hasn't built yet
+    pub exists: bool,
+    /// Listed in `conffiles`
+    pub conf_file: bool,
+}
+
+/// Output of [`CargoDeb::build_binaries`]
+#[derive(Debug, serde::Serialize)]
+pub struct BuiltBinaries {
+    pub config: Config,
+    pub package_deb: PackageConfig,
+}
+
+/// Output of [`CargoDeb::collect_assets`]. All assets are resolved.
+#[derive(Debug, serde::Serialize)]
+pub struct CollectedAssets {
+    pub config: Config,
+    pub package_deb: PackageConfig,
+}
+
+/// Output of [`CargoDeb::generate_control`]. The `control` can be edited before assembling the package.
+#[derive(Debug, serde::Serialize)]
+pub struct GeneratedControl {
+    pub config: Config,
+    pub package_deb: PackageConfig,
+    pub control: String,
+}
+
+#[derive(Clone)]
 pub struct CargoDebOptions {
     pub no_build: bool,
+    /// Don't build, and use binaries of the previous build if they haven't changed since
+    pub repackage: bool,
     pub strip_override: Option<bool>,
     pub separate_debug_symbols: Option<bool>,
     pub compress_debug_symbols: Option<bool>,
@@ -170,16 +618,55 @@ pub struct CargoDebOptions {
     pub manifest_path: Option<String>,
     pub cargo_build_cmd: String,
     pub cargo_build_flags: Vec<String>,
+    /// Build with `cargo auditable`, which embeds the dependency list in the binaries
+    pub auditable: bool,
     pub overrides: DebConfigOverrides,
     pub compress_type: Format,
     pub compress_system: bool,
     pub system_xz: bool,
     pub rsyncable: bool,
+    /// Compression level and xz dictionary size for the data archive
+    pub compress_tuning: CompressTuning,
     pub profile: Option<String>,
     pub cargo_locking_flags: CargoLockingFlags,
+    /// `debian/control` of the official packaging to compare the control file with
+    pub check_against: Option<String>,
+    /// `Packages` indexes of the target distro (or directories of APT lists) that must satisfy the dependencies
+    pub against: Vec<String>,
+    /// Reuse a previously built package if nothing has changed
+    pub cache: bool,
+    /// Rebuild the package, and update the cache
+    pub refresh: bool,
+    /// `--jobs` for Cargo. Also limits threads used by cargo-deb for stripping and compression
+    pub jobs: Option<usize>,
+    /// Make a package for every workspace member that has executables or `[package.metadata.deb]`, unless a package is selected
+    pub workspace: bool,
+    /// Only warn when prebuilt binaries in the assets are for a different architecture than the package
+    pub allow_foreign_arch: bool,
+    /// Delete stripped binaries and other temporary files after the package is written
+    pub delete_temp: bool,
+    /// Print size and checksums of the `.deb`, and write them to a `.sha256` file next to it
+    pub print_hash: bool,
+    /// Compute the SHA256 of the `.deb` for [`GeneratedPackage::sha256`] in the returned report.
+    /// It's skipped by default, because hashing a large package takes time.
+    pub hash_in_report: bool,
+    /// Report PIE, RELRO, BIND_NOW and stack protector of packaged binaries. Missing features in the list are errors
+    pub check_hardening: Option<Vec<HardeningFeature>>,
+    /// `cargo deb bundle`. Download the dependencies with APT, and put them in a tarball with the `.deb` and an install script
+    pub bundle: bool,
+    /// Old `.deb` files of other packages of the project, to check if files moved from them
+    pub previous: Vec<String>,
+    /// Add `Breaks` and `Replaces` for packages that files moved from
+    pub add_breaks_replaces: bool,
+    /// Previous release of the package to print the changes since
+    pub compare: Option<String>,
+    /// Members of the `.deb` archive (like `control.tar.gz` or just `data`) to also write as separate files next to it
+    pub emit: Vec<String>,
+    /// Set modes of files to `0644` or `0755` like `dh_fixperms`, removing setuid, setgid and sticky bits
+    pub normalize_permissions: bool,
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, serde::Serialize)]
 pub struct CargoLockingFlags {
     /// `--offline`
     pub offline: bool,
@@ -204,6 +691,7 @@ impl Default for CargoDebOptions {
     fn default() -> Self {
         Self {
             no_build: false,
+            repackage: false,
             strip_override: None,
             separate_debug_symbols: None,
             compress_debug_symbols: None,
@@ -217,38 +705,92 @@ impl Default for CargoDebOptions {
             manifest_path: None,
             cargo_build_cmd: "build".into(),
             cargo_build_flags: Vec::new(),
+            auditable: false,
             overrides: DebConfigOverrides::default(),
             compress_type: Format::Xz,
             compress_system: false,
             system_xz: false,
             rsyncable: false,
+            compress_tuning: CompressTuning::default(),
             profile: None,
             cargo_locking_flags: CargoLockingFlags::default(),
+            check_against: None,
+            against: Vec::new(),
+            cache: false,
+            refresh: false,
+            jobs: None,
+            workspace: false,
+            allow_foreign_arch: false,
+            delete_temp: false,
+            print_hash: false,
+            hash_in_report: false,
+            check_hardening: None,
+            bundle: false,
+            previous: Vec::new(),
+            add_breaks_replaces: false,
+            compare: None,
+            emit: Vec::new(),
+            normalize_permissions: false,
         }
     }
 }
 
+/// With `--locked` the package must be built from reviewed dependencies, so `Cargo.lock` can't have uncommitted changes
+fn check_lockfile_committed(workspace_root: &Path, listener: &dyn Listener) -> CDResult<()> {
+    let lock_path = workspace_root.join("Cargo.lock");
+    if !lock_path.exists() {
+        return Err(CargoDebError::LockfileNotCommitted(lock_path, "it doesn't exist".into()));
+    }
+    let output = match build_log::run_checked_output(Command::new("git").current_dir(workspace_root).args(["status", "--porcelain", "--ignored", "--", "Cargo.lock"]), listener) {
+        Ok(output) => output,
+        Err(_) => {
+            listener.warning(format!("Unable to check with git whether {} is committed", lock_path.display()));
+            return Ok(());
+        },
+    };
+    let status = String::from_utf8_lossy(&output);
+    let reason = match status.get(..2) {
+        None => return Ok(()),
+        Some("??") => "it's not tracked by git",
+        Some("!!") => "it's ignored by git",
+        Some(_) => "it has uncommitted changes",
+    };
+    Err(CargoDebError::LockfileNotCommitted(lock_path, reason.into()))
+}
+
 /// Run `dpkg` to install `deb` archive at the given path
-pub fn install_deb(path: &Path) -> CDResult<()> {
-    let status = Command::new("sudo").arg("dpkg").arg("-i").arg(path)
-        .status()?;
+pub fn install_deb(path: &Path, listener: &dyn Listener) -> CDResult<()> {
+    let status = build_log::run_logged(Command::new("sudo").arg("dpkg").arg("-i").arg(path), listener)?;
     if !status.success() {
         return Err(CargoDebError::InstallFailed);
     }
     Ok(())
 }
 
-pub fn write_deb(config: &Config, package_deb: &PackageConfig, &compress::CompressConfig { fast, compress_type, compress_system, rsyncable }: &compress::CompressConfig, listener: &dyn Listener) -> Result<PathBuf, CargoDebError> {
+pub fn write_deb(config: &Config, package_deb: &PackageConfig, compress_config: &compress::CompressConfig, listener: &dyn Listener) -> Result<PathBuf, CargoDebError> {
+    write_deb_with_control(config, package_deb, &package_deb.generate_control(config)?, compress_config, listener)
+}
+
+/// Same as `write_deb`, but uses the given contents of the `control` file
+pub fn write_deb_with_control(config: &Config, package_deb: &PackageConfig, control: &[u8], &compress::CompressConfig { fast, compress_type, compress_system, rsyncable, tuning }: &compress::CompressConfig, listener: &dyn Listener) -> Result<PathBuf, CargoDebError> {
+    // The compressed size is known only at the end, but it's usually smaller than the data
+    let data_size = package_deb.installed_size() * 1024;
+    if data_size > deb::ar::LARGE_MEMBER_SIZE {
+        listener.warning(format!(
+            "The package has {:.1} GB of data. Members of .deb archives over 4 GiB can't be read by some tools, and over {} bytes after compression can't be written at all. Consider moving large assets to another package",
+            data_size as f64 / 1e9, deb::ar::MAX_MEMBER_SIZE,
+        ));
+    }
     let (control_builder, data_result) = rayon::join(
         move || {
             // The control archive is the metadata for the package manager
-            let mut control_builder = ControlArchiveBuilder::new(util::compress::select_compressor(fast, compress_type, compress_system)?, package_deb.default_timestamp, listener);
-            control_builder.generate_archive(config, package_deb)?;
+            let mut control_builder = ControlArchiveBuilder::new(util::compress::select_compressor(fast, compress_type, compress_system, CompressTuning::default())?, package_deb.default_timestamp, listener);
+            control_builder.generate_archive_with_control(config, package_deb, control)?;
             Ok::<_, CargoDebError>(control_builder)
         },
         move || {
             // Initialize the contents of the data archive (files that go into the filesystem).
-            let dest = util::compress::select_compressor(fast, compress_type, compress_system)?;
+            let dest = util::compress::select_compressor(fast, compress_type, compress_system, tuning)?;
             let archive = Tarball::new(dest, package_deb.default_timestamp);
             let (compressed, asset_hashes) = archive.archive_files(package_deb, rsyncable, listener)?;
             let sums = package_deb.generate_sha256sums(&asset_hashes)?;
@@ -280,8 +822,19 @@ pub fn write_deb(config: &Config, package_deb: &PackageConfig, &compress::Compre
 }
 
 /// Builds a binary with `cargo build`
-pub fn cargo_build(config: &Config, target: Option<&str>, build_command: &str, build_flags: &[String], verbose: bool) -> CDResult<()> {
+pub fn cargo_build(config: &Config, target: Option<&str>, build_command: &str, build_flags: &[String], verbose: bool, listener: &dyn Listener) -> CDResult<()> {
+    let mut cmd = cargo_build_command(config, target, build_command, build_flags, verbose);
+
+    log::debug!("cargo build {:?}", cmd.get_args());
+
+    build_log::run_cargo(&mut cmd, config.quiet_cargo, listener)
+}
+
+fn cargo_build_command(config: &Config, target: Option<&str>, build_command: &str, build_flags: &[String], verbose: bool) -> Command {
     let mut cmd = Command::new("cargo");
+    if config.clean_env {
+        clean_cargo_env(&mut cmd);
+    }
     cmd.current_dir(&config.package_manifest_dir);
     cmd.args(build_command.split(' ')
         .filter(|cmd| if !cmd.starts_with('-') { true } else {
@@ -297,8 +850,8 @@ pub fn cargo_build(config: &Config, target: Option<&str>, build_command: &str, b
     if let Some(target) = target {
         cmd.args(["--target", target]);
         // Set helpful defaults for cross-compiling
-        if env::var_os("PKG_CONFIG_ALLOW_CROSS").is_none() && env::var_os("PKG_CONFIG_PATH").is_none() {
-            let pkg_config_path = format!("/usr/lib/{}/pkgconfig", debian_triple_from_rust_triple(target));
+        if let Some(debian_triple) = config.debian_triple.as_deref().filter(|_| config.clean_env || (env::var_os("PKG_CONFIG_ALLOW_CROSS").is_none() && env::var_os("PKG_CONFIG_PATH").is_none())) {
+            let pkg_config_path = format!("/usr/lib/{debian_triple}/pkgconfig");
             if Path::new(&pkg_config_path).exists() {
                 cmd.env("PKG_CONFIG_ALLOW_CROSS", "1");
                 cmd.env("PKG_CONFIG_PATH", pkg_config_path);
@@ -312,19 +865,69 @@ pub fn cargo_build(config: &Config, target: Option<&str>, build_command: &str, b
     if !features.is_empty() {
         cmd.args(["--features", &features.join(",")]);
     }
+    if !config.rustflags.is_empty() {
+        // Any rustflags variable replaces Cargo's config, so the flags it would have used have to be included
+        let mut rustflags = configured_rustflags(config, target);
+        rustflags.extend(config.rustflags.iter().cloned());
+        cmd.env_remove("RUSTFLAGS");
+        cmd.env("CARGO_ENCODED_RUSTFLAGS", rustflags.join("\x1f"));
+    }
+    cmd
+}
 
-    log::debug!("cargo build {:?}", cmd.get_args());
+/// Flags that Cargo would pass to rustc: from `CARGO_ENCODED_RUSTFLAGS`, `RUSTFLAGS`,
+/// target-specific or `build.rustflags` in `CARGO_*` variables, or the Cargo config, whichever comes first.
+fn configured_rustflags(config: &Config, target: Option<&str>) -> Vec<String> {
+    let target = target.unwrap_or(DEFAULT_TARGET);
+    if !config.clean_env {
+        if let Some(encoded) = env::var_os("CARGO_ENCODED_RUSTFLAGS") {
+            return encoded.to_string_lossy().split('\x1f').filter(|f| !f.is_empty()).map(From::from).collect();
+        }
+        let target_var = format!("CARGO_TARGET_{}_RUSTFLAGS", target.to_ascii_uppercase().replace(['-', '.'], "_"));
+        for var in ["RUSTFLAGS", &target_var] {
+            if let Some(flags) = env::var_os(var) {
+                return flags.to_string_lossy().split_whitespace().map(From::from).collect();
+            }
+        }
+    }
+    let cargo_config = config.cargo_config().ok().flatten();
+    if let Some(flags) = cargo_config.as_ref().and_then(|conf| conf.target_rustflags(target)) {
+        return flags;
+    }
+    match env::var_os("CARGO_BUILD_RUSTFLAGS") {
+        Some(flags) if !config.clean_env => flags.to_string_lossy().split_whitespace().map(From::from).collect(),
+        _ => cargo_config.and_then(|conf| conf.build_rustflags()).unwrap_or_default(),
+    }
+}
 
-    let status = cmd.status()
-        .map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
-    if !status.success() {
-        return Err(CargoDebError::BuildFailed);
+/// Variables set for `cargo build`. With `--clean-env` that's all of them.
+fn build_environment(config: &Config, target: Option<&str>) -> Vec<(OsString, OsString)> {
+    cargo_build_command(config, target, "build", &[], false).get_envs()
+        .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+        .collect()
+}
+
+/// For `--clean-env`. Keeps only what Cargo and rustup need to work, so that variables of the host
+/// (like `CC`, `PKG_CONFIG_PATH` or `RUSTFLAGS`) can't affect the build. Rustflags can still be set in `.cargo/config.toml`.
+fn clean_cargo_env(cmd: &mut Command) {
+    cmd.env_clear();
+    for (key, value) in env::vars_os() {
+        let Some(name) = key.to_str() else { continue };
+        let keep = matches!(name, "PATH" | "HOME" | "TMPDIR" | "SOURCE_DATE_EPOCH")
+            || name.starts_with("RUSTUP_")
+            || (name.starts_with("CARGO_") && !name.ends_with("RUSTFLAGS"));
+        if keep {
+            cmd.env(key, value);
+        }
     }
-    Ok(())
 }
 
 // Maps Rust's blah-unknown-linux-blah to Debian's blah-linux-blah. This is debian's multiarch.
-fn debian_triple_from_rust_triple(rust_target_triple: &str) -> String {
+// It's looked up by the Debian architecture first, which may have been set with arch-map.
+fn debian_triple_from_rust_triple(rust_target_triple: &str, debian_arch: &str) -> String {
+    if let Some(tuple) = arch::multiarch_tuple(debian_arch) {
+        return tuple.into();
+    }
     let mut p = rust_target_triple.split('-');
     let arch = p.next().unwrap();
     let abi = p.last().unwrap_or("gnu");
@@ -344,46 +947,6 @@ fn debian_triple_from_rust_triple(rust_target_triple: &str) -> String {
     format!("{darch}-linux-{dabi}")
 }
 
-/// Debianizes the architecture name. Weirdly, architecture and multiarch use different naming conventions in Debian!
-pub(crate) fn debian_architecture_from_rust_triple(target: &str) -> &str {
-    let mut parts = target.split('-');
-    let arch = parts.next().unwrap();
-    let abi = parts.last().unwrap_or("");
-    match (arch, abi) {
-        // https://wiki.debian.org/Multiarch/Tuples
-        // rustc --print target-list
-        // https://doc.rust-lang.org/std/env/consts/constant.ARCH.html
-        ("aarch64", _) => "arm64",
-        ("mips64", "gnuabin32") => "mipsn32",
-        ("mips64el", "gnuabin32") => "mipsn32el",
-        ("mipsisa32r6", _) => "mipsr6",
-        ("mipsisa32r6el", _) => "mipsr6el",
-        ("mipsisa64r6", "gnuabi64") => "mips64r6",
-        ("mipsisa64r6", "gnuabin32") => "mipsn32r6",
-        ("mipsisa64r6el", "gnuabi64") => "mips64r6el",
-        ("mipsisa64r6el", "gnuabin32") => "mipsn32r6el",
-        ("powerpc", "gnuspe") => "powerpcspe",
-        ("powerpc64", _) => "ppc64",
-        ("powerpc64le", _) => "ppc64el",
-        ("riscv64gc", _) => "riscv64",
-        ("i586" | "i686" | "x86", _) => "i386",
-        ("x86_64", "gnux32") => "x32",
-        ("x86_64", _) => "amd64",
-        ("loongarch64", _) => "loong64",
-        (arm, gnueabi) if arm.starts_with("arm") && gnueabi.ends_with("hf") => "armhf",
-        (arm, _) if arm.starts_with("arm") => "armel",
-        (other_arch, _) => other_arch,
-    }
-}
-
-fn ensure_success(status: ExitStatus) -> io::Result<()> {
-    if status.success() {
-        Ok(())
-    } else {
-        Err(io::Error::new(io::ErrorKind::Other, status.to_string()))
-    }
-}
-
 /// Strips the binary that was created with cargo
 pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, target: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
     let mut cargo_config = None;
@@ -391,17 +954,25 @@ pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, targ
     let strip_tmp;
     let mut objcopy_cmd = Path::new("objcopy");
     let mut strip_cmd = Path::new("strip");
+    let objcopy_env = env::var_os("CARGO_DEB_OBJCOPY").map(PathBuf::from);
+    let strip_env = env::var_os("CARGO_DEB_STRIP").map(PathBuf::from);
 
-    if let Some(target) = target {
+    if let Some(cmd) = &objcopy_env {
+        objcopy_cmd = cmd;
+    }
+    if let Some(cmd) = &strip_env {
+        strip_cmd = cmd;
+    }
+    if let Some(target) = target.filter(|_| objcopy_env.is_none() || strip_env.is_none()) {
         cargo_config = config.cargo_config()?;
         if let Some(ref conf) = cargo_config {
-            if let Some(cmd) = conf.objcopy_command(target) {
+            if let Some(cmd) = conf.objcopy_command(target).filter(|_| objcopy_env.is_none()) {
                 listener.info(format!("Using '{}' for '{target}'", cmd.display()));
                 objcopy_tmp = cmd;
                 objcopy_cmd = &objcopy_tmp;
             }
 
-            if let Some(cmd) = conf.strip_command(target) {
+            if let Some(cmd) = conf.strip_command(target).filter(|_| strip_env.is_none()) {
                 listener.info(format!("Using '{}' for '{target}'", cmd.display()));
                 strip_tmp = cmd;
                 strip_cmd = &strip_tmp;
@@ -409,7 +980,7 @@ pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, targ
         }
     }
 
-    let stripped_binaries_output_dir = config.default_deb_output_dir();
+    let stripped_binaries_output_dir = config.deb_temp_dir(package_deb);
     let (separate_debug_symbols, compress_debug_symbols) = match config.debug_symbols {
         DebugSymbols::Keep | DebugSymbols::Strip => (false, false),
         DebugSymbols::Separate { compress } => (true, compress),
@@ -430,18 +1001,16 @@ pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, targ
             let _ = fs::remove_file(&stripped_temp_path);
 
             log::debug!("stripping with {} from {} into {}", strip_cmd.display(), path.display(), stripped_temp_path.display());
-            Command::new(strip_cmd)
+            build_log::run_checked(Command::new(strip_cmd)
                // same as dh_strip
                .args(["--strip-unneeded", "--remove-section=.comment", "--remove-section=.note"])
                .arg("-o").arg(&stripped_temp_path)
-               .arg(path)
-               .status()
-               .and_then(ensure_success)
+               .arg(path), listener)
                .map_err(|err| {
                     if let Some(target) = target {
-                        CargoDebError::StripFailed(path.to_owned(), format!("{}: {}.\nhint: Target-specific strip commands are configured in [target.{}] strip = {{ path = \"{}\" }} in {}", strip_cmd.display(), err, target, strip_cmd.display(), conf_path.display()))
+                        CargoDebError::StripFailed(path.to_owned(), format!("{err}.\nhint: Target-specific strip commands are configured in [target.{}] strip = {{ path = \"{}\" }} in {}, or set with CARGO_DEB_STRIP", target, strip_cmd.display(), conf_path.display()))
                     } else {
-                        CargoDebError::CommandFailed(err, "strip")
+                        err
                     }
                 })?;
 
@@ -453,7 +1022,7 @@ pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, targ
                 log::debug!("extracting debug info with {} from {}", objcopy_cmd.display(), path.display());
 
                 // parse the ELF and use debug-id-based path if available
-                let debug_target_path = get_target_debug_path(asset, path)?;
+                let (debug_target_path, build_id) = get_target_debug_path(asset, path);
 
                 // --add-gnu-debuglink reads the file path given, so it can't get to-be-installed target path
                 // and the recommended fallback solution is to give it relative path in the same dir
@@ -464,39 +1033,39 @@ pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, targ
                 if !compress_debug_symbols {
                     args = &args[..1];
                 }
-                Command::new(objcopy_cmd)
+                build_log::run_checked(Command::new(objcopy_cmd)
                     .args(args)
                     .arg(path)
-                    .arg(&debug_temp_path)
-                    .status()
-                    .and_then(ensure_success)
+                    .arg(&debug_temp_path), listener)
                     .map_err(|err| {
                         if let Some(target) = target {
-                            CargoDebError::StripFailed(path.to_owned(), format!("{}: {}.\nhint: Target-specific strip commands are configured in [target.{}] objcopy = {{ path =\"{}\" }} in {}", objcopy_cmd.display(), err, target, objcopy_cmd.display(), conf_path.display()))
+                            CargoDebError::StripFailed(path.to_owned(), format!("{err}.\nhint: Target-specific strip commands are configured in [target.{}] objcopy = {{ path =\"{}\" }} in {}, or set with CARGO_DEB_OBJCOPY", target, objcopy_cmd.display(), conf_path.display()))
                         } else {
-                            CargoDebError::CommandFailed(err, "objcopy")
+                            err
                         }
                     })?;
 
                 let relative_debug_temp_path = debug_temp_path.file_name().ok_or(CargoDebError::Str("bad path"))?;
                 log::debug!("linking debug info with {} from {} into {:?}", objcopy_cmd.display(), stripped_temp_path.display(), relative_debug_temp_path);
-                Command::new(objcopy_cmd)
+                build_log::run_checked(Command::new(objcopy_cmd)
                     .current_dir(debug_temp_path.parent().ok_or(CargoDebError::Str("bad path"))?)
                     .arg("--add-gnu-debuglink")
                     // intentionally relative - the file name must match debug_target_path
                     .arg(relative_debug_temp_path)
-                    .arg(&stripped_temp_path)
-                    .status()
-                    .and_then(ensure_success)
-                    .map_err(|err| CargoDebError::CommandFailed(err, "objcopy"))?;
-
-                Some(Asset::new(
+                    .arg(&stripped_temp_path), listener)?;
+
+                let build_id = build_id.map(|build_id| BuildId {
+                    build_id,
+                    path: Path::new("/").join(&asset.c.target_path),
+                    debug_path: Path::new("/").join(&debug_target_path),
+                });
+                Some((Asset::new(
                     AssetSource::Path(debug_temp_path),
                     debug_target_path,
                     0o644,
                     IsBuilt::No,
                     false,
-                ).processed(if compress_debug_symbols { "compress"} else {"separate"}, path.to_path_buf()))
+                ).processed(if compress_debug_symbols { "compress"} else {"separate"}, path.to_path_buf()), build_id))
             } else {
                 None // no new asset
             };
@@ -517,37 +1086,116 @@ pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, targ
         Ok::<_, CargoDebError>(new_debug_asset)
     }).collect::<Result<Vec<_>, _>>()?;
 
-    package_deb.assets.resolved
-        .extend(added_debug_assets.into_iter().flatten());
+    for (debug_asset, build_id) in added_debug_assets.into_iter().flatten() {
+        if let Some(build_id) = build_id {
+            listener.info(format!("Build-id {} for {}", build_id.build_id, build_id.path.display()));
+            package_deb.build_ids.push(build_id);
+        }
+        package_deb.assets.resolved.push(debug_asset);
+    }
 
     Ok(())
 }
 
-fn get_target_debug_path(asset: &Asset, asset_path: &Path) -> Result<PathBuf, CargoDebError> {
-    let target_debug_path = match elf_gnu_debug_id(asset_path) {
-        Ok(Some(path)) => {
-            log::debug!("got gnu debug-id: {} for {}", path.display(), asset_path.display());
-            path
+/// Applies `transform` steps configured for assets, e.g. compression of executables.
+///
+/// Runs after stripping, and before the files are archived (and hashed).
+pub fn transform_assets(config: &Config, package_deb: &mut PackageConfig, target: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
+    if package_deb.assets.resolved.iter().all(|a| a.c.transforms.is_empty()) {
+        return Ok(());
+    }
+
+    let objcopy_cmd = match (env::var_os("CARGO_DEB_OBJCOPY"), target) {
+        (Some(cmd), _) => Some(cmd.into()),
+        (None, Some(target)) => config.cargo_config()?.and_then(|conf| conf.objcopy_command(target).map(|cmd| cmd.into_owned())),
+        (None, None) => None,
+    }.unwrap_or_else(|| "objcopy".into());
+    let upx_cmd = env::var_os("CARGO_DEB_UPX").unwrap_or_else(|| "upx".into());
+
+    let temp_dir = config.deb_temp_dir(package_deb);
+    package_deb.assets.resolved.par_iter_mut().enumerate()
+        .filter(|(_, asset)| !asset.c.transforms.is_empty())
+        .try_for_each(|(i, asset)| {
+            let transforms = asset.c.transforms.clone();
+            for transform in transforms {
+                let file_name = asset.c.target_path.file_name().ok_or(CargoDebError::Str("bad path"))?.to_string_lossy().into_owned();
+                let input_path;
+                let path = match &asset.source {
+                    AssetSource::Path(path) => path.as_path(),
+                    AssetSource::Data(data) => {
+                        // e.g. a template, so it has to be saved before running a command on it
+                        input_path = temp_dir.join(format!("{file_name}.tmp{i}-input"));
+                        fs::write(&input_path, data).map_err(|e| CargoDebError::IoFile("unable to save asset for transform", e, input_path.clone()))?;
+                        &input_path
+                    },
+                    AssetSource::Symlink(_) => {
+                        listener.warning(format!("Can't apply {} to '{}', because it's a symlink", transform.name(), asset.c.target_path.display()));
+                        return Ok(());
+                    },
+                };
+                let output_path = temp_dir.join(format!("{file_name}.tmp{i}-{}", transform.name()));
+                let _ = fs::remove_file(&output_path);
+
+                log::debug!("applying {} to {} into {}", transform.name(), path.display(), output_path.display());
+                let mut cmd = match transform {
+                    // already expanded by `expand_templates`
+                    AssetTransform::Template => continue,
+                    AssetTransform::CompressDebugSections => {
+                        let mut cmd = Command::new(&objcopy_cmd);
+                        cmd.arg("--compress-debug-sections").arg(path).arg(&output_path);
+                        cmd
+                    },
+                    AssetTransform::Upx => {
+                        let mut cmd = Command::new(&upx_cmd);
+                        cmd.args(["--best", "-q"]).arg("-o").arg(&output_path).arg(path);
+                        cmd
+                    },
+                };
+                build_log::run_checked(&mut cmd, listener)?;
+
+                listener.info(format!("Applied {} to '{}'", transform.name(), asset.c.target_path.display()));
+                let old_source = std::mem::replace(&mut asset.source, AssetSource::Path(output_path));
+                let original_path = asset.processed_from.take().and_then(|p| p.original_path).or_else(|| old_source.into_path());
+                asset.processed_from = Some(ProcessedFrom {
+                    original_path,
+                    action: transform.name(),
+                });
+            }
+            Ok::<_, CargoDebError>(())
+        })
+}
+
+/// Returns the debug file path, and the GNU build-id (as a hex string) if the ELF has one
+fn get_target_debug_path(asset: &Asset, asset_path: &Path) -> (PathBuf, Option<String>) {
+    match elf_gnu_build_id(asset_path) {
+        Ok(Some(build_id)) => {
+            log::debug!("got gnu build-id: {build_id} for {}", asset_path.display());
+            (build_id_debug_path(&build_id), Some(build_id))
         },
         Ok(None) => {
-            log::debug!("debug-id not found in {}", asset_path.display());
-            asset.c.default_debug_target_path()
+            log::debug!("build-id not found in {}", asset_path.display());
+            (asset.c.default_debug_target_path(), None)
         },
         Err(e) => {
             log::debug!("elf: {e} in {}", asset_path.display());
-            asset.c.default_debug_target_path()
+            (asset.c.default_debug_target_path(), None)
         },
-    };
-    Ok(target_debug_path)
+    }
+}
+
+/// `usr/lib/debug/.build-id/xx/yyyy.debug`, where `xx` is the first byte of the build-id
+fn build_id_debug_path(build_id: &str) -> PathBuf {
+    let (dir, file) = build_id.split_at(2.min(build_id.len()));
+    format!("usr/lib/debug/.build-id/{dir}/{file}.debug").into()
 }
 
 #[cfg(not(feature = "debug-id"))]
-fn elf_gnu_debug_id(_: &Path) -> io::Result<Option<PathBuf>> {
+fn elf_gnu_build_id(_: &Path) -> std::io::Result<Option<String>> {
     Ok(None)
 }
 
 #[cfg(feature = "debug-id")]
-fn elf_gnu_debug_id(elf_file_path: &Path) -> Result<Option<PathBuf>, elf::ParseError> {
+fn elf_gnu_build_id(elf_file_path: &Path) -> Result<Option<String>, elf::ParseError> {
     use elf::endian::AnyEndian;
     use elf::note::Note;
     use elf::ElfStream;
@@ -559,20 +1207,111 @@ fn elf_gnu_debug_id(elf_file_path: &Path) -> Result<Option<PathBuf>, elf::ParseE
     let abi_shdr = *abi_shdr;
     for note in stream.section_data_as_notes(&abi_shdr)? {
         if let Note::GnuBuildId(note) = note {
-            if let Some((byte, rest)) = note.0.split_first() {
-                let mut s = format!("usr/lib/debug/.build-id/{byte:02x}/");
-                for b in rest {
-                    use std::fmt::Write;
-                    write!(&mut s, "{b:02x}").unwrap();
-                }
-                s.push_str(".debug");
-                return Ok(Some(s.into()));
+            if note.0.len() < 2 {
+                continue;
             }
+            let mut s = String::with_capacity(note.0.len() * 2);
+            for b in note.0 {
+                use std::fmt::Write;
+                write!(&mut s, "{b:02x}").unwrap();
+            }
+            return Ok(Some(s));
         }
     }
     Ok(None)
 }
 
+/// Writes `<build-id> <binary path> <debug file path>` lines next to the `.deb`,
+/// so that crash reports can be matched with the package that has the debug symbols.
+///
+/// Returns `None` if there are no build-ids (e.g. debug symbols haven't been separated).
+pub fn write_build_id_index(deb_path: &Path, package_deb: &PackageConfig) -> CDResult<Option<PathBuf>> {
+    if package_deb.build_ids.is_empty() {
+        return Ok(None);
+    }
+    let mut index = String::with_capacity(package_deb.build_ids.len() * 100);
+    for b in &package_deb.build_ids {
+        use std::fmt::Write;
+        let _ = writeln!(&mut index, "{} {} {}", b.build_id, b.path.display(), b.debug_path.display());
+    }
+    let index_path = deb_path.with_extension("build-ids");
+    util::atomic_file::write(&index_path, index)
+        .map_err(|e| CargoDebError::IoFile("unable to write build-id index", e, index_path.clone()))?;
+    Ok(Some(index_path))
+}
+
+/// Writes `<deb>.sha256` in the format of `sha256sum`, and returns `Filename`, `Size`, `SHA256` and `SHA512` lines
+/// like in the `Packages` index of an APT repository.
+pub fn write_checksums(deb_path: &Path) -> CDResult<String> {
+    let (size, sha256, sha512) = hash_file(deb_path)?;
+    write_checksums_of(deb_path, size, &sha256, &sha512)
+}
+
+fn write_checksums_of(deb_path: &Path, size: u64, sha256: &str, sha512: &str) -> CDResult<String> {
+    let file_name = deb_path.file_name().ok_or(CargoDebError::Str("bad path"))?.to_string_lossy();
+    let sidecar_path = checksums_path(deb_path);
+    util::atomic_file::write(&sidecar_path, format!("{sha256}  {file_name}\n"))
+        .map_err(|e| CargoDebError::IoFile("unable to write the checksum file", e, sidecar_path.clone()))?;
+    Ok(format!("Filename: {file_name}\nSize: {size}\nSHA256: {sha256}\nSHA512: {sha512}\n"))
+}
+
+/// `<deb>.sha256`
+fn checksums_path(deb_path: &Path) -> PathBuf {
+    let mut path = deb_path.as_os_str().to_owned();
+    path.push(".sha256");
+    path.into()
+}
+
+/// Size, SHA256 and SHA512 (lowercase hex) of the file
+pub fn hash_file(deb_path: &Path) -> CDResult<(u64, String, String)> {
+    use sha2::{Digest, Sha256, Sha512};
+    use std::io::Read;
+
+    let mut file = fs::File::open(deb_path).map_err(|e| CargoDebError::IoFile("unable to read the package", e, deb_path.into()))?;
+    let mut sha256 = Sha256::new();
+    let mut sha512 = Sha512::new();
+    let mut size = 0;
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let len = file.read(&mut buf).map_err(|e| CargoDebError::IoFile("unable to read the package", e, deb_path.into()))?;
+        if len == 0 {
+            break;
+        }
+        sha256.update(&buf[..len]);
+        sha512.update(&buf[..len]);
+        size += len as u64;
+    }
+    Ok((size, util::hex(&sha256.finalize()), util::hex(&sha512.finalize())))
+}
+
+#[test]
+fn checksums_of_deb() {
+    let dir = tempfile::tempdir().unwrap();
+    let deb_path = dir.path().join("test_1.0_all.deb");
+    fs::write(&deb_path, "hello\n").unwrap();
+    let summary = write_checksums(&deb_path).unwrap();
+    let sha256 = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+    assert!(summary.starts_with(&format!("Filename: test_1.0_all.deb\nSize: 6\nSHA256: {sha256}\nSHA512: ")));
+    assert_eq!(format!("{sha256}  test_1.0_all.deb\n"), fs::read_to_string(dir.path().join("test_1.0_all.deb.sha256")).unwrap());
+}
+
+#[test]
+fn phases_state_is_serializable() {
+    let mut mock_listener = listener::MockListener::new();
+    mock_listener.expect_info().return_const(());
+
+    let mut cargo_deb = CargoDeb::new(CargoDebOptions { no_build: true, ..CargoDebOptions::default() });
+    let manifest = cargo_deb.resolve_manifest(&mock_listener).unwrap();
+    let json = serde_json::to_value(&manifest).unwrap();
+    assert_eq!(json["package_deb"]["deb_name"], "cargo-deb");
+    assert_eq!(json["package_deb"]["assets"]["unresolved"][0]["c"]["target_path"], "usr/bin/");
+}
+
+#[test]
+fn build_id_paths() {
+    assert_eq!(Path::new("usr/lib/debug/.build-id/ab/cdef0123.debug"), build_id_debug_path("abcdef0123"));
+}
+
 #[cfg(target_os = "linux")]
 fn warn_if_not_linux() {
 }
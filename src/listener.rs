@@ -6,10 +6,23 @@ pub trait Listener: Send + Sync {
     fn warning(&self, s: String);
     fn info(&self, s: String);
 
+    /// Details, like commands that have been run, that are only written to the build log
+    fn log(&self, _s: String) {}
+
     /// Notified when finished writing .deb file (possibly before install)
     fn generated_archive(&self, path: &Path) {
         println!("{}", path.display());
     }
+
+    /// With `--print-hash`, `Filename`, `Size`, `SHA256` and `SHA512` lines of the .deb
+    fn generated_checksums(&self, summary: &str) {
+        print!("{summary}");
+    }
+
+    /// With `--compare`, changes since the previous version of the package
+    fn generated_comparison(&self, changes: &str) {
+        print!("{changes}");
+    }
 }
 
 pub struct NoOpListener;
@@ -17,6 +30,8 @@ impl Listener for NoOpListener {
     fn info(&self, _s: String) {}
     fn warning(&self, _s: String) {}
     fn generated_archive(&self, _: &Path) {}
+    fn generated_checksums(&self, _: &str) {}
+    fn generated_comparison(&self, _: &str) {}
 }
 
 pub struct StdErrListener {
@@ -24,12 +39,12 @@ pub struct StdErrListener {
 }
 impl Listener for StdErrListener {
     fn warning(&self, s: String) {
-        let _ = writeln!(std::io::stdout(), "warning: {s}");
+        let _ = writeln!(std::io::stdout(), "cargo-deb: warning: {s}");
     }
 
     fn info(&self, s: String) {
         if self.verbose {
-            let _ = writeln!(std::io::stdout(), "info: {s}");
+            let _ = writeln!(std::io::stdout(), "cargo-deb: info: {s}");
         }
     }
 }
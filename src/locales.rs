@@ -0,0 +1,275 @@
+//! Translations from the `locales` dir, installed as `usr/share/locale/<lang>/LC_MESSAGES/<domain>.mo`.
+//!
+//! `.po` files are compiled to `.mo` here, so `msgfmt` isn't needed.
+use crate::error::{CDResult, CargoDebError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A translation found in the locales dir
+#[derive(Debug, PartialEq)]
+pub(crate) struct Translation {
+    pub source: PathBuf,
+    /// Install path, relative to `/`
+    pub target: PathBuf,
+}
+
+/// Finds translations in any of these layouts:
+///
+/// * `<dir>/<lang>.po` or `.mo` (the domain is `default_domain`)
+/// * `<dir>/<lang>/<domain>.po` or `.mo`
+/// * `<dir>/<lang>/LC_MESSAGES/<domain>.po` or `.mo`
+pub(crate) fn find_translations(dir: &Path, default_domain: &str) -> CDResult<Vec<Translation>> {
+    let mut found = Vec::new();
+    let entries = fs::read_dir(dir).map_err(|e| CargoDebError::IoFile("unable to read the locales dir", e, dir.into()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+        if path.is_dir() {
+            if !is_language(name) {
+                continue;
+            }
+            let messages_dir = path.join("LC_MESSAGES");
+            let lang_dir = if messages_dir.is_dir() { messages_dir } else { path.clone() };
+            for file in fs::read_dir(&lang_dir).map_err(|e| CargoDebError::IoFile("unable to read the locales dir", e, lang_dir.clone()))? {
+                let file = file?.path();
+                if let Some((domain, _)) = translation_file_name(&file) {
+                    found.push(Translation { target: target_path(name, domain), source: file.clone() });
+                }
+            }
+        } else if let Some((lang, _)) = translation_file_name(&path).filter(|(lang, _)| is_language(lang)) {
+            found.push(Translation { target: target_path(lang, default_domain), source: path.clone() });
+        }
+    }
+    found.sort_by(|a, b| a.target.cmp(&b.target).then_with(|| a.source.cmp(&b.source)));
+    // a compiled .mo next to its .po is the same translation
+    found.dedup_by(|b, a| a.target == b.target);
+    Ok(found)
+}
+
+fn target_path(lang: &str, domain: &str) -> PathBuf {
+    format!("usr/share/locale/{lang}/LC_MESSAGES/{domain}.mo").into()
+}
+
+/// File stem and extension of `.po` and `.mo` files
+fn translation_file_name(path: &Path) -> Option<(&str, &str)> {
+    let ext = path.extension()?.to_str()?;
+    (ext == "po" || ext == "mo").then_some(())?;
+    Some((path.file_stem()?.to_str()?, ext))
+}
+
+/// `de`, `pt_BR`, `sr@latin`, but not `POTFILES` or `messages`
+fn is_language(name: &str) -> bool {
+    let (name, modifier) = name.split_once('@').unwrap_or((name, "latin"));
+    let (lang, country) = name.split_once('_').unwrap_or((name, "XX"));
+    (2..=3).contains(&lang.len()) && lang.bytes().all(|c| c.is_ascii_lowercase()) &&
+        (2..=3).contains(&country.len()) && country.bytes().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) &&
+        !modifier.is_empty() && modifier.bytes().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Contents of the `.mo` file for a `.po` file
+pub(crate) fn compile_po_file(source: &Path) -> CDResult<Vec<u8>> {
+    let data = fs::read(source).map_err(|e| CargoDebError::IoFile("unable to read translation", e, source.into()))?;
+    let po = String::from_utf8(data).map_err(|_| CargoDebError::IoFile("translation is not UTF-8", std::io::ErrorKind::InvalidData.into(), source.into()))?;
+    Ok(compile_po(&po))
+}
+
+#[derive(Default)]
+struct PoEntry {
+    context: Option<String>,
+    id: Option<String>,
+    id_plural: Option<String>,
+    strs: BTreeMap<usize, String>,
+    fuzzy: bool,
+}
+
+/// Which string the continuation lines belong to
+#[derive(Clone, Copy)]
+enum Field {
+    Context,
+    Id,
+    IdPlural,
+    Str(usize),
+}
+
+/// Compiles gettext PO to the MO format, like `msgfmt` does: fuzzy, obsolete and untranslated messages are left out.
+fn compile_po(po: &str) -> Vec<u8> {
+    let mut messages = BTreeMap::new();
+    let mut entry = PoEntry::default();
+    let mut field = None;
+    let mut finish = |entry: PoEntry| {
+        let Some(id) = entry.id else { return };
+        // the header (empty msgid) is kept even if fuzzy
+        if (entry.fuzzy && !id.is_empty()) || entry.strs.is_empty() || entry.strs.values().all(String::is_empty) {
+            return;
+        }
+        let mut key = entry.context.map(|c| format!("{c}\u{4}")).unwrap_or_default();
+        key.push_str(&id);
+        if let Some(plural) = entry.id_plural {
+            key.push('\0');
+            key.push_str(&plural);
+        }
+        messages.insert(key, entry.strs.into_values().collect::<Vec<_>>().join("\0"));
+    };
+
+    for line in po.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            // a comment starts the next entry
+            if field.is_some() {
+                finish(std::mem::take(&mut entry));
+                field = None;
+            }
+            if let Some(flags) = comment.strip_prefix(',') {
+                entry.fuzzy |= flags.split(',').any(|f| f.trim() == "fuzzy");
+            }
+            continue;
+        }
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let new_field = match keyword {
+            "msgctxt" => Some(Field::Context),
+            "msgid" => Some(Field::Id),
+            "msgid_plural" => Some(Field::IdPlural),
+            "msgstr" => Some(Field::Str(0)),
+            k => k.strip_prefix("msgstr[").and_then(|n| n.strip_suffix(']')?.parse().ok()).map(Field::Str),
+        };
+        let text = if let Some(new_field) = new_field {
+            // msgctxt or msgid after a msgstr starts the next entry
+            if matches!((field, new_field), (Some(Field::Str(_)), Field::Context | Field::Id)) {
+                finish(std::mem::take(&mut entry));
+            }
+            field = Some(new_field);
+            rest
+        } else {
+            line
+        };
+        let text = unquote(text.trim());
+        match field {
+            Some(Field::Context) => entry.context.get_or_insert_with(String::new).push_str(&text),
+            Some(Field::Id) => entry.id.get_or_insert_with(String::new).push_str(&text),
+            Some(Field::IdPlural) => entry.id_plural.get_or_insert_with(String::new).push_str(&text),
+            Some(Field::Str(n)) => entry.strs.entry(n).or_default().push_str(&text),
+            None => {},
+        }
+    }
+    finish(entry);
+    write_mo(&messages)
+}
+
+/// Contents of a C string literal in quotes
+fn unquote(s: &str) -> String {
+    let s = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(s);
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('a') => out.push('\u{7}'),
+            Some('b') => out.push('\u{8}'),
+            Some('f') => out.push('\u{c}'),
+            Some('v') => out.push('\u{b}'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Little-endian MO file without a hash table. Messages must be sorted by their key.
+fn write_mo(messages: &BTreeMap<String, String>) -> Vec<u8> {
+    const HEADER_LEN: u32 = 28;
+    let n = messages.len() as u32;
+    let ids_table = HEADER_LEN;
+    let strs_table = ids_table + 8 * n;
+    let mut offset = strs_table + 8 * n;
+    let mut tables = Vec::with_capacity(16 * n as usize);
+    let mut strings = Vec::new();
+    for text in messages.keys().chain(messages.values()) {
+        tables.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        tables.extend_from_slice(&offset.to_le_bytes());
+        strings.extend_from_slice(text.as_bytes());
+        strings.push(0);
+        offset += text.len() as u32 + 1;
+    }
+    let mut mo = Vec::with_capacity(offset as usize);
+    for word in [0x9504_12de, 0, n, ids_table, strs_table, 0, offset] {
+        mo.extend_from_slice(&u32::to_le_bytes(word));
+    }
+    mo.extend_from_slice(&tables);
+    mo.extend_from_slice(&strings);
+    mo
+}
+
+#[test]
+fn compiles_po_files() {
+    let po = r#"
+msgid ""
+msgstr ""
+"Content-Type: text/plain; charset=UTF-8\n"
+
+#: src/main.rs:10
+msgid "Hello"
+msgstr "Hallo"
+
+#, fuzzy
+msgid "Maybe"
+msgstr "Vielleicht"
+
+msgctxt "menu"
+msgid "File"
+msgstr ""
+"Da"
+"tei"
+
+msgid "Untranslated"
+msgstr ""
+
+msgid "one file"
+msgid_plural "%d files"
+msgstr[0] "eine Datei"
+msgstr[1] "%d Dateien"
+
+#~ msgid "Old"
+#~ msgstr "Alt"
+"#;
+    let mo = compile_po(po);
+    let word = |i: usize| u32::from_le_bytes(mo[i * 4..i * 4 + 4].try_into().unwrap()) as usize;
+    assert_eq!(0x9504_12de, word(0));
+    assert_eq!(4, word(2));
+    let string = |table: usize, i: usize| {
+        let (len, offset) = (word(table / 4 + i * 2), word(table / 4 + i * 2 + 1));
+        std::str::from_utf8(&mo[offset..offset + len]).unwrap()
+    };
+    let ids = (0..4).map(|i| string(word(3), i)).collect::<Vec<_>>();
+    let strs = (0..4).map(|i| string(word(4), i)).collect::<Vec<_>>();
+    assert_eq!(["", "Hello", "menu\u{4}File", "one file\0%d files"], &ids[..]);
+    assert_eq!(["Content-Type: text/plain; charset=UTF-8\n", "Hallo", "Datei", "eine Datei\0%d Dateien"], &strs[..]);
+}
+
+#[test]
+fn finds_translations_in_layouts() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    for file in ["de.po", "de.mo", "pt_BR.po", "messages.pot", "POTFILES", "sr@latin/other.mo", "fr/LC_MESSAGES/example.mo"] {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+    let found = find_translations(dir, "example").unwrap();
+    let targets = found.iter().map(|t| t.target.to_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!([
+        "usr/share/locale/de/LC_MESSAGES/example.mo",
+        "usr/share/locale/fr/LC_MESSAGES/example.mo",
+        "usr/share/locale/pt_BR/LC_MESSAGES/example.mo",
+        "usr/share/locale/sr@latin/LC_MESSAGES/other.mo",
+    ], &targets[..]);
+    assert_eq!(dir.join("de.mo"), found[0].source);
+}
@@ -1,6 +1,9 @@
-use cargo_deb::compress::Format;
-use cargo_deb::{listener, CargoDeb, CargoDebError, CargoDebOptions, CargoLockingFlags};
+use cargo_deb::bump::BumpPart;
+use cargo_deb::compress::{CompressTuning, Format};
+use cargo_deb::{listener, CargoDeb, CargoDebError, CargoDebOptions, CargoLockingFlags, HardeningFeature};
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
 fn main() -> ExitCode {
@@ -16,6 +19,7 @@ fn main() -> ExitCode {
     cli_opts.optflag("", "compress-debug-symbols", "Apply objcopy --compress-debug-sections");
     cli_opts.optopt("o", "output", "Write .deb to this file or directory", "path");
     cli_opts.optopt("p", "package", "Select which Cargo workspace package to use", "name");
+    cli_opts.optflag("", "workspace", "Make packages of all workspace members that have binaries or [package.metadata.deb]");
     cli_opts.optflag("", "install", "Immediately install the created deb package");
     cli_opts.optflag("q", "quiet", "Don't print warnings");
     cli_opts.optflag("v", "verbose", "Print progress");
@@ -23,20 +27,60 @@ fn main() -> ExitCode {
     cli_opts.optopt("", "deb-version", "Override version string for the package", "version");
     cli_opts.optopt("", "deb-revision", "Override revision suffix string for the package", "num");
     cli_opts.optopt("", "maintainer", "Override Maintainer field", "name");
+    cli_opts.optflag("", "require-description", "Fail if the package has no description, instead of generating a placeholder");
     cli_opts.optopt("", "manifest-path", "Cargo project file location", "./Cargo.toml");
     cli_opts.optflag("", "offline", "Passed to Cargo");
-    cli_opts.optflag("", "locked", "Passed to Cargo");
-    cli_opts.optflag("", "frozen", "Passed to Cargo");
+    cli_opts.optflag("", "locked", "Passed to Cargo. Also requires Cargo.lock to be committed in git");
+    cli_opts.optflag("", "frozen", "Passed to Cargo. Also requires Cargo.lock to be committed in git");
+    cli_opts.optopt("", "maintainer-scripts", "Directory with maintainer scripts, instead of maintainer-scripts from the metadata", "dir");
+    cli_opts.optopt("", "maintainer-scripts-only", "Use only these scripts from the maintainer-scripts directory", "postinst,prerm");
     cli_opts.optopt("", "variant", "Alternative Cargo.toml configuration section to use", "name");
     cli_opts.optopt("", "target", "Rust target for cross-compilation", "triple");
+    cli_opts.optopt("", "arch-map", "TOML file mapping Rust target triples to Debian architectures", "file");
+    cli_opts.optflag("", "allow-foreign-arch", "Only warn when prebuilt binaries in assets are for a different architecture than the package");
     cli_opts.optopt("", "profile", "Select which Cargo build profile to use", "release|<custom>");
     cli_opts.optflag("", "no-build", "Assume the project is already built");
+    cli_opts.optflag("", "repackage", "Don't build, and reuse binaries of the previous build, if they haven't changed since then");
     cli_opts.optopt("", "cargo-build", "Override cargo build subcommand", "subcommand");
+    cli_opts.optflag("", "auditable", "Build with `cargo auditable`, which embeds the dependency list in the binaries for vulnerability scanners");
+    cli_opts.optopt("", "rustflags", "Flags for rustc, added to rustflags from the metadata, RUSTFLAGS or .cargo/config.toml", "flags");
+    cli_opts.optflag("", "static-pie", "Build statically linked position-independent executables that don't depend on any libraries");
+    cli_opts.optflag("", "remap-build-paths", "Build with --remap-path-prefix, so that binaries don't contain paths of the project and CARGO_HOME");
+    cli_opts.optflag("", "quiet-cargo", "Write Cargo's output only to the build log, and show only its errors if the build fails");
+    cli_opts.optflag("", "clean-env", "Run Cargo with only PATH, HOME, CARGO_* and RUSTUP_* variables from the environment, for reproducible builds");
+    cli_opts.optflag("", "normalize-permissions", "Set modes of files to 644 or 755 like dh_fixperms, and remove setuid, setgid and sticky bits not in keep-special-bits");
+    cli_opts.optopt("j", "jobs", "Number of parallel jobs for cargo build, stripping and compression", "N");
     cli_opts.optflag("", "fast", "Use faster compression, which makes a larger deb file");
     cli_opts.optopt("Z", "compress-type", "Compress with the given compression format", "gz|xz");
     cli_opts.optflag("", "compress-system", "Use the corresponding command-line tool for compression");
     cli_opts.optflag("", "system-xz", "Compress using command-line xz command instead of built-in. Deprecated, use --compress-system instead");
+    cli_opts.optopt("", "compress-level", "Compression level of the data archive, instead of the default 6 for xz or 9 for gzip", "0-9");
+    cli_opts.optopt("", "xz-dict-size", "Dictionary size for xz, e.g. 256MiB. Larger sizes compress large packages better, but need more memory", "size");
     cli_opts.optflag("", "rsyncable", "Use worse compression, but reduce differences between versions of packages");
+    cli_opts.optflag("", "cache", "Reuse the previously built .deb if Cargo.lock, config and sources haven't changed");
+    cli_opts.optflag("", "refresh", "Ignore the cached .deb, and rebuild it");
+    cli_opts.optopt("", "work-dir", "Directory for temporary files, the build log and the cache, and the default output dir [default: target/debian]", "dir");
+    cli_opts.optopt("", "temp-dir", "Directory for staging and other intermediate files, also used as TMPDIR for Cargo and other tools [default: work dir]", "dir");
+    cli_opts.optflag("", "print-hash", "Print size, SHA256 and SHA512 of the .deb, and save the SHA256 to a .sha256 file next to it");
+    cli_opts.optmulti("", "previous", "Old .deb of another package of the project, to check if files moved from it", "path");
+    cli_opts.optflag("", "add-breaks-replaces", "Add Breaks and Replaces for packages that files moved from since the previous build");
+    cli_opts.optmulti("", "emit", "Also write these members of the .deb as separate files next to it", "control.tar.gz,data.tar.xz");
+    cli_opts.optflag("", "explode", "Also write all members of the .deb as separate files next to it");
+    cli_opts.optopt("", "compare", "Print changes of control fields, dependencies and files since a previous release of the package", "old.deb");
+    cli_opts.optopt("", "report", "Write paths, versions, checksums and warnings of the generated packages to this file as JSON", "path");
+    cli_opts.optflagopt("", "check-hardening", "Report whether packaged binaries have PIE, RELRO, BIND_NOW and stack protector. Listed features are required", "pie,relro,bind-now,stack-protector");
+    cli_opts.optflag("", "delete-temp", "Delete stripped binaries and other temporary files from the work dir (or --temp-dir) after the package is written");
+    cli_opts.optopt("", "check-against", "Report differences between the generated control file and this debian/control", "path");
+    cli_opts.optmulti("", "against", "Fail if Depends can't be satisfied by this Packages[.gz|.xz] file or a dir of APT lists, e.g. /var/lib/apt/lists", "path");
+    cli_opts.optflag("", "print-config", "Print the resolved configuration as JSON, and don't build anything");
+    cli_opts.optflag("", "json", "With `assets`, print the list as JSON");
+    cli_opts.optflag("", "list-outputs", "Print paths of the files that would be written with the given options, and don't build anything");
+    cli_opts.optflag("", "print-env", "Print the package's name, version, arch and binary paths as KEY=VALUE lines, and don't build anything");
+    cli_opts.optopt("", "simulate", "Resolve the package described by a fixture without running Cargo, and print what would be packaged as JSON", "fixture.toml");
+    cli_opts.optflag("", "print-schema", "Print JSON Schema of the [package.metadata.deb] table");
+    cli_opts.optflag("", "tag", "With `bump`, commit the new version and tag it in git");
+    cli_opts.optopt("", "distribution", "With `bump`, the distribution of the new changelog entry", "unstable|<series>");
+    cli_opts.optopt("", "urgency", "With `bump`, the urgency of the new changelog entry", "low|medium|high|emergency|critical");
     cli_opts.optflag("h", "help", "Print this help menu");
 
     let mut matches = match cli_opts.parse(&args[1..]) {
@@ -66,7 +110,7 @@ fn main() -> ExitCode {
     if matches.opt_present("h") {
         print!("{}", cli_opts.usage_with_format(|opts| {
             let mut out = String::with_capacity(2000);
-            out.push_str("Usage: cargo deb [options] [-- <cargo build flags>]\nhttps://lib.rs/cargo-deb ");
+            out.push_str("Usage: cargo deb [options] [-- <cargo build flags>]\n       cargo deb bundle [options] [-- <cargo build flags>]\n       cargo deb clean [--manifest-path <path>] [--work-dir <dir>]\n       cargo deb extract <package.deb> <dir>\n       cargo deb assets [--json] [options]\n       cargo deb bump <major|minor|patch|revision> [--variant <name>] [--tag] [--distribution <series>] [--urgency <urgency>]\nhttps://lib.rs/cargo-deb ");
             out.push_str(env!("CARGO_PKG_VERSION"));
             out.push_str("\n\n");
             for opt in opts.filter(|opt| !opt.contains("--system-xz") && !opt.contains("--no-separate-debug-symbols")) {
@@ -83,6 +127,11 @@ fn main() -> ExitCode {
         return ExitCode::SUCCESS;
     }
 
+    if matches.opt_present("print-schema") {
+        println!("{:#}", cargo_deb::metadata_schema());
+        return ExitCode::SUCCESS;
+    }
+
     let install = matches.opt_present("install");
 
     let compress_type = match matches.opt_str("compress-type").as_deref() {
@@ -94,6 +143,51 @@ fn main() -> ExitCode {
         },
     };
 
+    let compress_level = match matches.opt_str("compress-level").map(|l| l.parse::<u32>()) {
+        Some(Ok(level @ 0..=9)) => Some(level),
+        Some(_) => {
+            print_error(&CargoDebError::Str("--compress-level must be a number from 0 to 9"));
+            return ExitCode::FAILURE;
+        },
+        None => None,
+    };
+
+    let xz_dict_size = match matches.opt_str("xz-dict-size") {
+        Some(_) if !matches!(compress_type, Format::Xz) => {
+            print_error(&CargoDebError::Str("--xz-dict-size requires xz compression"));
+            return ExitCode::FAILURE;
+        },
+        Some(size) => match cargo_deb::compress::parse_xz_dict_size(&size) {
+            Some(size) => Some(size),
+            None => {
+                print_error(&CargoDebError::Str("--xz-dict-size must be a size from 4KiB to 1536MiB, like 64MiB"));
+                return ExitCode::FAILURE;
+            },
+        },
+        None => None,
+    };
+
+    let check_hardening = match matches.opt_str("check-hardening") {
+        _ if !matches.opt_present("check-hardening") => None,
+        None => Some(Vec::new()),
+        Some(list) => match list.split(',').map(str::trim).filter(|f| !f.is_empty()).map(HardeningFeature::from_name).collect::<Option<Vec<_>>>() {
+            Some(required) => Some(required),
+            None => {
+                print_error(&CargoDebError::Str("--check-hardening accepts a list of: pie, relro, bind-now, stack-protector"));
+                return ExitCode::FAILURE;
+            },
+        },
+    };
+
+    let jobs = match matches.opt_str("jobs").map(|j| j.parse::<usize>()) {
+        Some(Ok(0) | Err(_)) => {
+            print_error(&CargoDebError::Str("--jobs must be a positive number"));
+            return ExitCode::FAILURE;
+        },
+        Some(Ok(jobs)) => Some(jobs),
+        None => None,
+    };
+
     // `cargo deb` invocation passes the `deb` arg through.
     if matches.free.first().is_some_and(|arg| arg == "deb") {
         matches.free.remove(0);
@@ -112,6 +206,72 @@ fn main() -> ExitCode {
         &listener_tmp2
     };
 
+    // Subcommands can't be after `--`, where cargo build flags are
+    let before_separator = args.iter().take_while(|a| *a != "--").count();
+    if matches.free.first().is_some_and(|arg| arg == "bump") && args[..before_separator].iter().any(|a| a == "bump") {
+        let Some(part) = matches.free.get(1).and_then(|p| BumpPart::from_name(p)) else {
+            print_error(&CargoDebError::Str("bump needs one of: major, minor, patch, revision"));
+            return ExitCode::FAILURE;
+        };
+        let manifest_path = matches.opt_str("manifest-path");
+        return match cargo_deb::bump::bump(manifest_path.as_deref().map(Path::new), matches.opt_str("package").as_deref(), matches.opt_str("variant").as_deref(), part, matches.opt_present("tag"), matches.opt_str("distribution").as_deref(), matches.opt_str("urgency").as_deref(), listener) {
+            Ok(version) => {
+                println!("{version}");
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    if matches.free.first().is_some_and(|arg| arg == "clean") && args[..before_separator].iter().any(|a| a == "clean") {
+        let manifest_path = matches.opt_str("manifest-path");
+        let work_dir = matches.opt_str("work-dir");
+        let locking_flags = CargoLockingFlags {
+            offline: matches.opt_present("offline"),
+            frozen: matches.opt_present("frozen"),
+            locked: matches.opt_present("locked"),
+        };
+        return match cargo_deb::clean::clean(manifest_path.as_deref().map(Path::new), work_dir.as_deref().map(Path::new), locking_flags) {
+            Ok(removed) => {
+                for path in removed {
+                    println!("Removed {}", path.display());
+                }
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    if matches.free.first().is_some_and(|arg| arg == "extract") && args[..before_separator].iter().any(|a| a == "extract") {
+        let (Some(deb_path), Some(dest), None) = (matches.free.get(1), matches.free.get(2), matches.free.get(3)) else {
+            print_error(&CargoDebError::Str("extract needs a .deb file and a destination dir"));
+            return ExitCode::FAILURE;
+        };
+        return match cargo_deb::extract::extract(Path::new(deb_path), Path::new(dest), listener) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    let list_assets = matches.free.first().is_some_and(|arg| arg == "assets") && args[..before_separator].iter().any(|a| a == "assets");
+    if list_assets {
+        matches.free.remove(0);
+    }
+
+    let bundle = matches.free.first().is_some_and(|arg| arg == "bundle") && args[..before_separator].iter().any(|a| a == "bundle");
+    if bundle {
+        matches.free.remove(0);
+    }
+
     let deb_version = matches.opt_str("deb-version");
     let deb_revision = matches.opt_str("deb-revision");
 
@@ -119,8 +279,25 @@ fn main() -> ExitCode {
         listener.warning(format!("--deb-version takes precedence over --deb-revision. Revision '{}' will be ignored", deb_revision.as_deref().unwrap_or_default()));
     }
 
-    match CargoDeb::new(CargoDebOptions {
+    let temp_dir = matches.opt_str("temp-dir").map(|dir| env::current_dir().unwrap_or_default().join(dir));
+    if let Some(dir) = &temp_dir {
+        if let Err(e) = fs::create_dir_all(dir) {
+            print_error(&CargoDebError::IoFile("unable to create temp dir", e, dir.clone()));
+            return ExitCode::FAILURE;
+        }
+        // Scratch files of Cargo, rustc, dpkg-shlibdeps and cargo-deb itself go there too
+        env::set_var("TMPDIR", dir);
+    }
+
+    let print_config = matches.opt_present("print-config");
+    let print_env = matches.opt_present("print-env");
+    let list_outputs = matches.opt_present("list-outputs");
+    let json = matches.opt_present("json");
+    let simulate = matches.opt_str("simulate");
+    let report_path = matches.opt_str("report");
+    let mut cargo_deb = CargoDeb::new(CargoDebOptions {
         no_build: matches.opt_present("no-build"),
+        repackage: matches.opt_present("repackage"),
         strip_override: if matches.opt_present("strip") { Some(true) } else if matches.opt_present("no-strip") { Some(false) } else { None },
         separate_debug_symbols: if matches.opt_present("separate-debug-symbols") { Some(true) } else if matches.opt_present("no-separate-debug-symbols") { Some(false) } else { None },
         compress_debug_symbols: if matches.opt_present("compress-debug-symbols") { Some(true) } else { None },
@@ -137,20 +314,140 @@ fn main() -> ExitCode {
             deb_version,
             deb_revision,
             maintainer: matches.opt_str("maintainer"),
+            require_description: matches.opt_present("require-description"),
+            work_dir: matches.opt_str("work-dir").map(PathBuf::from),
+            temp_dir: temp_dir.clone(),
+            arch_map: matches.opt_str("arch-map").map(PathBuf::from),
+            clean_env: matches.opt_present("clean-env"),
+            quiet_cargo: matches.opt_present("quiet-cargo"),
+            rustflags: matches.opt_str("rustflags"),
+            remap_build_paths: matches.opt_present("remap-build-paths"),
+            static_pie: matches.opt_present("static-pie"),
+            maintainer_scripts: matches.opt_str("maintainer-scripts").map(|dir| env::current_dir().unwrap_or_default().join(dir)),
+            maintainer_scripts_only: matches.opt_str("maintainer-scripts-only").map(|list| list.split(',').map(|name| name.trim().to_owned()).filter(|name| !name.is_empty()).collect()),
         },
         compress_type,
         compress_system: matches.opt_present("compress-system"),
         system_xz: matches.opt_present("system-xz"),
         rsyncable: matches.opt_present("rsyncable"),
+        compress_tuning: CompressTuning { level: compress_level, xz_dict_size },
         profile: matches.opt_str("profile"),
         cargo_build_cmd: matches.opt_str("cargo-build").unwrap_or("build".to_string()),
+        auditable: matches.opt_present("auditable"),
         cargo_locking_flags: CargoLockingFlags {
             offline: matches.opt_present("offline"),
             frozen: matches.opt_present("frozen"),
             locked: matches.opt_present("locked"),
         },
+        check_against: matches.opt_str("check-against"),
+        against: matches.opt_strs("against"),
+        cache: matches.opt_present("cache"),
+        refresh: matches.opt_present("refresh"),
+        jobs,
+        workspace: matches.opt_present("workspace"),
+        allow_foreign_arch: matches.opt_present("allow-foreign-arch"),
+        delete_temp: matches.opt_present("delete-temp"),
+        print_hash: matches.opt_present("print-hash"),
+        hash_in_report: report_path.is_some(),
+        check_hardening,
+        bundle,
+        previous: matches.opt_strs("previous"),
+        add_breaks_replaces: matches.opt_present("add-breaks-replaces"),
+        compare: matches.opt_str("compare"),
+        emit: if matches.opt_present("explode") {
+            ["debian-binary", "control", "data"].map(String::from).into()
+        } else {
+            matches.opt_strs("emit").iter().flat_map(|list| list.split(',')).map(|name| name.trim().to_owned()).filter(|name| !name.is_empty()).collect()
+        },
+        normalize_permissions: matches.opt_present("normalize-permissions"),
         cargo_build_flags: matches.free,
-    }).process(listener) {
+    });
+
+    if let Some(fixture) = simulate {
+        return match cargo_deb.simulate(Path::new(&fixture), listener) {
+            Ok(json) => {
+                println!("{json}");
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    if print_config {
+        return match cargo_deb.resolve_manifest(listener).and_then(|manifest| Ok(serde_json::to_string_pretty(&manifest)?)) {
+            Ok(json) => {
+                println!("{json}");
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    if print_env {
+        return match cargo_deb.resolve_manifest(listener) {
+            Ok(manifest) => {
+                print!("{}", manifest.environment());
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    if list_assets {
+        return match cargo_deb.list_assets(listener).and_then(|listed| Ok(if json {
+            serde_json::to_string_pretty(&listed)?
+        } else {
+            for missing in &listed.not_listed {
+                listener.warning(format!("not listed: {missing}"));
+            }
+            listed.assets.iter().map(|asset| {
+                let source = asset.source.as_deref().map_or_else(|| "(generated)".into(), |p| p.display().to_string());
+                format!("{} {} <- {source}", asset.mode, asset.target.display())
+            }).collect::<Vec<_>>().join("\n")
+        })) {
+            Ok(text) => {
+                println!("{text}");
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    if list_outputs {
+        return match cargo_deb.list_outputs(listener) {
+            Ok(paths) => {
+                for path in paths {
+                    println!("{}", path.display());
+                }
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    let written = cargo_deb.process(listener).and_then(|report| {
+        if let Some(path) = &report_path {
+            let json = serde_json::to_string_pretty(&report)?;
+            fs::write(path, json).map_err(|e| CargoDebError::IoFile("unable to write the report", e, path.into()))?;
+        }
+        Ok(())
+    });
+    match written {
         Ok(()) => ExitCode::SUCCESS,
         Err(err) => {
             print_error(&err);
@@ -0,0 +1,141 @@
+//! Files that moved to this package from another package of the project (e.g. from `foo` to `foo-data`)
+//! since a previous build. Upgrades fail when dpkg would overwrite a file of another installed package,
+//! unless the package that has the file now `Breaks` and `Replaces` the old version of the other package.
+use crate::config::{Config, PackageConfig};
+use crate::deb::apt::compare_versions;
+use crate::deb::parity::{field, parse_paragraphs};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::snapshot::{read_control, read_file_list};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files of the current package that were in another package
+#[derive(Debug, PartialEq)]
+pub(crate) struct MovedFiles {
+    /// Name of the other package
+    pub package: String,
+    pub files: Vec<String>,
+}
+
+/// Whether the other package is likely of the same project, e.g. `foo-data` for `foo`
+fn is_related(name: &str, other: &str) -> bool {
+    let prefixed = |a: &str, b: &str| a.strip_prefix(b).is_some_and(|rest| rest.starts_with('-'));
+    prefixed(name, other) || prefixed(other, name)
+}
+
+/// `.deb` files of related packages in the cache and output dirs, which have versions older than this package
+/// (packages of the same version are siblings built together, and their files only overlap).
+/// Of each package only the newest version is used.
+fn discovered_packages(config: &Config, package_deb: &PackageConfig) -> Vec<PathBuf> {
+    let dirs = [config.work_dir.join("cache"), config.deb_output_path(package_deb).parent().map(Path::to_path_buf).unwrap_or_default()];
+    let mut found = dirs.iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name();
+            // name_version_arch.deb
+            let mut parts = name.to_str()?.strip_suffix(".deb")?.split('_');
+            let (package, version) = (parts.next()?, parts.next()?);
+            if !is_related(&package_deb.deb_name, package) || compare_versions(version, &package_deb.deb_version) != Ordering::Less {
+                return None;
+            }
+            Some((package.to_owned(), version.to_owned(), entry.path()))
+        })
+        .collect::<Vec<_>>();
+    found.sort_by(|(_, a, _), (_, b, _)| compare_versions(b, a));
+    let mut seen = HashSet::new();
+    found.into_iter().filter(|(name, ..)| seen.insert(name.clone())).map(|(.., path)| path).collect()
+}
+
+/// Name of the package, and its paths that are also in the current package
+fn files_in_common(deb_path: &Path, current: &HashSet<&str>) -> CDResult<(String, Vec<String>)> {
+    let control = read_control(deb_path)?;
+    let control = parse_paragraphs(&control).into_iter().next().unwrap_or_default();
+    let package = field(&control, "Package").ok_or(CargoDebError::Str("the package has no Package field"))?.to_owned();
+    let mut files = read_file_list(deb_path)?.into_iter().filter(|path| current.contains(path.as_str())).collect::<Vec<_>>();
+    files.sort();
+    Ok((package, files))
+}
+
+/// Only the explicitly given packages have to be readable. Others in the dirs may be from other tools, e.g. zstd-compressed.
+fn find_moved_files(package_deb: &PackageConfig, explicit: &[PathBuf], discovered: &[PathBuf], listener: &dyn Listener) -> CDResult<Vec<MovedFiles>> {
+    let current: HashSet<&str> = package_deb.assets.resolved.iter()
+        .filter_map(|asset| asset.c.target_path.to_str())
+        .collect();
+    let mut moved = Vec::new();
+    let mut seen = HashSet::new();
+    let previous = explicit.iter().map(|path| (path, true)).chain(discovered.iter().map(|path| (path, false)));
+    for (deb_path, is_explicit) in previous {
+        let (package, files) = match files_in_common(deb_path, &current) {
+            Ok(found) => found,
+            Err(e) if !is_explicit => {
+                listener.warning(format!("Skipped {} when looking for moved files: {e}", deb_path.display()));
+                continue;
+            },
+            Err(e) => return Err(e),
+        };
+        if package == package_deb.deb_name || !seen.insert(package.clone()) {
+            continue;
+        }
+        if !files.is_empty() {
+            moved.push(MovedFiles { package, files });
+        }
+    }
+    Ok(moved)
+}
+
+/// Whether the relation field already has the package
+fn mentions(relations: Option<&str>, package: &str) -> bool {
+    relations.unwrap_or_default().split([',', '|'])
+        .any(|rel| rel.trim().split(|c: char| c.is_whitespace() || c == '(' || c == ':').next() == Some(package))
+}
+
+/// Warns about files that moved from other packages, and with `add_relations`, adds `Breaks` and `Replaces` for them.
+///
+/// Packages of the same project usually share the version, so the relations are with versions older than this package's.
+pub(crate) fn check(config: &Config, package_deb: &mut PackageConfig, explicit: &[PathBuf], add_relations: bool, listener: &dyn Listener) -> CDResult<()> {
+    let discovered = discovered_packages(config, package_deb);
+    if explicit.is_empty() && discovered.is_empty() {
+        return Ok(());
+    }
+    for MovedFiles { package, files } in find_moved_files(package_deb, explicit, &discovered, listener)? {
+        // Conflicting packages (like alternative variants) are removed before the other is installed
+        let handled = mentions(package_deb.breaks.as_deref(), &package) && mentions(package_deb.replaces.as_deref(), &package);
+        if handled || mentions(package_deb.conflicts.as_deref(), &package) {
+            continue;
+        }
+        let relation = format!("{package} (<< {})", package_deb.deb_version);
+        let examples = files.iter().take(3).map(|f| format!("/{f}")).collect::<Vec<_>>().join(", ");
+        if add_relations {
+            listener.info(format!("{} files moved from {package} (like {examples}). Adding Breaks and Replaces: {relation}", files.len()));
+            for field in [&mut package_deb.breaks, &mut package_deb.replaces] {
+                if !mentions(field.as_deref(), &package) {
+                    *field = Some(match field.take() {
+                        Some(existing) if !existing.trim().is_empty() => format!("{existing}, {relation}"),
+                        _ => relation.clone(),
+                    });
+                }
+            }
+        } else {
+            listener.warning(format!("{} files moved from {package} (like {examples}), so upgrades will fail without `breaks = \"{relation}\"` and `replaces = \"{relation}\"`. Use --add-breaks-replaces to add them", files.len()));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn relation_mentions_package() {
+    assert!(is_related("foo", "foo-data"));
+    assert!(is_related("foo-data", "foo"));
+    assert!(!is_related("foo", "foobar"));
+    assert!(!is_related("foo", "foo"));
+
+    assert!(mentions(Some("foo-data (<< 1.0), bar"), "foo-data"));
+    assert!(mentions(Some("bar | foo:any"), "foo"));
+    assert!(!mentions(Some("foo-data (<< 1.0)"), "foo"));
+    assert!(!mentions(None, "foo"));
+}
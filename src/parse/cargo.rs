@@ -6,6 +6,8 @@ use std::{env, fs};
 pub struct CargoConfig {
     path: PathBuf,
     config: toml::Value,
+    /// Multiarch tuple of the package's architecture, for names of cross-compilers
+    debian_triple: Option<String>,
 }
 
 impl CargoConfig {
@@ -55,7 +57,7 @@ impl CargoConfig {
 
     fn from_str(input: &str, path: PathBuf) -> CDResult<Self> {
         let config = toml::from_str(input)?;
-        Ok(CargoConfig { path, config })
+        Ok(CargoConfig { path, config, debian_triple: None })
     }
 
     fn target_conf(&self, target_triple: &str) -> Option<&toml::value::Table> {
@@ -65,6 +67,12 @@ impl CargoConfig {
         None
     }
 
+    /// Looks for cross-compiling tools by this name instead of guessing it from the Rust target
+    pub(crate) fn with_debian_triple(mut self, debian_triple: Option<String>) -> Self {
+        self.debian_triple = debian_triple;
+        self
+    }
+
     pub fn strip_command(&self, target_triple: &str) -> Option<Cow<'_, Path>> {
         self.target_specific_command("strip", target_triple)
     }
@@ -80,7 +88,9 @@ impl CargoConfig {
             }
         }
 
-        let debian_target_triple = crate::debian_triple_from_rust_triple(target_triple);
+        let debian_target_triple = self.debian_triple.clone().unwrap_or_else(|| {
+            crate::debian_triple_from_rust_triple(target_triple, crate::arch::debian_architecture_from_rust_triple(target_triple))
+        });
         if let Some(linker) = self.linker_command(target_triple) {
             if linker.parent().is_some() {
                 let linker_file_name = linker.file_name().unwrap().to_str().unwrap();
@@ -102,6 +112,24 @@ impl CargoConfig {
         None
     }
 
+    /// `target.<triple>.rustflags`. `target.'cfg(…)'.rustflags` aren't supported.
+    pub fn target_rustflags(&self, target_triple: &str) -> Option<Vec<String>> {
+        Self::flags(self.target_conf(target_triple)?.get("rustflags")?)
+    }
+
+    /// `build.rustflags`, used by Cargo only if there are no target-specific flags
+    pub fn build_rustflags(&self) -> Option<Vec<String>> {
+        Self::flags(self.config.get("build")?.get("rustflags")?)
+    }
+
+    fn flags(value: &toml::Value) -> Option<Vec<String>> {
+        Some(match value {
+            toml::Value::String(s) => s.split_whitespace().map(From::from).collect(),
+            toml::Value::Array(a) => a.iter().filter_map(|f| f.as_str()).map(From::from).collect(),
+            _ => return None,
+        })
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
@@ -152,3 +180,18 @@ objcopy = { path = "objcopy2" }
     assert_eq!("objcopy2", c.objcopy_command("foo").unwrap().as_os_str());
     assert_eq!(None, c.objcopy_command("bar"));
 }
+
+#[test]
+fn parse_rustflags() {
+    let c = CargoConfig::from_str(r#"
+[build]
+rustflags = "-C target-cpu=native  -C debuginfo=1"
+
+[target.aarch64-unknown-linux-gnu]
+rustflags = ["-C", "target-feature=+crt-static"]
+"#, ".".into()).unwrap();
+
+    assert_eq!(["-C", "target-feature=+crt-static"], c.target_rustflags("aarch64-unknown-linux-gnu").unwrap().as_slice());
+    assert_eq!(None, c.target_rustflags("x86_64-unknown-linux-gnu"));
+    assert_eq!(["-C", "target-cpu=native", "-C", "debuginfo=1"], c.build_rustflags().unwrap().as_slice());
+}
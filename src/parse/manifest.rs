@@ -1,9 +1,9 @@
 use crate::assets::RawAsset;
 use crate::error::{CDResult, CargoDebError};
 use crate::CargoLockingFlags;
-use cargo_toml::DebugSetting;
+use cargo_toml::{DebugSetting, StripSetting};
 use log::debug;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
 use std::fs;
@@ -21,29 +21,101 @@ use std::process::Command;
 /// multiple units, only process those matching this unit name.
 ///
 /// For details on the other options please see `dh_installsystemd::Options`.
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub(crate) struct SystemdUnitsConfig {
     pub unit_scripts: Option<PathBuf>,
     pub unit_name: Option<String>,
     pub enable: Option<bool>,
     pub start: Option<bool>,
+    pub start_on_install: Option<bool>,
     pub restart_after_upgrade: Option<bool>,
     pub stop_on_upgrade: Option<bool>,
+    pub instances: Option<Vec<String>>,
+    pub verify: Option<bool>,
 }
 
-pub(crate) fn manifest_debug_flag(manifest: &cargo_toml::Manifest<CargoPackageMetadata>, selected_profile: &str) -> Option<bool> {
-    let profile = if selected_profile == "release" {
-        manifest.profile.release.as_ref()?
+/// Checks of the target system done in the `preinst` script before the package is installed.
+///
+/// See `preinst_checks::generate` for the generated script.
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct PreinstChecksConfig {
+    pub min_glibc: Option<String>,
+    pub systemd: Option<bool>,
+    pub cpu_features: Option<CpuFeaturesConfig>,
+    pub disk_space: Option<bool>,
+}
+
+/// `true` detects features enabled via `RUSTFLAGS`, or an explicit list of Rust target feature names
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum CpuFeaturesConfig {
+    Detect(bool),
+    List(Vec<String>),
+}
+
+/// A file of another package that is moved aside with `dpkg-divert`, so that this package can install its own version
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct DivertConfig {
+    /// Absolute path of the diverted file
+    pub path: String,
+    /// Where the other package's file is moved. Defaults to `{path}.distrib`
+    pub to: Option<String>,
+}
+
+/// A config file installed as a default, and copied to its location by `postinst`, instead of being a conffile
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct ConfigDefaultConfig {
+    /// Absolute target path of the asset
+    pub path: String,
+    /// When the file is replaced with the package's version
+    #[serde(default)]
+    pub update: ConfigUpdate,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ConfigUpdate {
+    /// Only installed if there's no such file
+    #[default]
+    IfAbsent,
+    /// Replaced on every upgrade, keeping the previous file as `.dpkg-old`
+    Always,
+}
+
+fn manifest_profile<'a>(manifest: &'a cargo_toml::Manifest<CargoPackageMetadata>, selected_profile: &str) -> Option<&'a cargo_toml::Profile> {
+    if selected_profile == "release" {
+        manifest.profile.release.as_ref()
     } else {
-        manifest.profile.custom.get(selected_profile)?
-    };
-    Some(*profile.debug.as_ref()? != DebugSetting::None)
+        manifest.profile.custom.get(selected_profile)
+    }
+}
+
+pub(crate) fn manifest_debug_flag(manifest: &cargo_toml::Manifest<CargoPackageMetadata>, selected_profile: &str) -> Option<bool> {
+    Some(*manifest_profile(manifest, selected_profile)?.debug.as_ref()? != DebugSetting::None)
+}
+
+/// The profile's `strip`, if Cargo strips the binaries itself
+pub(crate) fn manifest_strip_setting(manifest: &cargo_toml::Manifest<CargoPackageMetadata>, selected_profile: &str) -> Option<StripSetting> {
+    manifest_profile(manifest, selected_profile)?.strip.clone()
+}
+
+/// The profile's `split-debuginfo`, like `packed`
+pub(crate) fn manifest_split_debuginfo(manifest: &cargo_toml::Manifest<CargoPackageMetadata>, selected_profile: &str) -> Option<String> {
+    manifest_profile(manifest, selected_profile)?.split_debuginfo.clone()
 }
 
 /// Debian-compatible version of the semver version
 pub(crate) fn manifest_version_string<'a>(package: &'a cargo_toml::Package<CargoPackageMetadata>, revision: Option<&str>) -> Cow<'a, str> {
-    let mut version = Cow::Borrowed(package.version());
+    debian_version_string(package.version(), revision)
+}
+
+/// Debian version for the Cargo version and Debian revision
+pub(crate) fn debian_version_string<'a>(cargo_version: &'a str, revision: Option<&str>) -> Cow<'a, str> {
+    let mut version = Cow::Borrowed(cargo_version);
 
     // Make debian's version ordering (newer versions) more compatible with semver's.
     // Keep "semver-1" and "semver-xxx" as-is (assuming these are irrelevant, or debian revision already),
@@ -64,6 +136,17 @@ pub(crate) fn manifest_version_string<'a>(package: &'a cargo_toml::Package<Cargo
     version
 }
 
+/// Expands a revision template like `1~{variant}1`, so that variants for different distro releases
+/// can get their own revisions of the same version. A leading `-` is allowed, as in `-0ubuntu1`.
+pub(crate) fn revision_from_template(template: &str, variant: Option<&str>) -> CDResult<String> {
+    let template = template.strip_prefix('-').unwrap_or(template);
+    if !template.contains("{variant}") {
+        return Ok(template.into());
+    }
+    let variant = variant.ok_or(CargoDebError::Str("{variant} in the revision can only be used with --variant"))?;
+    Ok(template.replace("{variant}", variant))
+}
+
 #[derive(Clone, Debug, Deserialize, Default)]
 pub(crate) struct CargoPackageMetadata {
     pub deb: Option<CargoDeb>,
@@ -76,6 +159,22 @@ pub(crate) enum LicenseFile {
     Vec(Vec<String>),
 }
 
+/// `locales`: a dir of translations, optionally with their gettext domain
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum LocalesConfig {
+    Dir(String),
+    Table { dir: String, domain: Option<String> },
+}
+
+/// `symbols`: whether to generate the `symbols` file, or the path of a committed one to compare with
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum SymbolsConfig {
+    Enabled(bool),
+    Baseline(String),
+}
+
 #[derive(Deserialize, Clone, Debug)]
 #[serde(untagged)]
 pub(crate) enum SystemUnitsSingleOrMultiple {
@@ -83,6 +182,18 @@ pub(crate) enum SystemUnitsSingleOrMultiple {
     Multi(Vec<SystemdUnitsConfig>),
 }
 
+/// Init system that the package supports, via `init-system`
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum InitSystem {
+    /// Units from `systemd-units`
+    Systemd,
+    /// LSB scripts in `/etc/init.d`
+    Sysvinit,
+    /// `openrc-run` scripts in `/etc/init.d`
+    Openrc,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(untagged)]
 pub(crate) enum DependencyList {
@@ -99,27 +210,56 @@ impl DependencyList {
     }
 }
 
+/// `RUSTFLAGS`-style string, or a list of flags like in `.cargo/config.toml`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum RustFlags {
+    String(String),
+    Vec(Vec<String>),
+}
+
+impl RustFlags {
+    pub(crate) fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::String(s) => s.split_whitespace().map(From::from).collect(),
+            Self::Vec(flags) => flags,
+        }
+    }
+}
+
 /// Type-alias for list of assets
 ///
 pub(crate) type AssetList = Vec<RawAsset>;
 
 /// Type-alias for a merge map,
 ///
-pub(crate) type MergeMap<'a> = BTreeMap<&'a PathBuf, (&'a PathBuf, u32)>;
+pub(crate) type MergeMap<'a> = BTreeMap<&'a PathBuf, &'a RawAsset>;
 
 #[derive(Deserialize)]
 #[serde(untagged)]
 pub(crate) enum CargoDebAssetArrayOrTable {
-    Table(CargoDebAsset),
+    // Serde would also accept an array as a struct's fields in order
     Array([String; 3]),
+    Table(CargoDebAsset),
     Invalid(toml::Value),
 }
 
 #[derive(Clone, Debug, Deserialize, Default)]
 pub(crate) struct CargoDebAsset {
-    pub source: String,
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Executable of this or another workspace member, instead of `source`
+    #[serde(default)]
+    pub bin: Option<String>,
     pub dest: String,
-    pub mode: String,
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Names of `AssetTransform`s to apply after the build
+    #[serde(default)]
+    pub transform: Vec<String>,
+    /// Expand `{{variables}}` in the file
+    #[serde(default)]
+    pub template: bool,
 }
 
 #[derive(Clone, Debug, Deserialize, Default)]
@@ -130,6 +270,8 @@ pub(crate) struct CargoDeb {
     pub copyright: Option<String>,
     pub license_file: Option<LicenseFile>,
     pub changelog: Option<String>,
+    pub changelog_distribution: Option<String>,
+    pub changelog_urgency: Option<String>,
     pub depends: Option<DependencyList>,
     pub pre_depends: Option<DependencyList>,
     pub recommends: Option<DependencyList>,
@@ -141,20 +283,45 @@ pub(crate) struct CargoDeb {
     pub provides: Option<String>,
     pub extended_description: Option<String>,
     pub extended_description_file: Option<String>,
+    pub description_fallback: Option<String>,
     pub section: Option<String>,
     pub priority: Option<String>,
+    pub protected: Option<bool>,
+    pub important: Option<bool>,
+    pub tag: Option<Vec<String>>,
     pub revision: Option<String>,
     pub conf_files: Option<Vec<String>>,
     pub assets: Option<AssetList>,
     pub merge_assets: Option<MergeAssets>,
     pub triggers_file: Option<String>,
     pub maintainer_scripts: Option<String>,
+    pub maintainer_scripts_only: Option<Vec<String>>,
+    pub preinst_script: Option<String>,
+    pub postinst_script: Option<String>,
+    pub prerm_script: Option<String>,
+    pub postrm_script: Option<String>,
     pub features: Option<Vec<String>>,
     pub default_features: Option<bool>,
+    pub rustflags: Option<RustFlags>,
+    pub static_pie: Option<bool>,
     pub separate_debug_symbols: Option<bool>,
     pub compress_debug_symbols: Option<bool>,
     pub preserve_symlinks: Option<bool>,
+    pub include_readme: Option<bool>,
+    pub respect_package_exclude: Option<bool>,
     pub systemd_units: Option<SystemUnitsSingleOrMultiple>,
+    pub init_system: Option<Vec<InitSystem>>,
+    pub install_prefix: Option<String>,
+    pub preinst_checks: Option<PreinstChecksConfig>,
+    pub cpu_variants: Option<Vec<String>>,
+    pub arch_map: Option<BTreeMap<String, String>>,
+    pub inspectors: Option<Vec<String>>,
+    pub diverts: Option<Vec<DivertConfig>>,
+    pub config_defaults: Option<Vec<ConfigDefaultConfig>>,
+    pub purge_paths: Option<Vec<String>>,
+    pub keep_special_bits: Option<Vec<String>>,
+    pub locales: Option<LocalesConfig>,
+    pub symbols: Option<SymbolsConfig>,
     pub variants: Option<HashMap<String, CargoDeb>>,
 }
 
@@ -196,13 +363,13 @@ impl MergeByKey {
 
     /// Folds the parent asset into a merge-map preparing to prepare for a merge,
     ///
-    fn prep_parent_item<'a>(&'a self, mut parent: MergeMap<'a>, RawAsset { source_path: src,target_path: dest, chmod: perm }: &'a RawAsset) -> MergeMap<'_> {
+    fn prep_parent_item<'a>(&self, mut parent: MergeMap<'a>, asset: &'a RawAsset) -> MergeMap<'a> {
         match &self {
             MergeByKey::Src(_) => {
-                parent.insert(src, (dest, *perm));
+                parent.insert(&asset.source_path, asset);
             },
             MergeByKey::Dest(_) => {
-                parent.insert(dest, (src, *perm));
+                parent.insert(&asset.target_path, asset);
             },
         }
         parent
@@ -213,24 +380,24 @@ impl MergeByKey {
     fn merge_with(&self, parent: MergeMap<'_>) -> AssetList {
         match self {
             MergeByKey::Src(assets) => assets.iter()
-                .fold(parent, |mut acc, RawAsset { source_path: src,target_path: dest, chmod: perm }| {
-                    if let Some((replaced_dest, replaced_perm)) = acc.insert(src, (dest, *perm)) {
-                        debug!("Replacing {:?} w/ {:?}", (replaced_dest, replaced_perm), (dest, perm));
+                .fold(parent, |mut acc, asset| {
+                    if let Some(replaced) = acc.insert(&asset.source_path, asset) {
+                        debug!("Replacing {:?} w/ {:?}", (&replaced.target_path, replaced.chmod), (&asset.target_path, asset.chmod));
                     }
                     acc
                 })
-                .into_iter()
-                .map(|(src, (dest, perm))| RawAsset { source_path: src.clone(), target_path: dest.clone(), chmod: perm })
+                .into_values()
+                .cloned()
                 .collect(),
             MergeByKey::Dest(assets) => assets.iter()
-                .fold(parent, |mut acc, RawAsset { source_path: src, target_path: dest, chmod: perm }| {
-                    if let Some((replaced_src, replaced_perm)) = acc.insert(dest, (src, *perm)) {
-                        debug!("Replacing {:?} w/ {:?}", (replaced_src, replaced_perm), (src, perm));
+                .fold(parent, |mut acc, asset| {
+                    if let Some(replaced) = acc.insert(&asset.target_path, asset) {
+                        debug!("Replacing {:?} w/ {:?}", (&replaced.source_path, replaced.chmod), (&asset.source_path, asset.chmod));
                     }
                     acc
                 })
-                .into_iter()
-                .map(|(dest, (src, perm))| RawAsset { source_path: src.clone(), target_path: dest.clone(), chmod: perm })
+                .into_values()
+                .cloned()
                 .collect(),
         }
     }
@@ -241,17 +408,23 @@ impl CargoDeb {
     ///
     /// **Note**: For backwards compat, if `merge_assets` is set, this will apply **after** the variant has overridden the assets.
     ///
+    /// If neither has `assets`, `merge_assets` is kept, to be merged with the implied assets.
     pub(crate) fn inherit_from(self, parent: CargoDeb) -> CargoDeb {
         let mut assets = self.assets.or(parent.assets);
+        let mut merge_assets = self.merge_assets;
 
-        if let (Some(merge_assets), Some(old_assets)) = (self.merge_assets, assets.as_mut()) {
-            if let Some(mut append) = merge_assets.append {
-                old_assets.append(&mut append);
-            }
+        if let Some(old_assets) = assets.as_mut() {
+            if let Some(merge_assets) = merge_assets.take() {
+                if let Some(mut append) = merge_assets.append {
+                    old_assets.append(&mut append);
+                }
 
-            if let Some(strategy) = merge_assets.by {
-                assets = Some(strategy.merge(old_assets));
+                if let Some(strategy) = merge_assets.by {
+                    assets = Some(strategy.merge(old_assets));
+                }
             }
+        } else {
+            merge_assets = merge_assets.or(parent.merge_assets);
         }
 
         CargoDeb {
@@ -260,6 +433,8 @@ impl CargoDeb {
             copyright: self.copyright.or(parent.copyright),
             license_file: self.license_file.or(parent.license_file),
             changelog: self.changelog.or(parent.changelog),
+            changelog_distribution: self.changelog_distribution.or(parent.changelog_distribution),
+            changelog_urgency: self.changelog_urgency.or(parent.changelog_urgency),
             depends: self.depends.or(parent.depends),
             pre_depends: self.pre_depends.or(parent.pre_depends),
             recommends: self.recommends.or(parent.recommends),
@@ -269,31 +444,59 @@ impl CargoDeb {
             breaks: self.breaks.or(parent.breaks),
             replaces: self.replaces.or(parent.replaces),
             provides: self.provides.or(parent.provides),
+            protected: self.protected.or(parent.protected),
+            important: self.important.or(parent.important),
+            tag: self.tag.or(parent.tag),
             extended_description: self.extended_description.or(parent.extended_description),
             extended_description_file: self.extended_description_file.or(parent.extended_description_file),
+            description_fallback: self.description_fallback.or(parent.description_fallback),
             section: self.section.or(parent.section),
             priority: self.priority.or(parent.priority),
             revision: self.revision.or(parent.revision),
             conf_files: self.conf_files.or(parent.conf_files),
             assets,
-            merge_assets: None,
+            merge_assets,
             triggers_file: self.triggers_file.or(parent.triggers_file),
             maintainer_scripts: self.maintainer_scripts.or(parent.maintainer_scripts),
+            maintainer_scripts_only: self.maintainer_scripts_only.or(parent.maintainer_scripts_only),
+            preinst_script: self.preinst_script.or(parent.preinst_script),
+            postinst_script: self.postinst_script.or(parent.postinst_script),
+            prerm_script: self.prerm_script.or(parent.prerm_script),
+            postrm_script: self.postrm_script.or(parent.postrm_script),
             features: self.features.or(parent.features),
             default_features: self.default_features.or(parent.default_features),
+            rustflags: self.rustflags.or(parent.rustflags),
+            static_pie: self.static_pie.or(parent.static_pie),
             separate_debug_symbols: self.separate_debug_symbols.or(parent.separate_debug_symbols),
             compress_debug_symbols: self.compress_debug_symbols.or(parent.compress_debug_symbols),
             preserve_symlinks: self.preserve_symlinks.or(parent.preserve_symlinks),
+            include_readme: self.include_readme.or(parent.include_readme),
+            respect_package_exclude: self.respect_package_exclude.or(parent.respect_package_exclude),
             systemd_units: self.systemd_units.or(parent.systemd_units),
+            install_prefix: self.install_prefix.or(parent.install_prefix),
+            preinst_checks: self.preinst_checks.or(parent.preinst_checks),
+            cpu_variants: self.cpu_variants.or(parent.cpu_variants),
+            arch_map: self.arch_map.or(parent.arch_map),
+            inspectors: self.inspectors.or(parent.inspectors),
+            diverts: self.diverts.or(parent.diverts),
+            init_system: self.init_system.or(parent.init_system),
+            locales: self.locales.or(parent.locales),
+            config_defaults: self.config_defaults.or(parent.config_defaults),
+            purge_paths: self.purge_paths.or(parent.purge_paths),
+            keep_special_bits: self.keep_special_bits.or(parent.keep_special_bits),
+            symbols: self.symbols.or(parent.symbols),
             variants: self.variants.or(parent.variants),
         }
     }
 }
 
+/// Only the fields that cargo-deb needs. Everything else is optional, so that changes in Cargo's output don't break parsing.
 #[derive(Deserialize)]
 struct CargoMetadata {
     pub packages: Vec<CargoMetadataPackage>,
-    pub resolve: CargoMetadataResolve,
+    /// `null` with `--no-deps`
+    #[serde(default)]
+    pub resolve: Option<CargoMetadataResolve>,
     #[serde(default)]
     pub workspace_members: Vec<String>,
     #[serde(default)]
@@ -303,8 +506,18 @@ struct CargoMetadata {
     pub workspace_root: String,
 }
 
+impl CargoMetadata {
+    /// The workspace's `Cargo.toml` has no `[package]`
+    fn is_virtual_workspace(&self) -> bool {
+        let root_manifest_path = Path::new(&self.workspace_root).join("Cargo.toml");
+        !self.packages.iter().any(|p| self.workspace_members.contains(&p.id) && p.manifest_path == root_manifest_path)
+    }
+}
+
 #[derive(Deserialize)]
 struct CargoMetadataResolve {
+    /// `null` in virtual workspaces
+    #[serde(default)]
     pub root: Option<String>,
 }
 
@@ -312,23 +525,32 @@ struct CargoMetadataResolve {
 struct CargoMetadataPackage {
     pub id: String,
     pub name: String,
+    #[serde(default)]
     pub targets: Vec<CargoMetadataTarget>,
     pub manifest_path: PathBuf,
+    /// `[package.metadata]`
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct CargoMetadataTarget {
     pub name: String,
+    #[serde(default)]
     pub kind: Vec<String>,
+    #[serde(default)]
     pub crate_types: Vec<String>,
     pub src_path: PathBuf,
 }
 
 pub(crate) struct ManifestFound {
     pub build_targets: Vec<CargoMetadataTarget>,
+    /// Executables of other workspace members, as `(package, bin)`
+    pub workspace_bins: Vec<(String, String)>,
     pub manifest_path: PathBuf,
     pub root_manifest: Option<cargo_toml::Manifest<CargoPackageMetadata>>,
     pub target_dir: PathBuf,
+    pub workspace_root: PathBuf,
     pub manifest: cargo_toml::Manifest<CargoPackageMetadata>,
 }
 
@@ -346,11 +568,19 @@ pub(crate) fn cargo_metadata(root_manifest_path: Option<&Path>, selected_package
     } else {
         metadata.workspace_default_members.first()
             .filter(|_| metadata.workspace_default_members.len() == 1)
-            .or(metadata.resolve.root.as_ref())
+            .or(metadata.resolve.as_ref().and_then(|r| r.root.as_ref()))
             .and_then(|root_id| metadata.packages.iter().position(move |p| &p.id == root_id))
-        .ok_or_else(|| CargoDebError::NoRootFoundInWorkspace(available_package_names()))
+        .ok_or_else(|| if metadata.is_virtual_workspace() {
+            CargoDebError::VirtualWorkspace(metadata.workspace_root.clone().into(), available_package_names())
+        } else {
+            CargoDebError::NoRootFoundInWorkspace(available_package_names())
+        })
     }?;
     let target_package = metadata.packages.swap_remove(target_package_pos);
+    let workspace_bins = metadata.packages.iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+        .flat_map(|p| p.targets.iter().filter(|t| t.kind.iter().any(|k| k == "bin")).map(|t| (p.name.clone(), t.name.clone())))
+        .collect();
     let workspace_root_manifest_path = Path::new(&metadata.workspace_root).join("Cargo.toml");
     let root_manifest = cargo_toml::Manifest::<CargoPackageMetadata>::from_path_with_metadata(workspace_root_manifest_path).ok();
     let target_dir = metadata.target_directory.into();
@@ -365,12 +595,52 @@ pub(crate) fn cargo_metadata(root_manifest_path: Option<&Path>, selected_package
     Ok(ManifestFound {
         manifest_path: target_package.manifest_path,
         build_targets: target_package.targets,
+        workspace_bins,
         root_manifest,
         target_dir,
+        workspace_root: metadata.workspace_root.into(),
         manifest,
     })
 }
 
+/// Names of workspace members that have executables or `[package.metadata.deb]`, for `--workspace`
+pub(crate) fn packageable_workspace_members(root_manifest_path: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> CDResult<Vec<String>> {
+    let metadata = run_cargo_metadata(root_manifest_path, cargo_locking_flags)?;
+    Ok(metadata.packages.into_iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+        .filter(|p| {
+            p.targets.iter().any(|t| t.kind.iter().any(|k| k == "bin")) ||
+            p.metadata.as_ref().and_then(|m| m.get("deb")).is_some()
+        })
+        .map(|p| p.name)
+        .collect())
+}
+
+/// The target dir, and names of all workspace members
+pub(crate) struct WorkspaceMember {
+    pub name: String,
+    /// Names of Debian packages it can make, including variants
+    pub deb_names: Vec<String>,
+}
+
+pub(crate) fn target_dir_and_members(root_manifest_path: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> CDResult<(PathBuf, Vec<WorkspaceMember>)> {
+    let metadata = run_cargo_metadata(root_manifest_path, cargo_locking_flags)?;
+    let members = metadata.packages.into_iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+        .map(|p| {
+            let deb = p.metadata.as_ref().and_then(|m| m.get("deb"));
+            let name_of = |deb: Option<&serde_json::Value>, crate_name: &str| deb.and_then(|d| d.get("name")?.as_str())
+                .map_or_else(|| crate::config::debian_package_name(crate_name), String::from);
+            let mut deb_names = vec![name_of(deb, &p.name)];
+            if let Some(variants) = deb.and_then(|d| d.get("variants")?.as_object()) {
+                deb_names.extend(variants.iter().map(|(variant, v)| name_of(Some(v), &format!("{}-{variant}", p.name))));
+            }
+            WorkspaceMember { name: p.name, deb_names }
+        })
+        .collect();
+    Ok((metadata.target_directory.into(), members))
+}
+
 /// Returns the path of the `Cargo.toml` that we want to build.
 fn run_cargo_metadata(manifest_path: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> CDResult<CargoMetadata> {
     let mut cmd = Command::new("cargo");
@@ -399,7 +669,7 @@ fn run_cargo_metadata(manifest_path: Option<&Path>, cargo_locking_flags: CargoLo
     }
 
     let stdout = String::from_utf8(output.stdout).unwrap();
-    let metadata = serde_json::from_str(&stdout)?;
+    let metadata = serde_json::from_str(&stdout).map_err(CargoDebError::CargoMetadataParse)?;
     Ok(metadata)
 }
 
@@ -412,7 +682,7 @@ mod tests {
         // Test merging assets by dest
         fn create_test_asset(src: impl Into<PathBuf>, target_path: impl Into<PathBuf>, perm: u32) -> RawAsset {
             RawAsset {
-                source_path: src.into(), target_path: target_path.into(), chmod: perm
+                source_path: src.into(), target_path: target_path.into(), chmod: perm, transforms: Vec::new(), bin: None,
             }
         }
 
@@ -524,6 +794,12 @@ mod tests {
         assert_eq!("lib/test/other-empty.txt", additional_asset.source_path.as_os_str(), "should have merged the source location");
         assert_eq!("/opt/test/other-empty.txt", additional_asset.target_path.as_os_str(), "should preserve dest location");
         assert_eq!(0o655, additional_asset.chmod, "should have merged the dest location");
+
+        // Without assets, the merge is kept for the implied assets
+        let variant = CargoDeb { merge_assets: Some(MergeAssets { append: Some(vec![create_test_asset("a", "/b", 0o644)]), by: None }), .. Default::default() };
+        let merged = variant.inherit_from(CargoDeb::default());
+        assert!(merged.assets.is_none());
+        assert_eq!(1, merged.merge_assets.and_then(|m| m.append).map_or(0, |a| a.len()));
     }
 }
 
@@ -541,4 +817,18 @@ fn deb_ver() {
     assert_eq!("1.2.0-new-1", manifest_version_string(&c, None));
     assert_eq!("1.2.0-new-11", manifest_version_string(&c, Some("11")));
     assert_eq!("1.2.0-new", manifest_version_string(&c, Some("0")));
+
+    assert_eq!("0ubuntu1", revision_from_template("-0ubuntu1", None).unwrap());
+    assert_eq!("1~jammy1", revision_from_template("1~{variant}1", Some("jammy")).unwrap());
+    assert!(revision_from_template("1~{variant}1", None).is_err());
+}
+
+#[test]
+fn tolerant_cargo_metadata() {
+    let json = r#"{"packages":[{"id":"a 0.1.0","name":"a","manifest_path":"/ws/a/Cargo.toml","targets":[{"name":"a","kind":["bin"],"src_path":"/ws/a/src/main.rs","new_field":1}]}],
+        "resolve":null,"workspace_members":["a 0.1.0"],"target_directory":"/ws/target","workspace_root":"/ws","version":2}"#;
+    let metadata: CargoMetadata = serde_json::from_str(json).unwrap();
+    assert!(metadata.resolve.is_none());
+    assert!(metadata.packages[0].targets[0].crate_types.is_empty());
+    assert!(metadata.is_virtual_workspace());
 }
@@ -0,0 +1,295 @@
+//! JSON Schema of the `[package.metadata.deb]` table, for editors and other tools.
+//!
+//! It's written by hand to match the `CargoDeb` struct. The tests check that both have the same keys.
+use crate::config::MAINTAINER_SCRIPTS;
+use serde_json::{json, Value};
+
+fn string(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn boolean(description: &str) -> Value {
+    json!({ "type": "boolean", "description": description })
+}
+
+fn string_list(description: &str) -> Value {
+    json!({ "type": "array", "items": { "type": "string" }, "description": description })
+}
+
+fn dependency_list(description: &str) -> Value {
+    json!({
+        "description": description,
+        "anyOf": [
+            { "type": "string" },
+            { "type": "array", "items": { "type": "string" } },
+        ],
+    })
+}
+
+fn asset_list(description: &str) -> Value {
+    json!({
+        "type": "array",
+        "description": description,
+        "items": {
+            "anyOf": [
+                {
+                    "type": "array",
+                    "description": "[source, dest, mode]",
+                    "items": { "type": "string" },
+                    "minItems": 3,
+                    "maxItems": 3,
+                },
+                {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["dest"],
+                    "oneOf": [
+                        { "required": ["source", "mode"] },
+                        { "required": ["bin"] },
+                    ],
+                    "properties": {
+                        "source": string("Path relative to the package root, or a glob. `target/release/` is mapped to the actual build dir"),
+                        "bin": string("Name of an executable, or of a workspace member with one executable, to build and package instead of `source`"),
+                        "dest": string("Absolute installation path. Ends with `/` for directories"),
+                        "mode": string("Permissions in octal, e.g. \"644\", or symbolic, e.g. \"rw-r--r--\" or \"u=rw,go=r\". Defaults to \"755\" for `bin`"),
+                        "transform": string_list("Processing steps applied after the build: template, compress-debug-sections, upx"),
+                        "template": boolean("Expand {{variables}} in the file"),
+                    },
+                },
+            ],
+        },
+    })
+}
+
+fn systemd_unit() -> Value {
+    json!({
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "unit-scripts": string("Directory with the unit files. Defaults to `maintainer-scripts`"),
+            "unit-name": string("Only process units with this name"),
+            "enable": boolean("Enable the unit on installation"),
+            "start": boolean("Start the unit on installation"),
+            "start-on-install": boolean("Start the unit on the first installation, not only on upgrades"),
+            "restart-after-upgrade": boolean("Restart, rather than stop and start, the unit on upgrade"),
+            "stop-on-upgrade": boolean("Stop the unit when upgrading"),
+            "instances": string_list("Instances of template units (`name@.service`) to enable and start. Defaults to `DefaultInstance` of the unit"),
+            "verify": boolean("Check the unit files with `systemd-analyze verify`, if it's installed"),
+        },
+    })
+}
+
+fn package_properties() -> Value {
+    json!({
+        "name": string("Name of the Debian package. Defaults to the Cargo package name"),
+        "maintainer": string("Maintainer field. Defaults to the first author"),
+        "copyright": string("Copyright holders. Defaults to the authors"),
+        "license-file": {
+            "description": "License file path, optionally with a number of lines to skip, or text of the last line of the skipped header",
+            "anyOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" } },
+            ],
+        },
+        "changelog": string("Path to a changelog file in the Debian format"),
+        "changelog-distribution": string("Distribution of changelog entries added by `cargo deb bump`, like `unstable` or `noble`"),
+        "changelog-urgency": string("Urgency of changelog entries added by `cargo deb bump`. Defaults to medium"),
+        "depends": dependency_list("Runtime dependencies. `$auto` is replaced with dependencies detected with dpkg-shlibdeps"),
+        "pre-depends": dependency_list("Pre-Depends field"),
+        "recommends": dependency_list("Recommends field"),
+        "suggests": dependency_list("Suggests field"),
+        "enhances": string("Enhances field"),
+        "conflicts": string("Conflicts field"),
+        "breaks": string("Breaks field"),
+        "replaces": string("Replaces field"),
+        "provides": string("Provides field"),
+        "extended-description": string("Long description. Defaults to the README"),
+        "extended-description-file": string("File with the long description"),
+        "description-fallback": string("Short description used when Cargo.toml and the README don't have one. Supports {{package}}, {{version}}, {{arch}}, {{prefix}} and {{maintainer}}"),
+        "section": string("Debian section"),
+        "priority": string("Debian priority. Defaults to optional"),
+        "protected": boolean("Add `Protected: yes`, so that dpkg refuses to remove the package"),
+        "important": boolean("Add `Important: yes`, so that apt asks for confirmation before removing the package"),
+        "tag": string_list("Debtags for the `Tag` field, like `role::program`"),
+        "revision": string("Debian revision appended to the version. {variant} is replaced with the name of the variant, e.g. 1~{variant}1"),
+        "conf-files": string_list("Configuration files that dpkg shouldn't overwrite, as target or source paths of assets"),
+        "assets": asset_list("Files to install. Defaults to the binaries and the README"),
+        "merge-assets": {
+            "type": "object",
+            "description": "Change assets of the parent config in a variant",
+            "additionalProperties": false,
+            "properties": {
+                "append": asset_list("Assets to add"),
+                "by": {
+                    "type": "object",
+                    "description": "Assets that replace parent's assets with the same source or destination",
+                    "additionalProperties": false,
+                    "properties": {
+                        "src": asset_list("Replace by source path"),
+                        "dest": asset_list("Replace by destination path"),
+                    },
+                },
+            },
+        },
+        "triggers-file": string("Path to the dpkg triggers file"),
+        "maintainer-scripts": string("Directory with preinst, postinst, prerm, postrm, config and templates"),
+        "maintainer-scripts-only": {
+            "type": "array",
+            "description": "Use only these scripts from `maintainer-scripts`, and ignore the others in the directory",
+            "items": { "enum": MAINTAINER_SCRIPTS },
+        },
+        "preinst-script": string("Shell code added to the preinst script, after the generated code"),
+        "postinst-script": string("Shell code added to the postinst script, after the generated code"),
+        "prerm-script": string("Shell code added to the prerm script, before the generated code"),
+        "postrm-script": string("Shell code added to the postrm script, before the generated code"),
+        "features": string_list("Cargo features to build with"),
+        "default-features": boolean("Build with Cargo's default features"),
+        "rustflags": {
+            "description": "Flags for rustc, added to the ones from RUSTFLAGS or .cargo/config.toml",
+            "anyOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" } },
+            ],
+        },
+        "static-pie": boolean("Build statically linked position-independent executables, and fail if they're linked dynamically"),
+        "separate-debug-symbols": boolean("Put debug symbols in separate files in /usr/lib/debug"),
+        "compress-debug-symbols": boolean("Compress the separate debug symbols"),
+        "include-readme": boolean("Install the package's readme in /usr/share/doc. Defaults to true only when `assets` aren't specified"),
+        "preserve-symlinks": boolean("Package symlinks as symlinks instead of copying their targets"),
+        "respect-package-exclude": boolean("Asset globs skip files excluded by `package.exclude`, `package.include` or .gitignore"),
+        "systemd-units": {
+            "description": "Install systemd units",
+            "anyOf": [
+                systemd_unit(),
+                { "type": "array", "items": systemd_unit() },
+            ],
+        },
+        "install-prefix": string("Install executables in this directory, with wrapper scripts in /usr/bin"),
+        "preinst-checks": {
+            "type": "object",
+            "description": "Checks of the target system done before installation",
+            "additionalProperties": false,
+            "properties": {
+                "min-glibc": string("Minimum glibc version"),
+                "systemd": boolean("Require the system to be booted with systemd"),
+                "cpu-features": {
+                    "description": "Rust target features the CPU must support, or true to detect them from RUSTFLAGS",
+                    "anyOf": [
+                        { "type": "boolean" },
+                        { "type": "array", "items": { "type": "string" } },
+                    ],
+                },
+                "disk-space": boolean("Require free disk space for the installed size"),
+            },
+        },
+        "init-system": {
+            "type": "array",
+            "description": "Init systems to install services for. `sysvinit` and `openrc` scripts are taken from `maintainer-scripts`, or generated from systemd services. Defaults to `[\"systemd\"]`",
+            "items": { "enum": ["systemd", "sysvinit", "openrc"] },
+        },
+        "cpu-variants": string_list("Values of `-C target-cpu` for additional builds of the executables, selected with update-alternatives"),
+        "arch-map": {
+            "type": "object",
+            "description": "Debian architecture names for Rust target triples (globs are allowed), for targets that cargo-deb doesn't know",
+            "additionalProperties": { "type": "string" },
+        },
+        "diverts": {
+            "type": "array",
+            "description": "Files of other packages that this package replaces, moved aside with dpkg-divert",
+            "items": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["path"],
+                "properties": {
+                    "path": string("Absolute path of the file to divert"),
+                    "to": string("Where the other package's file is moved. Defaults to `{path}.distrib`"),
+                },
+            },
+        },
+        "locales": {
+            "description": "Dir with gettext translations (`<lang>.po`, `<lang>.mo`, or `<lang>/LC_MESSAGES/<domain>.mo`), installed in `/usr/share/locale`",
+            "anyOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["dir"],
+                    "properties": {
+                        "dir": string("Dir with the translations"),
+                        "domain": string("Name of the `.mo` files for `<lang>.po` and `<lang>.mo` files. Defaults to the package name"),
+                    },
+                },
+            ],
+        },
+        "symbols": {
+            "description": "Generate the `symbols` control file from the packaged shared libraries. A path of a committed symbols file keeps the minimum versions of its symbols, and fails the build if any are removed",
+            "anyOf": [
+                { "type": "boolean" },
+                { "type": "string" },
+            ],
+        },
+        "config-defaults": {
+            "type": "array",
+            "description": "Config files that aren't conffiles. They're installed in `/usr/share/<package>/defaults/`, and copied to their place by `postinst`, without dpkg's prompts about changed files",
+            "items": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["path"],
+                "properties": {
+                    "path": string("Absolute target path of the asset, like `/etc/app/app.toml`"),
+                    "update": {
+                        "description": "`if-absent` installs the file only if it doesn't exist. `always` replaces it on every upgrade, keeping the previous one as `.dpkg-old`",
+                        "enum": ["if-absent", "always"],
+                        "default": "if-absent",
+                    },
+                },
+            },
+        },
+        "purge-paths": string_list("Absolute paths of files and dirs created at run time, like caches and sockets, deleted when the package is purged. Wildcards are allowed in file names"),
+        "keep-special-bits": string_list("Installed paths of files that keep their setuid, setgid or sticky bits when built with --normalize-permissions"),
+        "inspectors": string_list("Shell commands run after the package is built, with the dir of unpacked files as $1 and the .deb as $2. The build fails if any of them fails"),
+    })
+}
+
+/// JSON Schema of `[package.metadata.deb]`
+#[must_use]
+pub fn metadata_schema() -> Value {
+    let mut variant_properties = package_properties();
+    variant_properties["merge-assets"]["description"] = "Change assets of the parent config".into();
+
+    let mut properties = package_properties();
+    properties["variants"] = json!({
+        "type": "object",
+        "description": "Alternative configurations selected with `--variant`. They inherit unset values",
+        "additionalProperties": {
+            "type": "object",
+            "additionalProperties": false,
+            "properties": variant_properties,
+        },
+    });
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "[package.metadata.deb]",
+        "description": "cargo-deb configuration in Cargo.toml",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": properties,
+    })
+}
+
+#[test]
+fn schema_matches_deserializer() {
+    use crate::parse::manifest::CargoDeb;
+
+    // deny_unknown_fields lists all expected fields in the error message
+    let err = toml::from_str::<CargoDeb>("unknown-key-for-test = 1").unwrap_err().to_string();
+    let expected = err.split_once("expected one of ").unwrap().1;
+    let mut known = expected.split(", ").map(|field| field.trim().trim_matches('`')).collect::<Vec<_>>();
+    known.sort_unstable();
+
+    let schema = metadata_schema();
+    let mut in_schema = schema["properties"].as_object().unwrap().keys().map(|k| k.as_str()).collect::<Vec<_>>();
+    in_schema.sort_unstable();
+    assert_eq!(known, in_schema);
+}
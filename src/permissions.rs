@@ -0,0 +1,42 @@
+//! `--normalize-permissions` resets modes of packaged files the way `dh_fixperms` does, so that the package
+//! doesn't depend on the umask and checkout of the machine it was built on: `0755` for executables, and `0644` for
+//! everything else. Setuid, setgid and sticky bits are removed, except on files listed in `keep-special-bits`.
+use crate::assets::AssetCommon;
+use crate::config::PackageConfig;
+use crate::listener::Listener;
+use std::path::Path;
+
+/// Documentation and shared libraries don't need to be executable, even if the source file was
+fn is_never_executable(asset: &AssetCommon) -> bool {
+    asset.target_path.starts_with("usr/share/doc") || asset.target_path.starts_with("usr/share/man") || asset.is_dynamic_library()
+}
+
+fn normalized_mode(chmod: u32, never_executable: bool, keep_special_bits: bool) -> u32 {
+    let mode = if chmod & 0o111 != 0 && !never_executable { 0o755 } else { 0o644 };
+    if keep_special_bits { mode | (chmod & 0o7000) } else { mode }
+}
+
+/// Changes modes of the assets, and reports the files that had a different mode
+pub(crate) fn normalize(package_deb: &mut PackageConfig, listener: &dyn Listener) {
+    let keep = package_deb.keep_special_bits.iter().map(|p| Path::new(p.trim_start_matches('/')).to_path_buf()).collect::<Vec<_>>();
+    for path in keep.iter().filter(|&p| !package_deb.assets.resolved.iter().any(|a| &a.c.target_path == p)) {
+        listener.warning(format!("keep-special-bits has /{}, but it's not in the package", path.display()));
+    }
+    for asset in package_deb.assets.resolved.iter_mut().filter(|a| !a.source.archive_as_symlink_only()) {
+        let mode = normalized_mode(asset.c.chmod, is_never_executable(&asset.c), keep.contains(&asset.c.target_path));
+        if mode != asset.c.chmod {
+            listener.info(format!("Changed mode of /{} from {:o} to {mode:o}", asset.c.target_path.display(), asset.c.chmod));
+            asset.c.chmod = mode;
+        }
+    }
+}
+
+#[test]
+fn normalizes_modes() {
+    assert_eq!(0o755, normalized_mode(0o700, false, false));
+    assert_eq!(0o644, normalized_mode(0o600, false, false));
+    assert_eq!(0o644, normalized_mode(0o775, true, false));
+    assert_eq!(0o755, normalized_mode(0o4755, false, false));
+    assert_eq!(0o4755, normalized_mode(0o4750, false, true));
+    assert_eq!(0o644, normalized_mode(0o666, false, true));
+}
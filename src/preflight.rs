@@ -0,0 +1,153 @@
+//! Checks that the external programs needed for the selected options are installed, before starting the build.
+//! Otherwise a missing `strip` or `upx` would be found out only after a long compilation, and one at a time.
+use crate::assets::AssetTransform;
+use crate::config::{Config, DebugSymbols, PackageConfig};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::compress::Format;
+use crate::CargoDebOptions;
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+struct Tool {
+    /// For messages, e.g. `cargo-auditable` is run as `cargo auditable`
+    name: String,
+    program: OsString,
+    /// Arguments that print the version
+    args: &'static [&'static str],
+    /// Missing optional tools are only warned about
+    required: bool,
+}
+
+impl Tool {
+    fn new(program: impl Into<OsString>, required: bool) -> Self {
+        let program = program.into();
+        Self { name: program.to_string_lossy().into_owned(), program, args: &["--version"], required }
+    }
+}
+
+/// `CARGO_DEB_STRIP`, or the command from `.cargo/config.toml` for the target, or the host's default
+fn configured_command(config: &Config, target: Option<&str>, env_var: &str, default: &str) -> CDResult<OsString> {
+    if let Some(cmd) = env::var_os(env_var) {
+        return Ok(cmd);
+    }
+    if let Some(target) = target {
+        if let Some(conf) = config.cargo_config()? {
+            let cmd = if default == "strip" { conf.strip_command(target) } else { conf.objcopy_command(target) };
+            if let Some(cmd) = cmd {
+                return Ok(cmd.as_os_str().into());
+            }
+        }
+    }
+    Ok(default.into())
+}
+
+/// Programs that the build of this package will run
+fn needed_tools(config: &Config, package_deb: &PackageConfig, options: &CargoDebOptions, cached: bool) -> CDResult<Vec<Tool>> {
+    let target = options.target.as_deref();
+    let mut tools = Vec::new();
+    if !cached {
+        if !options.no_build && !options.repackage {
+            tools.push(Tool::new("cargo", true));
+            if options.auditable {
+                tools.push(Tool { name: "cargo-auditable".into(), program: "cargo".into(), args: &["auditable", "--version"], required: true });
+            }
+        }
+        if options.strip_override.unwrap_or(config.debug_symbols != DebugSymbols::Keep) {
+            tools.push(Tool::new(configured_command(config, target, "CARGO_DEB_STRIP", "strip")?, true));
+            if matches!(config.debug_symbols, DebugSymbols::Separate { .. }) {
+                tools.push(Tool::new(configured_command(config, target, "CARGO_DEB_OBJCOPY", "objcopy")?, true));
+            }
+        }
+        let transforms = package_deb.assets.unresolved.iter().map(|a| &a.c)
+            .chain(package_deb.assets.resolved.iter().map(|a| &a.c))
+            .flat_map(|c| &c.transforms);
+        for transform in transforms {
+            let tool = match transform {
+                AssetTransform::Template => continue,
+                AssetTransform::CompressDebugSections => Tool::new(configured_command(config, target, "CARGO_DEB_OBJCOPY", "objcopy")?, true),
+                AssetTransform::Upx => Tool::new(env::var_os("CARGO_DEB_UPX").unwrap_or_else(|| "upx".into()), true),
+            };
+            tools.push(tool);
+        }
+        // without it the package is made without the dependencies, like before
+        if package_deb.wildcard_depends.split(',').any(|dep| dep.trim() == "$auto") {
+            tools.push(Tool::new("dpkg-shlibdeps", false));
+        }
+        if options.compress_system || (cfg!(not(feature = "lzma")) && matches!(options.compress_type, Format::Xz)) {
+            tools.push(Tool::new(options.compress_type.program(), true));
+        }
+    }
+    if options.install {
+        tools.push(Tool::new("sudo", true));
+        tools.push(Tool::new("dpkg", true));
+    }
+    if options.bundle {
+        tools.push(Tool::new("apt-get", true));
+        tools.push(Tool::new("apt-cache", true));
+    }
+    let mut seen = HashSet::new();
+    tools.retain(|tool| seen.insert((tool.program.clone(), tool.args)));
+    Ok(tools)
+}
+
+/// How to get the program, for the error message
+fn install_hint(program: &str, debian_triple: Option<&str>) -> String {
+    let name = Path::new(program).file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let package = match &*name {
+        "cargo" => return "install Rust from https://rustup.rs".into(),
+        "cargo-auditable" => return "cargo install cargo-auditable".into(),
+        n if n.ends_with("strip") || n.ends_with("objcopy") => match debian_triple {
+            Some(triple) => format!("binutils-{triple}"),
+            None => "binutils".into(),
+        },
+        "dpkg-shlibdeps" => "dpkg-dev".into(),
+        "upx" => "upx-ucl".into(),
+        "xz" => "xz-utils".into(),
+        "apt-get" | "apt-cache" => "apt".into(),
+        "gzip" | "sudo" | "dpkg" => name.into_owned(),
+        _ => return "install it, or check that it's in PATH".into(),
+    };
+    format!("apt-get install {package}")
+}
+
+/// Runs each needed program with `--version`, and reports all missing ones at once
+pub(crate) fn check_tools(config: &Config, package_deb: &PackageConfig, options: &CargoDebOptions, cached: bool, listener: &dyn Listener) -> CDResult<()> {
+    let debian_triple = config.debian_triple.as_deref();
+    let mut missing = Vec::new();
+    for tool in needed_tools(config, package_deb, options, cached)? {
+        let name = &tool.name;
+        match Command::new(&tool.program).args(tool.args).output() {
+            // wrapper scripts may not support `--version`, but a missing Cargo subcommand is an error
+            Ok(output) if output.status.success() || tool.args == ["--version"] => {
+                let text = if output.stdout.is_empty() { &output.stderr } else { &output.stdout };
+                let version = String::from_utf8_lossy(text);
+                listener.info(format!("Using {name}: {}", version.lines().next().unwrap_or_default().trim()));
+            },
+            _ => {
+                let hint = install_hint(name, debian_triple);
+                if tool.required {
+                    missing.push(format!("  {name} (hint: {hint})"));
+                } else {
+                    listener.warning(format!("{name} is not installed (hint: {hint})"));
+                }
+            },
+        }
+    }
+    if !missing.is_empty() {
+        return Err(CargoDebError::MissingTools(missing.join("\n")));
+    }
+    Ok(())
+}
+
+#[test]
+fn install_hints() {
+    assert_eq!("apt-get install binutils", install_hint("strip", None));
+    assert_eq!("apt-get install binutils-aarch64-linux-gnu", install_hint("/usr/bin/aarch64-linux-gnu-objcopy", Some("aarch64-linux-gnu")));
+    assert_eq!("apt-get install dpkg-dev", install_hint("dpkg-shlibdeps", None));
+    assert_eq!("cargo install cargo-auditable", install_hint("cargo-auditable", None));
+    assert_eq!("install it, or check that it's in PATH", install_hint("./my-strip-wrapper.sh", None));
+}
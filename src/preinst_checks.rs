@@ -0,0 +1,195 @@
+//! Generates a `preinst` script fragment that verifies the target system is able
+//! to run the package, so that the installation fails with a readable message
+//! instead of the binaries crashing at runtime.
+use crate::config::PackageConfig;
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::parse::manifest::{CpuFeaturesConfig, PreinstChecksConfig};
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::Write;
+use std::path::Component;
+use std::process::Command;
+
+/// Returns shell code for the `preinst` script, or `None` if no checks are enabled
+pub(crate) fn generate(checks: &PreinstChecksConfig, package_deb: &PackageConfig, target: Option<&str>, listener: &dyn Listener) -> CDResult<Option<String>> {
+    let name = &package_deb.deb_name;
+    let mut script = String::new();
+
+    if let Some(min_glibc) = &checks.min_glibc {
+        let _ = write!(script, concat!(
+            "\tglibc_version=$(getconf GNU_LIBC_VERSION 2>/dev/null | cut -d' ' -f2)\n",
+            "\tif [ -n \"$glibc_version\" ] && ! dpkg --compare-versions \"$glibc_version\" ge \"{min_glibc}\" ; then\n",
+            "\t\techo \"{name} requires glibc {min_glibc} or later, but this system has glibc $glibc_version\" >&2\n",
+            "\t\texit 1\n",
+            "\tfi\n",
+        ), name = name, min_glibc = min_glibc);
+    }
+
+    if checks.systemd == Some(true) {
+        let _ = write!(script, concat!(
+            "\tif [ ! -d /run/systemd/system ] ; then\n",
+            "\t\techo \"{name} requires systemd, but this system has not been booted with systemd\" >&2\n",
+            "\t\texit 1\n",
+            "\tfi\n",
+        ), name = name);
+    }
+
+    let arch = target.unwrap_or(crate::DEFAULT_TARGET).split('-').next().unwrap_or_default();
+    let cpu_features = match &checks.cpu_features {
+        Some(CpuFeaturesConfig::Detect(true)) => {
+            let detected = rustflags_target_features(target)?;
+            if !detected.is_empty() {
+                listener.info(format!("preinst will check CPU features enabled in RUSTFLAGS: {}", detected.iter().map(|f| f.as_str()).collect::<Vec<_>>().join(", ")));
+            }
+            detected.iter().filter_map(|f| cpuinfo_flag(arch, f)).collect::<BTreeSet<_>>()
+        },
+        Some(CpuFeaturesConfig::List(features)) => features.iter().filter_map(|f| {
+            let flag = cpuinfo_flag(arch, f);
+            if flag.is_none() {
+                listener.warning(format!("preinst can't check CPU feature '{f}' on {arch}"));
+            }
+            flag
+        }).collect(),
+        Some(CpuFeaturesConfig::Detect(false)) | None => BTreeSet::new(),
+    };
+    if !cpu_features.is_empty() {
+        let flags = cpu_features.into_iter().collect::<Vec<_>>().join(" ");
+        let _ = write!(script, concat!(
+            "\tcpu_flags=$(grep -m1 -E '^(flags|Features)' /proc/cpuinfo 2>/dev/null | cut -d: -f2)\n",
+            "\tif [ -n \"$cpu_flags\" ] ; then\n",
+            "\t\tfor flag in {flags} ; do\n",
+            "\t\t\tcase \" $cpu_flags \" in\n",
+            "\t\t\t\t*\" $flag \"*) ;;\n",
+            "\t\t\t\t*) echo \"{name} requires a CPU with the '$flag' feature, which this CPU doesn't have\" >&2 ; exit 1 ;;\n",
+            "\t\t\tesac\n",
+            "\t\tdone\n",
+            "\tfi\n",
+        ), name = name, flags = flags);
+    }
+
+    if checks.disk_space == Some(true) {
+        // Files are installed mostly in /usr, which may be on its own partition
+        let mount_dir = package_deb.install_prefix.as_deref()
+            .and_then(|p| p.components().find_map(|c| match c { Component::Normal(c) => c.to_str(), _ => None }))
+            .unwrap_or("usr");
+        let _ = write!(script, concat!(
+            "\tavailable_kb=$(df -Pk /{mount_dir} 2>/dev/null | awk 'NR==2 {{print $4}}')\n",
+            "\tif [ -n \"$available_kb\" ] && [ \"$available_kb\" -lt {installed_size} ] ; then\n",
+            "\t\techo \"{name} needs {installed_size}KB of disk space in /{mount_dir}, but only ${{available_kb}}KB is available\" >&2\n",
+            "\t\texit 1\n",
+            "\tfi\n",
+        ), name = name, mount_dir = mount_dir, installed_size = package_deb.installed_size());
+    }
+
+    if script.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(format!("if [ \"$1\" = \"install\" ] || [ \"$1\" = \"upgrade\" ] ; then\n{script}fi\n")))
+}
+
+/// Target features enabled by `RUSTFLAGS` (e.g. `-C target-cpu=x86-64-v3`) that aren't in the target's baseline
+fn rustflags_target_features(target: Option<&str>) -> CDResult<BTreeSet<String>> {
+    let rustflags: Vec<String> = if let Some(encoded) = env::var_os("CARGO_ENCODED_RUSTFLAGS") {
+        encoded.to_string_lossy().split('\x1f').filter(|f| !f.is_empty()).map(From::from).collect()
+    } else if let Some(flags) = env::var_os("RUSTFLAGS") {
+        flags.to_string_lossy().split_whitespace().map(From::from).collect()
+    } else {
+        Vec::new()
+    };
+    if rustflags.is_empty() {
+        return Ok(BTreeSet::new());
+    }
+    let baseline = rustc_target_features(target, &[])?;
+    let mut enabled = rustc_target_features(target, &rustflags)?;
+    enabled.retain(|f| !baseline.contains(f) && f != "crt-static");
+    Ok(enabled)
+}
+
+pub(crate) fn rustc_target_features(target: Option<&str>, rustflags: &[String]) -> CDResult<BTreeSet<String>> {
+    let mut cmd = Command::new(env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()));
+    cmd.args(["--print", "cfg"]);
+    if let Some(target) = target {
+        cmd.args(["--target", target]);
+    }
+    let output = cmd.args(rustflags).output()
+        .map_err(|e| CargoDebError::CommandFailed(e, "rustc"))?;
+    if !output.status.success() {
+        return Err(CargoDebError::CommandError("rustc --print cfg", String::new(), output.stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines()
+        .filter_map(|line| line.strip_prefix("target_feature=\"")?.strip_suffix('"'))
+        .map(From::from)
+        .collect())
+}
+
+/// Name of the Rust target feature in `/proc/cpuinfo`
+pub(crate) fn cpuinfo_flag(arch: &str, feature: &str) -> Option<&'static str> {
+    Some(match arch {
+        "x86_64" | "i686" | "i586" | "i386" => match feature {
+            "sse3" => "pni",
+            "ssse3" => "ssse3",
+            "sse4.1" => "sse4_1",
+            "sse4.2" => "sse4_2",
+            "sse4a" => "sse4a",
+            "popcnt" => "popcnt",
+            "cmpxchg16b" => "cx16",
+            "lzcnt" => "abm",
+            "avx" => "avx",
+            "avx2" => "avx2",
+            "fma" => "fma",
+            "f16c" => "f16c",
+            "bmi1" => "bmi1",
+            "bmi2" => "bmi2",
+            "movbe" => "movbe",
+            "xsave" => "xsave",
+            "aes" => "aes",
+            "pclmulqdq" => "pclmulqdq",
+            "sha" => "sha_ni",
+            "adx" => "adx",
+            "rdrand" => "rdrand",
+            "rdseed" => "rdseed",
+            "avx512f" => "avx512f",
+            "avx512bw" => "avx512bw",
+            "avx512cd" => "avx512cd",
+            "avx512dq" => "avx512dq",
+            "avx512vl" => "avx512vl",
+            _ => return None,
+        },
+        "aarch64" => match feature {
+            "neon" => "asimd",
+            "aes" => "aes",
+            "sha2" => "sha2",
+            "sha3" => "sha3",
+            "crc" => "crc32",
+            "lse" => "atomics",
+            "rdm" => "asimdrdm",
+            "dotprod" => "asimddp",
+            "fp16" => "fphp",
+            "sve" => "sve",
+            "sve2" => "sve2",
+            _ => return None,
+        },
+        arm if arm.starts_with("arm") || arm.starts_with("thumb") => match feature {
+            "neon" => "neon",
+            "vfp3" | "vfp3d16" => "vfpv3",
+            "vfp4" => "vfpv4",
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpuinfo_flags() {
+        assert_eq!(Some("sse4_2"), cpuinfo_flag("x86_64", "sse4.2"));
+        assert_eq!(Some("asimd"), cpuinfo_flag("aarch64", "neon"));
+        assert_eq!(Some("neon"), cpuinfo_flag("armv7", "neon"));
+        assert_eq!(None, cpuinfo_flag("x86_64", "crt-static"));
+        assert_eq!(None, cpuinfo_flag("riscv64gc", "d"));
+    }
+}
@@ -0,0 +1,87 @@
+//! Files and dirs that the program creates at run time (caches, sockets, state), which aren't in the package,
+//! so dpkg doesn't know about them. `postrm` deletes them when the package is purged.
+use crate::config::PackageConfig;
+use crate::error::{CDResult, CargoDebError};
+use crate::util::shell_quote;
+use std::fmt::Write;
+use std::path::{Component, Path};
+
+/// Dirs shared with the system and other packages, which are too shallow to be rejected by depth alone
+const SHARED_DIRS: &[&str] = &[
+    "etc/alternatives", "etc/apt", "etc/cron.d", "etc/cron.daily", "etc/cron.hourly", "etc/cron.monthly", "etc/cron.weekly",
+    "etc/dbus-1", "etc/default", "etc/init.d", "etc/ld.so.conf.d", "etc/logrotate.d", "etc/modprobe.d", "etc/network",
+    "etc/pam.d", "etc/profile.d", "etc/security", "etc/skel", "etc/ssl", "etc/sudoers.d", "etc/sysctl.d", "etc/systemd",
+    "etc/tmpfiles.d", "etc/udev", "etc/X11", "etc/xdg",
+    "run/dbus", "run/lock", "run/systemd", "run/udev", "run/user",
+    "usr/lib/debug", "usr/lib/systemd", "usr/lib/sysusers.d", "usr/lib/tmpfiles.d", "usr/lib/udev",
+    "usr/local/bin", "usr/local/etc", "usr/local/lib", "usr/local/sbin", "usr/local/share",
+    "usr/share/applications", "usr/share/dbus-1", "usr/share/doc", "usr/share/icons", "usr/share/locale", "usr/share/man",
+    "var/cache/apt", "var/cache/debconf", "var/lib/apt", "var/lib/dbus", "var/lib/dpkg", "var/lib/systemd", "var/lib/ucf",
+    "var/log/journal", "var/spool/cron",
+];
+
+/// Whether the dir can belong to a single package, e.g. `/var/lib/app`, but not `/var/lib` or `/usr/lib/systemd`
+fn is_own_dir(names: &[&str]) -> bool {
+    let min_depth = match names.first() {
+        Some(&("etc" | "opt" | "run" | "srv")) => 2,
+        // everything directly in them is shared
+        Some(&("usr" | "var")) => 3,
+        _ => return false,
+    };
+    let is_multiarch_lib_dir = names.len() == 3 && names[..2] == ["usr", "lib"] && names[2].contains("-linux-");
+    names.len() >= min_depth && !is_multiarch_lib_dir && !SHARED_DIRS.contains(&names.join("/").as_str())
+}
+
+/// Rejects relative paths, `..`, shared dirs like `/var/lib` or `/etc/systemd`, and wildcards that could match other packages' files
+fn check_path(path: &str) -> CDResult<()> {
+    let names = Path::new(path).components().skip(1).map(|c| match c {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    }).collect::<Option<Vec<_>>>().unwrap_or_default();
+    let is_wildcard = |name: &str| name.contains(['*', '?']);
+    let is_safe = path.starts_with('/') && match names.split_last() {
+        // a wildcard can only match files in the package's own dir
+        Some((&last, parent)) if is_wildcard(last) => !last.chars().all(|c| c == '*' || c == '?') && is_own_dir(parent),
+        Some(_) => !names.iter().any(|&name| is_wildcard(name)) && is_own_dir(&names),
+        None => false,
+    };
+    if !is_safe {
+        return Err(CargoDebError::InvalidPurgePath(path.into()));
+    }
+    Ok(())
+}
+
+/// Quoted for the shell, except `*` and `?` wildcards
+fn shell_pattern(path: &str) -> String {
+    if path.contains(['*', '?']) && path.bytes().all(|c| c.is_ascii_alphanumeric() || b"/._-+:,=@*?".contains(&c)) {
+        return path.to_owned();
+    }
+    shell_quote(path)
+}
+
+/// Shell code for `postrm` that deletes the paths on purge
+pub(crate) fn generate_script(package_deb: &PackageConfig) -> CDResult<Option<String>> {
+    if package_deb.purge_paths.is_empty() {
+        return Ok(None);
+    }
+    let mut postrm = String::from("if [ \"$1\" = \"purge\" ] ; then\n");
+    for path in &package_deb.purge_paths {
+        check_path(path)?;
+        let _ = writeln!(postrm, "\trm -rf {}", shell_pattern(path.trim_end_matches('/')));
+    }
+    postrm.push_str("fi\n");
+    Ok(Some(postrm))
+}
+
+#[test]
+fn purge_path_safety() {
+    for path in ["/var/cache/app", "/run/app.sock", "/var/log/app/*.log", "/var/lib/app/", "/etc/app", "/opt/app/cache"] {
+        assert!(check_path(path).is_ok(), "{path}");
+    }
+    for path in ["/", "/var", "var/lib/app", "/var/lib/../..", "/var/*", "/*/app", "/usr/lib", "/var/lib", "/var/lib/", "/etc/systemd",
+        "/var/lib/*", "/var/lib/a*", "/var/log/app-*.log", "/usr/lib/systemd", "/usr/lib/x86_64-linux-gnu", "/home/user", "/var/lib/*/cache"] {
+        assert!(check_path(path).is_err(), "{path}");
+    }
+    assert_eq!("/var/log/app/*.log", shell_pattern("/var/log/app/*.log"));
+    assert_eq!("'/var/lib/my app'", shell_pattern("/var/lib/my app"));
+}
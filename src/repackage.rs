@@ -0,0 +1,116 @@
+//! `--repackage` makes the package again from the binaries of the previous build, without running Cargo,
+//! e.g. after changing only maintainer scripts or assets. Every build records the size, mtime and SHA-256
+//! of the files Cargo has built, and `--repackage` refuses to use files that don't match the record,
+//! because they may have been rebuilt since then with other features, flags or profile.
+use crate::config::{Config, PackageConfig};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::{atomic_file, hex};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// State of a built file right after the build
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct BuiltFile {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    sha256: String,
+}
+
+/// `target/debian/build-records/<package>.json`
+fn record_path(config: &Config, package_deb: &PackageConfig) -> PathBuf {
+    config.work_dir.join("build-records").join(format!("{}.json", package_deb.name))
+}
+
+/// Files built by Cargo that go into the package
+fn built_paths(package_deb: &PackageConfig) -> Vec<&Path> {
+    package_deb.assets.resolved.iter().filter(|a| a.c.is_built()).filter_map(|a| a.source.path()).collect()
+}
+
+fn metadata(path: &Path) -> CDResult<(u64, (u64, u32))> {
+    let metadata = fs::metadata(path).map_err(|e| CargoDebError::IoFile("unable to read built file", e, path.into()))?;
+    let mtime = metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).unwrap_or_default();
+    Ok((metadata.len(), (mtime.as_secs(), mtime.subsec_nanos())))
+}
+
+/// Streamed, because binaries with debug info can be large
+fn sha256(path: &Path) -> CDResult<String> {
+    let mut hasher = Sha256::new();
+    fs::File::open(path).and_then(|mut file| io::copy(&mut file, &mut hasher))
+        .map_err(|e| CargoDebError::IoFile("unable to read built file", e, path.into()))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn describe(path: &Path) -> CDResult<BuiltFile> {
+    let (size, (mtime_secs, mtime_nanos)) = metadata(path)?;
+    Ok(BuiltFile { size, mtime_secs, mtime_nanos, sha256: sha256(path)? })
+}
+
+/// The mtime is checked first to avoid hashing. A file with a different mtime is still the same if it has the same contents.
+fn is_unchanged(path: &Path, built: &BuiltFile) -> CDResult<bool> {
+    let (size, mtime) = metadata(path)?;
+    if size != built.size {
+        return Ok(false);
+    }
+    if mtime == (built.mtime_secs, built.mtime_nanos) {
+        return Ok(true);
+    }
+    Ok(sha256(path)? == built.sha256)
+}
+
+/// Saves the state of the built files, so that `--repackage` can use them later
+pub(crate) fn record(config: &Config, package_deb: &PackageConfig) -> CDResult<()> {
+    let record = built_paths(package_deb).into_iter()
+        .map(|path| Ok((path.to_path_buf(), describe(path)?)))
+        .collect::<CDResult<BTreeMap<_, _>>>()?;
+    let path = record_path(config, package_deb);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| CargoDebError::IoFile("unable to create build record dir", e, dir.into()))?;
+    }
+    atomic_file::write(&path, serde_json::to_vec_pretty(&record)?)
+        .map_err(|e| CargoDebError::IoFile("unable to save build record", e, path.clone()))
+}
+
+/// Checks that the built files are the same as after the previous build
+pub(crate) fn verify(config: &Config, package_deb: &PackageConfig, listener: &dyn Listener) -> CDResult<()> {
+    let path = record_path(config, package_deb);
+    let record: BTreeMap<PathBuf, BuiltFile> = match fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(CargoDebError::CantRepackage(format!("there's no record of a previous build of {}", package_deb.name)));
+        },
+        Err(e) => return Err(CargoDebError::IoFile("unable to read build record", e, path)),
+    };
+    let paths = built_paths(package_deb);
+    for &path in &paths {
+        let Some(built) = record.get(path) else {
+            return Err(CargoDebError::CantRepackage(format!("{} hasn't been built by the previous build", path.display())));
+        };
+        if !path.exists() || !is_unchanged(path, built)? {
+            return Err(CargoDebError::CantRepackage(format!("{} has changed since the previous build", path.display())));
+        }
+    }
+    listener.info(format!("Repackaging {} files from the previous build", paths.len()));
+    Ok(())
+}
+
+#[test]
+fn detects_changed_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bin");
+    fs::write(&path, "built").unwrap();
+    let mut built = describe(&path).unwrap();
+    assert!(is_unchanged(&path, &built).unwrap());
+
+    // touched, but not rebuilt
+    built.mtime_secs -= 100;
+    assert!(is_unchanged(&path, &built).unwrap());
+
+    fs::write(&path, "rebuilt").unwrap();
+    assert!(!is_unchanged(&path, &built).unwrap());
+}
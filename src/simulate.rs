@@ -0,0 +1,162 @@
+//! `cargo deb --simulate fixture.toml` resolves the configuration and assets of a package described
+//! by a fixture, without running Cargo or touching the project, and prints what would be packaged.
+//!
+//! The fixture is a `Cargo.toml` with an extra `[simulate]` table:
+//!
+//! ```toml
+//! [package]
+//! name = "example"
+//! version = "1.0.0"
+//!
+//! [package.metadata.deb]
+//! assets = [["config/*.toml", "etc/example/", "644"], "$auto"]
+//!
+//! [simulate]
+//! target = "aarch64-unknown-linux-gnu"
+//! files = { "config/example.toml" = "port = 80\n", "LICENSE" = "" }
+//! ```
+use crate::config::Config;
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::parse::manifest::{CargoMetadataTarget, CargoPackageMetadata, ManifestFound};
+use crate::CargoDebOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct SimulateConfig {
+    /// Rust target triple. The `--target` option takes precedence
+    target: Option<String>,
+    /// Files of the package, relative to the manifest, with their contents
+    #[serde(default)]
+    files: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Fixture {
+    #[serde(default)]
+    simulate: SimulateConfig,
+}
+
+/// What would be packaged
+#[derive(Serialize)]
+struct Plan {
+    package: String,
+    version: String,
+    architecture: String,
+    conf_files: Vec<String>,
+    assets: Vec<PlannedAsset>,
+    control: String,
+}
+
+#[derive(Serialize)]
+struct PlannedAsset {
+    /// Relative to the fixture's package. `None` for generated files
+    source: Option<String>,
+    target: String,
+    mode: String,
+    built: bool,
+}
+
+/// Returns the plan as JSON
+pub(crate) fn simulate(fixture_path: &Path, options: &CargoDebOptions, listener: &dyn Listener) -> CDResult<String> {
+    let manifest_text = fs::read_to_string(fixture_path)
+        .map_err(|e| CargoDebError::IoFile("unable to read the fixture", e, fixture_path.into()))?;
+    let fixture: Fixture = toml::from_str(&manifest_text)?;
+
+    // Files have to exist for asset globs and readme/license lookups
+    let package_dir = tempfile::tempdir()?;
+    let root = package_dir.path().canonicalize()?;
+    let manifest_path = root.join("Cargo.toml");
+    fs::write(&manifest_path, &manifest_text)?;
+    for (rel_path, contents) in &fixture.simulate.files {
+        if Path::new(rel_path).is_absolute() || rel_path.split(['/', '\\']).any(|c| c == "..") {
+            return Err(CargoDebError::Str("files in [simulate] must be relative paths inside the package"));
+        }
+        let path = root.join(rel_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, contents)?;
+    }
+    let has_targets = ["src/main.rs", "src/lib.rs"].iter().any(|p| fixture.simulate.files.contains_key(*p));
+    if !has_targets {
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join("src/main.rs"), "")?;
+    }
+
+    let mut manifest = cargo_toml::Manifest::<CargoPackageMetadata>::from_slice_with_metadata(manifest_text.as_bytes())
+        .map_err(|e| CargoDebError::TomlParsing(e, fixture_path.into()))?;
+    manifest.complete_from_path(&manifest_path)
+        .map_err(|e| CargoDebError::TomlParsing(e, fixture_path.into()))?;
+    let build_targets = build_targets(&manifest, &root);
+
+    let target = options.target.as_deref().or(fixture.simulate.target.as_deref());
+    let found = ManifestFound {
+        build_targets,
+        workspace_bins: Vec::new(),
+        manifest_path: manifest_path.clone(),
+        root_manifest: None,
+        target_dir: root.join("target"),
+        workspace_root: root.clone(),
+        manifest,
+    };
+    let (config, mut package_deb) = Config::from_found_manifest(found, None, target, options.variant.as_deref(), options.overrides.clone(),
+        options.profile.clone(), options.separate_debug_symbols, options.compress_debug_symbols, options.cargo_locking_flags, listener)?;
+    config.prepare_assets_before_build(&mut package_deb)?;
+
+    // Pretend everything has been built
+    for asset in package_deb.assets.unresolved.iter().filter(|a| a.c.is_built() && !crate::config::is_glob_pattern(&a.source_path)) {
+        if let Some(parent) = asset.source_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&asset.source_path, "")?;
+    }
+    package_deb.resolve_assets()?;
+
+    // Libraries of the empty binaries can't be detected, so only the explicit dependencies are used
+    let declared_depends = std::mem::take(&mut package_deb.wildcard_depends);
+    package_deb.wildcard_depends = declared_depends.split(',').map(str::trim).filter(|d| !d.is_empty() && *d != "$auto").collect::<Vec<_>>().join(", ");
+    package_deb.resolve_binary_dependencies(None, listener)?;
+    package_deb.resolved_depends = package_deb.resolved_depends.take().filter(|d| !d.is_empty()).map(|deps| {
+        let mut deps = deps.split(", ").collect::<Vec<_>>();
+        deps.sort_unstable();
+        deps.join(", ")
+    });
+
+    let control = String::from_utf8(package_deb.generate_control(&config)?)?;
+    let plan = Plan {
+        assets: package_deb.assets.resolved.iter().map(|asset| PlannedAsset {
+            source: asset.source.path().map(|p| p.strip_prefix(&root).unwrap_or(p).display().to_string()),
+            target: format!("/{}", asset.c.target_path.display()),
+            mode: format!("{:o}", asset.c.chmod),
+            built: asset.c.is_built(),
+        }).collect(),
+        conf_files: package_deb.conf_files,
+        package: package_deb.deb_name,
+        version: package_deb.deb_version,
+        architecture: package_deb.architecture,
+        control,
+    };
+    Ok(serde_json::to_string_pretty(&plan)?)
+}
+
+/// Like the targets in `cargo metadata`
+fn build_targets(manifest: &cargo_toml::Manifest<CargoPackageMetadata>, root: &Path) -> Vec<CargoMetadataTarget> {
+    let target = |product: &cargo_toml::Product, kind: &str, default_crate_type: &str| {
+        let crate_types = if product.crate_type.is_empty() { vec![default_crate_type.to_owned()] } else { product.crate_type.clone() };
+        CargoMetadataTarget {
+            name: product.name.clone().unwrap_or_default(),
+            kind: if kind == "lib" { crate_types.clone() } else { vec![kind.to_owned()] },
+            crate_types,
+            src_path: root.join(product.path.as_deref().unwrap_or_default()),
+        }
+    };
+    manifest.lib.iter().map(|lib| target(lib, "lib", "lib"))
+        .chain(manifest.bin.iter().map(|bin| target(bin, "bin", "bin")))
+        .chain(manifest.example.iter().map(|example| target(example, "example", "bin")))
+        .collect()
+}
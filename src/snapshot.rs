@@ -0,0 +1,277 @@
+//! Text snapshots of built `.deb` files, for comparing packages against golden files in tests.
+//!
+//! The snapshot has the control fields, the list of files with their modes, maintainer scripts,
+//! and the text of the copyright and changelog files. Checksums and file sizes of the executables
+//! aren't included, since they change with every compiler version.
+use crate::error::{CDResult, CargoDebError};
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Control fields with values that depend on the build machine, which are masked by default
+pub const HOST_DEPENDENT_FIELDS: &[&str] = &["Architecture", "Installed-Size", "Depends"];
+
+/// Reads the `.deb` and makes a human-readable snapshot of its contents.
+///
+/// Values of `masked_fields` in the control file are replaced with `*`.
+pub fn snapshot_deb(deb_path: &Path, masked_fields: &[&str]) -> CDResult<String> {
+    let file = fs::File::open(deb_path).map_err(|e| CargoDebError::IoFile("unable to open package", e, deb_path.into()))?;
+    let mut archive = ar::Archive::new(file);
+
+    let mut out = String::new();
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+
+        if name == "debian-binary" {
+            let _ = write!(out, "## debian-binary\n{}", String::from_utf8_lossy(&data));
+        } else if let Some(ext) = name.strip_prefix("control.tar.") {
+            out.push_str("\n## control.tar\n");
+            snapshot_tar(&mut out, decompress(ext, &data)?, |path, content| {
+                if path == "control" {
+                    Some(mask_fields(&String::from_utf8_lossy(content), masked_fields))
+                } else if path == "md5sums" || path == "sha256sums" {
+                    None
+                } else {
+                    Some(String::from_utf8_lossy(content).into_owned())
+                }
+            })?;
+        } else if let Some(ext) = name.strip_prefix("data.tar.") {
+            out.push_str("\n## data.tar\n");
+            snapshot_tar(&mut out, decompress(ext, &data)?, |path, content| {
+                let doc_file = path.strip_prefix("usr/share/doc/")?.split_once('/')?.1;
+                if doc_file == "copyright" {
+                    Some(String::from_utf8_lossy(content).into_owned())
+                } else if doc_file.starts_with("changelog") && doc_file.ends_with(".gz") {
+                    let mut text = String::new();
+                    GzDecoder::new(content).read_to_string(&mut text).ok()?;
+                    Some(text)
+                } else {
+                    None
+                }
+            })?;
+        } else {
+            let _ = writeln!(out, "\n## {name} ({} bytes)", data.len());
+        }
+    }
+    Ok(out)
+}
+
+/// Reads the `control` file of the `.deb`
+pub(crate) fn read_control(deb_path: &Path) -> CDResult<String> {
+    let file = fs::File::open(deb_path).map_err(|e| CargoDebError::IoFile("unable to open package", e, deb_path.into()))?;
+    let mut archive = ar::Archive::new(file);
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let Some(ext) = std::str::from_utf8(entry.header().identifier()).ok().and_then(|n| n.strip_prefix("control.tar.")).map(str::to_owned) else { continue };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        for file in tar::Archive::new(decompress(&ext, &data)?).entries()? {
+            let mut file = file?;
+            if file.path()?.to_string_lossy().trim_start_matches("./") == "control" {
+                let mut control = String::new();
+                file.read_to_string(&mut control)?;
+                return Ok(control);
+            }
+        }
+    }
+    Err(CargoDebError::IoFile("the package has no control file", io::ErrorKind::NotFound.into(), deb_path.into()))
+}
+
+/// Paths of files and symlinks (not directories) in the `data.tar` of the `.deb`, without the leading `./`
+pub(crate) fn read_file_list(deb_path: &Path) -> CDResult<Vec<String>> {
+    let file = fs::File::open(deb_path).map_err(|e| CargoDebError::IoFile("unable to open package", e, deb_path.into()))?;
+    let mut archive = ar::Archive::new(file);
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let Some(ext) = std::str::from_utf8(entry.header().identifier()).ok().and_then(|n| n.strip_prefix("data.tar.")).map(str::to_owned) else { continue };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        let mut paths = Vec::new();
+        // only the headers are needed, the contents are skipped
+        for file in tar::Archive::new(decompress(&ext, &data)?).entries()? {
+            let file = file?;
+            if !file.header().entry_type().is_dir() {
+                paths.push(file.path()?.to_string_lossy().trim_start_matches("./").to_owned());
+            }
+        }
+        return Ok(paths);
+    }
+    Err(CargoDebError::IoFile("the package has no data.tar", io::ErrorKind::NotFound.into(), deb_path.into()))
+}
+
+/// A file or symlink in one of the tarballs of a `.deb`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TarFile {
+    /// Without the leading `./`
+    pub path: String,
+    pub size: u64,
+    pub mode: u32,
+    pub link: Option<String>,
+    pub sha256: [u8; 32],
+}
+
+/// Files and symlinks (not directories) of the `control.tar.` or `data.tar.` member of the `.deb`
+pub(crate) fn read_tar_files(deb_path: &Path, member_prefix: &str) -> CDResult<Vec<TarFile>> {
+    let file = fs::File::open(deb_path).map_err(|e| CargoDebError::IoFile("unable to open package", e, deb_path.into()))?;
+    let mut archive = ar::Archive::new(file);
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let Some(ext) = std::str::from_utf8(entry.header().identifier()).ok().and_then(|n| n.strip_prefix(member_prefix)).map(str::to_owned) else { continue };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        let mut files = Vec::new();
+        for file in tar::Archive::new(decompress(&ext, &data)?).entries()? {
+            let mut file = file?;
+            let header = file.header();
+            if header.entry_type().is_dir() {
+                continue;
+            }
+            let (mode, size) = (header.mode()?, header.size()?);
+            let link = header.link_name()?.map(|l| l.to_string_lossy().into_owned());
+            let path = file.path()?.to_string_lossy().trim_start_matches("./").to_owned();
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            files.push(TarFile { path, size, mode, link, sha256: Sha256::digest(&content).into() });
+        }
+        return Ok(files);
+    }
+    let msg = if member_prefix.starts_with("data") { "the package has no data.tar" } else { "the package has no control.tar" };
+    Err(CargoDebError::IoFile(msg, io::ErrorKind::NotFound.into(), deb_path.into()))
+}
+
+/// Compares the snapshot with the contents of `golden_path`, and panics with a diff if they differ, or if the file is missing.
+///
+/// With `CARGO_DEB_UPDATE_SNAPSHOTS=1` the file is (re)written instead, so that the changes can be reviewed in version control.
+#[track_caller]
+pub fn assert_snapshot(snapshot: &str, golden_path: &Path) {
+    let update = std::env::var_os("CARGO_DEB_UPDATE_SNAPSHOTS").is_some_and(|v| v != "0");
+    if update {
+        if let Some(dir) = golden_path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        fs::write(golden_path, snapshot).unwrap_or_else(|e| panic!("can't write {}: {e}", golden_path.display()));
+        return;
+    }
+    let expected = match fs::read_to_string(golden_path) {
+        Ok(expected) => expected,
+        Err(e) => panic!("can't read the snapshot {}: {e}\nRun with CARGO_DEB_UPDATE_SNAPSHOTS=1 to create it", golden_path.display()),
+    };
+    if expected != snapshot {
+        let mut diff = String::new();
+        let (expected_lines, actual_lines): (Vec<_>, Vec<_>) = (expected.lines().collect(), snapshot.lines().collect());
+        for i in 0..expected_lines.len().max(actual_lines.len()) {
+            match (expected_lines.get(i), actual_lines.get(i)) {
+                (Some(e), Some(a)) if e == a => {},
+                (e, a) => {
+                    if let Some(e) = e { let _ = writeln!(diff, "{:>5} - {e}", i + 1); }
+                    if let Some(a) = a { let _ = writeln!(diff, "{:>5} + {a}", i + 1); }
+                },
+            }
+        }
+        panic!("Package doesn't match the snapshot {}\n{diff}\nRun with CARGO_DEB_UPDATE_SNAPSHOTS=1 to accept the changes", golden_path.display());
+    }
+}
+
+/// `ext` is empty for uncompressed tarballs
+pub(crate) fn decompress<'a>(ext: &str, data: &'a [u8]) -> CDResult<Box<dyn Read + 'a>> {
+    Ok(match ext {
+        "" => Box::new(data),
+        "gz" => Box::new(GzDecoder::new(data)),
+        #[cfg(feature = "lzma")]
+        "xz" => Box::new(xz2::read::XzDecoder::new(data)),
+        _ => return Err(CargoDebError::Str("unsupported compression of the tarball in the package")),
+    })
+}
+
+/// Lists files in the tarball, and appends contents returned by `text_of(path, content)` after the listing
+fn snapshot_tar(out: &mut String, tarball: impl Read, text_of: impl Fn(&str, &[u8]) -> Option<String>) -> io::Result<()> {
+    let mut texts = Vec::new();
+    for entry in tar::Archive::new(tarball).entries()? {
+        let mut entry = entry?;
+        let header = entry.header();
+        let mode = header.mode()?;
+        let kind = match header.entry_type() {
+            tar::EntryType::Directory => 'd',
+            tar::EntryType::Symlink => 'l',
+            tar::EntryType::Link => 'h',
+            _ => '-',
+        };
+        let link = header.link_name()?.map(|l| format!(" -> {}", l.display())).unwrap_or_default();
+        let path = entry.path()?.to_string_lossy().trim_start_matches("./").to_owned();
+        // Empty owner fields are extracted as root
+        let (uid, gid) = (header.uid().unwrap_or(0), header.gid().unwrap_or(0));
+        let _ = writeln!(out, "{kind}{} {uid}:{gid} {path}{link}", mode_string(mode));
+
+        if kind == '-' {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            if let Some(text) = text_of(&path, &content) {
+                texts.push((path, text));
+            }
+        }
+    }
+    for (path, text) in texts {
+        let _ = write!(out, "\n### {path}\n{text}");
+        if !text.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    Ok(())
+}
+
+fn mask_fields(control: &str, masked_fields: &[&str]) -> String {
+    let mut out = String::with_capacity(control.len());
+    let mut masking = false;
+    for line in control.lines() {
+        if line.starts_with([' ', '\t']) {
+            if masking {
+                continue;
+            }
+        } else {
+            masking = false;
+            if let Some((name, _)) = line.split_once(':') {
+                if masked_fields.iter().any(|f| f.eq_ignore_ascii_case(name)) {
+                    masking = true;
+                    let _ = writeln!(out, "{name}: *");
+                    continue;
+                }
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn mode_string(mode: u32) -> String {
+    let mut s = String::with_capacity(9);
+    for (i, shift) in [6, 3, 0].into_iter().enumerate() {
+        let bits = mode >> shift;
+        s.push(if bits & 4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 2 != 0 { 'w' } else { '-' });
+        let special = mode & [0o4000, 0o2000, 0o1000][i] != 0;
+        s.push(match (bits & 1 != 0, special, i == 2) {
+            (true, false, _) => 'x',
+            (false, false, _) => '-',
+            (true, true, false) => 's',
+            (false, true, false) => 'S',
+            (true, true, true) => 't',
+            (false, true, true) => 'T',
+        });
+    }
+    s
+}
+
+#[test]
+fn masks_fields() {
+    let control = "Package: foo\nDepends: libc6 (>= 2.34),\n libfoo\nDescription: bar\n baz\n";
+    assert_eq!("Package: foo\nDepends: *\nDescription: bar\n baz\n", mask_fields(control, &["depends"]));
+    assert_eq!("rwsr-xr-x", mode_string(0o4755));
+    assert_eq!("rw-r--r-T", mode_string(0o1644));
+}
@@ -0,0 +1,218 @@
+//! The `symbols` control file of packages with shared libraries, which `dpkg-shlibdeps` uses to make packages
+//! linking to the library depend on the version that added the symbols they use, rather than on any version.
+//!
+//! Symbols are read from the dynamic symbol table of the packaged libraries. If there's a committed baseline,
+//! it provides the minimum versions of existing symbols, and symbols missing from the library are an ABI break.
+use crate::config::{Config, PackageConfig};
+use crate::error::{CDResult, CargoDebError};
+use crate::hardening::Elf;
+use crate::listener::Listener;
+use crate::parse::manifest::SymbolsConfig;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+
+const SHT_DYNSYM: u32 = 11;
+const SHT_GNU_VERDEF: u32 = 0x6fff_fffd;
+const SHT_GNU_VERSYM: u32 = 0x6fff_ffff;
+const PT_DYNAMIC: u32 = 2;
+const DT_SONAME: u64 = 14;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STB_GNU_UNIQUE: u8 = 10;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+const STV_HIDDEN: u8 = 2;
+const STV_INTERNAL: u8 = 1;
+const VER_FLG_BASE: u16 = 1;
+
+/// Exported symbols of a shared library, as `name@version` (`@Base` for unversioned symbols)
+#[derive(Debug, PartialEq)]
+pub(crate) struct SharedLibrary {
+    pub soname: String,
+    pub symbols: Vec<String>,
+}
+
+/// `None` if it's not a shared library with a `SONAME`
+pub(crate) fn read_shared_library(data: &[u8]) -> Option<SharedLibrary> {
+    let elf = Elf::new(data)?;
+    let sections = elf.section_headers();
+    let &(_, dynsym_offset, dynsym_size, strtab_index, _) = sections.iter().find(|s| s.0 == SHT_DYNSYM)?;
+    let &(_, strtab_offset, strtab_size, _, _) = sections.get(strtab_index as usize)?;
+    let strtab = elf.data.get(strtab_offset..strtab_offset.saturating_add(strtab_size))?;
+    let string = |offset: u64| -> Option<&str> {
+        let s = strtab.get(usize::try_from(offset).ok()?..)?;
+        std::str::from_utf8(s.split(|&b| b == 0).next()?).ok()
+    };
+
+    let soname = elf.program_headers()
+        .filter(|&(p_type, _, _)| p_type == PT_DYNAMIC)
+        .flat_map(|(_, offset, size)| elf.dynamic_entries(offset, size))
+        .find(|&(tag, _)| tag == DT_SONAME)
+        .and_then(|(_, val)| string(val))?
+        .to_owned();
+
+    let versions = version_names(&elf, &sections);
+    let versym = sections.iter().find(|s| s.0 == SHT_GNU_VERSYM).map(|&(_, offset, _, _, _)| offset);
+    let entry_size = if elf.is_64 { 24 } else { 16 };
+    let mut symbols = Vec::new();
+    // The first entry is always the undefined symbol
+    for i in 1..dynsym_size / entry_size {
+        let sym = dynsym_offset + i * entry_size;
+        let (name, info, other, shndx) = if elf.is_64 {
+            (elf.u32(sym)?, *elf.data.get(sym + 4)?, *elf.data.get(sym + 5)?, elf.u16(sym + 6)?)
+        } else {
+            (elf.u32(sym)?, *elf.data.get(sym + 12)?, *elf.data.get(sym + 13)?, elf.u16(sym + 14)?)
+        };
+        let (binding, sym_type, visibility) = (info >> 4, info & 0xf, other & 3);
+        if shndx == 0 || !matches!(binding, STB_GLOBAL | STB_WEAK | STB_GNU_UNIQUE) || matches!(sym_type, STT_SECTION | STT_FILE) || matches!(visibility, STV_HIDDEN | STV_INTERNAL) {
+            continue;
+        }
+        let Some(name) = string(u64::from(name)).filter(|n| !n.is_empty()) else { continue };
+        let version = versym.and_then(|offset| elf.u16(offset + i * 2)).and_then(|v| versions.get(&(v & 0x7fff))).map_or("Base", String::as_str);
+        symbols.push(format!("{name}@{version}"));
+    }
+    symbols.sort();
+    symbols.dedup();
+    Some(SharedLibrary { soname, symbols })
+}
+
+/// Names of version definitions from a version script, by their index in the `.gnu.version` table.
+/// The base definition (the library's own name) isn't included, so its symbols are `@Base`.
+fn version_names(elf: &Elf<'_>, sections: &[(u32, usize, usize, u32, u32)]) -> BTreeMap<u16, String> {
+    let mut names = BTreeMap::new();
+    let Some(&(_, offset, size, link, _)) = sections.iter().find(|s| s.0 == SHT_GNU_VERDEF) else { return names };
+    let Some(&(_, str_offset, str_size, _, _)) = sections.get(link as usize) else { return names };
+    let Some(strtab) = elf.data.get(str_offset..str_offset.saturating_add(str_size)) else { return names };
+    let mut pos = offset;
+    while pos < offset.saturating_add(size) {
+        let (Some(flags), Some(index), Some(aux), Some(next)) = (elf.u16(pos + 2), elf.u16(pos + 4), elf.u32(pos + 12), elf.u32(pos + 16)) else { break };
+        let name = elf.u32(pos + aux as usize)
+            .and_then(|name| strtab.get(name as usize..)?.split(|&b| b == 0).next())
+            .and_then(|name| std::str::from_utf8(name).ok());
+        if let Some(name) = name.filter(|_| flags & VER_FLG_BASE == 0) {
+            names.insert(index, name.to_owned());
+        }
+        if next == 0 {
+            break;
+        }
+        pos += next as usize;
+    }
+    names
+}
+
+/// A library's section of a `symbols` file
+#[derive(Debug, Default)]
+struct BaselineLibrary {
+    /// The rest of the header line after the SONAME, like `libfoo1 #MINVER#`
+    dependency: String,
+    /// Minimum version by `name@version`
+    symbols: BTreeMap<String, String>,
+}
+
+/// Parses the format of `deb-symbols(5)`. Symbol tags like `(c++)`, alternative dependencies and fields aren't supported.
+fn parse_symbols_file(content: &str) -> BTreeMap<String, BaselineLibrary> {
+    let mut libraries: BTreeMap<String, BaselineLibrary> = BTreeMap::new();
+    let mut current = None;
+    for line in content.lines() {
+        if line.starts_with([' ', '\t']) {
+            let mut parts = line.split_whitespace();
+            if let (Some(lib), Some(symbol), Some(version)) = (current.as_ref().and_then(|soname| libraries.get_mut(soname)), parts.next(), parts.next()) {
+                lib.symbols.insert(symbol.to_owned(), version.to_owned());
+            }
+        } else if !line.starts_with(['#', '|', '*']) && !line.trim().is_empty() {
+            let (soname, dependency) = line.trim().split_once(char::is_whitespace).unwrap_or((line.trim(), ""));
+            libraries.insert(soname.to_owned(), BaselineLibrary { dependency: dependency.trim().to_owned(), symbols: BTreeMap::new() });
+            current = Some(soname.to_owned());
+        }
+    }
+    libraries
+}
+
+/// Contents of the `symbols` file, with symbols from the baseline keeping their minimum version, and new symbols getting `version`.
+/// Returns symbols that were added, and an error if symbols were removed.
+fn symbols_file(libraries: &[SharedLibrary], package: &str, version: &str, baseline: &BTreeMap<String, BaselineLibrary>) -> CDResult<(String, Vec<String>)> {
+    let mut out = String::new();
+    let mut added = Vec::new();
+    let mut removed = String::new();
+    for lib in libraries {
+        let base = baseline.get(&lib.soname);
+        let dependency = base.map(|b| b.dependency.as_str()).filter(|d| !d.is_empty()).map_or_else(|| format!("{package} #MINVER#"), String::from);
+        let _ = writeln!(out, "{} {dependency}", lib.soname);
+        for symbol in &lib.symbols {
+            let min_version = base.and_then(|b| b.symbols.get(symbol));
+            if base.is_some() && min_version.is_none() {
+                added.push(format!("{} {symbol}", lib.soname));
+            }
+            let _ = writeln!(out, " {symbol} {}", min_version.map_or(version, String::as_str));
+        }
+        for symbol in base.iter().flat_map(|b| b.symbols.keys()).filter(|&s| lib.symbols.binary_search(s).is_err()) {
+            let _ = writeln!(removed, "  {} {symbol}", lib.soname);
+        }
+    }
+    if !removed.is_empty() {
+        return Err(CargoDebError::SymbolsRemoved(removed.trim_end().to_owned()));
+    }
+    Ok((out, added))
+}
+
+/// Makes the `symbols` file from the packaged shared libraries if `symbols` is set, and compares it with the baseline file, if any
+pub(crate) fn generate(config: &Config, package_deb: &mut PackageConfig, listener: &dyn Listener) -> CDResult<()> {
+    let baseline = match &package_deb.symbols {
+        None | Some(SymbolsConfig::Enabled(false)) => return Ok(()),
+        Some(SymbolsConfig::Enabled(true)) => None,
+        Some(SymbolsConfig::Baseline(path)) => Some(config.path_in_package(path)),
+    };
+    let baseline = baseline.as_deref();
+    let mut libraries = Vec::new();
+    for asset in &package_deb.assets.resolved {
+        let is_library = asset.c.is_dynamic_library() || asset.c.target_path.file_name().and_then(|f| f.to_str()).is_some_and(|f| f.contains(".so."));
+        if !is_library || asset.source.archive_as_symlink_only() || asset.c.target_path.starts_with("usr/lib/debug") {
+            continue;
+        }
+        if let Some(lib) = read_shared_library(&asset.source.data()?) {
+            libraries.push(lib);
+        } else {
+            listener.warning(format!("/{} has no SONAME, so it's not in the symbols file. Link it with -C link-arg=-Wl,-soname,<name>", asset.c.target_path.display()));
+        }
+    }
+    if libraries.is_empty() {
+        listener.warning("symbols is set, but the package has no shared libraries with a SONAME".into());
+        return Ok(());
+    }
+    libraries.sort_by(|a, b| a.soname.cmp(&b.soname));
+
+    let baseline_libraries = match baseline {
+        Some(path) => parse_symbols_file(&fs::read_to_string(path).map_err(|e| CargoDebError::IoFile("unable to read symbols file", e, path.into()))?),
+        None => BTreeMap::new(),
+    };
+    for soname in baseline_libraries.keys().filter(|&s| !libraries.iter().any(|lib| &lib.soname == s)) {
+        listener.warning(format!("{soname} from the symbols file is not in the package. If its SONAME has changed, the symbols file needs to be replaced"));
+    }
+
+    // Symbols added in this version are satisfied by any revision of it
+    let version = package_deb.deb_version.rsplit_once('-').map_or(package_deb.deb_version.as_str(), |(upstream, _)| upstream);
+    let (file, added) = symbols_file(&libraries, &package_deb.deb_name, version, &baseline_libraries)?;
+    if let Some(path) = baseline.filter(|_| !added.is_empty()) {
+        listener.info(format!("{} new symbols since {}, e.g. {}. Update it with `dpkg-deb -I <deb> symbols`", added.len(), path.display(), added[0]));
+    }
+    package_deb.symbols_file = Some(file);
+    Ok(())
+}
+
+#[test]
+fn symbols_file_with_baseline() {
+    let libs = [SharedLibrary { soname: "libfoo.so.1".into(), symbols: vec!["foo_new@Base".into(), "foo_open@Base".into()] }];
+    let (file, added) = symbols_file(&libs, "libfoo1", "1.2.0", &BTreeMap::new()).unwrap();
+    assert_eq!("libfoo.so.1 libfoo1 #MINVER#\n foo_new@Base 1.2.0\n foo_open@Base 1.2.0\n", file);
+    assert!(added.is_empty());
+
+    let baseline = parse_symbols_file("# comment\nlibfoo.so.1 libfoo1 (>= 1.0) #MINVER#\n* Build-Depends-Package: foo\n foo_open@Base 1.0.0\n");
+    let (file, added) = symbols_file(&libs, "libfoo1", "1.2.0", &baseline).unwrap();
+    assert_eq!("libfoo.so.1 libfoo1 (>= 1.0) #MINVER#\n foo_new@Base 1.2.0\n foo_open@Base 1.0.0\n", file);
+    assert_eq!(["libfoo.so.1 foo_new@Base"], &added[..]);
+
+    let baseline = parse_symbols_file("libfoo.so.1 libfoo1 #MINVER#\n foo_close@Base 1.0.0\n foo_open@Base 1.0.0\n");
+    let err = symbols_file(&libs, "libfoo1", "1.2.0", &baseline).unwrap_err();
+    assert!(err.to_string().contains("libfoo.so.1 foo_close@Base"));
+}
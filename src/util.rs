@@ -1,6 +1,7 @@
 use std::collections::BTreeSet;
 use std::path::Path;
 
+pub(crate) mod atomic_file;
 pub(crate) mod ok_or;
 pub(crate) mod pathbytes;
 pub(crate) mod wordsplit;
@@ -14,6 +15,50 @@ pub(crate) fn fname_from_path(path: &Path) -> String {
     path.file_name().unwrap().to_string_lossy().into()
 }
 
+/// The candidate most similar to the `word`, if it's similar enough to be a typo
+pub(crate) fn closest_match<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_distance = (word.chars().count() / 3).clamp(1, 3);
+    candidates.iter()
+        .map(|&c| (edit_distance(word, c), c))
+        .filter(|&(d, _)| d <= max_distance)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Lowercase hex, like in checksum files
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(out, "{b:02x}");
+    }
+    out
+}
+
+/// Quotes the string for `sh`, unless it's made only of characters that are safe unquoted
+pub(crate) fn shell_quote(s: &str) -> String {
+    if !s.is_empty() && s.bytes().all(|c| c.is_ascii_alphanumeric() || b"/._-+:,=@".contains(&c)) {
+        return s.to_owned();
+    }
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
 #[cfg(test)]
 pub(crate) use tests::is_path_file;
 
@@ -300,4 +345,19 @@ pub(crate) mod tests {
         assert_eq!("ab", two.join(""));
         assert_eq!("a,b", two.join(","));
     }
+
+    #[test]
+    fn shell_quoting() {
+        assert_eq!("/usr/bin/tool.distrib", shell_quote("/usr/bin/tool.distrib"));
+        assert_eq!("'/opt/my tool/it'\\''s'", shell_quote("/opt/my tool/it's"));
+        assert_eq!("''", shell_quote(""));
+    }
+
+    #[test]
+    fn closest_matches() {
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+        assert_eq!(Some("utils"), closest_match("util", &["admin", "utils"]));
+        assert_eq!(Some("optional"), closest_match("optinal", &["optional", "required"]));
+        assert_eq!(None, closest_match("xyz", &["admin", "utils"]));
+    }
 }
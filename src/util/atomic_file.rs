@@ -0,0 +1,62 @@
+//! Output files are written under a temporary name in the same directory, and renamed when they're complete,
+//! so an interrupted or failed build never leaves a truncated `.deb` that looks like a finished one.
+use std::fs;
+use std::io;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// A hidden `.<name>.XXXXXX.partial` file next to the `path`. It's deleted if it's dropped without `persist`.
+pub(crate) fn create(path: &Path) -> io::Result<NamedTempFile> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = format!(".{}.", path.file_name().ok_or(io::ErrorKind::InvalidInput)?.to_string_lossy());
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(&prefix).suffix(".partial");
+    // tempfiles are private by default, but outputs should get the same mode as from `File::create`
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    builder.tempfile_in(dir)
+}
+
+/// Flushes the file to disk, and renames it to the `path`, replacing the previous file
+pub(crate) fn persist(file: NamedTempFile, path: &Path) -> io::Result<()> {
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Like `fs::write`, but readers see either the old or the new file
+pub(crate) fn write(path: &Path, data: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = create(path)?;
+    io::Write::write_all(&mut file, data.as_ref())?;
+    persist(file, path)
+}
+
+/// Like `fs::copy`, but readers see either the old or the new file
+pub(crate) fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    let mut file = create(to)?;
+    io::copy(&mut fs::File::open(from)?, &mut file)?;
+    persist(file, to)
+}
+
+#[test]
+fn leaves_no_partial_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test_1.0_all.deb");
+    write(&path, "old").unwrap();
+
+    let mut partial = create(&path).unwrap();
+    io::Write::write_all(&mut partial, b"trunc").unwrap();
+    assert_eq!("old", fs::read_to_string(&path).unwrap());
+    drop(partial);
+
+    copy(&path, &dir.path().join("copy.deb")).unwrap();
+    let mut names = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(["copy.deb", "test_1.0_all.deb"], names.as_slice());
+}
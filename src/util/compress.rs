@@ -1,8 +1,6 @@
 use crate::error::{CDResult, CargoDebError};
 use std::io::{BufWriter, Read};
 use std::num::NonZeroU64;
-#[cfg(feature = "lzma")]
-use std::num::NonZeroUsize;
 use std::process::{Child, ChildStdin, Command, Stdio};
 use std::{io, ops};
 use zopfli::{BlockType, GzipEncoder, Options};
@@ -12,6 +10,38 @@ pub struct CompressConfig {
     pub compress_type: Format,
     pub compress_system: bool,
     pub rsyncable: bool,
+    /// Only applies to the data archive
+    pub tuning: CompressTuning,
+}
+
+/// Advanced settings for large packages
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CompressTuning {
+    /// 0-9, instead of the default level (or `--fast`)
+    pub level: Option<u32>,
+    /// Size of the xz (LZMA2) dictionary in bytes. Larger dictionaries find repetitions further apart in the data,
+    /// but need more memory to compress and decompress.
+    pub xz_dict_size: Option<u32>,
+}
+
+/// Parses sizes like `64MiB`, `512K` or `1G` (powers of 1024) for `--xz-dict-size`.
+/// xz supports dictionaries from 4KiB to 1.5GiB.
+pub fn parse_xz_dict_size(size: &str) -> Option<u32> {
+    let size = size.trim();
+    let digits_end = size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+    let (num, unit) = size.split_at(digits_end);
+    let multiplier = match unit.trim().trim_end_matches("iB").trim_end_matches('B') {
+        "" => 1,
+        "K" | "k" => 1 << 10,
+        "M" | "m" => 1 << 20,
+        "G" | "g" => 1 << 30,
+        _ => return None,
+    };
+    let bytes = num.parse::<u64>().ok()?.checked_mul(multiplier)?;
+    if !(4 << 10..=3 << 29).contains(&bytes) {
+        return None;
+    }
+    u32::try_from(bytes).ok()
 }
 
 #[derive(Clone, Copy)]
@@ -29,14 +59,17 @@ impl Format {
         }
     }
 
-    fn program(self) -> &'static str {
+    pub(crate) fn program(self) -> &'static str {
         match self {
             Self::Xz => "xz",
             Self::Gzip => "gzip",
         }
     }
 
-    fn level(self, fast: bool) -> u32 {
+    fn level(self, fast: bool, tuning: CompressTuning) -> u32 {
+        if let Some(level) = tuning.level {
+            return level;
+        }
         match self {
             Self::Xz => if fast { 1 } else { 6 },
             Self::Gzip => if fast { 1 } else { 9 },
@@ -152,9 +185,17 @@ impl ops::Deref for Compressed {
     }
 }
 
-fn system_compressor(compress_format: Format, fast: bool) -> CDResult<Compressor> {
-    let mut child = Command::new(compress_format.program())
-        .arg(format!("-{}", compress_format.level(fast)))
+fn system_compressor(compress_format: Format, fast: bool, tuning: CompressTuning) -> CDResult<Compressor> {
+    let mut cmd = Command::new(compress_format.program());
+    let level = compress_format.level(fast, tuning);
+    match (compress_format, tuning.xz_dict_size) {
+        (Format::Xz, Some(dict_size)) => cmd.arg(format!("--lzma2=preset={level},dict={dict_size}")),
+        _ => cmd.arg(format!("-{level}")),
+    };
+    if let Format::Xz = compress_format {
+        cmd.arg(format!("--threads={}", rayon::current_num_threads()));
+    }
+    let mut child = cmd
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::inherit())
@@ -171,38 +212,47 @@ fn system_compressor(compress_format: Format, fast: bool) -> CDResult<Compressor
     Ok(Compressor::new(Writer::StdIn { compress_format, child, handle, stdin }))
 }
 
-pub fn select_compressor(fast: bool, compress_format: Format, use_system: bool) -> CDResult<Compressor> {
+pub fn select_compressor(fast: bool, compress_format: Format, use_system: bool, tuning: CompressTuning) -> CDResult<Compressor> {
     if use_system {
-        return system_compressor(compress_format, fast);
+        return system_compressor(compress_format, fast, tuning);
     }
 
     match compress_format {
         #[cfg(feature = "lzma")]
         Format::Xz => {
             // Compression level 6 is a good trade off between size and [ridiculously] long compression time
-            let encoder = xz2::stream::MtStreamBuilder::new()
-                .threads(std::thread::available_parallelism().unwrap_or(NonZeroUsize::new(1).unwrap()).get() as u32)
-                .preset(compress_format.level(fast))
-                .encoder()
-                .map_err(CargoDebError::LzmaCompressionError)?;
+            let level = compress_format.level(fast, tuning);
+            let mut builder = xz2::stream::MtStreamBuilder::new();
+            // Same as available parallelism, unless limited with `--jobs`
+            builder.threads(rayon::current_num_threads() as u32);
+            if let Some(dict_size) = tuning.xz_dict_size {
+                let mut options = xz2::stream::LzmaOptions::new_preset(level).map_err(CargoDebError::LzmaCompressionError)?;
+                options.dict_size(dict_size);
+                let mut filters = xz2::stream::Filters::new();
+                filters.lzma2(&options);
+                builder.filters(filters);
+            } else {
+                builder.preset(level);
+            }
+            let encoder = builder.encoder().map_err(CargoDebError::LzmaCompressionError)?;
 
             let writer = xz2::write::XzEncoder::new_stream(Vec::new(), encoder);
             Ok(Compressor::new(Writer::Xz(writer)))
         },
         #[cfg(not(feature = "lzma"))]
-        Format::Xz => system_compressor(compress_format, fast),
+        Format::Xz => system_compressor(compress_format, fast, tuning),
         Format::Gzip => {
             use flate2::write::GzEncoder;
             use flate2::Compression;
 
-            let writer = if !fast {
+            let writer = if !fast && tuning.level.is_none() {
                 let inner_writer = GzipEncoder::new_buffered(Options {
                     iteration_count: NonZeroU64::new(7).unwrap(),
                     ..Options::default()
                 }, BlockType::Dynamic, Vec::new()).unwrap();
                 Writer::ZopfliGz(inner_writer)
             } else {
-                let inner_writer = GzEncoder::new(Vec::new(), Compression::new(compress_format.level(fast)));
+                let inner_writer = GzEncoder::new(Vec::new(), Compression::new(compress_format.level(fast, tuning)));
                 Writer::Gz(inner_writer)
             };
             Ok(Compressor::new(writer))
@@ -224,3 +274,16 @@ pub(crate) fn gzipped(mut content: &[u8]) -> io::Result<Vec<u8>> {
     encoder.finish()?;
     Ok(compressed)
 }
+
+#[test]
+fn xz_dict_sizes() {
+    assert_eq!(Some(64 << 20), parse_xz_dict_size("64MiB"));
+    assert_eq!(Some(64 << 20), parse_xz_dict_size("64M"));
+    assert_eq!(Some(1536 << 20), parse_xz_dict_size("1536MiB"));
+    assert_eq!(Some(8192), parse_xz_dict_size("8192"));
+    assert_eq!(Some(1 << 30), parse_xz_dict_size("1G"));
+    assert_eq!(None, parse_xz_dict_size("2GiB"));
+    assert_eq!(None, parse_xz_dict_size("1KiB"));
+    assert_eq!(None, parse_xz_dict_size("64 parsecs"));
+    assert_eq!(None, parse_xz_dict_size("MiB"));
+}
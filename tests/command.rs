@@ -255,6 +255,14 @@ fn run_cargo_deb_command_on_example_dir_with_separate_debug_symbols() {
     );
 }
 
+#[test]
+#[cfg(target_os = "linux")]
+fn static_pie_with_proc_macro_dependency() {
+    // Without an explicit --target, crt-static would also apply to the proc-macro, which can't be built that way
+    let (_cdir, ddir) = extract_built_package_from_manifest("tests/static-pie/Cargo.toml", DEFAULT_COMPRESSION_EXT, &[]);
+    assert!(ddir.path().join("usr/bin/static-pie-test").exists());
+}
+
 #[test]
 #[cfg(feature = "lzma")]
 fn run_cargo_deb_command_on_example_dir_with_variant() {
@@ -414,3 +422,68 @@ fn cwd_dir2() {
 fn cwd_dir3() {
     dir_test_run_in_subdir("tests/dir-confusion/src");
 }
+
+/// Builds a fixture and compares the package with `tests/snapshots/{name}.txt`.
+/// Set `CARGO_DEB_UPDATE_SNAPSHOTS=1` to create or update the golden files.
+#[track_caller]
+fn check_snapshot(name: &str, manifest_path: &str, args: &[&str]) {
+    use cargo_deb::snapshot::{assert_snapshot, snapshot_deb, HOST_DEPENDENT_FIELDS};
+
+    let (_bdir, deb_path) = cargo_deb(manifest_path, args);
+    let snapshot = snapshot_deb(&deb_path, HOST_DEPENDENT_FIELDS).unwrap();
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR"));
+    assert_snapshot(&snapshot, &root.join("tests/snapshots").join(format!("{name}.txt")));
+}
+
+#[test]
+#[cfg(all(feature = "lzma", target_os = "linux"))]
+fn snapshot_simple_bin_with_asset_globs() {
+    check_snapshot("example", "example/Cargo.toml", &[]);
+}
+
+#[test]
+#[cfg(all(feature = "lzma", target_os = "linux"))]
+fn snapshot_workspace_member() {
+    check_snapshot("test-ws1", "tests/test-workspace/test-ws1/Cargo.toml", &["--no-strip", "--fast"]);
+}
+
+#[test]
+#[cfg(all(feature = "lzma", target_os = "linux"))]
+fn snapshot_cdylib() {
+    check_snapshot("test-ws2", "tests/test-workspace/test-ws2/Cargo.toml", &["--no-strip"]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn snapshot_explicit_target() {
+    // Cross-compiling toolchains may not be installed, but an explicit target uses the same
+    // target/<triple>/release layout, and the same gzip path as cross builds with --fast
+    let rustc = Command::new(env::var_os("RUSTC").unwrap_or_else(|| "rustc".into())).arg("-vV").output().unwrap();
+    let host = String::from_utf8(rustc.stdout).unwrap().lines()
+        .find_map(|l| l.strip_prefix("host: ").map(String::from)).unwrap();
+    check_snapshot("example-target", "example/Cargo.toml", &["--target", &host, "--compress-type", "gzip", "--no-strip"]);
+}
+
+#[test]
+fn simulate_fixture() {
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR"));
+    let output = Command::new(root.join(env!("CARGO_BIN_EXE_cargo-deb")))
+        .arg("--simulate").arg(root.join("tests/simulate/cross.toml"))
+        .output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(plan["architecture"], "arm64");
+    assert_eq!(plan["conf_files"], serde_json::json!(["/opt/simulated/app.toml"]));
+    let targets = plan["assets"].as_array().unwrap().iter().map(|a| a["target"].as_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!(targets, [
+        "/usr/share/doc/simulated/copyright",
+        "/usr/bin/simulated",
+        "/usr/libexec/simulated/helper",
+        "/opt/simulated/app.toml",
+        "/opt/simulated/other.toml",
+        "/usr/share/doc/simulated/README.md",
+    ]);
+    let control = plan["control"].as_str().unwrap();
+    assert!(control.contains("\nDepends: extra, libfoo1 (>= 1.0)\n"), "{control}");
+}
@@ -0,0 +1,6 @@
+use proc_macro::TokenStream;
+
+#[proc_macro]
+pub fn greet(_: TokenStream) -> TokenStream {
+    r#"fn greet() { println!("hello"); }"#.parse().unwrap()
+}
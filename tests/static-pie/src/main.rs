@@ -0,0 +1,5 @@
+greet_macro::greet!();
+
+fn main() {
+    greet();
+}